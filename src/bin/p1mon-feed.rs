@@ -0,0 +1,101 @@
+//! Simulator of a meter writing P1 telegrams with a valid CRC, such that the node can be run without meter.
+//!
+//! The telegrams are written to the standard output, to the file given with --output, or with --pty to a new
+//! pseudo-terminal whose name (e.g. /dev/pts/7) is printed on the standard error to be given to the node.
+
+use std::fs::OpenOptions;
+use std::io::{self, Write};
+use std::time::Duration;
+
+use chrono::Utc;
+
+#[path = "../feed.rs"]
+mod feed;
+
+fn main() -> io::Result<()> {
+    let args: Vec<String> = std::env::args().collect();
+    let arg = |name: &str| {
+        args.windows(2)
+            .find(|w| w[0] == name)
+            .map(|w| w[1].as_str())
+    };
+    let invalid = |name: &str, value: &str| {
+        io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!("invalid {name} '{value}'"),
+        )
+    };
+
+    //the time between two telegrams in seconds: --interval (default 1, DSMR 5 meters send one telegram per second)
+    let interval = match arg("--interval") {
+        Some(s) => s
+            .parse()
+            .ok()
+            .and_then(|s| Duration::try_from_secs_f64(s).ok())
+            .ok_or_else(|| invalid("interval", s))?,
+        None => Duration::from_secs(1),
+    };
+    //the number of telegrams written: --count (default unlimited)
+    let count = match arg("--count") {
+        Some(s) => s.parse().map_err(|_| invalid("count", s))?,
+        None => usize::MAX,
+    };
+    //the telegrams of the recording --recording path are written in a loop, their CRC computed again
+    //without recording, the telegrams are generated with the current time
+    let recorded = match arg("--recording") {
+        Some(path) => {
+            let telegrams = feed::recorded_telegrams(&std::fs::read(path)?);
+            if telegrams.is_empty() {
+                return Err(invalid("recording", path));
+            }
+            Some(telegrams)
+        }
+        None => None,
+    };
+
+    // the slave side of the pseudo-terminal is kept open such that the writes do not fail before the node opens it
+    let mut _slave = None;
+    let mut out: Box<dyn Write> = if args.iter().any(|a| a == "--pty") {
+        let (master, slave) = open_pty()?;
+        _slave = Some(slave);
+        master
+    } else if let Some(path) = arg("--output") {
+        Box::new(OpenOptions::new().append(true).create(true).open(path)?)
+    } else {
+        Box::new(io::stdout())
+    };
+
+    for n in 0..count {
+        let telegram = match &recorded {
+            Some(telegrams) => telegrams[n % telegrams.len()].clone(),
+            None => feed::generated_telegram(
+                n as u64,
+                &Utc::now().with_timezone(&chrono_tz::Europe::Amsterdam),
+            ),
+        };
+        out.write_all(&feed::with_crc(&telegram))?;
+        out.flush()?;
+        if n + 1 < count {
+            std::thread::sleep(interval);
+        }
+    }
+    Ok(())
+}
+
+/// opens a new pseudo-terminal and prints the name of its slave side, returns the master side and the slave side
+#[cfg(unix)]
+fn open_pty() -> io::Result<(Box<dyn Write>, Box<dyn std::any::Any>)> {
+    use serialport::SerialPort;
+
+    let (master, slave) = serialport::TTYPort::pair()?;
+    eprintln!("{}", slave.name().unwrap_or_default());
+    Ok((Box::new(master), Box::new(slave)))
+}
+
+#[cfg(not(unix))]
+fn open_pty() -> io::Result<(Box<dyn Write>, Box<dyn std::any::Any>)> {
+    Err(io::Error::new(
+        io::ErrorKind::Unsupported,
+        "--pty is only available on Unix",
+    ))
+}
@@ -0,0 +1,150 @@
+//! Capture of the raw telegrams to a file, for reproducing the problems seen in the field.
+//!
+//! Each telegram is preceded by a `# <local time> <source>` line, which is skipped when the capture is replayed
+//! since it does not start with `/`.
+//! When the file would exceed the maximum size, `.1` is appended to its name (replacing the previous such file)
+//! and a new file is started.
+
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::sync::mpsc::{self, Sender};
+use std::thread::JoinHandle;
+
+/// the writer of the capture file, shared by the sources of a node
+/// the telegrams are written by a dedicated thread such that the file operations (including the rotation) do not
+/// block the runtime; the thread ends once the capture is dropped, after writing the telegrams still queued
+pub struct Capture {
+    // the header line and the telegram, None only while dropping
+    tx: Option<Sender<(String, Vec<u8>)>>,
+    writer: Option<JoinHandle<()>>,
+}
+
+impl Capture {
+    pub fn new(path: &Path, max_size: u64) -> Self {
+        let (tx, rx) = mpsc::channel::<(String, Vec<u8>)>();
+        let mut file = CaptureFile {
+            path: path.to_owned(),
+            max_size,
+            file: None,
+            size: 0,
+        };
+        let writer = std::thread::spawn(move || {
+            for (prefix, telegram) in rx {
+                file.write(&prefix, &telegram);
+            }
+        });
+        Self {
+            tx: Some(tx),
+            writer: Some(writer),
+        }
+    }
+
+    /// queues the telegram for the capture file, preceded by the current local time and the source name
+    pub fn write(&self, source: &str, telegram: &[u8]) {
+        let prefix = format!(
+            "# {} {source}\r\n",
+            chrono::Local::now().format("%Y-%m-%dT%H:%M:%S%.3f%:z")
+        );
+        if let Some(tx) = &self.tx {
+            let _ = tx.send((prefix, telegram.to_vec()));
+        }
+    }
+}
+
+impl Drop for Capture {
+    /// waits for the queued telegrams to be written
+    fn drop(&mut self) {
+        drop(self.tx.take());
+        if let Some(writer) = self.writer.take() {
+            let _ = writer.join();
+        }
+    }
+}
+
+/// the capture file, only accessed by the writer thread
+struct CaptureFile {
+    path: PathBuf,
+    max_size: u64,
+    // opened at the first write
+    file: Option<File>,
+    size: u64,
+}
+
+impl CaptureFile {
+    /// appends the telegram to the capture file; errors are only logged such that the capture does not stop the
+    /// processing of the telegrams
+    fn write(&mut self, prefix: &str, telegram: &[u8]) {
+        if let Err(e) = self.try_write(prefix, telegram) {
+            log::warn!(
+                "Cannot write to the capture file {}: {e}",
+                self.path.display()
+            );
+            self.file = None;
+        }
+    }
+
+    fn try_write(&mut self, prefix: &str, telegram: &[u8]) -> io::Result<()> {
+        let len = (prefix.len() + telegram.len()) as u64;
+
+        if self.file.is_none() {
+            self.open()?;
+        }
+        if self.size > 0 && self.size + len > self.max_size {
+            self.file = None;
+            fs::rename(&self.path, self.rotated_path())?;
+            self.open()?;
+        }
+        let file = self.file.as_mut().unwrap();
+        file.write_all(prefix.as_bytes())?;
+        file.write_all(telegram)?;
+        self.size += len;
+        Ok(())
+    }
+
+    fn rotated_path(&self) -> PathBuf {
+        let mut path = self.path.clone().into_os_string();
+        path.push(".1");
+        PathBuf::from(path)
+    }
+
+    fn open(&mut self) -> io::Result<()> {
+        let file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&self.path)?;
+        self.size = file.metadata()?.len();
+        self.file = Some(file);
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_capture_rotation() {
+        let dir = std::env::temp_dir().join(format!("p1mon-capture-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("capture.txt");
+
+        let capture = Capture::new(&path, 100);
+        let telegram = b"/ISK5\\2M550T-1012\r\n\r\n1-0:1.7.0(00.316*kW)\r\n!ABCD\r\n";
+        capture.write("main", telegram);
+        capture.write("main", telegram);
+        // the telegrams are written once the capture is dropped
+        drop(capture);
+
+        // the second telegram does not fit in the first file
+        let old = fs::read_to_string(dir.join("capture.txt.1")).unwrap();
+        let new = fs::read_to_string(&path).unwrap();
+        for content in [old, new] {
+            assert!(content.starts_with("# "));
+            assert!(content
+                .ends_with(" main\r\n/ISK5\\2M550T-1012\r\n\r\n1-0:1.7.0(00.316*kW)\r\n!ABCD\r\n"));
+        }
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,214 @@
+//! Commands accepted by the node, received from Yamcs on the node link:
+//! - `resend_definitions` sends again the definitions of all the known parameters, e.g. after a restart of Yamcs
+//! - `reset_counters` restarts the sequence counts and the statistics from 0, and optionally forgets the last values
+//!   sent such that all the values of the next telegram are published
+//! - `pause` stops publishing the values, the telegrams are still read and counted in the statistics
+//! - `resume` publishes again the values, starting with the next telegram
+//! - `read_now` publishes right away the latest value of every parameter, even if it did not change
+//!
+//! The commands apply to all the sources of the node and are acknowledged as soon as they are handed over to them.
+
+use ygw::protobuf::ygw::{
+    command_ack::AckStatus, value::V, CommandAck, CommandArgument, CommandDefinition,
+    CommandDefinitionList, PreparedCommand, Value,
+};
+
+// the key of the acknowledgement sent for each command
+const ACK_KEY: &str = "CommandComplete";
+// the argument of reset_counters
+const CLEAR_LAST_VALUES: &str = "clear_last_values";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Command {
+    ResendDefinitions,
+    ResetCounters { clear_last_values: bool },
+    Pause,
+    Resume,
+    ReadNow,
+}
+
+impl Command {
+    const ALL: &'static [Command] = &[
+        Command::ResendDefinitions,
+        Command::ResetCounters {
+            clear_last_values: false,
+        },
+        Command::Pause,
+        Command::Resume,
+        Command::ReadNow,
+    ];
+
+    fn name(&self) -> &'static str {
+        match self {
+            Command::ResendDefinitions => "resend_definitions",
+            Command::ResetCounters { .. } => "reset_counters",
+            Command::Pause => "pause",
+            Command::Resume => "resume",
+            Command::ReadNow => "read_now",
+        }
+    }
+
+    fn description(&self) -> &'static str {
+        match self {
+            Command::ResendDefinitions => "Send again the definitions of all the known parameters",
+            Command::ResetCounters { .. } => {
+                "Restart the sequence counts and the statistics counters from 0"
+            }
+            Command::Pause => "Stop publishing the values of the meters, e.g. during a maintenance",
+            Command::Resume => "Publish again the values of the meters",
+            Command::ReadNow => {
+                "Publish right away the latest value of every parameter, e.g. after a restart of Yamcs"
+            }
+        }
+    }
+
+    fn arguments(&self) -> Vec<CommandArgument> {
+        match self {
+            Command::ResendDefinitions | Command::Pause | Command::Resume | Command::ReadNow => {
+                Vec::new()
+            }
+            Command::ResetCounters { .. } => vec![CommandArgument {
+                name: CLEAR_LAST_VALUES.to_owned(),
+                description: Some(
+                    "Forget the last values sent, such that the next values are sent even if unchanged"
+                        .to_owned(),
+                ),
+                unit: None,
+                argtype: "boolean".to_owned(),
+                default_value: Some(Value {
+                    v: Some(V::BooleanValue(false)),
+                }),
+            }],
+        }
+    }
+
+    /// returns the command with the name of the prepared command, which may be qualified by the Yamcs namespace
+    pub fn from_prepared(pc: &PreparedCommand) -> std::result::Result<Command, String> {
+        let name = pc
+            .command_id
+            .command_name
+            .as_deref()
+            .ok_or_else(|| "command without name".to_owned())?;
+        let relative_name = name.rsplit('/').next().unwrap_or(name);
+        let cmd = Command::ALL
+            .iter()
+            .find(|c| c.name() == relative_name)
+            .copied()
+            .ok_or_else(|| format!("unknown command '{name}'"))?;
+        match cmd {
+            Command::ResendDefinitions | Command::Pause | Command::Resume | Command::ReadNow => {
+                Ok(cmd)
+            }
+            Command::ResetCounters { .. } => Ok(Command::ResetCounters {
+                clear_last_values: bool_argument(pc, CLEAR_LAST_VALUES)?.unwrap_or(false),
+            }),
+        }
+    }
+}
+
+/// returns the value of the boolean argument of the command or None if it is not assigned
+fn bool_argument(pc: &PreparedCommand, name: &str) -> std::result::Result<Option<bool>, String> {
+    let Some(assignment) = pc.assignments.iter().find(|a| a.name == name) else {
+        return Ok(None);
+    };
+    match assignment
+        .eng_value
+        .as_ref()
+        .or(assignment.raw_value.as_ref())
+        .and_then(|v| v.v.as_ref())
+    {
+        Some(V::BooleanValue(b)) => Ok(Some(*b)),
+        v => Err(format!("invalid value {v:?} of the argument {name}")),
+    }
+}
+
+/// returns the definitions of the commands, sent to Yamcs when the node starts
+pub fn definitions() -> CommandDefinitionList {
+    CommandDefinitionList {
+        definitions: Command::ALL
+            .iter()
+            .map(|c| CommandDefinition {
+                relative_name: c.name().to_owned(),
+                description: Some(c.description().to_owned()),
+                arguments: c.arguments(),
+                ..Default::default()
+            })
+            .collect(),
+    }
+}
+
+/// returns the acknowledgement of the command, with the error message if it failed
+pub fn ack(pc: &PreparedCommand, result: std::result::Result<(), String>) -> CommandAck {
+    let (status, message) = match result {
+        Ok(()) => (AckStatus::Ok, None),
+        Err(e) => (AckStatus::Nok, Some(e)),
+    };
+    CommandAck {
+        command_id: pc.command_id.clone(),
+        ack: status as i32,
+        key: ACK_KEY.to_owned(),
+        time: ygw::protobuf::now(),
+        message,
+        return_pv: None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ygw::protobuf::ygw::{CommandAssignment, CommandId, Value};
+
+    fn prepared(name: Option<&str>) -> PreparedCommand {
+        PreparedCommand {
+            command_id: CommandId {
+                command_name: name.map(str::to_owned),
+                ..Default::default()
+            },
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_command_names() {
+        assert_eq!(
+            Command::from_prepared(&prepared(Some("/P1MON/resend_definitions"))),
+            Ok(Command::ResendDefinitions)
+        );
+        assert_eq!(
+            Command::from_prepared(&prepared(Some("resend_definitions"))),
+            Ok(Command::ResendDefinitions)
+        );
+        assert_eq!(
+            Command::from_prepared(&prepared(Some("/P1MON/read_now"))),
+            Ok(Command::ReadNow)
+        );
+        assert!(Command::from_prepared(&prepared(Some("/P1MON/self_destruct"))).is_err());
+        assert!(Command::from_prepared(&prepared(None)).is_err());
+
+        let mut pc = prepared(Some("/P1MON/reset_counters"));
+        assert_eq!(
+            Command::from_prepared(&pc),
+            Ok(Command::ResetCounters {
+                clear_last_values: false
+            })
+        );
+        let assign = |v| CommandAssignment {
+            name: "clear_last_values".to_owned(),
+            raw_value: None,
+            eng_value: Some(Value { v: Some(v) }),
+        };
+        pc.assignments = vec![assign(V::BooleanValue(true))];
+        assert_eq!(
+            Command::from_prepared(&pc),
+            Ok(Command::ResetCounters {
+                clear_last_values: true
+            })
+        );
+        pc.assignments = vec![assign(V::StringValue("yes".to_owned()))];
+        assert!(Command::from_prepared(&pc).is_err());
+
+        let ack = ack(&prepared(Some("x")), Err("unknown command 'x'".to_owned()));
+        assert_eq!(ack.ack, AckStatus::Nok as i32);
+        assert_eq!(ack.command_id.command_name.as_deref(), Some("x"));
+    }
+}
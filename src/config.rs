@@ -0,0 +1,210 @@
+//! Configuration file given with `--config`, listing the meters monitored by one server process.
+//!
+//! Each meter is monitored by its own node, named after the meter:
+//!
+//! ```toml
+//! [[meter]]
+//! name = "house"
+//! serial_device = "/dev/ttyUSB0"
+//!
+//! [[meter]]
+//! name = "workshop"
+//! description = "Meter of the workshop"
+//! serial_device = "/dev/ttyUSB1"
+//! parameter_group = "workshop"
+//! codes = "/etc/ygw-p1mon/workshop.csv"
+//! state_file = "/var/lib/ygw-p1mon/workshop.state"
+//! capture = "/var/log/ygw-p1mon/workshop.capture"
+//! metrics = "127.0.0.1:9101"
+//! ```
+//!
+//! The parameter group is p1mon if not given, the OBIS codes file is searched in the default locations.
+//! The serial device can also be the address of a network bridge, tcp://host:port or tls://host:port.
+//! The description shown in Yamcs is the default one of the node if not given.
+//! Each meter with a metrics address has its own metrics endpoint, the addresses cannot be shared.
+//! Likewise the state and capture files of the meters have to be distinct.
+//! The options given on the command line apply to all the meters.
+
+use std::collections::HashSet;
+use std::net::SocketAddr;
+use std::path::{Path, PathBuf};
+
+use serde::Deserialize;
+use ygw::{Result, YgwError};
+
+/// the parameter group of the meters for which it is not given
+const DEFAULT_PARAMETER_GROUP: &str = "p1mon";
+
+/// one meter of the configuration file
+#[derive(Debug, Deserialize, PartialEq)]
+#[serde(deny_unknown_fields)]
+pub struct MeterConfig {
+    pub name: String,
+    pub description: Option<String>,
+    pub serial_device: String,
+    parameter_group: Option<String>,
+    pub codes: Option<PathBuf>,
+    pub state_file: Option<PathBuf>,
+    pub capture: Option<PathBuf>,
+    pub metrics: Option<SocketAddr>,
+}
+
+impl MeterConfig {
+    pub fn parameter_group(&self) -> &str {
+        self.parameter_group
+            .as_deref()
+            .unwrap_or(DEFAULT_PARAMETER_GROUP)
+    }
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct ConfigFile {
+    meter: Vec<MeterConfig>,
+}
+
+/// reads the meters from the configuration file
+pub fn load(path: &Path) -> Result<Vec<MeterConfig>> {
+    let s = std::fs::read_to_string(path).map_err(|e| {
+        YgwError::IOError(
+            format!("cannot read the configuration file {}", path.display()),
+            e,
+        )
+    })?;
+    parse(&s)
+}
+
+/// parses the configuration, checking that there is at least one meter and that the names, the metrics addresses
+/// and the files written are unique
+fn parse(s: &str) -> Result<Vec<MeterConfig>> {
+    let config: ConfigFile = toml::from_str(s)
+        .map_err(|e| YgwError::DecodeError(format!("cannot parse the configuration file: {e}")))?;
+    if config.meter.is_empty() {
+        return Err(YgwError::DecodeError(
+            "no meter in the configuration file".to_owned(),
+        ));
+    }
+    let mut names = HashSet::new();
+    let mut metrics = HashSet::new();
+    // the state and capture files, which cannot be written by two meters (or be the same file for one meter)
+    let mut files = HashSet::new();
+    for meter in &config.meter {
+        if meter.name.is_empty() || !names.insert(meter.name.as_str()) {
+            return Err(YgwError::DecodeError(format!(
+                "invalid or duplicate meter name '{}' in the configuration file",
+                meter.name
+            )));
+        }
+        if let Some(addr) = meter.metrics.filter(|addr| !metrics.insert(*addr)) {
+            return Err(YgwError::DecodeError(format!(
+                "the metrics address {addr} of the meter '{}' is used by another meter",
+                meter.name
+            )));
+        }
+        for path in [&meter.state_file, &meter.capture].into_iter().flatten() {
+            if !files.insert(path.as_path()) {
+                return Err(YgwError::DecodeError(format!(
+                    "the file {} of the meter '{}' is already used",
+                    path.display(),
+                    meter.name
+                )));
+            }
+        }
+    }
+    Ok(config.meter)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse() {
+        let meters = parse(
+            r#"
+            [[meter]]
+            name = "house"
+            serial_device = "/dev/ttyUSB0"
+
+            [[meter]]
+            name = "workshop"
+            description = "Meter of the workshop"
+            serial_device = "/dev/ttyUSB1"
+            parameter_group = "workshop"
+            codes = "workshop.csv"
+            metrics = "127.0.0.1:9101"
+            "#,
+        )
+        .unwrap();
+        assert_eq!(meters.len(), 2);
+        assert_eq!(meters[0].parameter_group(), "p1mon");
+        assert_eq!(meters[0].codes, None);
+        assert_eq!(meters[0].description, None);
+        assert_eq!(meters[1].parameter_group(), "workshop");
+        assert_eq!(
+            meters[1].description.as_deref(),
+            Some("Meter of the workshop")
+        );
+        assert_eq!(meters[1].codes, Some(PathBuf::from("workshop.csv")));
+        assert_eq!(meters[0].metrics, None);
+        assert_eq!(meters[1].metrics, Some("127.0.0.1:9101".parse().unwrap()));
+
+        let duplicate = r#"
+            [[meter]]
+            name = "house"
+            serial_device = "/dev/ttyUSB0"
+            [[meter]]
+            name = "house"
+            serial_device = "/dev/ttyUSB1"
+            "#;
+        assert!(parse(duplicate).is_err());
+        let shared_metrics = r#"
+            [[meter]]
+            name = "house"
+            serial_device = "/dev/ttyUSB0"
+            metrics = "127.0.0.1:9100"
+            [[meter]]
+            name = "workshop"
+            serial_device = "/dev/ttyUSB1"
+            metrics = "127.0.0.1:9100"
+            "#;
+        assert!(parse(shared_metrics).is_err());
+        let shared_state_file = r#"
+            [[meter]]
+            name = "house"
+            serial_device = "/dev/ttyUSB0"
+            state_file = "p1mon.state"
+            [[meter]]
+            name = "workshop"
+            serial_device = "/dev/ttyUSB1"
+            state_file = "p1mon.state"
+            "#;
+        assert!(parse(shared_state_file).is_err());
+        let shared_capture = r#"
+            [[meter]]
+            name = "house"
+            serial_device = "/dev/ttyUSB0"
+            capture = "p1mon.capture"
+            [[meter]]
+            name = "workshop"
+            serial_device = "/dev/ttyUSB1"
+            capture = "p1mon.capture"
+            "#;
+        assert!(parse(shared_capture).is_err());
+        let distinct_files = r#"
+            [[meter]]
+            name = "house"
+            serial_device = "/dev/ttyUSB0"
+            state_file = "house.state"
+            capture = "house.capture"
+            [[meter]]
+            name = "workshop"
+            serial_device = "/dev/ttyUSB1"
+            state_file = "workshop.state"
+            capture = "workshop.capture"
+            "#;
+        assert_eq!(parse(distinct_files).unwrap().len(), 2);
+        assert!(parse("meter = []").is_err());
+        assert!(parse("[[meter]]\nname = \"house\"\n").is_err());
+    }
+}
@@ -0,0 +1,281 @@
+//! Parameters derived from the values of other parameters.
+//!
+//! A derived parameter is defined like the other parameters but instead of an OBIS code, its code is an expression
+//! starting with `=`:
+//! - `=avg(1-0:1.7.0;15min)` aggregates the values of `1-0:1.7.0` over windows of 15 minutes aligned
+//!   to the wall-clock; the functions are min, max, avg and last.
+//! - `=1-0:1.7.0 - 1-0:2.7.0` is the difference of the values of two parameters received in the same telegram;
+//!   the spaces around the `-` are required since the codes themselves contain `-`.
+//! - `=1-0:1.8.1 + 1-0:1.8.2` is the sum of the values of two or more parameters received in the same telegram.
+//! - `=max(1-0:31.7.0;1-0:51.7.0;1-0:71.7.0)` is the maximum (or with min, the minimum) of the values of two or more
+//!   parameters received in the same telegram.
+//! - `=headroom(0-0:17.0.0;1-0:1.7.0)` is the power which can still be drawn before the limiter threshold
+//!   (the first code) trips the breaker, given the live power (the second code) received in the same telegram;
+//!   there is no value when the meter reports that no limit is set.
+
+use std::time::Duration;
+
+/// the threshold reported by the eMUCS meters whose limiter is not active
+const NO_LIMIT: f64 = 999.9;
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Function {
+    Min,
+    Max,
+    Avg,
+    Last,
+}
+
+#[derive(Debug, Clone)]
+pub enum Derivation {
+    Aggregate { source: String, window: Window },
+    Difference(String, String),
+    Sum(Vec<String>),
+    Min(Vec<String>),
+    Max(Vec<String>),
+    Headroom(String, String),
+}
+
+impl Derivation {
+    /// returns the codes of the parameters the derived value is computed from
+    pub fn sources(&self) -> Vec<&str> {
+        match self {
+            Derivation::Aggregate { source, .. } => vec![source],
+            Derivation::Difference(a, b) | Derivation::Headroom(a, b) => vec![a, b],
+            Derivation::Sum(sources) | Derivation::Min(sources) | Derivation::Max(sources) => {
+                sources.iter().map(String::as_str).collect()
+            }
+        }
+    }
+
+    /// computes the derived value from the values x of the sources (in the order of sources()) received at the time t
+    /// returns the time of the derived value and the value or None if there is no value to publish,
+    /// e.g. because a source is missing
+    pub fn compute(&mut self, t: i64, x: &[Option<f64>]) -> Option<(i64, f64)> {
+        match self {
+            Derivation::Aggregate { window, .. } => window.add(t, x[0]?),
+            Derivation::Difference(..) => Some((t, x[0]? - x[1]?)),
+            Derivation::Sum(_) => Some((t, x.iter().copied().sum::<Option<f64>>()?)),
+            Derivation::Min(_) => Some((t, all(x)?.into_iter().fold(f64::INFINITY, f64::min))),
+            Derivation::Max(_) => Some((t, all(x)?.into_iter().fold(f64::NEG_INFINITY, f64::max))),
+            Derivation::Headroom(..) => {
+                let limit = x[0].filter(|&limit| limit < NO_LIMIT)?;
+                Some((t, limit - x[1]?))
+            }
+        }
+    }
+}
+
+/// returns the values if none is missing
+fn all(x: &[Option<f64>]) -> Option<Vec<f64>> {
+    x.iter().copied().collect()
+}
+
+/// the values received during a time window, the window starts at a multiple of its length
+#[derive(Debug, Clone)]
+pub struct Window {
+    function: Function,
+    length_millis: i64,
+    // the start of the current window and the number, sum, min, max and last of its values
+    start: Option<i64>,
+    count: u32,
+    sum: f64,
+    min: f64,
+    max: f64,
+    last: f64,
+}
+
+impl Window {
+    pub fn new(function: Function, length: Duration) -> Self {
+        Self {
+            function,
+            length_millis: length.as_millis() as i64,
+            start: None,
+            count: 0,
+            sum: 0.0,
+            min: f64::INFINITY,
+            max: f64::NEG_INFINITY,
+            last: 0.0,
+        }
+    }
+
+    /// adds the value x received at the time t (in milliseconds since the UNIX epoch)
+    /// if t is after the end of the current window, returns the end time of the window and its aggregated value,
+    /// the value x being then the first of the next window
+    pub fn add(&mut self, t: i64, x: f64) -> Option<(i64, f64)> {
+        let start = t - t.rem_euclid(self.length_millis);
+        let mut result = None;
+        match self.start {
+            Some(s) if s == start => {}
+            Some(s) if s > start => {
+                log::warn!(
+                    "Value received at {t} is before the current aggregation window, ignored"
+                );
+                return None;
+            }
+            Some(s) => {
+                result = Some((s + self.length_millis, self.value()));
+                self.reset(start);
+            }
+            None => self.reset(start),
+        }
+        self.count += 1;
+        self.sum += x;
+        self.min = self.min.min(x);
+        self.max = self.max.max(x);
+        self.last = x;
+
+        result
+    }
+
+    fn reset(&mut self, start: i64) {
+        self.start = Some(start);
+        self.count = 0;
+        self.sum = 0.0;
+        self.min = f64::INFINITY;
+        self.max = f64::NEG_INFINITY;
+    }
+
+    fn value(&self) -> f64 {
+        match self.function {
+            Function::Min => self.min,
+            Function::Max => self.max,
+            Function::Avg => self.sum / self.count as f64,
+            Function::Last => self.last,
+        }
+    }
+}
+
+/// parses the expression following the = of a derived parameter code
+pub fn parse(expr: &str) -> Result<Derivation, String> {
+    let expr = expr.trim();
+    if let Some((a, b)) = expr.split_once(" - ") {
+        let [a, b] = [a.trim(), b.trim()];
+        if a.is_empty() || b.is_empty() || b.contains(" - ") || expr.contains(" + ") {
+            return Err(format!("expected two codes in '{expr}'"));
+        }
+        return Ok(Derivation::Difference(a.to_owned(), b.to_owned()));
+    }
+    if expr.contains(" + ") {
+        let sources: Vec<String> = expr.split(" + ").map(|s| s.trim().to_owned()).collect();
+        if sources.iter().any(String::is_empty) {
+            return Err(format!("missing code in '{expr}'"));
+        }
+        return Ok(Derivation::Sum(sources));
+    }
+    let Some((fname, args)) = expr.strip_suffix(')').and_then(|e| e.split_once('(')) else {
+        return Err(format!("cannot parse the expression '{expr}'"));
+    };
+    if fname.trim() == "headroom" {
+        return match args.split(';').map(str::trim).collect::<Vec<_>>()[..] {
+            [limit, power] if !limit.is_empty() && !power.is_empty() => {
+                Ok(Derivation::Headroom(limit.to_owned(), power.to_owned()))
+            }
+            _ => Err(format!("expected a limit and a power code in '{expr}'")),
+        };
+    }
+    let function = match fname.trim() {
+        "min" => Function::Min,
+        "max" => Function::Max,
+        "avg" => Function::Avg,
+        "last" => Function::Last,
+        f => return Err(format!("unknown function '{f}'")),
+    };
+    let args: Vec<&str> = args.split(';').map(str::trim).collect();
+    if args.iter().any(|a| a.is_empty()) {
+        return Err(format!("missing argument in '{expr}'"));
+    }
+    // the function of several codes if the last argument is not a window length
+    if args.len() > 1 && crate::throttle::parse_interval(args[args.len() - 1]).is_none() {
+        let sources = args.iter().map(|a| a.to_string()).collect();
+        return match function {
+            Function::Min => Ok(Derivation::Min(sources)),
+            Function::Max => Ok(Derivation::Max(sources)),
+            _ => Err(format!("expected a code and a window length in '{expr}'")),
+        };
+    }
+    let [source, length] = args[..] else {
+        return Err(format!("expected a code and a window length in '{expr}'"));
+    };
+    let length = crate::throttle::parse_interval(length)
+        .filter(|l| l.as_millis() > 0)
+        .ok_or_else(|| format!("invalid window length '{length}'"))?;
+
+    Ok(Derivation::Aggregate {
+        source: source.to_owned(),
+        window: Window::new(function, length),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_window() {
+        let mut w = Window::new(Function::Avg, Duration::from_secs(60));
+        // 12:00:50, 12:00:55 and 12:01:05
+        let t0 = 1_700_000_000_000 - 1_700_000_000_000 % 60_000;
+        assert_eq!(w.add(t0 + 50_000, 1.0), None);
+        assert_eq!(w.add(t0 + 55_000, 2.0), None);
+        assert_eq!(w.add(t0 + 65_000, 10.0), Some((t0 + 60_000, 1.5)));
+        // a gap of several windows closes only the last window with values
+        assert_eq!(w.add(t0 + 300_000, 0.0), Some((t0 + 120_000, 10.0)));
+    }
+
+    #[test]
+    fn test_parse() {
+        let Ok(Derivation::Aggregate { source, window }) = parse("max(1-0:1.7.0;15min)") else {
+            panic!("cannot parse");
+        };
+        assert_eq!(source, "1-0:1.7.0");
+        assert_eq!(window.function, Function::Max);
+        assert_eq!(window.length_millis, 900_000);
+
+        assert!(parse("median(1-0:1.7.0;15min)").is_err());
+        assert!(parse("avg(1-0:1.7.0)").is_err());
+        assert!(parse("avg(1-0:1.7.0;0s)").is_err());
+
+        let Ok(Derivation::Difference(a, b)) = parse("1-0:1.7.0 - 1-0:2.7.0") else {
+            panic!("cannot parse");
+        };
+        assert_eq!((a.as_str(), b.as_str()), ("1-0:1.7.0", "1-0:2.7.0"));
+        assert!(parse("1-0:1.7.0 - ").is_err());
+        assert!(parse("1-0:1.7.0-1-0:2.7.0").is_err());
+
+        let Ok(Derivation::Sum(sources)) = parse("1-0:1.8.1 + 1-0:1.8.2 + 1-0:1.8.3") else {
+            panic!("cannot parse");
+        };
+        assert_eq!(sources, vec!["1-0:1.8.1", "1-0:1.8.2", "1-0:1.8.3"]);
+        assert!(parse("1-0:1.8.1 + ").is_err());
+        assert!(parse("1-0:1.8.1 + 1-0:1.8.2 - 1-0:2.8.1").is_err());
+    }
+
+    #[test]
+    fn test_compute() {
+        let mut sum = parse("1-0:1.8.1 + 1-0:1.8.2").unwrap();
+        assert_eq!(sum.compute(10, &[Some(1.5), Some(2.0)]), Some((10, 3.5)));
+        assert_eq!(sum.compute(10, &[Some(1.5), None]), None);
+        let mut diff = parse("1-0:1.7.0 - 1-0:2.7.0").unwrap();
+        assert_eq!(diff.compute(10, &[Some(0.0), Some(0.5)]), Some((10, -0.5)));
+        assert_eq!(diff.compute(10, &[None, Some(0.5)]), None);
+        let mut max = parse("max(1-0:31.7.0;1-0:51.7.0;1-0:71.7.0)").unwrap();
+        assert_eq!(
+            max.compute(10, &[Some(1.0), Some(3.0), Some(2.0)]),
+            Some((10, 3.0))
+        );
+        // a single-phase meter does not produce a value
+        assert_eq!(max.compute(10, &[Some(1.0), None, None]), None);
+        assert!(parse("avg(1-0:31.7.0;1-0:51.7.0)").is_err());
+
+        let mut headroom = parse("headroom(0-0:17.0.0;1-0:1.7.0)").unwrap();
+        assert_eq!(
+            headroom.compute(10, &[Some(16.5), Some(2.5)]),
+            Some((10, 14.0))
+        );
+        assert_eq!(headroom.compute(10, &[Some(999.9), Some(2.5)]), None);
+        assert_eq!(headroom.compute(10, &[None, Some(2.5)]), None);
+        assert!(parse("headroom(0-0:17.0.0)").is_err());
+        assert!(parse("headroom(0-0:17.0.0;1-0:1.7.0;1-0:2.7.0)").is_err());
+    }
+}
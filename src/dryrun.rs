@@ -0,0 +1,64 @@
+//! Output of the dry run, where the telegrams are read from the standard input instead of the serial port
+//! and the messages which would be sent to Yamcs are printed instead.
+//!
+//! Each value is printed on one line as `name<TAB>type<TAB>value[ unit]`, the events as `event type: message`.
+//! The definitions are not printed, only used to name the values.
+
+use std::collections::HashMap;
+use std::io::{self, Write};
+
+use ygw::msg::YgwMessage;
+use ygw::protobuf::ygw::{value::V, ParameterDefinition, Value};
+
+#[derive(Default)]
+pub struct DryRunPrinter {
+    // the definitions received so far, by parameter id
+    definitions: HashMap<u32, ParameterDefinition>,
+}
+
+impl DryRunPrinter {
+    /// prints the values and the events of the message, records the definitions
+    pub fn print(&mut self, msg: &YgwMessage, out: &mut impl Write) -> io::Result<()> {
+        match msg {
+            YgwMessage::ParameterDefinitions(_, pdefs) => {
+                for pdef in &pdefs.definitions {
+                    self.definitions.insert(pdef.id, pdef.clone());
+                }
+            }
+            YgwMessage::ParameterData(_, pdata) => {
+                for pv in &pdata.parameters {
+                    let value = pv.eng_value.as_ref().map_or_else(String::new, format_value);
+                    match self.definitions.get(&pv.id) {
+                        Some(pdef) => {
+                            write!(out, "{}\t{}\t{value}", pdef.relative_name, pdef.ptype)?;
+                            if let Some(unit) = &pdef.unit {
+                                write!(out, " {unit}")?;
+                            }
+                            writeln!(out)?;
+                        }
+                        None => writeln!(out, "#{}\t?\t{value}", pv.id)?,
+                    }
+                }
+            }
+            YgwMessage::Event(_, event) => writeln!(
+                out,
+                "event {}: {}",
+                event.r#type.as_deref().unwrap_or_default(),
+                event.message
+            )?,
+            _ => {}
+        }
+        Ok(())
+    }
+}
+
+fn format_value(value: &Value) -> String {
+    match &value.v {
+        Some(V::FloatValue(x)) => x.to_string(),
+        Some(V::DoubleValue(x)) => x.to_string(),
+        Some(V::Sint64Value(x)) => x.to_string(),
+        Some(V::BooleanValue(b)) => b.to_string(),
+        Some(V::StringValue(s)) => s.clone(),
+        v => format!("{v:?}"),
+    }
+}
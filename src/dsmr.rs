@@ -0,0 +1,96 @@
+//! Version of the DSMR specification followed by a meter, reported since DSMR 4 by the code `1-3:0.2.8`
+//! (e.g. `50` for 5.0, `42` for 4.2).
+//!
+//! The version tells how often the meter sends a telegram (every second since DSMR 5, every 10 seconds before)
+//! and whether the telegrams carry a CRC (since DSMR 4). It is detected from the first valid telegram
+//! and can be given explicitly for the meters which report a wrong version or none at all.
+
+use std::fmt;
+use std::time::Duration;
+
+use ygw::{Result, YgwError};
+
+/// the code of the DSMR version
+pub const VERSION_CODE: &str = "1-3:0.2.8";
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct DsmrVersion {
+    major: u8,
+    minor: u8,
+}
+
+impl DsmrVersion {
+    /// parses the version as reported by the meter (50, 42) or as written by humans (5.0, 4.2, 5)
+    pub fn from_str(s: &str) -> Result<DsmrVersion> {
+        let s = s.trim();
+        let digits = |s: &str| s.parse::<u8>().ok().filter(|_| s.len() == 1);
+        let version = match s.split_once('.') {
+            Some((major, minor)) => digits(major).zip(digits(minor)),
+            None if s.len() == 2 => digits(&s[..1]).zip(digits(&s[1..])),
+            None => digits(s).map(|major| (major, 0)),
+        };
+        match version {
+            Some((major @ 2.., minor)) => Ok(DsmrVersion { major, minor }),
+            _ => Err(YgwError::ParseError(format!("invalid DSMR version '{s}'"))),
+        }
+    }
+
+    /// returns the version reported by the telegram, if it contains the version code
+    pub fn from_telegram(p1t: &[u8]) -> Option<DsmrVersion> {
+        p1t.split(|&b| b == b'\n')
+            .filter_map(|line| std::str::from_utf8(line).ok())
+            .find_map(|line| {
+                let value = line.trim().strip_prefix(VERSION_CODE)?.strip_prefix('(')?;
+                Self::from_str(value.strip_suffix(')')?).ok()
+            })
+    }
+
+    /// returns the time between two telegrams
+    pub fn telegram_interval(&self) -> Duration {
+        if self.major >= 5 {
+            Duration::from_secs(1)
+        } else {
+            Duration::from_secs(10)
+        }
+    }
+
+    /// returns true if the telegrams end with a CRC, false if they end with the ! alone
+    pub fn crc_required(&self) -> bool {
+        self.major >= 4
+    }
+}
+
+impl fmt::Display for DsmrVersion {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}.{}", self.major, self.minor)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_version() {
+        let v = |s| DsmrVersion::from_str(s).unwrap();
+        assert_eq!(v("50"), v("5.0"));
+        assert_eq!(v("5"), v("5.0"));
+        assert_eq!(v("42").to_string(), "4.2");
+        assert!(DsmrVersion::from_str("").is_err());
+        assert!(DsmrVersion::from_str("502").is_err());
+        assert!(DsmrVersion::from_str("1.0").is_err());
+
+        assert_eq!(v("50").telegram_interval(), Duration::from_secs(1));
+        assert_eq!(v("42").telegram_interval(), Duration::from_secs(10));
+        assert!(v("40").crc_required());
+        assert!(!v("3").crc_required());
+
+        let telegram = b"/ISK5\\2M550T-1012\r\n\r\n1-3:0.2.8(42)\r\n0-0:1.0.0(101209113020W)\r\n";
+        assert_eq!(DsmrVersion::from_telegram(telegram), Some(v("4.2")));
+        assert_eq!(DsmrVersion::from_telegram(b"1-3:0.2.8(x)\r\n"), None);
+        assert_eq!(
+            DsmrVersion::from_telegram(b"0-0:1.0.0(101209113020W)\r\n"),
+            None
+        );
+    }
+}
@@ -0,0 +1,194 @@
+//! Parsing of the DSMR event logs and of the eMUCS list of monthly peaks.
+//!
+//! An event log line looks like
+//! `0-0:99.97.0(2)(0-0:96.7.19)(230101120000W)(0000000240*s)(230608091501S)(0000000351*s)`:
+//! the number of entries, the code of the logged event and then one (timestamp, value) pair per entry.
+//!
+//! A list of peaks looks like
+//! `0-0:98.1.0(2)(1-0:1.6.0)(1-0:1.6.0)(200501000000S)(200423192538S)(03.695*kW)(200601000000S)(200531184500S)(03.020*kW)`:
+//! the number of peaks, the codes of the peak values and then one (end of the period, time of the peak, peak)
+//! triplet per peak.
+
+#[derive(Debug, PartialEq)]
+pub struct LogEntry<'a> {
+    pub timestamp: &'a str,
+    pub value: &'a str,
+    pub unit: Option<&'a str>,
+}
+
+#[derive(Debug, PartialEq)]
+pub struct EventLog<'a> {
+    pub count: u32,
+    pub code: &'a str,
+    pub entries: Vec<LogEntry<'a>>,
+}
+
+impl<'a> EventLog<'a> {
+    /// returns the most recent entry, timestamps in the DSMR format sort chronologically
+    pub fn last(&self) -> Option<&LogEntry<'a>> {
+        self.entries
+            .iter()
+            .max_by_key(|e| e.timestamp.trim_end_matches(['S', 'W']))
+    }
+}
+
+/// parses the groups of an event log line (without the OBIS code of the line itself)
+/// returns None if the count cannot be parsed
+pub fn parse_event_log<'a>(groups: &[&'a str]) -> Option<EventLog<'a>> {
+    let count = groups.first()?.parse().ok()?;
+    let code = groups.get(1).copied().unwrap_or("");
+
+    let entries = groups
+        .get(2..)
+        .unwrap_or(&[])
+        .chunks_exact(2)
+        .map(|pair| {
+            let mut value = pair[1].split('*');
+            LogEntry {
+                timestamp: pair[0],
+                value: value.next().unwrap_or(""),
+                unit: value.next(),
+            }
+        })
+        .collect();
+
+    Some(EventLog {
+        count,
+        code,
+        entries,
+    })
+}
+
+#[derive(Debug, PartialEq)]
+pub struct Peak<'a> {
+    pub period: &'a str,
+    pub timestamp: &'a str,
+    pub value: &'a str,
+    pub unit: Option<&'a str>,
+}
+
+#[derive(Debug, PartialEq)]
+pub struct PeakList<'a> {
+    pub count: u32,
+    pub peaks: Vec<Peak<'a>>,
+}
+
+/// parses the groups of a list of peaks line (without the OBIS code of the line itself)
+/// the number of codes following the count and the number of peaks vary from one meter to the other:
+/// the codes are skipped and the complete triplets are returned, at most count of them
+/// returns None if the count cannot be parsed
+pub fn parse_peak_list<'a>(groups: &[&'a str]) -> Option<PeakList<'a>> {
+    let count: u32 = groups.first()?.parse().ok()?;
+    let first = groups[1..]
+        .iter()
+        .position(|g| !g.contains(':'))
+        .map_or(groups.len(), |i| i + 1);
+
+    let peaks = groups[first..]
+        .chunks_exact(3)
+        .take(count as usize)
+        .map(|triplet| {
+            let mut value = triplet[2].split('*');
+            Peak {
+                period: triplet[0],
+                timestamp: triplet[1],
+                value: value.next().unwrap_or(""),
+                unit: value.next(),
+            }
+        })
+        .collect();
+
+    Some(PeakList { count, peaks })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_event_log() {
+        let groups = [
+            "2",
+            "0-0:96.7.19",
+            "230608091501S",
+            "0000000351*s",
+            "230101120000W",
+            "0000000240*s",
+        ];
+        let log = parse_event_log(&groups).unwrap();
+        assert_eq!(log.count, 2);
+        assert_eq!(log.code, "0-0:96.7.19");
+        assert_eq!(log.entries.len(), 2);
+        assert_eq!(
+            log.last(),
+            Some(&LogEntry {
+                timestamp: "230608091501S",
+                value: "0000000351",
+                unit: Some("s")
+            })
+        );
+    }
+
+    #[test]
+    fn test_parse_empty_event_log() {
+        let log = parse_event_log(&["0", "0-0:96.7.19"]).unwrap();
+        assert_eq!(log.count, 0);
+        assert!(log.last().is_none());
+        assert!(parse_event_log(&[""]).is_none());
+    }
+
+    #[test]
+    fn test_parse_peak_list() {
+        let groups = [
+            "2",
+            "1-0:1.6.0",
+            "1-0:1.6.0",
+            "200501000000S",
+            "200423192538S",
+            "03.695*kW",
+            "200601000000S",
+            "200531184500S",
+            "03.020*kW",
+        ];
+        let list = parse_peak_list(&groups).unwrap();
+        assert_eq!(list.count, 2);
+        assert_eq!(
+            list.peaks[1],
+            Peak {
+                period: "200601000000S",
+                timestamp: "200531184500S",
+                value: "03.020",
+                unit: Some("kW")
+            }
+        );
+
+        // a single code, and fewer peaks than announced
+        let list = parse_peak_list(&[
+            "3",
+            "1-0:1.6.0",
+            "200501000000S",
+            "200423192538S",
+            "03.695*kW",
+        ])
+        .unwrap();
+        assert_eq!((list.count, list.peaks.len()), (3, 1));
+        // more peaks than announced
+        let list = parse_peak_list(&[
+            "1",
+            "1-0:1.6.0",
+            "1-0:1.6.0",
+            "200501000000S",
+            "200423192538S",
+            "03.695*kW",
+            "200601000000S",
+            "200531184500S",
+            "03.020*kW",
+        ])
+        .unwrap();
+        assert_eq!((list.count, list.peaks.len()), (1, 1));
+        // an empty list
+        let list = parse_peak_list(&["0", "1-0:1.6.0", "1-0:1.6.0"]).unwrap();
+        assert!(list.peaks.is_empty());
+        assert!(parse_peak_list(&["x"]).is_none());
+    }
+}
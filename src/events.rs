@@ -0,0 +1,161 @@
+//! Events sent to Yamcs for the telegrams rejected by the CRC check, the lines which cannot be parsed,
+//! the telegram timestamps too far from the host time, the voltage sags and swells counted by the meter
+//! and the rollovers of the cumulative registers, such that the operators see them without access to the log
+//! of the gateway.
+//!
+//! The events are rate-limited per category: at most one event is sent per interval, the next one
+//! reports how many were suppressed in the meantime.
+
+use std::collections::HashMap;
+use std::time::{Duration, Instant};
+
+use ygw::protobuf::ygw::{Event, EventSeverity, Timestamp};
+
+// the offending data included in the message is truncated to this number of characters
+const MAX_SNIPPET_LENGTH: usize = 80;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Category {
+    CrcFailure,
+    ParseError,
+    TimestampRejected,
+    PowerQuality,
+    Rollover,
+}
+
+impl Category {
+    fn event_type(&self) -> &'static str {
+        match self {
+            Category::CrcFailure => "CRC_FAILURE",
+            Category::ParseError => "PARSE_ERROR",
+            Category::TimestampRejected => "TIMESTAMP_REJECTED",
+            Category::PowerQuality => "POWER_QUALITY",
+            Category::Rollover => "ROLLOVER",
+        }
+    }
+}
+
+pub struct EventLimiter {
+    interval: Duration,
+    // the time of the last event sent and the number of events suppressed since, per category
+    last: HashMap<Category, (Instant, u32)>,
+}
+
+impl EventLimiter {
+    pub fn new(interval: Duration) -> Self {
+        Self {
+            interval,
+            last: HashMap::new(),
+        }
+    }
+
+    /// returns the number of events suppressed since the last one if an event of the category can be sent
+    /// at the time now, None if it has to be suppressed
+    pub fn check(&mut self, category: Category, now: Instant) -> Option<u32> {
+        match self.last.get_mut(&category) {
+            Some((t, suppressed)) if now.duration_since(*t) < self.interval => {
+                *suppressed += 1;
+                None
+            }
+            Some((t, suppressed)) => {
+                *t = now;
+                Some(std::mem::take(suppressed))
+            }
+            None => {
+                self.last.insert(category, (now, 0));
+                Some(0)
+            }
+        }
+    }
+}
+
+/// creates the warning event about the data (telegram or line) rejected for the reason given in message
+pub fn event(
+    source: &str,
+    category: Category,
+    message: &str,
+    data: &str,
+    suppressed: u32,
+    generation_time: Timestamp,
+) -> Event {
+    let mut message = format!("{source}: {message}: '{}'", snippet(data));
+    if suppressed > 0 {
+        message.push_str(&format!(" ({suppressed} similar events suppressed)"));
+    }
+    Event {
+        source: Some("p1mon".to_owned()),
+        generation_time: Some(generation_time),
+        r#type: Some(category.event_type().to_owned()),
+        message,
+        severity: Some(EventSeverity::Warning as i32),
+        extra: HashMap::from([("suppressed".to_owned(), suppressed.to_string())]),
+        ..Default::default()
+    }
+}
+
+/// returns the data truncated to a safe length, with the line endings escaped
+fn snippet(data: &str) -> String {
+    let data = data.trim_end();
+    let mut s: String = data
+        .chars()
+        .take(MAX_SNIPPET_LENGTH)
+        .collect::<String>()
+        .escape_debug()
+        .to_string();
+    if data.chars().count() > MAX_SNIPPET_LENGTH {
+        s.push_str("...");
+    }
+    s
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_limiter() {
+        let mut limiter = EventLimiter::new(Duration::from_secs(60));
+        let t0 = Instant::now();
+        assert_eq!(limiter.check(Category::CrcFailure, t0), Some(0));
+        for i in 1..=5 {
+            assert_eq!(
+                limiter.check(Category::CrcFailure, t0 + Duration::from_secs(i)),
+                None
+            );
+        }
+        // the categories are limited independently
+        assert_eq!(
+            limiter.check(Category::ParseError, t0 + Duration::from_secs(1)),
+            Some(0)
+        );
+        assert_eq!(
+            limiter.check(Category::CrcFailure, t0 + Duration::from_secs(60)),
+            Some(5)
+        );
+        assert_eq!(
+            limiter.check(Category::CrcFailure, t0 + Duration::from_secs(61)),
+            None
+        );
+    }
+
+    #[test]
+    fn test_event() {
+        let line = format!("1-0:1.7.0(00.316*kW{}\r\n", "0".repeat(100));
+        let ev = event(
+            "main",
+            Category::ParseError,
+            "Cannot parse the line",
+            &line,
+            3,
+            Timestamp::default(),
+        );
+        assert_eq!(ev.r#type.as_deref(), Some("PARSE_ERROR"));
+        assert_eq!(ev.severity, Some(EventSeverity::Warning as i32));
+        assert_eq!(ev.extra["suppressed"], "3");
+        assert!(ev
+            .message
+            .starts_with("main: Cannot parse the line: '1-0:1.7.0(00.316*kW000"));
+        assert!(ev.message.ends_with("...' (3 similar events suppressed)"));
+        assert!(ev.message.len() < 160);
+    }
+}
@@ -0,0 +1,94 @@
+//! Telegrams written by the p1mon-feed binary in place of a meter, also used by the tests of the node.
+//!
+//! The telegrams are either taken from a recording (like test-data.txt) or generated with the current time,
+//! the CRC being computed when writing them such that a recording edited by hand stays valid.
+
+use chrono::DateTime;
+use chrono_tz::{OffsetComponents, Tz};
+
+/// returns the telegrams of the recording from the / to the ! included, without their CRC
+pub fn recorded_telegrams(data: &[u8]) -> Vec<Vec<u8>> {
+    let mut telegrams = Vec::new();
+    let mut rest = data;
+    while let Some(start) = rest.iter().position(|&b| b == b'/') {
+        let Some(end) = rest[start..].iter().position(|&b| b == b'!') else {
+            break;
+        };
+        telegrams.push(rest[start..=start + end].to_vec());
+        rest = &rest[start + end + 1..];
+    }
+    telegrams
+}
+
+/// appends the CRC and the final CRLF to a telegram ending with !
+pub fn with_crc(telegram: &[u8]) -> Vec<u8> {
+    let crc = crc16::State::<crc16::ARC>::calculate(telegram);
+    let mut telegram = telegram.to_vec();
+    telegram.extend_from_slice(format!("{crc:04X}\r\n").as_bytes());
+    telegram
+}
+
+/// returns the time as written by the meters, e.g. 240506201008S (S in summer time, W in winter time)
+pub fn meter_time(time: &DateTime<Tz>) -> String {
+    let season = if time.offset().dst_offset().is_zero() {
+        'W'
+    } else {
+        'S'
+    };
+    format!("{}{season}", time.format("%y%m%d%H%M%S"))
+}
+
+/// generates the n-th telegram of a DSMR 5 meter at the time, without its CRC
+/// the consumption varies from one telegram to the next and the total consumption increases accordingly
+pub fn generated_telegram(n: u64, time: &DateTime<Tz>) -> Vec<u8> {
+    let power = 0.2 + (n % 10) as f64 * 0.1;
+    let energy = 1000.0 + n as f64 * 0.001;
+    let lines = [
+        "/FEED5\\2P1MON-FEED".to_owned(),
+        String::new(),
+        "1-3:0.2.8(50)".to_owned(),
+        format!("0-0:1.0.0({})", meter_time(time)),
+        "0-0:96.1.1(4530303034303031353934373534343134)".to_owned(),
+        format!("1-0:1.8.1({energy:010.3}*kWh)"),
+        "1-0:1.8.2(000000.000*kWh)".to_owned(),
+        "1-0:2.8.1(000000.000*kWh)".to_owned(),
+        "1-0:2.8.2(000000.000*kWh)".to_owned(),
+        "0-0:96.14.0(0001)".to_owned(),
+        format!("1-0:1.7.0({power:06.3}*kW)"),
+        "1-0:2.7.0(00.000*kW)".to_owned(),
+        "1-0:32.7.0(230.0*V)".to_owned(),
+        format!("1-0:31.7.0({:03}*A)", (power * 1000.0 / 230.0) as u32),
+    ];
+    let mut telegram = lines.join("\r\n").into_bytes();
+    telegram.extend_from_slice(b"\r\n!");
+    telegram
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::TimeZone;
+    use chrono_tz::Europe::Amsterdam;
+
+    #[test]
+    fn test_feed() {
+        let data = include_bytes!("../test-data.txt");
+        let telegrams = recorded_telegrams(data);
+        // the last telegram of the recording is incomplete
+        assert_eq!(telegrams.len(), 4);
+        // the CRC computed is the one of the recording
+        assert!(data.starts_with(b"\r\n/FLU5"));
+        let first = with_crc(&telegrams[0]);
+        assert_eq!(&data[2..first.len() + 2], &first[..]);
+
+        let summer = Amsterdam.with_ymd_and_hms(2024, 5, 6, 20, 10, 8).unwrap();
+        assert_eq!(meter_time(&summer), "240506201008S");
+        let winter = Amsterdam.with_ymd_and_hms(2024, 1, 6, 20, 10, 8).unwrap();
+        assert_eq!(meter_time(&winter), "240106201008W");
+
+        let telegram = String::from_utf8(generated_telegram(3, &summer)).unwrap();
+        assert!(telegram.contains("0-0:1.0.0(240506201008S)\r\n"));
+        assert!(telegram.contains("1-0:1.8.1(001000.003*kWh)\r\n"));
+        assert!(telegram.contains("1-0:1.7.0(00.500*kW)\r\n"));
+    }
+}
@@ -1,25 +1,458 @@
-use p1mon::P1Mon;
-use ygw::{ygw_server::ServerBuilder, Result};
+use std::path::Path;
 
+use p1mon::P1MonBuilder;
+use ygw::{ygw_server::ServerBuilder, Result, YgwError, YgwNode};
+
+mod capture;
+mod commands;
+mod config;
+mod derived;
+mod dryrun;
+mod dsmr;
+mod eventlog;
+mod events;
+#[cfg(test)]
+mod feed;
+mod mbus;
+#[cfg(feature = "metrics")]
+mod metrics;
+mod mqtt;
 mod p1mon;
+mod parse_error;
+mod profile;
+mod rollover;
+mod seqstore;
+mod serial;
+mod stats;
+mod tcp;
+mod throttle;
+mod units;
+mod wildcard;
+
+// the capture file is rotated when it would exceed this size
+const CAPTURE_MAX_SIZE: u64 = 10_000_000;
 
+// the serial port of the meter when --serial-device is not given
+const DEFAULT_SERIAL_DEVICE: &str = "/dev/pts/7";
 
 #[tokio::main]
 async fn main() -> Result<()> {
     env_logger::init();
 
-    //let node1 = P1Mon::new("/dev/ttyUSB0")?;
-    let node1 = P1Mon::new("/dev/pts/7", "p1mon")?;
-        
-    let server = ServerBuilder::new()
-    .add_node(Box::new(node1))
-    .build();
+    let args: Vec<String> = std::env::args().collect();
+    //the OBIS codes file: --codes path, searched in the default locations if not given, - for the standard input
+    let codes_path = args
+        .windows(2)
+        .find(|w| w[0] == "--codes")
+        .map(|w| Path::new(&w[1]));
+
+    //print the values decoded from the telegrams piped into the standard input instead of connecting to Yamcs
+    let dry_run = args.iter().any(|a| a == "--dry-run");
+    //print the definitions of the OBIS codes and the problems found in them, then exit (with an error if any)
+    //without opening the serial port; --check-telegrams path also compares them with the recorded telegrams
+    let check = args.iter().any(|a| a == "--check");
+
+    //monitor each meter of the configuration file with its own node: --config path
+    let config_path = args
+        .windows(2)
+        .find(|w| w[0] == "--config")
+        .map(|w| Path::new(&w[1]));
+
+    //the prefix of the MQTT topics: --mqtt-prefix (default p1mon)
+    let mqtt_prefix = args
+        .windows(2)
+        .find(|w| w[0] == "--mqtt-prefix")
+        .map_or("p1mon", |w| w[1].as_str());
+
+    let mut nodes = Vec::new();
+    if let Some(config_path) = config_path {
+        for option in [
+            "--codes",
+            "--source",
+            "--serial-device",
+            "--state-file",
+            "--capture",
+            "--dry-run",
+            "--metrics",
+            "--name",
+            "--description",
+        ] {
+            if args.iter().any(|a| a == option) {
+                return Err(YgwError::ParseError(format!(
+                    "{option} cannot be used with --config, give it for each meter in the configuration file"
+                )));
+            }
+        }
+        let meters = config::load(config_path)?;
+        let several = meters.len() > 1;
+        for meter in meters {
+            let mut builder = P1MonBuilder::new(meter.parameter_group()).name(&meter.name);
+            if !check {
+                builder = builder.serial_device(&meter.serial_device);
+            }
+            if let Some(path) = &meter.codes {
+                builder = builder.codes_path(path);
+            }
+            if let Some(description) = &meter.description {
+                builder = builder.description(description);
+            }
+            if let Some(path) = &meter.state_file {
+                builder = builder.state_file(path);
+            }
+            if let Some(path) = &meter.capture {
+                builder = builder.capture_file(path, CAPTURE_MAX_SIZE);
+            }
+            if let Some(addr) = meter.metrics {
+                #[cfg(feature = "metrics")]
+                {
+                    builder = builder.metrics(addr, metrics_params(&args));
+                }
+                #[cfg(not(feature = "metrics"))]
+                return Err(YgwError::ParseError(format!(
+                    "the metrics address {addr} of the meter '{}' requires the metrics feature",
+                    meter.name
+                )));
+            }
+            //the MQTT topics of each meter are prefixed with its name when there are several
+            let prefix = if several {
+                format!("{mqtt_prefix}/{}", meter.name)
+            } else {
+                mqtt_prefix.to_owned()
+            };
+            nodes.push(configure_builder(builder, &args, &prefix)?.build()?);
+        }
+    } else {
+        //the serial port of the meter: --serial-device path, e.g. /dev/ttyUSB0, or the address of a network bridge
+        //tcp://host:port (tls://host:port over TLS with the tls feature)
+        let serial_device = args
+            .windows(2)
+            .find(|w| w[0] == "--serial-device")
+            .map_or(DEFAULT_SERIAL_DEVICE, |w| w[1].as_str());
+        if dry_run && codes_path == Some(Path::new("-")) {
+            return Err(YgwError::ParseError(
+                "--dry-run reads the telegrams from the standard input, --codes cannot be -"
+                    .to_owned(),
+            ));
+        }
+        let mut builder = P1MonBuilder::new("p1mon");
+        if !dry_run && !check {
+            builder = builder.serial_device(serial_device);
+        }
+        if codes_path == Some(Path::new("-")) {
+            builder = builder.codes(&std::io::read_to_string(std::io::stdin())?);
+        } else if let Some(path) = codes_path {
+            builder = builder.codes_path(path);
+        }
+        //the name and description of the node in Yamcs: --name (default P1MON) and --description,
+        //e.g. to tell apart two instances monitoring different meters
+        if let Some(w) = args.windows(2).find(|w| w[0] == "--name") {
+            builder = builder.name(&w[1]);
+        }
+        if let Some(w) = args.windows(2).find(|w| w[0] == "--description") {
+            builder = builder.description(&w[1]);
+        }
+
+        //persist the sequence counts: --state-file path, saved every minute and when stopping
+        if let Some(w) = args.windows(2).find(|w| w[0] == "--state-file") {
+            builder = builder.state_file(Path::new(&w[1]));
+        }
+
+        //write the raw telegrams to a capture file rotated at 10 MB: --capture path
+        if let Some(w) = args.windows(2).find(|w| w[0] == "--capture") {
+            builder = builder.capture_file(Path::new(&w[1]), CAPTURE_MAX_SIZE);
+        }
+
+        //serve the statistics and the latest values of the --metrics-params (comma separated parameter names)
+        //in the Prometheus format on http://<--metrics address>/metrics, e.g. --metrics 127.0.0.1:9100
+        //with --config, the address is given for each meter in the configuration file instead
+        #[cfg(feature = "metrics")]
+        if let Some(w) = args.windows(2).find(|w| w[0] == "--metrics") {
+            let addr = w[1]
+                .parse()
+                .map_err(|_| YgwError::ParseError(format!("invalid metrics address '{}'", w[1])))?;
+            builder = builder.metrics(addr, metrics_params(&args));
+        }
+        let mut node1 = configure_builder(builder, &args, mqtt_prefix)?.build()?;
+
+        //additional meters monitored by the same node: --source name,serial_device,parameter_group
+        for w in args.windows(2).filter(|w| w[0] == "--source") {
+            let [name, serial_device, parameter_group] = w[1].split(',').collect::<Vec<_>>()[..]
+            else {
+                return Err(YgwError::ParseError(format!(
+                    "invalid source '{}', expected name,serial_device,parameter_group",
+                    w[1]
+                )));
+            };
+            node1.add_source(name, serial_device, parameter_group)?;
+        }
+
+        nodes.push(node1);
+    }
+
+    let several = nodes.len() > 1;
+
+    if check {
+        let recording = match args.windows(2).find(|w| w[0] == "--check-telegrams") {
+            Some(w) => Some(std::fs::read(&w[1]).map_err(|e| {
+                YgwError::IOError(format!("cannot read the telegrams {}", w[1]), e)
+            })?),
+            None => None,
+        };
+        let mut problems = 0;
+        for node in &nodes {
+            if several {
+                println!("# {}", node.properties().name);
+            }
+            problems += node.check(recording.as_deref(), &mut std::io::stdout())?;
+        }
+        if problems > 0 {
+            return Err(YgwError::DecodeError(format!(
+                "{problems} problems found in the OBIS codes"
+            )));
+        }
+        return Ok(());
+    }
+
+    //print the parameters which will be published and exit
+    if args.iter().any(|a| a == "--list-parameters") {
+        for node in &nodes {
+            if several {
+                println!("# {}", node.properties().name);
+            }
+            for pdef in node.parameter_definitions() {
+                println!(
+                    "{}\t{}\t{}\t{}\t{}",
+                    pdef.id,
+                    pdef.relative_name,
+                    pdef.ptype,
+                    pdef.unit.unwrap_or_default(),
+                    pdef.description.unwrap_or_default()
+                );
+            }
+        }
+        return Ok(());
+    }
+
+    if dry_run {
+        let node = nodes.pop().unwrap();
+        return node
+            .dry_run(std::io::stdin().lock(), &mut std::io::stdout())
+            .await;
+    }
+
+    // the nodes get consecutive ids in the order of the configuration file
+    let server = nodes
+        .into_iter()
+        .fold(ServerBuilder::new(), |builder, node| {
+            builder.add_node(Box::new(node))
+        })
+        .build();
 
     let handle = server.start().await?;
 
     if let Err(err) = handle.jh.await {
         println!("server terminated with error {:?}", err);
     }
-   Ok(())
+    Ok(())
+}
+
+/// applies the options given on the command line to the builder of a node
+/// mqtt_prefix is the prefix of the MQTT topics of the node
+fn configure_builder(
+    mut builder: P1MonBuilder,
+    args: &[String],
+    mqtt_prefix: &str,
+) -> Result<P1MonBuilder> {
+    //return from the reads of the serial port without data after --read-timeout (default 100ms)
+    if let Some(w) = args.windows(2).find(|w| w[0] == "--read-timeout") {
+        let timeout = throttle::parse_interval(&w[1])
+            .filter(|t| !t.is_zero())
+            .ok_or_else(|| YgwError::ParseError(format!("invalid read timeout '{}'", w[1])))?;
+        builder = builder.read_timeout(timeout);
+    }
+
+    //reopen the serial device after an error with a delay doubling up to --max-reconnect-delay (default 60s)
+    if let Some(w) = args.windows(2).find(|w| w[0] == "--max-reconnect-delay") {
+        let delay = throttle::parse_interval(&w[1]).ok_or_else(|| {
+            YgwError::ParseError(format!("invalid maximum reconnect delay '{}'", w[1]))
+        })?;
+        builder = builder.max_reconnect_delay(delay);
+    }
+
+    //publish the values at most every --min-interval (e.g. 10s, 0 to publish every telegram)
+    if let Some(w) = args.windows(2).find(|w| w[0] == "--min-interval") {
+        let interval = throttle::parse_interval(&w[1])
+            .ok_or_else(|| YgwError::ParseError(format!("invalid minimum interval '{}'", w[1])))?;
+        builder = builder.min_interval(interval);
+    }
+
+    //publish the statistics of the telegrams received every --status-interval (e.g. 60s)
+    if let Some(w) = args.windows(2).find(|w| w[0] == "--status-interval") {
+        let interval = throttle::parse_interval(&w[1])
+            .ok_or_else(|| YgwError::ParseError(format!("invalid status interval '{}'", w[1])))?;
+        builder = builder.status_interval(interval);
+    }
+    //report the M-Bus channels --mbus-links (e.g. 1,2) as sub-links, failed when their readings do not change
+    //for --mbus-timeout (default 2h)
+    if let Some(w) = args.windows(2).find(|w| w[0] == "--mbus-links") {
+        let timeout = match args.windows(2).find(|w| w[0] == "--mbus-timeout") {
+            Some(w) => throttle::parse_interval(&w[1])
+                .ok_or_else(|| YgwError::ParseError(format!("invalid M-Bus timeout '{}'", w[1])))?,
+            None => p1mon::DEFAULT_MBUS_TIMEOUT,
+        };
+        let channels: Vec<&str> = w[1].split(',').map(str::trim).collect();
+        if let Some(c) = channels
+            .iter()
+            .find(|c| !matches!(c.parse::<u32>(), Ok(1..)))
+        {
+            return Err(YgwError::ParseError(format!("invalid M-Bus channel '{c}'")));
+        }
+        builder = builder.mbus_links(&channels, timeout);
+    }
+    //add the codes of the national variant of DSMR followed by the meter: --profile belgium for eMUCS-P1
+    if let Some(w) = args.windows(2).find(|w| w[0] == "--profile") {
+        builder = builder.profile(profile::Profile::from_str(&w[1])?);
+    }
+    //publish also the codes not defined in obiscodes.csv
+    let discovery = args.iter().any(|a| a == "--discovery");
+    //or publish only them, without the meter identification from the header of the telegrams
+    let allowlist = args.iter().any(|a| a == "--allowlist");
+    if discovery && allowlist {
+        return Err(YgwError::ParseError(
+            "--discovery cannot be used with --allowlist".to_owned(),
+        ));
+    }
+    builder = builder.discovery(discovery);
+    builder = builder.allowlist(allowlist);
+    //send an event when the meter counts a voltage sag or swell
+    builder = builder.power_quality_events(args.iter().any(|a| a == "--power-quality-events"));
+    //the timezone of the meter clock: --timezone name (default Europe/Amsterdam)
+    if let Some(w) = args.windows(2).find(|w| w[0] == "--timezone") {
+        builder = builder.timezone(&w[1]);
+    }
+    //use the host time for the telegrams with a timestamp more than --max-time-ahead (e.g. 1h) in the future
+    //or --max-time-behind (e.g. 48h) in the past
+    if let Some(w) = args.windows(2).find(|w| w[0] == "--max-time-ahead") {
+        let max = throttle::parse_interval(&w[1]).ok_or_else(|| {
+            YgwError::ParseError(format!("invalid maximum time ahead '{}'", w[1]))
+        })?;
+        builder = builder.max_time_ahead(max);
+    }
+    if let Some(w) = args.windows(2).find(|w| w[0] == "--max-time-behind") {
+        let max = throttle::parse_interval(&w[1]).ok_or_else(|| {
+            YgwError::ParseError(format!("invalid maximum time behind '{}'", w[1]))
+        })?;
+        builder = builder.max_time_behind(max);
+    }
+    //send the definitions of all the parameters in the OBIS codes file at startup instead of when first received
+    builder = builder.define_upfront(args.iter().any(|a| a == "--define-upfront"));
+    //publish the values of the M-Bus channels (e.g. the gas) with the electricity, instead of in the group
+    //<parameter_group>_mbus with the time of their reading
+    builder = builder.mbus_group(!args.iter().any(|a| a == "--no-mbus-group"));
+    //report the link as connecting instead of ok until the first valid telegram is received
+    builder = builder.defer_link_up(args.iter().any(|a| a == "--defer-link-up"));
+    //save the state file also every --state-save-every messages published (e.g. 100)
+    if let Some(w) = args.windows(2).find(|w| w[0] == "--state-save-every") {
+        let n = w[1].parse().ok().filter(|&n| n > 0).ok_or_else(|| {
+            YgwError::ParseError(format!("invalid number of messages '{}'", w[1]))
+        })?;
+        builder = builder.state_save_every(n);
+    }
+    //send only the changed values, the unchanged ones at least every --max-silence (e.g. 60s)
+    if let Some(w) = args.windows(2).find(|w| w[0] == "--max-silence") {
+        let max_silence = throttle::parse_interval(&w[1])
+            .ok_or_else(|| YgwError::ParseError(format!("invalid maximum silence '{}'", w[1])))?;
+        builder = builder.max_silence(max_silence);
+    }
+
+    //the acquisition time of the values: --time-source host (default), meter for a host without trusted clock,
+    //or host-synced to discard the telegrams until the host clock is synchronized
+    if let Some(w) = args.windows(2).find(|w| w[0] == "--time-source") {
+        builder = builder.time_source(p1mon::TimeSource::from_str(&w[1])?);
+    }
+
+    //the values without an expiry in the table expire after --expiry 3x (default, three telegram intervals),
+    //a fixed time like 30s, or never with off
+    if let Some(w) = args.windows(2).find(|w| w[0] == "--expiry") {
+        builder = builder.expiry(p1mon::Expiry::from_str(&w[1])?);
+    }
+
+    //report the link as failed after --max-crc-failures consecutive telegrams with a wrong CRC
+    if let Some(w) = args.windows(2).find(|w| w[0] == "--max-crc-failures") {
+        let n = w[1].parse().map_err(|_| {
+            YgwError::ParseError(format!("invalid number of CRC failures '{}'", w[1]))
+        })?;
+        builder = builder.max_crc_failures(n);
+    }
+
+    //handling of the telegrams with a wrong CRC: --crc-policy strict (default), tolerant or threshold
+    if let Some(w) = args.windows(2).find(|w| w[0] == "--crc-policy") {
+        builder = builder.crc_policy(p1mon::CrcPolicy::from_str(&w[1])?);
+    }
+
+    //the CRC variant of the telegrams: --crc-algorithm arc (default, as specified by DSMR), kermit, modbus, x25,
+    //xmodem, ccitt-false or none for the telegrams not to be checked
+    if let Some(w) = args.windows(2).find(|w| w[0] == "--crc-algorithm") {
+        builder = builder.crc_algorithm(p1mon::CrcAlgorithm::from_str(&w[1])?);
+    }
+
+    //the DSMR version of the meter, e.g. --dsmr-version 3.0 for a meter sending its telegrams without CRC,
+    //instead of the one it reports which gives the expected telegram interval
+    if let Some(w) = args.windows(2).find(|w| w[0] == "--dsmr-version") {
+        builder = builder.dsmr_version(dsmr::DsmrVersion::from_str(&w[1])?);
+    }
+
+    //discard the telegrams longer than --max-telegram-size bytes (default 8192) or --max-telegram-lines (default 128)
+    if let Some(w) = args.windows(2).find(|w| w[0] == "--max-telegram-size") {
+        let n = w[1]
+            .parse()
+            .map_err(|_| YgwError::ParseError(format!("invalid telegram size '{}'", w[1])))?;
+        builder = builder.max_telegram_size(n);
+    }
+    if let Some(w) = args.windows(2).find(|w| w[0] == "--max-telegram-lines") {
+        let n = w[1].parse().map_err(|_| {
+            YgwError::ParseError(format!("invalid number of telegram lines '{}'", w[1]))
+        })?;
+        builder = builder.max_telegram_lines(n);
+    }
+
+    //discard the telegrams not completed within --telegram-timeout after their header (default 5s)
+    if let Some(w) = args.windows(2).find(|w| w[0] == "--telegram-timeout") {
+        let timeout = throttle::parse_interval(&w[1])
+            .ok_or_else(|| YgwError::ParseError(format!("invalid telegram timeout '{}'", w[1])))?;
+        builder = builder.telegram_timeout(timeout);
+    }
+
+    //publish the values also to an MQTT broker: --mqtt host[:port] with the topic prefix --mqtt-prefix (default p1mon)
+    //followed by the node name when several meters are configured
+    if let Some(w) = args.windows(2).find(|w| w[0] == "--mqtt") {
+        builder = builder.mqtt(&w[1], mqtt_prefix);
+    }
+    //over TLS, check the certificate of the bridge against the CA certificates of the PEM file --tls-ca
+    //(default the Mozilla root certificates) for the name --tls-server-name (default the host of the address)
+    #[cfg(feature = "tls")]
+    if let Some(w) = args.windows(2).find(|w| w[0] == "--tls-server-name") {
+        builder = builder.tls_server_name(&w[1]);
+    }
+    #[cfg(feature = "tls")]
+    if let Some(w) = args.windows(2).find(|w| w[0] == "--tls-ca") {
+        builder = builder.tls_ca_file(Path::new(&w[1]));
+    }
+    //publish a heartbeat with the time since the last telegram every --heartbeat-interval (e.g. 10s)
+    if let Some(w) = args.windows(2).find(|w| w[0] == "--heartbeat-interval") {
+        let interval = throttle::parse_interval(&w[1]).ok_or_else(|| {
+            YgwError::ParseError(format!("invalid heartbeat interval '{}'", w[1]))
+        })?;
+        builder = builder.heartbeat_interval(interval);
+    }
+    Ok(builder)
 }
 
+/// returns the parameter names given with --metrics-params, whose latest values are served with the statistics
+#[cfg(feature = "metrics")]
+fn metrics_params(args: &[String]) -> Vec<String> {
+    args.windows(2)
+        .find(|w| w[0] == "--metrics-params")
+        .map(|w| w[1].split(',').map(str::to_owned).collect())
+        .unwrap_or_default()
+}
@@ -0,0 +1,198 @@
+//! M-Bus devices connected to the meter.
+//!
+//! Up to four devices (gas, water, heat...) are read by the meter on the channels 1 to 4, their values having
+//! the channel as second field of the OBIS code (e.g. `0-2:24.2.1` for channel 2). The type of each device is
+//! given by `0-n:24.1.0` such that a wildcard definition can name the values after the device type
+//! with `{device}`, e.g. `0-*:24.2.1,{device}_{1}` gives `gas_2` for a gas meter on channel 2.
+//!
+//! The channels can also be reported as sub-links of the node, each with its own status: since the meter keeps
+//! sending the last reading of a device which stopped communicating (e.g. because of a dead battery), the link of
+//! a channel is failed when the time of its readings has not changed for a given time.
+
+use std::collections::HashMap;
+use std::time::{Duration, Instant};
+
+use ygw::msg::Addr;
+use ygw::protobuf::ygw::{self as protobuf, LinkState, Timestamp};
+
+// the placeholder replaced by the device type in the name and group of a wildcard definition
+pub const DEVICE_PLACEHOLDER: &str = "{device}";
+// the name used when the type of the device is not known
+const UNKNOWN_DEVICE: &str = "mbus";
+
+// the names of the device types defined by EN 13757-3
+const DEVICE_TYPES: &[(u32, &str)] = &[
+    (2, "electricity"),
+    (3, "gas"),
+    (4, "heat"),
+    (6, "warm_water"),
+    (7, "water"),
+    (10, "cooling"),
+    (11, "cooling"),
+    (12, "heat"),
+    (13, "heat"),
+    (21, "hot_water"),
+    (22, "cold_water"),
+];
+
+/// returns the M-Bus channel of the code, e.g. 2 for 0-2:24.2.1, or None if it is not the code of an M-Bus value
+pub fn channel(code: &str) -> Option<&str> {
+    let (a, rest) = code.split_once('-')?;
+    let (b, _) = rest.split_once(':')?;
+    (a == "0" && !b.is_empty() && b.bytes().all(|c| c.is_ascii_digit()) && b != "0").then_some(b)
+}
+
+/// the device types of the M-Bus channels, recorded from the device type codes of the telegrams
+#[derive(Debug, Default)]
+pub struct Devices {
+    types: HashMap<String, &'static str>,
+}
+
+impl Devices {
+    /// records the device type if the code is a device type code 0-n:24.1.0
+    /// returns true if it is such a code
+    pub fn record(&mut self, code: &str, value: &str) -> bool {
+        let Some(channel) = channel(code).filter(|_| code.ends_with(":24.1.0")) else {
+            return false;
+        };
+        let name = value
+            .trim()
+            .parse::<u32>()
+            .ok()
+            .and_then(|t| DEVICE_TYPES.iter().find(|(k, _)| *k == t))
+            .map(|(_, name)| *name);
+        match name {
+            Some(name) => {
+                self.types.insert(channel.to_owned(), name);
+            }
+            None => log::warn!("Unknown M-Bus device type '{value}' on channel {channel}"),
+        }
+        true
+    }
+
+    /// returns the name of the type of the device whose value has the code, e.g. gas for 0-2:24.2.1
+    /// if the device type of the channel is gas
+    pub fn device_name(&self, code: &str) -> &'static str {
+        channel(code)
+            .and_then(|c| self.types.get(c))
+            .copied()
+            .unwrap_or(UNKNOWN_DEVICE)
+    }
+}
+
+/// the sub-link of an M-Bus channel
+pub struct ChannelLink {
+    pub channel: String,
+    pub addr: Addr,
+    // the link is failed when the time of the readings has not changed for this time
+    timeout: Duration,
+    // the time of the last reading and when it was first seen
+    last_reading: Option<Timestamp>,
+    last_change: Instant,
+    // the number of different readings received
+    readings: u64,
+    failed: bool,
+}
+
+impl ChannelLink {
+    pub fn new(channel: &str, addr: Addr, timeout: Duration, now: Instant) -> Self {
+        Self {
+            channel: channel.to_owned(),
+            addr,
+            timeout,
+            last_reading: None,
+            last_change: now,
+            readings: 0,
+            failed: false,
+        }
+    }
+
+    /// records the time of the latest reading of the channel, None if the telegram did not contain any,
+    /// and updates the state of the link at the time now
+    /// returns true if the state of the link changed
+    pub fn update(&mut self, reading: Option<&Timestamp>, now: Instant) -> bool {
+        if let Some(t) = reading.filter(|&t| self.last_reading.as_ref() != Some(t)) {
+            self.last_reading = Some(t.clone());
+            self.last_change = now;
+            self.readings += 1;
+        }
+        let stale = now.duration_since(self.last_change) >= self.timeout;
+        if stale == self.failed {
+            return false;
+        }
+        self.failed = stale;
+        true
+    }
+
+    /// returns the status of the link, disabled for the reason if given
+    pub fn status(&self, disabled: Option<&str>) -> protobuf::LinkStatus {
+        let (state, err) = match disabled {
+            Some(reason) => (LinkState::Disabled, Some(reason.to_owned())),
+            None if self.failed => (
+                LinkState::Failed,
+                Some(format!(
+                    "no new reading on the M-Bus channel {} for {}s",
+                    self.channel,
+                    self.timeout.as_secs()
+                )),
+            ),
+            None => (LinkState::Ok, None),
+        };
+        protobuf::LinkStatus {
+            state: state as i32,
+            err,
+            data_in_count: self.readings,
+            data_out_count: 0,
+            data_in_size: 0,
+            data_out_size: 0,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_devices() {
+        assert_eq!(channel("0-2:24.2.1"), Some("2"));
+        assert_eq!(channel("0-0:96.1.1"), None);
+        assert_eq!(channel("1-0:1.7.0"), None);
+
+        let mut devices = Devices::default();
+        assert!(!devices.record("0-2:24.2.1", "003"));
+        assert!(devices.record("0-2:24.1.0", "003"));
+        assert!(devices.record("0-1:24.1.0", "007"));
+        assert!(devices.record("0-3:24.1.0", "099"));
+        assert_eq!(devices.device_name("0-2:24.2.1"), "gas");
+        assert_eq!(devices.device_name("0-1:24.2.1"), "water");
+        assert_eq!(devices.device_name("0-3:24.2.1"), "mbus");
+        assert_eq!(devices.device_name("1-0:1.7.0"), "mbus");
+    }
+
+    #[test]
+    fn test_channel_link() {
+        let t0 = Instant::now();
+        let timeout = Duration::from_secs(3600);
+        let reading = |millis| Timestamp { millis, picos: 0 };
+        let mut link = ChannelLink::new("1", Addr::new(1, 1), timeout, t0);
+
+        assert!(!link.update(Some(&reading(0)), t0));
+        // the meter repeats the same reading
+        assert!(!link.update(Some(&reading(0)), t0 + Duration::from_secs(3599)));
+        assert!(link.update(Some(&reading(0)), t0 + timeout));
+        assert_eq!(link.status(None).state, LinkState::Failed as i32);
+        assert!(!link.update(None, t0 + timeout * 2));
+        // a new reading
+        assert!(link.update(Some(&reading(1000)), t0 + timeout * 2));
+        let status = link.status(None);
+        assert_eq!(
+            (status.state, status.data_in_count),
+            (LinkState::Ok as i32, 2)
+        );
+        assert_eq!(
+            link.status(Some("paused")).state,
+            LinkState::Disabled as i32
+        );
+    }
+}
@@ -0,0 +1,242 @@
+//! HTTP endpoint exposing the statistics of the sources and the latest values of some parameters
+//! in the Prometheus text format, enabled with the `metrics` feature.
+//!
+//! The endpoint answers `GET /metrics` with:
+//! - `p1mon_telegrams_total`, `p1mon_crc_failures_total` and `p1mon_parse_errors_total` counters
+//! - the `p1mon_last_telegram_age_seconds` gauge
+//! - one `p1mon_value` gauge per exported parameter
+//!
+//! all labelled with the name of the source.
+
+use std::collections::{BTreeMap, HashSet};
+use std::fmt::Write;
+use std::sync::{Arc, Mutex};
+use std::time::Instant;
+
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::{TcpListener, TcpStream};
+
+use crate::stats::Stats;
+
+pub struct Metrics {
+    // the names of the parameters of which the latest value is exported
+    params: HashSet<String>,
+    sources: Mutex<BTreeMap<String, SourceMetrics>>,
+}
+
+#[derive(Default)]
+struct SourceMetrics {
+    telegrams: u64,
+    crc_failures: u64,
+    parse_errors: u64,
+    last_telegram: Option<Instant>,
+    values: BTreeMap<String, f64>,
+}
+
+impl Metrics {
+    pub fn new(params: impl IntoIterator<Item = String>) -> Self {
+        Self {
+            params: params.into_iter().collect(),
+            sources: Mutex::new(BTreeMap::new()),
+        }
+    }
+
+    /// copies the counters of the source
+    pub fn update_stats(&self, source: &str, stats: &Stats) {
+        let mut sources = self.sources.lock().unwrap();
+        let m = sources.entry(source.to_owned()).or_default();
+        m.telegrams = stats.telegrams_received;
+        m.crc_failures = stats.crc_failures;
+        m.parse_errors = stats.parse_errors;
+        m.last_telegram = stats.last_telegram;
+    }
+
+    /// keeps the latest values of the exported parameters, given as (name, value)
+    pub fn update_values<'a>(
+        &self,
+        source: &str,
+        values: impl IntoIterator<Item = (&'a str, f64)>,
+    ) {
+        let mut sources = self.sources.lock().unwrap();
+        let m = sources.entry(source.to_owned()).or_default();
+        for (name, x) in values {
+            if self.params.contains(name) {
+                m.values.insert(name.to_owned(), x);
+            }
+        }
+    }
+
+    /// returns the metrics in the Prometheus text format
+    pub fn render(&self, now: Instant) -> String {
+        let sources = self.sources.lock().unwrap();
+        let mut out = String::new();
+        counter(
+            &mut out,
+            &sources,
+            "telegrams_total",
+            "Number of telegrams received",
+            |m| m.telegrams,
+        );
+        counter(
+            &mut out,
+            &sources,
+            "crc_failures_total",
+            "Number of telegrams with a wrong CRC",
+            |m| m.crc_failures,
+        );
+        counter(
+            &mut out,
+            &sources,
+            "parse_errors_total",
+            "Number of lines which could not be parsed",
+            |m| m.parse_errors,
+        );
+
+        let _ = writeln!(
+            out,
+            "# HELP p1mon_last_telegram_age_seconds Time since the last telegram with a valid CRC\n\
+             # TYPE p1mon_last_telegram_age_seconds gauge"
+        );
+        for (source, m) in sources.iter() {
+            if let Some(t) = m.last_telegram {
+                let _ = writeln!(
+                    out,
+                    "p1mon_last_telegram_age_seconds{{source=\"{}\"}} {}",
+                    escape(source),
+                    now.duration_since(t).as_secs_f64()
+                );
+            }
+        }
+
+        if !self.params.is_empty() {
+            let _ = writeln!(
+                out,
+                "# HELP p1mon_value Latest value of the parameter\n# TYPE p1mon_value gauge"
+            );
+            for (source, m) in sources.iter() {
+                for (name, x) in &m.values {
+                    let _ = writeln!(
+                        out,
+                        "p1mon_value{{source=\"{}\",parameter=\"{}\"}} {x}",
+                        escape(source),
+                        escape(name)
+                    );
+                }
+            }
+        }
+        out
+    }
+
+    /// answers the HTTP requests received on the listener until the task is aborted
+    pub async fn serve(self: Arc<Self>, listener: TcpListener) {
+        loop {
+            match listener.accept().await {
+                Ok((stream, _)) => {
+                    let metrics = self.clone();
+                    tokio::spawn(async move {
+                        if let Err(e) = metrics.answer(stream).await {
+                            log::debug!("Error answering a metrics request: {e}");
+                        }
+                    });
+                }
+                Err(e) => log::warn!("Cannot accept a metrics connection: {e}"),
+            }
+        }
+    }
+
+    /// answers one request, the connection is then closed
+    async fn answer(&self, mut stream: TcpStream) -> std::io::Result<()> {
+        // only the request line is of interest, the request is expected to fit in one buffer
+        let mut buf = [0; 1024];
+        let n = stream.read(&mut buf).await?;
+        let request = String::from_utf8_lossy(&buf[..n]);
+        let response = if request.starts_with("GET /metrics ") {
+            let body = self.render(Instant::now());
+            format!(
+                "HTTP/1.1 200 OK\r\nContent-Type: text/plain; version=0.0.4\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{body}",
+                body.len()
+            )
+        } else {
+            "HTTP/1.1 404 Not Found\r\nContent-Length: 0\r\nConnection: close\r\n\r\n".to_owned()
+        };
+        stream.write_all(response.as_bytes()).await?;
+        stream.shutdown().await
+    }
+}
+
+/// writes the counter of each source
+fn counter(
+    out: &mut String,
+    sources: &BTreeMap<String, SourceMetrics>,
+    name: &str,
+    help: &str,
+    value: impl Fn(&SourceMetrics) -> u64,
+) {
+    let _ = writeln!(
+        out,
+        "# HELP p1mon_{name} {help}\n# TYPE p1mon_{name} counter"
+    );
+    for (source, m) in sources {
+        let _ = writeln!(
+            out,
+            "p1mon_{name}{{source=\"{}\"}} {}",
+            escape(source),
+            value(m)
+        );
+    }
+}
+
+/// escapes a label value
+fn escape(s: &str) -> String {
+    s.replace('\\', "\\\\")
+        .replace('"', "\\\"")
+        .replace('\n', "\\n")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    #[tokio::test]
+    async fn test_endpoint() {
+        let metrics = Arc::new(Metrics::new(["power".to_owned()]));
+        let t0 = Instant::now();
+        let mut stats = Stats::default();
+        stats.telegrams_received = 10;
+        stats.crc_failures = 1;
+        stats.last_telegram = Some(t0);
+        metrics.update_stats("main", &stats);
+        metrics.update_values("main", [("power", 0.316), ("voltage", 230.0)]);
+
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        let server = tokio::spawn(metrics.clone().serve(listener));
+
+        let mut stream = TcpStream::connect(addr).await.unwrap();
+        stream
+            .write_all(b"GET /metrics HTTP/1.1\r\nHost: localhost\r\n\r\n")
+            .await
+            .unwrap();
+        let mut response = String::new();
+        tokio::time::timeout(Duration::from_secs(5), stream.read_to_string(&mut response))
+            .await
+            .unwrap()
+            .unwrap();
+        server.abort();
+
+        let (header, body) = response.split_once("\r\n\r\n").unwrap();
+        assert!(header.starts_with("HTTP/1.1 200 OK"));
+        let samples: Vec<&str> = body.lines().filter(|l| !l.starts_with('#')).collect();
+        assert_eq!(samples[0], "p1mon_telegrams_total{source=\"main\"} 10");
+        assert_eq!(samples[1], "p1mon_crc_failures_total{source=\"main\"} 1");
+        assert_eq!(samples[2], "p1mon_parse_errors_total{source=\"main\"} 0");
+        assert!(samples[3].starts_with("p1mon_last_telegram_age_seconds{source=\"main\"} "));
+        // only the exported parameter
+        assert_eq!(
+            samples[4..],
+            ["p1mon_value{source=\"main\",parameter=\"power\"} 0.316"]
+        );
+        assert!(body.contains("# TYPE p1mon_telegrams_total counter\n"));
+    }
+}
@@ -0,0 +1,211 @@
+//! Publication of the parameter values to an MQTT broker, in parallel with the messages sent to Yamcs.
+//!
+//! Each value is published to `<prefix>/<parameter name>` with the engineering value as text payload,
+//! or to `<prefix>/<source name>/<parameter name>` when the node monitors more than one meter.
+//! The connection to the broker is handled by a background task which reconnects after errors;
+//! the values produced while the broker cannot be reached are dropped.
+
+use std::collections::HashMap;
+use std::time::Duration;
+
+use rumqttc::{AsyncClient, EventLoop, MqttOptions, QoS};
+use tokio::task::JoinHandle;
+use ygw::protobuf::ygw::{value::V, ParameterValue, Value};
+use ygw::{Result, YgwError};
+
+const DEFAULT_PORT: u16 = 1883;
+
+pub trait Publisher: Send {
+    /// publishes the payload to the topic; errors are only logged
+    fn publish(&mut self, topic: String, payload: String);
+}
+
+pub struct MqttSink {
+    prefix: String,
+    publisher: Box<dyn Publisher>,
+    // the connection to the broker, until it is started
+    eventloop: Option<(String, EventLoop)>,
+}
+
+impl MqttSink {
+    pub fn new(prefix: &str, publisher: Box<dyn Publisher>) -> Self {
+        Self {
+            prefix: prefix.trim_end_matches('/').to_owned(),
+            publisher,
+            eventloop: None,
+        }
+    }
+
+    /// connects to the broker given as host[:port] or mqtt://host[:port]
+    /// the client id is derived from the node name, such that the nodes of one server do not take over
+    /// the session of each other
+    /// the connection is only made once started
+    pub fn connect(broker: &str, node_name: &str, prefix: &str) -> Result<Self> {
+        let addr = broker.strip_prefix("mqtt://").unwrap_or(broker);
+        let (host, port) = match addr.rsplit_once(':') {
+            Some((host, port)) => (
+                host,
+                port.parse()
+                    .map_err(|_| YgwError::ParseError(format!("invalid MQTT broker '{broker}'")))?,
+            ),
+            None => (addr, DEFAULT_PORT),
+        };
+        let mut options = MqttOptions::new(format!("ygw-p1mon-{node_name}"), host, port);
+        options.set_keep_alive(Duration::from_secs(30));
+        let (client, eventloop) = AsyncClient::new(options, 100);
+
+        let mut sink = Self::new(prefix, Box::new(MqttPublisher { client }));
+        sink.eventloop = Some((broker.to_owned(), eventloop));
+        Ok(sink)
+    }
+
+    /// starts the background task connecting to the broker, this has to be called from within the tokio runtime
+    /// returns None if the sink was not connected or is already started
+    pub fn start(&mut self) -> Option<JoinHandle<()>> {
+        let (broker, mut eventloop) = self.eventloop.take()?;
+        Some(tokio::spawn(async move {
+            loop {
+                if let Err(e) = eventloop.poll().await {
+                    log::warn!("MQTT connection to {broker} failed: {e}");
+                    tokio::time::sleep(Duration::from_secs(10)).await;
+                }
+            }
+        }))
+    }
+
+    /// publishes the values of the source, given if the node has more than one;
+    /// names gives the name of the parameter of each id
+    pub fn publish(
+        &mut self,
+        source: Option<&str>,
+        pvalues: &[ParameterValue],
+        names: &HashMap<u32, &str>,
+    ) {
+        let prefix = match source {
+            Some(source) => format!("{}/{source}", self.prefix),
+            None => self.prefix.clone(),
+        };
+        for pv in pvalues {
+            let (Some(name), Some(payload)) =
+                (names.get(&pv.id), pv.eng_value.as_ref().and_then(payload))
+            else {
+                continue;
+            };
+            self.publisher.publish(format!("{prefix}/{name}"), payload);
+        }
+    }
+}
+
+struct MqttPublisher {
+    client: AsyncClient,
+}
+
+impl Publisher for MqttPublisher {
+    fn publish(&mut self, topic: String, payload: String) {
+        // the request queue is full when the broker cannot be reached
+        if let Err(e) = self
+            .client
+            .try_publish(topic, QoS::AtMostOnce, false, payload)
+        {
+            log::debug!("Cannot publish to MQTT: {e}");
+        }
+    }
+}
+
+fn payload(value: &Value) -> Option<String> {
+    match value.v.as_ref()? {
+        V::FloatValue(x) => Some(x.to_string()),
+        V::DoubleValue(x) => Some(x.to_string()),
+        V::Sint64Value(x) => Some(x.to_string()),
+        V::StringValue(s) => Some(s.clone()),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::{Arc, Mutex};
+
+    struct MockPublisher(Arc<Mutex<Vec<(String, String)>>>);
+
+    impl Publisher for MockPublisher {
+        fn publish(&mut self, topic: String, payload: String) {
+            self.0.lock().unwrap().push((topic, payload));
+        }
+    }
+
+    fn pvalue(id: u32, v: V) -> ParameterValue {
+        ParameterValue {
+            id,
+            eng_value: Some(Value { v: Some(v) }),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_publish() {
+        let published = Arc::new(Mutex::new(Vec::new()));
+        let mut sink = MqttSink::new("home/p1/", Box::new(MockPublisher(published.clone())));
+        let names = HashMap::from([(0, "power"), (1, "current_rate"), (2, "meter_id")]);
+
+        sink.publish(
+            None,
+            &[
+                pvalue(0, V::FloatValue(0.316)),
+                pvalue(1, V::Sint64Value(2)),
+                pvalue(2, V::StringValue("ISK5".to_owned())),
+                // no name, not published
+                pvalue(3, V::FloatValue(1.0)),
+            ],
+            &names,
+        );
+
+        let expected: Vec<(String, String)> = [
+            ("home/p1/power", "0.316"),
+            ("home/p1/current_rate", "2"),
+            ("home/p1/meter_id", "ISK5"),
+        ]
+        .iter()
+        .map(|(t, p)| (t.to_string(), p.to_string()))
+        .collect();
+        assert_eq!(*published.lock().unwrap(), expected);
+    }
+
+    #[test]
+    fn test_publish_sources() {
+        let published = Arc::new(Mutex::new(Vec::new()));
+        let mut sink = MqttSink::new("p1mon", Box::new(MockPublisher(published.clone())));
+        let names = HashMap::from([(0, "power")]);
+
+        sink.publish(Some("house"), &[pvalue(0, V::FloatValue(0.3))], &names);
+        sink.publish(Some("garage"), &[pvalue(0, V::FloatValue(1.5))], &names);
+
+        let topics: Vec<String> = published
+            .lock()
+            .unwrap()
+            .iter()
+            .map(|(t, _)| t.clone())
+            .collect();
+        assert_eq!(topics, vec!["p1mon/house/power", "p1mon/garage/power"]);
+    }
+
+    #[test]
+    fn test_connect() {
+        // outside of the runtime, nothing is started until start() is called
+        let mut sink = MqttSink::connect("mqtt://localhost:1883", "node", "p1mon").unwrap();
+        assert!(sink.eventloop.is_some());
+        assert!(MqttSink::connect("localhost:port", "node", "p1mon").is_err());
+
+        let rt = tokio::runtime::Builder::new_current_thread()
+            .enable_all()
+            .build()
+            .unwrap();
+        rt.block_on(async {
+            let task = sink.start().unwrap();
+            assert!(sink.start().is_none());
+            task.abort();
+            assert!(task.await.unwrap_err().is_cancelled());
+        });
+    }
+}
@@ -1,28 +1,435 @@
+use std::borrow::Cow;
+use std::collections::{HashMap, HashSet};
 use std::io::{self, BufRead, BufReader};
+use std::path::{Path, PathBuf};
 use std::str;
-use std::time::Duration;
-use std::{collections::HashMap, fs::File};
+use std::sync::atomic::{AtomicBool, AtomicU32, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant, SystemTime};
+use std::{fs, fs::File};
 
 use async_trait::async_trait;
-use chrono::{Datelike, NaiveDateTime, Timelike};
-use serialport::SerialPort;
+use chrono::{Datelike, LocalResult, NaiveDateTime, TimeZone, Timelike};
+use chrono_tz::{OffsetComponents, Tz};
+use serde::Deserialize;
 use tokio::sync::mpsc::{Receiver, Sender};
 use ygw::protobuf::ygw::{ParameterData, ParameterDefinitionList};
-use ygw::utc_converter::{utc_to_instant, DateTimeComponents};
+use ygw::utc_converter::{self, utc_to_instant, DateTimeComponents};
 use ygw::{
     msg::{Addr, YgwMessage},
     protobuf::ygw::{ParameterDefinition, ParameterValue, Timestamp, Value},
     Link, LinkStatus, Result, YgwError, YgwLinkNodeProperties, YgwNode,
 };
 
+use crate::capture::Capture;
+use crate::commands::{self, Command};
+use crate::derived::{self, Derivation};
+use crate::dryrun::DryRunPrinter;
+use crate::dsmr::DsmrVersion;
+use crate::events::{self, Category, EventLimiter};
+use crate::mbus::{self, ChannelLink, Devices};
+#[cfg(feature = "metrics")]
+use crate::metrics::Metrics;
+use crate::mqtt::MqttSink;
+use crate::parse_error::P1ParseError;
+use crate::profile::Profile;
+use crate::rollover::{self, Rollover};
+use crate::seqstore::{self, SeqStore};
+use crate::serial::{self, LineReader, LineSource};
+use crate::stats::{Stats, STATUS_GROUP};
+use crate::tcp;
+use crate::throttle::{self, Throttle};
+use crate::{eventlog, units, wildcard};
+
+// the names of the OBIS codes file in order of preference, the format is chosen by the extension
+const OBIS_CODES_FILES: &[&str] = &["obiscodes.toml", "obiscodes.csv"];
+// used when there is no OBIS codes file
+const DEFAULT_OBIS_CODES: &str = include_str!("dsmr5.csv");
+// the long power failure event log, 1-0:99.97.0 in DSMR 5
+const POWER_FAILURE_LOGS: &[&str] = &["0-0:99.97.0", "1-0:99.97.0"];
+// the list of the monthly peaks of the Belgian meters
+const PEAK_LISTS: &[&str] = &["0-0:98.1.0"];
+// the voltage sag and swell counters with the event they count and the phase; some meters send them instead
+// as event logs with the time and duration of each event, like the power failure log
+const POWER_QUALITY_CODES: &[(&str, &str, &str)] = &[
+    ("1-0:32.32.0", "voltage sag", "L1"),
+    ("1-0:52.32.0", "voltage sag", "L2"),
+    ("1-0:72.32.0", "voltage sag", "L3"),
+    ("1-0:32.36.0", "voltage swell", "L1"),
+    ("1-0:52.36.0", "voltage swell", "L2"),
+    ("1-0:72.36.0", "voltage swell", "L3"),
+];
+// the timezone of the timestamps sent by the meters, DSMR is used in the Netherlands
+const DEFAULT_TIMEZONE: Tz = chrono_tz::Europe::Amsterdam;
+// the text message, hex encoded by most meters
+const TEXT_MESSAGE_CODES: &[&str] = &["0-0:96.13.0"];
+// the key in the OBIS codes table, name and description of the parameters decoded from the header line:
+// the meter identification and its manufacturer and model parts
+const HEADER_PARAMS: &[(&str, &str, &str)] = &[
+    (
+        "/",
+        "meter_id",
+        "Meter identification (manufacturer and model)",
+    ),
+    (
+        "/manufacturer",
+        "meter_manufacturer",
+        "Manufacturer of the meter (three letters FLAG identifier)",
+    ),
+    ("/model", "meter_model", "Model of the meter"),
+];
+// the link is reported as failed when no telegram was received for this number of telegram intervals
+const SILENCE_INTERVALS: u32 = 3;
+// the values without an expiry in the table expire after this number of telegram intervals
+const DEFAULT_EXPIRY: Expiry = Expiry::Intervals(3);
+// the host clock is considered as not synchronized before this time (2024-01-01T00:00:00Z)
+const MIN_SYNCED_TIME: i64 = 1_704_067_200_000;
+// the number of consecutive CRC failures after which the link is reported as failed
+const DEFAULT_MAX_CRC_FAILURES: u32 = 5;
+// how often the OBIS codes file is checked for modifications
+const RELOAD_CHECK_INTERVAL: Duration = Duration::from_secs(5);
+const INITIAL_RECONNECT_DELAY: Duration = Duration::from_secs(1);
+const DEFAULT_MAX_RECONNECT_DELAY: Duration = Duration::from_secs(60);
+// the limits above which a telegram is discarded, a DSMR telegram is normally under 2 KB and 40 lines
+const DEFAULT_MAX_TELEGRAM_SIZE: usize = 8192;
+const DEFAULT_MAX_TELEGRAM_LINES: usize = 128;
+// the time within which a telegram has to be completed after its header
+const DEFAULT_TELEGRAM_TIMEOUT: Duration = Duration::from_secs(5);
+// at most one event per category is sent to Yamcs within this interval
+const EVENT_INTERVAL: Duration = Duration::from_secs(60);
+// at most one warning per parameter about a unit different from the unit column within this interval
+const UNIT_WARNING_INTERVAL: Duration = Duration::from_secs(3600);
+// the link of an M-Bus channel is failed when the readings have not changed for this time
+pub const DEFAULT_MBUS_TIMEOUT: Duration = Duration::from_secs(2 * 3600);
+// the link status is sent at least at this interval such that the data counters in Yamcs follow the traffic
+const LINK_STATUS_INTERVAL: Duration = Duration::from_secs(10);
+
 enum ParserState {
     LookForStart,
     LookForEnd,
 }
 
-#[derive(Debug)]
+/// assembles the telegrams from the lines received, whatever their source
+struct TelegramAssembler {
+    state: ParserState,
+    // the telegram is kept as bytes such that the CRC is computed over the data as received
+    p1t: Vec<u8>,
+    // the end of the header in p1t
+    m_idx: usize,
+    // the number of lines in p1t
+    lines: usize,
+    // the telegram being received is discarded when exceeding one of these
+    max_size: usize,
+    max_lines: usize,
+    // the time the header of the telegram being received was received
+    started: Instant,
+    // the telegram being received is discarded if not completed within this time
+    timeout: Duration,
+}
+
+/// collapses the carriage returns added before the \n by some USB-serial adapters: \r\r\n becomes \r\n
+/// the meter computes the CRC over lines ending with a single \r\n, the lines ending with \n alone are kept as they are
+fn normalize_line_end(line: &[u8]) -> Cow<'_, [u8]> {
+    let Some(content) = line.strip_suffix(b"\r\r\n") else {
+        return Cow::Borrowed(line);
+    };
+    let end = content
+        .iter()
+        .rposition(|&b| b != b'\r')
+        .map_or(0, |i| i + 1);
+    let mut normalized = content[..end].to_vec();
+    normalized.extend_from_slice(b"\r\n");
+    Cow::Owned(normalized)
+}
+
+/// a complete telegram, its CRC not yet checked
+struct RawTelegram {
+    data: Vec<u8>,
+    // the end of the header and the position of the ! in data
+    m_idx: usize,
+    bang: usize,
+}
+
+impl TelegramAssembler {
+    fn new(max_size: usize, max_lines: usize, timeout: Duration) -> Self {
+        Self {
+            state: ParserState::LookForStart,
+            p1t: Vec::new(),
+            m_idx: 0,
+            lines: 0,
+            max_size,
+            max_lines,
+            started: Instant::now(),
+            timeout,
+        }
+    }
+
+    /// discards the telegram being received, if any
+    fn discard(&mut self) {
+        self.p1t = Vec::new();
+        self.state = ParserState::LookForStart;
+    }
+
+    /// returns true if a telegram is being received
+    fn is_receiving(&self) -> bool {
+        matches!(self.state, ParserState::LookForEnd)
+    }
+
+    /// adds one line (including the \n), returns the telegram if the line completes it
+    /// the lines received before the start of a telegram are discarded
+    /// returns an error if the telegram being received exceeds the maximum size or number of lines,
+    /// the telegram is then discarded
+    fn add_line(&mut self, line: &[u8]) -> std::result::Result<Option<RawTelegram>, P1ParseError> {
+        self.add_line_at(line, Instant::now())
+    }
+
+    /// adds one line received at the time now
+    /// returns an error if the telegram being received was not completed within the timeout; it is then discarded
+    /// and the line may start the next telegram
+    fn add_line_at(
+        &mut self,
+        line: &[u8],
+        now: Instant,
+    ) -> std::result::Result<Option<RawTelegram>, P1ParseError> {
+        let expired = self.is_receiving() && now.duration_since(self.started) > self.timeout;
+        if expired {
+            self.discard();
+        }
+        let result = self.add(&normalize_line_end(line), now);
+        if expired {
+            return Err(P1ParseError::MissingTerminator(format!(
+                "not completed within {:?}",
+                self.timeout
+            )));
+        }
+        result
+    }
+
+    fn add(
+        &mut self,
+        line: &[u8],
+        now: Instant,
+    ) -> std::result::Result<Option<RawTelegram>, P1ParseError> {
+        match self.state {
+            ParserState::LookForStart => {
+                if line.first() == Some(&b'/') {
+                    self.p1t.clear();
+                    self.lines = 0;
+                    self.started = now;
+                    self.state = ParserState::LookForEnd;
+                    self.append(line)?;
+                    self.m_idx = self.p1t.len();
+                }
+                Ok(None)
+            }
+            ParserState::LookForEnd => {
+                // the start of the line in p1t
+                let n_idx = self.p1t.len();
+                self.append(line)?;
+                // the ! is normally at the start of the last line but it may follow other data on the same line
+                let Some(bang) = line.iter().position(|&b| b == b'!') else {
+                    return Ok(None);
+                };
+                self.state = ParserState::LookForStart;
+                Ok(Some(RawTelegram {
+                    data: std::mem::take(&mut self.p1t),
+                    m_idx: self.m_idx,
+                    bang: n_idx + bang,
+                }))
+            }
+        }
+    }
+
+    /// appends the line to the telegram being received, discards the telegram if it becomes too long
+    fn append(&mut self, line: &[u8]) -> std::result::Result<(), P1ParseError> {
+        let (size, lines) = (self.p1t.len() + line.len(), self.lines + 1);
+        if size > self.max_size || lines > self.max_lines {
+            // the memory of the discarded telegram is released
+            self.discard();
+            return Err(P1ParseError::MissingTerminator(format!(
+                "exceeding {} bytes or {} lines ({size} bytes, {lines} lines received)",
+                self.max_size, self.max_lines
+            )));
+        }
+        self.p1t.extend_from_slice(line);
+        self.lines = lines;
+        Ok(())
+    }
+}
+
+/// the handling of the telegrams with a wrong CRC
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum CrcPolicy {
+    /// the telegram is discarded, the link is reported as failed after max_crc_failures consecutive failures
+    Strict,
+    /// like strict but the telegram is processed anyway, flagged by the crc_ignored status parameters
+    Tolerant,
+    /// like strict but after max_crc_failures consecutive failures, the serial device is also reopened
+    Threshold,
+}
+
+impl CrcPolicy {
+    pub fn from_str(s: &str) -> Result<CrcPolicy> {
+        match s.to_lowercase().as_str() {
+            "strict" => Ok(CrcPolicy::Strict),
+            "tolerant" => Ok(CrcPolicy::Tolerant),
+            "threshold" => Ok(CrcPolicy::Threshold),
+            _ => Err(YgwError::ParseError(format!(
+                "invalid CRC policy '{s}', expected strict, tolerant or threshold"
+            ))),
+        }
+    }
+
+    fn as_str(&self) -> &'static str {
+        match self {
+            CrcPolicy::Strict => "strict",
+            CrcPolicy::Tolerant => "tolerant",
+            CrcPolicy::Threshold => "threshold",
+        }
+    }
+}
+
+/// the CRC-16 variant following the ! of the telegrams
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum CrcAlgorithm {
+    /// CRC-16/ARC as specified by DSMR
+    Arc,
+    Kermit,
+    Modbus,
+    X25,
+    Xmodem,
+    CcittFalse,
+    /// the telegrams are not checked, whether they end with a CRC or not
+    None,
+}
+
+impl CrcAlgorithm {
+    pub fn from_str(s: &str) -> Result<CrcAlgorithm> {
+        match s.to_lowercase().as_str() {
+            "arc" => Ok(CrcAlgorithm::Arc),
+            "kermit" => Ok(CrcAlgorithm::Kermit),
+            "modbus" => Ok(CrcAlgorithm::Modbus),
+            "x25" => Ok(CrcAlgorithm::X25),
+            "xmodem" => Ok(CrcAlgorithm::Xmodem),
+            "ccitt-false" => Ok(CrcAlgorithm::CcittFalse),
+            "none" => Ok(CrcAlgorithm::None),
+            _ => Err(YgwError::ParseError(format!(
+                "invalid CRC algorithm '{s}', expected arc, kermit, modbus, x25, xmodem, ccitt-false or none"
+            ))),
+        }
+    }
+
+    /// computes the CRC of the data, None if the telegrams are not checked
+    fn compute(&self, data: &[u8]) -> Option<u16> {
+        use crc16::State;
+        match self {
+            CrcAlgorithm::Arc => Some(State::<crc16::ARC>::calculate(data)),
+            CrcAlgorithm::Kermit => Some(State::<crc16::KERMIT>::calculate(data)),
+            CrcAlgorithm::Modbus => Some(State::<crc16::MODBUS>::calculate(data)),
+            CrcAlgorithm::X25 => Some(State::<crc16::X_25>::calculate(data)),
+            CrcAlgorithm::Xmodem => Some(State::<crc16::XMODEM>::calculate(data)),
+            CrcAlgorithm::CcittFalse => Some(State::<crc16::CCITT_FALSE>::calculate(data)),
+            CrcAlgorithm::None => None,
+        }
+    }
+}
+
+/// the clock giving the acquisition time of the values
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum TimeSource {
+    /// the host clock, the values being acquired when the telegram is received
+    Host,
+    /// the timestamp of the telegram, for the hosts without a trusted clock (the host time being used
+    /// for the telegrams without timestamp)
+    Meter,
+    /// the host clock, the values not being published until it has been synchronized, e.g. by NTP
+    HostSynced,
+}
+
+impl TimeSource {
+    pub fn from_str(s: &str) -> Result<TimeSource> {
+        match s.to_lowercase().as_str() {
+            "host" => Ok(TimeSource::Host),
+            "meter" => Ok(TimeSource::Meter),
+            "host-synced" => Ok(TimeSource::HostSynced),
+            _ => Err(YgwError::ParseError(format!(
+                "invalid time source '{s}', expected host, meter or host-synced"
+            ))),
+        }
+    }
+
+    fn description(&self) -> &'static str {
+        match self {
+            TimeSource::Host => "the host clock",
+            TimeSource::Meter => "the meter timestamps",
+            TimeSource::HostSynced => "the host clock once synchronized",
+        }
+    }
+}
+
+/// returns true if the host time looks synchronized, a host without RTC starting in the 1970s or at the time
+/// it was last shut down
+fn clock_synced(now: &Timestamp) -> bool {
+    timestamp_to_unix(now) >= MIN_SYNCED_TIME
+}
+
+/// the expiry of the values without an expiry of their own in the table
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Expiry {
+    /// this number of times the interval between two publications, such that the values expire soon
+    /// after the meter stops sending telegrams
+    Intervals(u32),
+    /// a fixed time
+    Fixed(Duration),
+    /// the values never expire
+    Disabled,
+}
+
+impl Expiry {
+    /// parses a number of intervals followed by x (e.g. 3x), a time (e.g. 30s) or off
+    pub fn from_str(s: &str) -> Result<Expiry> {
+        let expiry = if s.eq_ignore_ascii_case("off") {
+            Some(Expiry::Disabled)
+        } else if let Some(n) = s.strip_suffix('x') {
+            n.parse().ok().filter(|&n| n > 0).map(Expiry::Intervals)
+        } else {
+            throttle::parse_interval(s)
+                .filter(|d| !d.is_zero())
+                .map(Expiry::Fixed)
+        };
+        expiry.ok_or_else(|| {
+            YgwError::ParseError(format!(
+                "invalid expiry '{s}', expected a number of intervals (e.g. 3x), a time (e.g. 30s) or off"
+            ))
+        })
+    }
+}
+
+/// the handling of a decrease of a cumulative register
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Monotonic {
+    /// the decrease is logged as a warning
+    Warn,
+    /// the decrease is also flagged by the name_suspect parameter accompanying each value
+    Flag,
+}
+
+impl Monotonic {
+    fn from_str(s: &str) -> std::result::Result<Monotonic, String> {
+        match s.to_lowercase().as_str() {
+            "warn" => Ok(Monotonic::Warn),
+            "flag" => Ok(Monotonic::Flag),
+            _ => Err(format!(
+                "invalid monotonic check '{s}', expected warn or flag"
+            )),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
 enum DmsrParamType {
     Float,
+    Double,
     Integer,
     String,
 }
@@ -32,6 +439,7 @@ impl DmsrParamType {
         match s.to_lowercase().as_str() {
             "float" => Ok(DmsrParamType::Float),
             "integer" => Ok(DmsrParamType::Integer),
+            "double" => Ok(DmsrParamType::Double),
             "string" => Ok(DmsrParamType::String),
             _ => Err(YgwError::ParseError(format!(
                 "cannot parse {} into a type",
@@ -41,29 +449,540 @@ impl DmsrParamType {
     }
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 struct DmsrParam {
     description: String,
     // if the name is 'timestamp' the parameter will be parsed as time and used as gentime
     // if the name is 'ignore' the parameter will not be sent to Yamcs
     name: String,
     ptype: DmsrParamType,
+    // if set, the values are converted from the unit reported by the meter into this unit
+    unit: Option<String>,
+    // numeric values are calibrated as value*scale + offset (after the unit conversion)
+    scale: f64,
+    offset: f64,
+    // if set, the values are published in this group instead of the group of the source
+    group: Option<String>,
+    // if set, the integer values are published as the corresponding strings
+    enum_values: Option<HashMap<i64, String>>,
+    // if set, Yamcs marks the value as expired when no new value is received in this time
+    expire_ms: Option<u32>,
+    // float and double values which differ by at most this amount from the last value sent are considered unchanged
+    deadband: Option<f64>,
+    // the values below min or above max are flagged by the range status parameter
+    min: Option<f64>,
+    max: Option<f64>,
+    // if set, the 1-based index of the group of the line containing the value and of the one containing
+    // the time of the value; otherwise the value is in the first group, or in the second one after its time
+    value_group: Option<usize>,
+    time_group: Option<usize>,
+    // if set, each group of the line is published as the parameter name_<group name>, instead of a single value
+    group_names: Option<Vec<String>>,
+    // if set, the decreases of the value by more than a threshold are counted as rollovers of the register
+    rollover: Option<Rollover>,
+    // if set, the value is a cumulative register which should only increase, and the last value received
+    monotonic: Option<Monotonic>,
+    last_cumulative: Option<f64>,
+    // when the unit reported by the meter was last found different from the unit column
+    unit_warned: Option<Instant>,
+    // the last value sent and when, used to send only the values which changed
+    last_sent: Option<(Value, Instant)>,
+    // set for the derived parameters (whose code starts with =), computed from the values of other parameters
+    derivation: Option<Derivation>,
     // set to true when the parameter has been received and its value sent to Yamcs
     defined: bool,
     pid: u32,
+    origin: ParamOrigin,
+}
+
+/// where a parameter definition comes from
+#[derive(Debug, Clone, PartialEq)]
+enum ParamOrigin {
+    // a line in the OBIS codes file
+    File,
+    // created from the definition with the given code: a wildcard definition
+    // or the definition of a line containing multiple values
+    Derived(String),
+    // created in discovery mode for a code without definition
+    Discovered,
+    // the meter identification from the telegram header
+    Header,
+    // the codes added by the profile of the meter, for those not in the file
+    Profile,
+}
+
+impl DmsrParam {
+    /// creates a parameter without unit, calibration, group, enumeration, expiration or derivation
+    fn new(
+        name: String,
+        ptype: DmsrParamType,
+        description: String,
+        pid: u32,
+        origin: ParamOrigin,
+    ) -> Self {
+        Self {
+            description,
+            name,
+            ptype,
+            unit: None,
+            scale: 1.0,
+            offset: 0.0,
+            group: None,
+            enum_values: None,
+            expire_ms: None,
+            deadband: None,
+            min: None,
+            max: None,
+            value_group: None,
+            time_group: None,
+            group_names: None,
+            rollover: None,
+            monotonic: None,
+            last_cumulative: None,
+            unit_warned: None,
+            last_sent: None,
+            derivation: None,
+            defined: false,
+            pid,
+            origin,
+        }
+    }
+
+    /// returns true if the two parameters would produce the same definition in Yamcs
+    /// and the same values
+    fn same_definition(&self, other: &DmsrParam) -> bool {
+        self.name == other.name
+            && self.ptype == other.ptype
+            && self.description == other.description
+            && self.unit == other.unit
+            && self.scale == other.scale
+            && self.offset == other.offset
+            && self.group == other.group
+            && self.enum_values == other.enum_values
+            && self.expire_ms == other.expire_ms
+            && self.deadband == other.deadband
+            && self.min == other.min
+            && self.max == other.max
+            && self.value_group == other.value_group
+            && self.time_group == other.time_group
+            && self.group_names == other.group_names
+            && self.rollover.as_ref().map(Rollover::limits)
+                == other.rollover.as_ref().map(Rollover::limits)
+            && self.monotonic == other.monotonic
+    }
+}
+
+/// the definitions of the OBIS codes of a source, by code
+/// the ids of the parameters dropped at a reload are not reused, such that the values sent to Yamcs keep their meaning
+#[derive(Debug, Default, Clone)]
+struct ObisCodes {
+    params: HashMap<String, DmsrParam>,
+    // above all the ids given so far
+    next_pid: u32,
+}
+
+impl ObisCodes {
+    fn new(params: HashMap<String, DmsrParam>) -> Self {
+        let next_pid = params.values().map(|p| p.pid + 1).max().unwrap_or(0);
+        ObisCodes { params, next_pid }
+    }
+
+    /// returns a new id, above all the ids given so far
+    fn next_pid(&mut self) -> u32 {
+        let pid = self.next_pid;
+        self.next_pid += 1;
+        pid
+    }
+}
+
+impl std::ops::Deref for ObisCodes {
+    type Target = HashMap<String, DmsrParam>;
+
+    fn deref(&self) -> &Self::Target {
+        &self.params
+    }
+}
+
+impl std::ops::DerefMut for ObisCodes {
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        &mut self.params
+    }
+}
+
+/// added/removed/changed OBIS codes resulting from a reload of the codes file
+#[derive(Debug, Default)]
+struct ReloadSummary {
+    added: Vec<String>,
+    removed: Vec<String>,
+    changed: Vec<String>,
+}
+
+/// detects modifications of the OBIS codes file
+/// a reload is triggered only once the modification time has been stable for one check interval,
+/// such that a file which is in the process of being written is not loaded
+struct CodesWatcher {
+    // the path given explicitly, if any
+    codes_path: Option<PathBuf>,
+    mtime: Option<SystemTime>,
+    pending_mtime: Option<SystemTime>,
+    last_check: Instant,
+}
+
+impl CodesWatcher {
+    fn new(codes_path: Option<PathBuf>) -> Self {
+        Self {
+            mtime: codes_file(codes_path.as_deref()).and_then(|p| file_mtime(&p)),
+            codes_path,
+            pending_mtime: None,
+            last_check: Instant::now(),
+        }
+    }
+
+    /// returns true if the file has been modified and should be reloaded
+    fn check(&mut self) -> bool {
+        if self.last_check.elapsed() < RELOAD_CHECK_INTERVAL {
+            return false;
+        }
+        self.last_check = Instant::now();
+
+        let mtime = codes_file(self.codes_path.as_deref()).and_then(|p| file_mtime(&p));
+        if mtime.is_none() || mtime == self.mtime {
+            self.pending_mtime = None;
+            return false;
+        }
+        if mtime != self.pending_mtime {
+            self.pending_mtime = mtime;
+            return false;
+        }
+        self.mtime = mtime;
+        self.pending_mtime = None;
+        true
+    }
 }
 
 struct P1MonState {
-    seq_count: u32,
+    // the sequence count of each parameter group
+    seq_counts: HashMap<String, u32>,
+    // if set, the sequence counts are restored from and saved to the state file
+    seq_store: Option<Arc<SeqStore>>,
     addr: Addr,
     tx: Sender<YgwMessage>,
-    rx: Receiver<YgwMessage>,
+    // set when the node channel has been closed
+    closed: Arc<AtomicBool>,
+    // incremented each time a reload of the OBIS codes is requested
+    reload: Arc<AtomicU32>,
+    reload_seen: u32,
+    // incremented each time the definitions are requested to be sent again
+    resend: Arc<AtomicU32>,
+    resend_seen: u32,
+    // incremented each time the counters are requested to be reset, the flag telling if the last values are cleared too
+    reset: Arc<AtomicU32>,
+    reset_seen: u32,
+    reset_last_values: Arc<AtomicBool>,
+    // incremented each time the latest values are requested to be published
+    read_now: Arc<AtomicU32>,
+    read_now_seen: u32,
+    // the latest value of each parameter by id, kept between the telegrams not containing it
+    last_values: HashMap<u32, ParameterValue>,
+    // set while the acquisition is paused, the state seen by the source being kept in paused_seen
+    paused: Arc<AtomicBool>,
+    paused_seen: bool,
+    link_status: LinkStatus,
+    link_status_sent: Instant,
+    // set when the link has been reported as failed, until the next valid telegram
+    link_failed: bool,
+    // set while the link is reported as connecting, until the first valid telegram
+    connecting: bool,
+    // set while the values are held back until the host clock is synchronized
+    waiting_time_sync: bool,
+    // the number of bytes of the valid telegrams, also counted in the link status
+    data_in_size: u64,
+    stats: Stats,
+    events: EventLimiter,
+    // the sub-links of the M-Bus channels of the source
+    mbus_links: Vec<ChannelLink>,
+}
+
+impl P1MonState {
+    /// creates the state of a source sending its data on the link addr, with its own signalling flags
+    fn new(addr: Addr, tx: Sender<YgwMessage>) -> Self {
+        Self {
+            seq_counts: HashMap::new(),
+            seq_store: None,
+            addr,
+            tx,
+            closed: Arc::new(AtomicBool::new(false)),
+            reload: Arc::new(AtomicU32::new(0)),
+            reload_seen: 0,
+            resend: Arc::new(AtomicU32::new(0)),
+            resend_seen: 0,
+            reset: Arc::new(AtomicU32::new(0)),
+            reset_seen: 0,
+            reset_last_values: Arc::new(AtomicBool::new(false)),
+            read_now: Arc::new(AtomicU32::new(0)),
+            read_now_seen: 0,
+            last_values: HashMap::new(),
+            paused: Arc::new(AtomicBool::new(false)),
+            paused_seen: false,
+            link_status: LinkStatus::new(addr),
+            link_status_sent: Instant::now(),
+            link_failed: false,
+            connecting: false,
+            waiting_time_sync: false,
+            data_in_size: 0,
+            stats: Stats::default(),
+            events: EventLimiter::new(EVENT_INTERVAL),
+            mbus_links: Vec::new(),
+        }
+    }
+
+    /// sleeps for the duration unless the node is closed in the meantime
+    /// returns false if the node is closed
+    async fn sleep_unless_closed(&self, duration: Duration) -> bool {
+        let deadline = tokio::time::Instant::now() + duration;
+        while !self.is_closed() {
+            if tokio::time::Instant::now() >= deadline {
+                return true;
+            }
+            tokio::time::sleep(Duration::from_millis(100)).await;
+        }
+        false
+    }
+
+    /// sends the link status; while the acquisition is paused, the link is reported as disabled
+    /// and until the first valid telegram, as unavailable if the link up is deferred
+    async fn send_link_status(&mut self) -> Result<()> {
+        self.link_status_sent = Instant::now();
+        if self.paused_seen {
+            let ls = self.disabled_link_status("paused");
+            let _ = self.tx.send(YgwMessage::LinkStatus(self.addr, ls)).await;
+        } else if self.connecting && !self.link_failed {
+            let ls = ygw::protobuf::ygw::LinkStatus {
+                state: ygw::protobuf::ygw::LinkState::Unavail as i32,
+                ..self.disabled_link_status("connecting")
+            };
+            let _ = self.tx.send(YgwMessage::LinkStatus(self.addr, ls)).await;
+        } else {
+            self.link_status.send(&self.tx).await?;
+        }
+        let reason = self.paused_seen.then_some("paused");
+        for link in &self.mbus_links {
+            let _ = self
+                .tx
+                .send(YgwMessage::LinkStatus(link.addr, link.status(reason)))
+                .await;
+        }
+        Ok(())
+    }
+
+    /// returns the status of a disabled link with the reason, keeping the counts of the received data
+    fn disabled_link_status(&self, reason: &str) -> ygw::protobuf::ygw::LinkStatus {
+        ygw::protobuf::ygw::LinkStatus {
+            state: ygw::protobuf::ygw::LinkState::Disabled as i32,
+            err: Some(reason.to_owned()),
+            data_in_count: self.stats.telegrams_accepted,
+            data_out_count: 0,
+            data_in_size: self.data_in_size,
+            data_out_size: 0,
+        }
+    }
+
+    fn is_closed(&self) -> bool {
+        self.closed.load(Ordering::Relaxed)
+    }
+
+    /// returns true if a reload of the OBIS codes has been requested since the last call
+    fn reload_requested(&mut self) -> bool {
+        let reload = self.reload.load(Ordering::Relaxed);
+        let requested = reload != self.reload_seen;
+        self.reload_seen = reload;
+        requested
+    }
+
+    /// returns true if the definitions have been requested to be sent again since the last call
+    fn resend_requested(&mut self) -> bool {
+        let resend = self.resend.load(Ordering::Relaxed);
+        let requested = resend != self.resend_seen;
+        self.resend_seen = resend;
+        requested
+    }
+
+    /// returns a snapshot of the latest value of every parameter, ordered by id
+    /// the values not contained in every telegram (e.g. the gas read once per hour) are kept until the next one
+    fn latest_values(&self) -> Vec<ParameterValue> {
+        let mut pvalues: Vec<ParameterValue> = self.last_values.values().cloned().collect();
+        pvalues.sort_by_key(|pv| pv.id);
+        pvalues
+    }
+
+    /// returns true if the latest values have been requested to be published since the last call
+    fn read_now_requested(&mut self) -> bool {
+        let read_now = self.read_now.load(Ordering::Relaxed);
+        let requested = read_now != self.read_now_seen;
+        self.read_now_seen = read_now;
+        requested
+    }
+
+    /// returns Some if the counters have been requested to be reset since the last call,
+    /// with true if the last values sent are to be cleared too
+    fn reset_requested(&mut self) -> Option<bool> {
+        let reset = self.reset.load(Ordering::Relaxed);
+        if reset == self.reset_seen {
+            return None;
+        }
+        self.reset_seen = reset;
+        Some(self.reset_last_values.load(Ordering::Relaxed))
+    }
+
+    /// returns the new state if the acquisition has been paused or resumed since the last call
+    fn pause_changed(&mut self) -> Option<bool> {
+        let paused = self.paused.load(Ordering::Relaxed);
+        if paused == self.paused_seen {
+            return None;
+        }
+        self.paused_seen = paused;
+        Some(paused)
+    }
+}
+
+/// the settings of a source given on the command line, which apply to all the sources of the node
+#[derive(Clone)]
+struct SourceOptions {
+    // the reads return after this time without data, such that the reading loop checks for the node being closed,
+    // the link status and the statistics
+    read_timeout: Duration,
+    discovery: bool,
+    // only the codes of the OBIS codes file are published
+    allowlist: bool,
+    // the timezone of the timestamps sent by the meter
+    timezone: Tz,
+    // the telegram timestamps further ahead or behind the host time are replaced by the host time
+    max_time_ahead: Option<Duration>,
+    max_time_behind: Option<Duration>,
+    // if true, the definitions of the parameters in the table are sent when starting, before any telegram
+    define_upfront: bool,
+    // if true, the values of the M-Bus channels without group are published in the group parameter_group_mbus
+    mbus_group: bool,
+    // if true, the link is reported as connecting instead of ok until the first valid telegram
+    defer_link_up: bool,
+    // the unchanged values are sent again only after this time, zero to send all the values
+    max_silence: Duration,
+    // the expiry of the values without an expiry in the table
+    expiry: Expiry,
+    time_source: TimeSource,
+    // the number of consecutive CRC failures after which the link is reported as failed
+    max_crc_failures: u32,
+    crc_policy: CrcPolicy,
+    crc_algorithm: CrcAlgorithm,
+    // the DSMR version given explicitly, which takes precedence over the one reported by the meter
+    configured_dsmr_version: Option<DsmrVersion>,
+    // the delay between the attempts to reopen the serial device doubles up to this value
+    max_reconnect_delay: Duration,
+    // the telegrams longer than this (in bytes or lines) are discarded
+    max_telegram_size: usize,
+    max_telegram_lines: usize,
+    // the telegrams not completed within this time after their header are discarded
+    telegram_timeout: Duration,
+    // if set, the valid telegrams are written to the capture file
+    capture: Option<Arc<Capture>>,
+    // if set, the values published to Yamcs are also published to the MQTT broker
+    mqtt: Option<Arc<Mutex<MqttSink>>>,
+    // if set, the statistics and the latest values are exposed on the metrics endpoint
+    #[cfg(feature = "metrics")]
+    metrics: Option<Arc<Metrics>>,
+    // if set, the statistics are published in the status group at this interval
+    status_interval: Option<Duration>,
+    // if set, the heartbeat is published in the status group at this interval
+    heartbeat_interval: Option<Duration>,
+    // the link of an M-Bus channel is failed when its readings have not changed for this time
+    mbus_timeout: Duration,
+    // if set, an event is sent when a voltage sag or swell counter increases
+    power_quality_events: bool,
+    // the certificates trusted when connecting to a network bridge over TLS, the Mozilla roots if not set
+    #[cfg(feature = "tls")]
+    tls: Option<Arc<tcp::TlsConfig>>,
+}
+
+impl Default for SourceOptions {
+    fn default() -> Self {
+        Self {
+            read_timeout: serial::DEFAULT_READ_TIMEOUT,
+            discovery: false,
+            allowlist: false,
+            timezone: DEFAULT_TIMEZONE,
+            max_time_ahead: None,
+            max_time_behind: None,
+            define_upfront: false,
+            mbus_group: true,
+            defer_link_up: false,
+            max_silence: Duration::ZERO,
+            expiry: DEFAULT_EXPIRY,
+            time_source: TimeSource::Host,
+            max_crc_failures: DEFAULT_MAX_CRC_FAILURES,
+            crc_policy: CrcPolicy::Strict,
+            crc_algorithm: CrcAlgorithm::Arc,
+            configured_dsmr_version: None,
+            max_reconnect_delay: DEFAULT_MAX_RECONNECT_DELAY,
+            max_telegram_size: DEFAULT_MAX_TELEGRAM_SIZE,
+            max_telegram_lines: DEFAULT_MAX_TELEGRAM_LINES,
+            telegram_timeout: DEFAULT_TELEGRAM_TIMEOUT,
+            capture: None,
+            mqtt: None,
+            #[cfg(feature = "metrics")]
+            metrics: None,
+            status_interval: None,
+            heartbeat_interval: None,
+            mbus_timeout: DEFAULT_MBUS_TIMEOUT,
+            power_quality_events: false,
+            #[cfg(feature = "tls")]
+            tls: None,
+        }
+    }
+}
+
+/// a meter connected to a serial port or through a network bridge
+struct P1Source {
+    name: String,
+    parameter_group: String,
+    // the serial port, or the scripted lines of the tests
+    // None for a source created for a dry run, which does not read from a serial port
+    reader: Option<Box<dyn LineSource>>,
+    // the device reopened after an error, None if the port was given directly
+    serial_device: Option<String>,
+    obis_codes: ObisCodes,
+    // None if the OBIS codes were not read from a file and cannot be reloaded
+    codes_watcher: Option<CodesWatcher>,
+    options: SourceOptions,
+    // the profile whose codes were added to the table
+    profile: Profile,
+    throttle: Throttle,
+    // the DSMR version reported by the meter
+    dsmr_version: Option<DsmrVersion>,
+    // the M-Bus channels reported as sub-links, with their link id
+    mbus_links: Vec<(String, u32)>,
+    // the last meter identification published
+    meter_id: Option<String>,
+    // the last value of the voltage sag and swell counters, by code
+    power_quality_counts: HashMap<&'static str, i64>,
+    // the level added to the MQTT topics, the source name if the node has more than one source
+    mqtt_source: Option<String>,
 }
+
 pub struct P1Mon {
     props: YgwLinkNodeProperties,
-    parameter_group: String,
-    serial_port: Box<dyn SerialPort>,
-    obis_codes: HashMap<String, DmsrParam>,
+    sources: Vec<P1Source>,
+    // one sub-link per source if there are more than one sources, followed by one per M-Bus channel
+    links: Vec<Link>,
+    // the M-Bus channels reported as sub-links of each source
+    mbus_channels: Vec<String>,
+    // the file where the sequence counts are persisted, and the number of messages after which it is saved
+    state_file: Option<PathBuf>,
+    state_save_every: Option<u32>,
+    // the MQTT sink shared by the sources, connected when the node runs
+    mqtt: Option<Arc<Mutex<MqttSink>>>,
+    // the metrics shared by the sources and the address of the endpoint serving them
+    #[cfg(feature = "metrics")]
+    metrics: Option<(Arc<Metrics>, std::net::SocketAddr)>,
 }
 
 #[async_trait]
@@ -73,383 +992,7848 @@ impl YgwNode for P1Mon {
     }
 
     fn sub_links(&self) -> &[Link] {
-        &[]
+        &self.links
     }
 
     async fn run(
-        mut self: Box<Self>,
+        self: Box<Self>,
         node_id: u32,
         tx: Sender<YgwMessage>,
-        rx: Receiver<YgwMessage>,
+        mut rx: Receiver<YgwMessage>,
     ) -> Result<()> {
-        let addr = Addr::new(node_id, 0);
-        let mut link_status = LinkStatus::new(addr);
-        let mut state = P1MonState {
-            seq_count: 0,
-            addr,
-            tx,
-            rx,
+        let closed = Arc::new(AtomicBool::new(false));
+        let reload = Arc::new(AtomicU32::new(0));
+        let resend = Arc::new(AtomicU32::new(0));
+        let reset = Arc::new(AtomicU32::new(0));
+        let reset_last_values = Arc::new(AtomicBool::new(false));
+        let paused = Arc::new(AtomicBool::new(false));
+        let read_now = Arc::new(AtomicU32::new(0));
+        let mut hangup = Hangup::new()?;
+        let mut handles = Vec::new();
+
+        // with only one source, the source data is sent on the node link
+        let link_ids: Vec<u32> = if self.sources.len() == 1 {
+            vec![0]
+        } else {
+            LinkStatus::new(Addr::new(node_id, 0)).send(&tx).await?;
+            (1..=self.sources.len() as u32).collect()
+        };
+
+        let _ = tx
+            .send(YgwMessage::CommandDefinitions(
+                Addr::new(node_id, 0),
+                commands::definitions(),
+            ))
+            .await;
+
+        let seq_store = self
+            .state_file
+            .as_deref()
+            .map(|path| Arc::new(SeqStore::load(path, self.state_save_every)));
+
+        #[cfg(feature = "metrics")]
+        let metrics_server = match &self.metrics {
+            Some((metrics, addr)) => match tokio::net::TcpListener::bind(addr).await {
+                Ok(listener) => {
+                    log::info!("Serving the metrics on http://{addr}/metrics");
+                    Some(tokio::spawn(metrics.clone().serve(listener)))
+                }
+                Err(e) => {
+                    log::warn!("Cannot serve the metrics on {addr}: {e}");
+                    None
+                }
+            },
+            None => None,
         };
 
+        let mqtt_task = self.mqtt.as_ref().and_then(|m| m.lock().unwrap().start());
+
+        // with more than one source, the values of each source are published under its own MQTT topic
+        let multiple_sources = self.sources.len() > 1;
+        for (mut source, link_id) in self.sources.into_iter().zip(link_ids) {
+            source.mqtt_source = multiple_sources.then(|| source.name.clone());
+            let state = P1MonState {
+                seq_store: seq_store.clone(),
+                closed: closed.clone(),
+                reload: reload.clone(),
+                resend: resend.clone(),
+                reset: reset.clone(),
+                reset_last_values: reset_last_values.clone(),
+                paused: paused.clone(),
+                read_now: read_now.clone(),
+                mbus_links: source
+                    .mbus_links
+                    .iter()
+                    .map(|(channel, link_id)| {
+                        ChannelLink::new(
+                            channel,
+                            Addr::new(node_id, *link_id),
+                            source.options.mbus_timeout,
+                            Instant::now(),
+                        )
+                    })
+                    .collect(),
+                ..P1MonState::new(Addr::new(node_id, link_id), tx.clone())
+            };
+            handles.push(tokio::spawn(source.run(state)));
+        }
+
+        let mut flush_interval = tokio::time::interval(seqstore::FLUSH_INTERVAL);
+
+        // execute the commands until the channel is closed
+        // SIGHUP triggers a reload of the OBIS codes (on Unix, elsewhere the file is only reloaded when modified)
         loop {
-            //send an initial link status indicating that the link is up
-            link_status.send(&state.tx).await?;
-            if let Err(e) = self.process_serial_data(&mut state).await {
-                link_status.state_failed(format!("{:?}", e));
+            tokio::select! {
+                msg = rx.recv() => match msg {
+                    None => break,
+                    Some(YgwMessage::TcPacket(addr, pc)) => {
+                        let result = Command::from_prepared(&pc).map(|cmd| {
+                            log::info!("Executing the command {cmd:?}");
+                            match cmd {
+                                Command::ResendDefinitions => {
+                                    resend.fetch_add(1, Ordering::Relaxed);
+                                }
+                                Command::ResetCounters { clear_last_values } => {
+                                    reset_last_values.store(clear_last_values, Ordering::Relaxed);
+                                    reset.fetch_add(1, Ordering::Relaxed);
+                                }
+                                Command::Pause => paused.store(true, Ordering::Relaxed),
+                                Command::Resume => paused.store(false, Ordering::Relaxed),
+                                Command::ReadNow => {
+                                    read_now.fetch_add(1, Ordering::Relaxed);
+                                }
+                            }
+                        });
+                        if let Err(e) = &result {
+                            log::warn!("Rejecting the command {:?}: {e}", pc.command_id);
+                        }
+                        let _ = tx.send(YgwMessage::TcAck(addr, commands::ack(&pc, result))).await;
+                    }
+                    Some(msg) => log::debug!("Ignoring the message {msg:?}"),
+                },
+                _ = hangup.recv() => {
+                    log::info!("SIGHUP received, reloading the OBIS codes");
+                    reload.fetch_add(1, Ordering::Relaxed);
+                }
+                _ = flush_interval.tick() => {
+                    if let Some(seq_store) = &seq_store {
+                        seq_store.flush().await;
+                    }
+                }
             }
-            if state.rx.is_closed() {
-                break;
+        }
+        closed.store(true, Ordering::Relaxed);
+
+        for h in handles {
+            match h.await {
+                Ok(Err(e)) => log::warn!("P1 source terminated with error {:?}", e),
+                Err(e) => log::warn!("P1 source task failed: {:?}", e),
+                _ => {}
             }
-            tokio::time::sleep(Duration::from_secs(10)).await;
+        }
+        // the sources are stopped, the last counts are saved
+        if let Some(seq_store) = &seq_store {
+            seq_store.flush().await;
+        }
+        if let Some(mqtt_task) = mqtt_task {
+            mqtt_task.abort();
+        }
+        #[cfg(feature = "metrics")]
+        if let Some(metrics_server) = metrics_server {
+            metrics_server.abort();
         }
         Ok(())
     }
 }
 
-impl P1Mon {
-    pub fn new(serial_device: &str, parameter_group: &str) -> Result<Self> {
-        let obis_codes = read_codes()?;
-        let serial_port = serialport::new(serial_device, 115_200)
-            .timeout(std::time::Duration::from_millis(100))
-            .open()
-            .map_err(|e| {
-                YgwError::DeviceAccessError(format!("Cannot access {serial_device}: {}", e))
-            })?;
+/// the SIGHUP signal received by the process, asking to reload the OBIS codes
+/// on the platforms without signals it is never received, the file being only reloaded when modified
+struct Hangup {
+    #[cfg(unix)]
+    signal: tokio::signal::unix::Signal,
+}
 
+impl Hangup {
+    fn new() -> Result<Self> {
         Ok(Self {
-            props: YgwLinkNodeProperties {
-                name: "P1MON".to_owned(),
-                description: "Monitor electricity usage via P1 port".to_owned(),
-                tm: false,
-                tc: false,
-            },
-            serial_port,
-            obis_codes,
-            parameter_group: parameter_group.to_owned(),
+            #[cfg(unix)]
+            signal: tokio::signal::unix::signal(tokio::signal::unix::SignalKind::hangup())?,
         })
     }
-    /// read data from serial port
-    /// returns only if there was an error
-    async fn process_serial_data(&mut self, p1mon_state: &mut P1MonState) -> Result<()> {
-        let ser = self.serial_port.try_clone().map_err(|e| YgwError::Other(Box::new(e)))?;
-        let mut ser = BufReader::new(ser);
 
-        let mut p1t = String::new();
+    /// waits for the next signal
+    async fn recv(&mut self) {
+        #[cfg(unix)]
+        self.signal.recv().await;
+        #[cfg(not(unix))]
+        std::future::pending::<()>().await;
+    }
+}
 
-        let mut state = ParserState::LookForStart;
-        let mut m_idx = 0;
+impl P1Mon {
+    /// creates a node monitoring the meter connected to the serial_device
+    /// codes_path is the OBIS codes file, if not given the file is searched in the default locations
+    /// the other options are given with P1MonBuilder
+    // the binary creates its nodes with the builder, this remains for the embedders of the node
+    #[allow(dead_code)]
+    pub fn new(
+        serial_device: &str,
+        parameter_group: &str,
+        codes_path: Option<&Path>,
+    ) -> Result<Self> {
+        let mut builder = P1MonBuilder::new(parameter_group).serial_device(serial_device);
+        if let Some(path) = codes_path {
+            builder = builder.codes_path(path);
+        }
+        builder.build()
+    }
 
-        while !p1mon_state.rx.is_closed() {
-            let n_idx = p1t.len();
+    /// checks the OBIS codes of the first source without connection to the meter or to Yamcs: writes to out
+    /// the code, name, type, unit and id of each definition followed by the problems found, such as two
+    /// definitions with the same name; with the recorded telegrams, the codes defined in the file but never
+    /// received and the codes received without definition are also written, without being problems
+    /// returns the number of problems found
+    pub fn check(&self, recording: Option<&[u8]>, out: &mut impl io::Write) -> io::Result<usize> {
+        let obis_codes = &self.sources[0].obis_codes;
+        let mut codes: Vec<(&String, &DmsrParam)> = obis_codes.iter().collect();
+        codes.sort_by_key(|(_, p)| p.pid);
+        for (code, p) in &codes {
+            writeln!(
+                out,
+                "{code}\t{}\t{:?}\t{}\t{}",
+                p.name,
+                p.ptype,
+                p.unit.as_deref().unwrap_or_default(),
+                p.pid
+            )?;
+        }
 
-            match ser.read_line(&mut p1t) {
-                Ok(0) => {
-                    return Err(YgwError::IOError("While reading from serial port".into()
-                    , io::Error::from(
-                        io::ErrorKind::UnexpectedEof,
-                    )));
-                }
-                Err(e) => {
-                    log::warn!("Error reading from serial port: {}", e);
-                    p1t.clear();
-                    state = ParserState::LookForStart;
-                    continue;
-                }
-                _ => {}
+        let mut problems = Vec::new();
+        let mut names: HashMap<&str, &str> = HashMap::new();
+        for (code, p) in codes.iter().filter(|(_, p)| p.name != "ignore") {
+            if let Some(other) = names.insert(&p.name, code) {
+                problems.push(format!(
+                    "the codes {other} and {code} have the same name {}",
+                    p.name
+                ));
+            }
+        }
+        if let Some(recording) = recording {
+            let received: HashSet<String> = recording
+                .split(|&b| b == b'\n')
+                .filter_map(|line| {
+                    let line = String::from_utf8_lossy(line);
+                    Some(split_p1_line(line.trim()).ok()?[0].to_owned())
+                })
+                .collect();
+            let defined = |code: &str| {
+                obis_codes.contains_key(code)
+                    || obis_codes
+                        .keys()
+                        .any(|k| wildcard::matches(k, code).is_some())
+            };
+            let mut undefined: Vec<&String> =
+                received.iter().filter(|code| !defined(code)).collect();
+            undefined.sort();
+            for code in undefined {
+                writeln!(out, "no definition: {code}")?;
+            }
+            for (code, _) in codes.iter().filter(|(code, p)| {
+                p.origin == ParamOrigin::File
+                    && p.derivation.is_none()
+                    && !received
+                        .iter()
+                        .any(|r| r == *code || wildcard::matches(code, r).is_some())
+            }) {
+                writeln!(out, "not received: {code}")?;
             }
+        }
+        for problem in &problems {
+            writeln!(out, "problem: {problem}")?;
+        }
+        Ok(problems.len())
+    }
 
-            match state {
-                ParserState::LookForStart => {
-                    if p1t.as_bytes()[0] == b'/' {
-                        state = ParserState::LookForEnd;
-                        m_idx = p1t.len();
-                    } else {
-                        p1t.clear();
-                    }
-                }
+    /// processes the telegrams read from the input as the first source would, without connection to Yamcs:
+    /// the values published, the CRC failures and the parse errors are written to out instead
+    /// the telegrams with a wrong CRC are processed anyway, such that a telegram edited by hand can be checked
+    pub async fn dry_run(self, input: impl BufRead, out: &mut impl io::Write) -> Result<()> {
+        let mut source = self.sources.into_iter().next().unwrap();
+        source.options.crc_policy = CrcPolicy::Tolerant;
+        let (tx, mut rx) = tokio::sync::mpsc::channel(100);
+        let mut state = P1MonState::new(Addr::new(0, 0), tx);
+        state.stats.crc_policy = source.options.crc_policy.as_str();
+        // all the events are reported
+        state.events = EventLimiter::new(Duration::ZERO);
+        let definitions = source.status_definitions();
+        let _ = state
+            .tx
+            .send(YgwMessage::ParameterDefinitions(
+                state.addr,
+                ParameterDefinitionList { definitions },
+            ))
+            .await;
 
-                ParserState::LookForEnd => {
-                    if p1t.as_bytes()[n_idx] == b'!' {
-                        let Some(hex) = p1t.get(n_idx + 1..n_idx + 5) else {
-                            log::warn!("Invalid line {}", &p1t[n_idx..]);
-                            p1t.clear();
-                            state = ParserState::LookForStart;
-                            continue;
-                        };
-                        let Ok(crc) = u16::from_str_radix(hex, 16) else {
-                            log::warn!("Cannot parse hex crc {hex}");
-                            continue;
-                        };
-                        let computed_crc =
-                            crc16::State::<crc16::ARC>::calculate(&p1t.as_bytes()[0..n_idx + 1]);
-                        if crc != computed_crc {
-                            log::info!("CRC verification failed")
-                        } else {
-                            self.process_p1telegram(p1mon_state, &p1t[m_idx..n_idx])
-                                .await;
-                        }
-                        p1t.clear();
-                        state = ParserState::LookForStart;
-                    }
-                }
+        let mut assembler = TelegramAssembler::new(
+            source.options.max_telegram_size,
+            source.options.max_telegram_lines,
+            source.options.telegram_timeout,
+        );
+        let mut printer = DryRunPrinter::default();
+        for line in input.split(b'\n') {
+            let mut line = line?;
+            line.push(b'\n');
+            source
+                .process_line(&mut state, &mut assembler, &line)
+                .await?;
+            while let Ok(msg) = rx.try_recv() {
+                printer.print(&msg, out)?;
             }
         }
-
         Ok(())
     }
 
-    /// processes the telegram string into parameter values
-    /// returns parameter values as well as parameter definitions for those parameters for which no definition was generated previously
-    /// once the definition has been generated, the DmsrParam.defined is set to true
-    async fn process_p1telegram(&mut self, p1mon_state: &mut P1MonState, p1t: &str) {
-        let mut pdefs = Vec::new();
-        let mut pvalues = Vec::new();
-        let mut gentime = None;
-        let now = ygw::protobuf::now();
-
-        log::debug!("Processing telegram {p1t}");
-
-        for line in p1t.lines() {
-            if line.is_empty() {
-                continue;
-            }
-            let Ok(v) = split_p1_line(line) else {
-                log::warn!("Cannot parse p1 line {}", line);
-                continue;
-            };
+    fn with_source(source: P1Source) -> Self {
+        Self {
+            props: YgwLinkNodeProperties {
+                name: "P1MON".to_owned(),
+                description: "Monitor electricity usage via P1 port".to_owned(),
+                tm: false,
+                tc: true,
+            },
+            sources: vec![source],
+            links: Vec::new(),
+            mbus_channels: Vec::new(),
+            state_file: None,
+            state_save_every: None,
+            mqtt: None,
+            #[cfg(feature = "metrics")]
+            metrics: None,
+        }
+    }
 
-            if let Some(dmsr_param) = self.obis_codes.get_mut(v[0]) {
-                if dmsr_param.name == "ignore" {
-                    continue;
-                }
+    /// sets the name of the node in Yamcs (P1MON by default), which has to be unique among the nodes of the server
+    fn set_name(&mut self, name: &str) {
+        self.props.name = name.to_owned();
+    }
 
-                let a: Vec<&str> = v[1].split("*").collect();
-                let unit: Option<&str> = a.get(1).map(|&x| x);
+    /// sets the description of the node shown in Yamcs, e.g. to tell the meters of two houses apart
+    fn set_description(&mut self, description: &str) {
+        self.props.description = description.to_owned();
+    }
 
-                if !dmsr_param.defined {
-                    pdefs.push(get_pdef(dmsr_param, unit));
-                    dmsr_param.defined = true;
+    /// adds another meter connected to the serial_device, using the same OBIS codes file as the first one
+    /// when more than one meter is monitored, each of them is reported as a sub-link of the node
+    pub fn add_source(
+        &mut self,
+        name: &str,
+        serial_device: &str,
+        parameter_group: &str,
+    ) -> Result<()> {
+        let source = match &self.sources[0].codes_watcher {
+            Some(w) => {
+                let codes_path = w.codes_path.clone();
+                P1Source::new(
+                    name,
+                    serial_device,
+                    parameter_group,
+                    codes_path.as_deref(),
+                    self.sources[0].options.read_timeout,
+                )?
+            }
+            // the table of the first source is still as parsed since the node is not running yet
+            None => {
+                let mut source = P1Source::with_codes(
+                    name,
+                    P1Source::open_port(serial_device, self.sources[0].options.read_timeout)?,
+                    parameter_group,
+                    self.sources[0].obis_codes.clone(),
+                    None,
+                );
+                source.serial_device = Some(serial_device.to_owned());
+                source
+            }
+        };
+        self.push_source(source);
+        Ok(())
+    }
+
+    /// adds the source with the options of the first one
+    fn push_source(&mut self, mut source: P1Source) {
+        source.options = self.sources[0].options.clone();
+        // the profile adds its codes to the table, the throttle has its own state
+        source.add_profile_codes(self.sources[0].profile);
+        source
+            .throttle
+            .set_min_interval(self.sources[0].throttle.min_interval());
+        self.sources.push(source);
+        self.update_links();
+    }
+
+    /// creates the sub-links: one per source if there are more than one, then one per M-Bus channel of each source
+    fn update_links(&mut self) {
+        let multiple_sources = self.sources.len() > 1;
+        let mut links = Vec::new();
+        if multiple_sources {
+            for (idx, source) in self.sources.iter().enumerate() {
+                links.push(Link {
+                    id: idx as u32 + 1,
+                    props: YgwLinkNodeProperties {
+                        name: source.name.clone(),
+                        description: format!("P1 meter {}", source.name),
+                        tm: false,
+                        tc: false,
+                    },
+                });
+            }
+        }
+        for source in self.sources.iter_mut() {
+            source.mbus_links.clear();
+            for channel in &self.mbus_channels {
+                let link_id = links.len() as u32 + 1;
+                let (name, description) = if multiple_sources {
+                    (
+                        format!("{}_mbus{channel}", source.name),
+                        format!("M-Bus channel {channel} of the P1 meter {}", source.name),
+                    )
+                } else {
+                    (format!("mbus{channel}"), format!("M-Bus channel {channel}"))
+                };
+                links.push(Link {
+                    id: link_id,
+                    props: YgwLinkNodeProperties {
+                        name,
+                        description,
+                        tm: false,
+                        tc: false,
+                    },
+                });
+                source.mbus_links.push((channel.clone(), link_id));
+            }
+        }
+        self.links = links;
+    }
+
+    /// reports each of the M-Bus channels (e.g. "1" for the values 0-1:...) as a sub-link, failed when the time
+    /// of its readings has not changed for the timeout; the values of the channels are published on their link
+    fn set_mbus_links(&mut self, channels: &[&str], timeout: Duration) {
+        self.mbus_channels = channels.iter().map(|c| c.to_string()).collect();
+        for source in self.sources.iter_mut() {
+            source.options.mbus_timeout = timeout;
+        }
+        self.update_links();
+    }
+
+    /// enables the discovery mode: the codes not found in the OBIS codes file are published
+    /// as string parameters named after the code
+    fn set_discovery(&mut self, discovery: bool) {
+        for source in self.sources.iter_mut() {
+            source.options.discovery = discovery;
+        }
+    }
+
+    /// adds the codes of the profile of the meters (e.g. eMUCS-P1 for the Belgian meters) to the OBIS codes table,
+    /// for the codes which it does not define
+    fn set_profile(&mut self, profile: Profile) {
+        for source in self.sources.iter_mut() {
+            source.add_profile_codes(profile);
+        }
+    }
+
+    /// enables the allowlist mode: only the codes of the OBIS codes file are published,
+    /// not the meter identification from the header of the telegrams
+    fn set_allowlist(&mut self, allowlist: bool) {
+        for source in self.sources.iter_mut() {
+            source.options.allowlist = allowlist;
+        }
+    }
+
+    /// sends a POWER_QUALITY event when a voltage sag or swell counter increases, e.g. voltage sag detected on L1
+    fn set_power_quality_events(&mut self, enabled: bool) {
+        for source in self.sources.iter_mut() {
+            source.options.power_quality_events = enabled;
+        }
+    }
+
+    /// sets the timezone (IANA name, e.g. Europe/Brussels) of the timestamps sent by the meters,
+    /// Europe/Amsterdam by default
+    fn set_timezone(&mut self, timezone: &str) -> Result<()> {
+        let tz: Tz = timezone
+            .parse()
+            .map_err(|_| YgwError::ParseError(format!("unknown timezone '{timezone}'")))?;
+        for source in self.sources.iter_mut() {
+            source.options.timezone = tz;
+        }
+        Ok(())
+    }
+
+    /// replaces the telegram timestamps more than max_time_ahead in the future of the host time by the host time
+    /// (e.g. after the clock of the meter was set wrongly); the rejected timestamps are counted and reported by events
+    fn set_max_time_ahead(&mut self, max_time_ahead: Duration) {
+        for source in self.sources.iter_mut() {
+            source.options.max_time_ahead = Some(max_time_ahead);
+        }
+    }
+
+    /// replaces the telegram timestamps more than max_time_behind in the past of the host time by the host time
+    /// (e.g. after the clock of the meter was reset); the rejected timestamps are counted and reported by events
+    fn set_max_time_behind(&mut self, max_time_behind: Duration) {
+        for source in self.sources.iter_mut() {
+            source.options.max_time_behind = Some(max_time_behind);
+        }
+    }
+
+    /// sends the definitions of all the parameters in the OBIS codes table when the node starts, such that
+    /// Yamcs knows the rarely reported ones before they are received
+    /// the other parameters (and those whose unit is only known from the telegram) are defined when received
+    fn set_define_upfront(&mut self, define_upfront: bool) {
+        for source in self.sources.iter_mut() {
+            source.options.define_upfront = define_upfront;
+        }
+    }
+
+    /// publishes the values of the M-Bus channels (e.g. the gas) without group of their own in the group
+    /// <parameter_group>_mbus (the default) such that they keep the time of their reading, or with the electricity
+    fn set_mbus_group(&mut self, mbus_group: bool) {
+        for source in self.sources.iter_mut() {
+            source.options.mbus_group = mbus_group;
+        }
+    }
+
+    /// reports the link as connecting (unavailable) rather than ok until the first valid telegram is received,
+    /// such that an open serial port without data is not shown as a healthy link
+    fn set_defer_link_up(&mut self, defer_link_up: bool) {
+        for source in self.sources.iter_mut() {
+            source.options.defer_link_up = defer_link_up;
+        }
+    }
+
+    /// writes all the telegrams with a valid CRC to the capture file, preceded by the local time and the source name
+    /// the file is rotated when it would exceed max_size bytes
+    fn set_capture_file(&mut self, path: &Path, max_size: u64) {
+        let capture = Arc::new(Capture::new(path, max_size));
+        for source in self.sources.iter_mut() {
+            source.options.capture = Some(capture.clone());
+        }
+    }
+
+    /// publishes the values also to the MQTT sink, to the topics <prefix>/<parameter name>
+    /// or <prefix>/<source name>/<parameter name> if there are more than one sources
+    fn set_mqtt_sink(&mut self, sink: MqttSink) {
+        let sink = Arc::new(Mutex::new(sink));
+        for source in self.sources.iter_mut() {
+            source.options.mqtt = Some(sink.clone());
+        }
+        self.mqtt = Some(sink);
+    }
+
+    /// serves the statistics of the sources and the latest values of the parameters named in params
+    /// in the Prometheus format on http://addr/metrics
+    #[cfg(feature = "metrics")]
+    fn set_metrics(&mut self, addr: std::net::SocketAddr, params: Vec<String>) {
+        let metrics = Arc::new(Metrics::new(params));
+        for source in self.sources.iter_mut() {
+            source.options.metrics = Some(metrics.clone());
+        }
+        self.metrics = Some((metrics, addr));
+    }
+
+    /// publishes the statistics of each source (telegrams received, CRC failures...) in the p1mon_status group
+    /// every interval
+    fn set_status_interval(&mut self, interval: Duration) {
+        for source in self.sources.iter_mut() {
+            source.options.status_interval = Some(interval);
+        }
+    }
+
+    /// publishes a heartbeat count and the time since the last telegram in the p1mon_status group every interval,
+    /// such that a silent meter can be detected in Yamcs
+    fn set_heartbeat_interval(&mut self, interval: Duration) {
+        for source in self.sources.iter_mut() {
+            source.options.heartbeat_interval = Some(interval);
+        }
+    }
+
+    /// returns the definitions of the parameters configured in the OBIS codes table of the first source, sorted by id
+    /// the parameters created only when receiving the data (meter identification, expanded wildcards,
+    /// components of the power failure logs and discovered codes) are not included
+    pub fn parameter_definitions(&self) -> Vec<ParameterDefinition> {
+        parameter_definitions(&self.sources[0].obis_codes)
+    }
+
+    /// persists the sequence counts to the state file such that they continue after a restart
+    /// the file is written every minute if the counts changed and when the node stops
+    /// if the file cannot be read, the counts start from 0
+    fn set_state_file(&mut self, path: &Path) {
+        self.state_file = Some(path.to_owned());
+    }
+
+    /// saves the state file also every n messages published, in addition to every minute and when the node stops
+    fn set_state_save_every(&mut self, n: u32) {
+        self.state_save_every = Some(n);
+    }
+
+    /// sets the minimum interval between two publications of the parameter values
+    /// the telegrams received in the meantime are still decoded and the latest value of each parameter is published
+    /// at the end of the interval; the definitions of new parameters are sent without delay
+    fn set_min_interval(&mut self, min_interval: Duration) {
+        for source in self.sources.iter_mut() {
+            source.throttle.set_min_interval(min_interval);
+        }
+    }
+
+    /// sets the number of consecutive telegrams with a wrong CRC after which the link is reported as failed
+    /// the link is reported as ok again with the next valid telegram
+    fn set_max_crc_failures(&mut self, max_crc_failures: u32) {
+        for source in self.sources.iter_mut() {
+            source.options.max_crc_failures = max_crc_failures.max(1);
+        }
+    }
+
+    /// sets the handling of the telegrams with a wrong CRC, strict by default
+    fn set_crc_policy(&mut self, crc_policy: CrcPolicy) {
+        for source in self.sources.iter_mut() {
+            source.options.crc_policy = crc_policy;
+        }
+    }
+
+    /// sets the CRC variant of the telegrams, CRC-16/ARC (as specified by DSMR) by default,
+    /// or none for the meters and test rigs sending no CRC or one which cannot be checked
+    fn set_crc_algorithm(&mut self, crc_algorithm: CrcAlgorithm) {
+        for source in self.sources.iter_mut() {
+            source.options.crc_algorithm = crc_algorithm;
+        }
+    }
+
+    /// sets the DSMR version of the meters instead of the one they report, e.g. 3.0 for a meter sending
+    /// its telegrams without CRC
+    fn set_dsmr_version(&mut self, version: DsmrVersion) {
+        for source in self.sources.iter_mut() {
+            source.options.configured_dsmr_version = Some(version);
+        }
+    }
+
+    /// sets the maximum delay between two attempts to reopen the serial device after an error
+    /// the delay starts at one second and doubles after each attempt
+    fn set_max_reconnect_delay(&mut self, max_reconnect_delay: Duration) {
+        for source in self.sources.iter_mut() {
+            source.options.max_reconnect_delay = max_reconnect_delay.max(INITIAL_RECONNECT_DELAY);
+        }
+    }
+
+    /// sets the time after which a read of the serial port without data returns (100 ms by default)
+    /// a longer timeout suits the slow adapters, it delays the shutdown and the periodic tasks by at most this time
+    fn set_read_timeout(&mut self, read_timeout: Duration) {
+        for source in self.sources.iter_mut() {
+            source.options.read_timeout = read_timeout;
+        }
+    }
+
+    /// sets the maximum size in bytes of a telegram, the longer telegrams are discarded
+    /// the default leaves room for meters with long event logs
+    fn set_max_telegram_size(&mut self, max_size: usize) {
+        for source in self.sources.iter_mut() {
+            source.options.max_telegram_size = max_size;
+        }
+    }
+
+    /// sets the maximum number of lines of a telegram, the longer telegrams are discarded
+    fn set_max_telegram_lines(&mut self, max_lines: usize) {
+        for source in self.sources.iter_mut() {
+            source.options.max_telegram_lines = max_lines;
+        }
+    }
+
+    /// sets the time within which a telegram has to be completed after its header (5 seconds by default)
+    /// the telegrams cut off, e.g. by a reset of the meter, are discarded after this time
+    fn set_telegram_timeout(&mut self, timeout: Duration) {
+        for source in self.sources.iter_mut() {
+            source.options.telegram_timeout = timeout;
+        }
+    }
+
+    /// enables the change detection: a value is sent only if it differs from the last value sent
+    /// (by more than the deadband of the parameter for float and double values) or if it has not been sent
+    /// for max_silence; zero sends all the values
+    fn set_max_silence(&mut self, max_silence: Duration) {
+        for source in self.sources.iter_mut() {
+            source.options.max_silence = max_silence;
+        }
+    }
+
+    /// sets the certificates trusted and the server name checked when connecting to a network bridge over TLS
+    #[cfg(feature = "tls")]
+    fn set_tls(&mut self, tls: tcp::TlsConfig) {
+        let tls = Arc::new(tls);
+        for source in self.sources.iter_mut() {
+            source.options.tls = Some(tls.clone());
+        }
+    }
+
+    /// sets the clock giving the acquisition time of the values, the host clock by default
+    fn set_time_source(&mut self, time_source: TimeSource) {
+        for source in self.sources.iter_mut() {
+            source.options.time_source = time_source;
+        }
+    }
+
+    /// sets the expiry of the values without an expiry in the table, three telegram intervals by default
+    fn set_expiry(&mut self, expiry: Expiry) {
+        for source in self.sources.iter_mut() {
+            source.options.expiry = expiry;
+        }
+    }
+}
+
+/// collects the options of a node before creating it, e.g. for a meter of the configuration file:
+///
+/// P1MonBuilder::new("workshop").serial_device("/dev/ttyUSB1").name("workshop").build()
+///
+/// the options not given keep their default, they cannot be changed once the node is created
+#[derive(Default)]
+pub struct P1MonBuilder {
+    parameter_group: String,
+    serial_device: Option<String>,
+    codes_path: Option<PathBuf>,
+    codes: Option<String>,
+    name: Option<String>,
+    description: Option<String>,
+    state_file: Option<PathBuf>,
+    state_save_every: Option<u32>,
+    capture: Option<(PathBuf, u64)>,
+    read_timeout: Option<Duration>,
+    max_reconnect_delay: Option<Duration>,
+    min_interval: Option<Duration>,
+    status_interval: Option<Duration>,
+    heartbeat_interval: Option<Duration>,
+    mbus_links: Option<(Vec<String>, Duration)>,
+    mbus_group: Option<bool>,
+    profile: Option<Profile>,
+    discovery: Option<bool>,
+    allowlist: Option<bool>,
+    power_quality_events: Option<bool>,
+    timezone: Option<String>,
+    max_time_ahead: Option<Duration>,
+    max_time_behind: Option<Duration>,
+    define_upfront: Option<bool>,
+    defer_link_up: Option<bool>,
+    max_crc_failures: Option<u32>,
+    crc_policy: Option<CrcPolicy>,
+    crc_algorithm: Option<CrcAlgorithm>,
+    dsmr_version: Option<DsmrVersion>,
+    max_telegram_size: Option<usize>,
+    max_telegram_lines: Option<usize>,
+    telegram_timeout: Option<Duration>,
+    max_silence: Option<Duration>,
+    time_source: Option<TimeSource>,
+    expiry: Option<Expiry>,
+    mqtt: Option<(String, String)>,
+    #[cfg(feature = "metrics")]
+    metrics: Option<(std::net::SocketAddr, Vec<String>)>,
+    #[cfg(feature = "tls")]
+    tls_server_name: Option<String>,
+    #[cfg(feature = "tls")]
+    tls_ca_file: Option<PathBuf>,
+}
+
+impl P1MonBuilder {
+    /// starts a node publishing its parameters in the parameter_group
+    pub fn new(parameter_group: &str) -> Self {
+        Self {
+            parameter_group: parameter_group.to_owned(),
+            ..Default::default()
+        }
+    }
+
+    /// the serial device of the meter; without it, the node is only usable for a dry run or a check of the definitions
+    pub fn serial_device(mut self, serial_device: &str) -> Self {
+        self.serial_device = Some(serial_device.to_owned());
+        self
+    }
+
+    /// the OBIS codes file, searched in the default locations if not given
+    pub fn codes_path(mut self, path: &Path) -> Self {
+        self.codes_path = Some(path.to_owned());
+        self
+    }
+
+    /// the OBIS codes given in CSV format (as in the OBIS codes file) instead of read from a file,
+    /// they are then never reloaded
+    pub fn codes(mut self, codes: &str) -> Self {
+        self.codes = Some(codes.to_owned());
+        self
+    }
+
+    /// the name of the node in Yamcs, P1MON if not given
+    pub fn name(mut self, name: &str) -> Self {
+        self.name = Some(name.to_owned());
+        self
+    }
+
+    /// the description of the node in Yamcs
+    pub fn description(mut self, description: &str) -> Self {
+        self.description = Some(description.to_owned());
+        self
+    }
+
+    /// the file where the sequence counts are persisted, see P1Mon::set_state_file
+    pub fn state_file(mut self, path: &Path) -> Self {
+        self.state_file = Some(path.to_owned());
+        self
+    }
+
+    /// saves the state file also every n messages published, see P1Mon::set_state_save_every
+    pub fn state_save_every(mut self, n: u32) -> Self {
+        self.state_save_every = Some(n);
+        self
+    }
+
+    /// the file where the telegrams are captured, rotated when it would exceed max_size bytes
+    pub fn capture_file(mut self, path: &Path, max_size: u64) -> Self {
+        self.capture = Some((path.to_owned(), max_size));
+        self
+    }
+
+    /// the time after which a read of the serial port without data returns, see P1Mon::set_read_timeout
+    pub fn read_timeout(mut self, read_timeout: Duration) -> Self {
+        self.read_timeout = Some(read_timeout);
+        self
+    }
+
+    /// the maximum delay between two attempts to reopen the serial device, see P1Mon::set_max_reconnect_delay
+    pub fn max_reconnect_delay(mut self, max_reconnect_delay: Duration) -> Self {
+        self.max_reconnect_delay = Some(max_reconnect_delay);
+        self
+    }
+
+    /// the minimum interval between two publications of the parameter values, see P1Mon::set_min_interval
+    pub fn min_interval(mut self, min_interval: Duration) -> Self {
+        self.min_interval = Some(min_interval);
+        self
+    }
+
+    /// the interval of the publication of the statistics, see P1Mon::set_status_interval
+    pub fn status_interval(mut self, interval: Duration) -> Self {
+        self.status_interval = Some(interval);
+        self
+    }
+
+    /// the interval of the publication of the heartbeat, see P1Mon::set_heartbeat_interval
+    pub fn heartbeat_interval(mut self, interval: Duration) -> Self {
+        self.heartbeat_interval = Some(interval);
+        self
+    }
+
+    /// the M-Bus channels reported as sub-links, see P1Mon::set_mbus_links
+    pub fn mbus_links(mut self, channels: &[&str], timeout: Duration) -> Self {
+        let channels = channels.iter().map(|c| c.to_string()).collect();
+        self.mbus_links = Some((channels, timeout));
+        self
+    }
+
+    /// publishes the values of the M-Bus channels in a group of their own, see P1Mon::set_mbus_group
+    pub fn mbus_group(mut self, mbus_group: bool) -> Self {
+        self.mbus_group = Some(mbus_group);
+        self
+    }
+
+    /// the profile of the meters whose codes are added to the table, see P1Mon::set_profile
+    pub fn profile(mut self, profile: Profile) -> Self {
+        self.profile = Some(profile);
+        self
+    }
+
+    /// publishes the codes not found in the table, see P1Mon::set_discovery
+    pub fn discovery(mut self, discovery: bool) -> Self {
+        self.discovery = Some(discovery);
+        self
+    }
+
+    /// publishes only the codes of the table, see P1Mon::set_allowlist
+    pub fn allowlist(mut self, allowlist: bool) -> Self {
+        self.allowlist = Some(allowlist);
+        self
+    }
+
+    /// sends an event when a voltage sag or swell counter increases, see P1Mon::set_power_quality_events
+    pub fn power_quality_events(mut self, enabled: bool) -> Self {
+        self.power_quality_events = Some(enabled);
+        self
+    }
+
+    /// the timezone (IANA name) of the timestamps sent by the meters, checked when the node is built
+    pub fn timezone(mut self, timezone: &str) -> Self {
+        self.timezone = Some(timezone.to_owned());
+        self
+    }
+
+    /// the maximum time a telegram timestamp can be in the future, see P1Mon::set_max_time_ahead
+    pub fn max_time_ahead(mut self, max_time_ahead: Duration) -> Self {
+        self.max_time_ahead = Some(max_time_ahead);
+        self
+    }
+
+    /// the maximum time a telegram timestamp can be in the past, see P1Mon::set_max_time_behind
+    pub fn max_time_behind(mut self, max_time_behind: Duration) -> Self {
+        self.max_time_behind = Some(max_time_behind);
+        self
+    }
+
+    /// sends the definitions of all the parameters when the node starts, see P1Mon::set_define_upfront
+    pub fn define_upfront(mut self, define_upfront: bool) -> Self {
+        self.define_upfront = Some(define_upfront);
+        self
+    }
+
+    /// reports the link as connecting until the first valid telegram, see P1Mon::set_defer_link_up
+    pub fn defer_link_up(mut self, defer_link_up: bool) -> Self {
+        self.defer_link_up = Some(defer_link_up);
+        self
+    }
+
+    /// the number of consecutive wrong CRCs failing the link, see P1Mon::set_max_crc_failures
+    pub fn max_crc_failures(mut self, max_crc_failures: u32) -> Self {
+        self.max_crc_failures = Some(max_crc_failures);
+        self
+    }
+
+    /// the handling of the telegrams with a wrong CRC, see P1Mon::set_crc_policy
+    pub fn crc_policy(mut self, crc_policy: CrcPolicy) -> Self {
+        self.crc_policy = Some(crc_policy);
+        self
+    }
+
+    /// the CRC variant of the telegrams, see P1Mon::set_crc_algorithm
+    pub fn crc_algorithm(mut self, crc_algorithm: CrcAlgorithm) -> Self {
+        self.crc_algorithm = Some(crc_algorithm);
+        self
+    }
+
+    /// the DSMR version of the meters instead of the one they report, see P1Mon::set_dsmr_version
+    pub fn dsmr_version(mut self, version: DsmrVersion) -> Self {
+        self.dsmr_version = Some(version);
+        self
+    }
+
+    /// the maximum size in bytes of a telegram, see P1Mon::set_max_telegram_size
+    pub fn max_telegram_size(mut self, max_size: usize) -> Self {
+        self.max_telegram_size = Some(max_size);
+        self
+    }
+
+    /// the maximum number of lines of a telegram, see P1Mon::set_max_telegram_lines
+    pub fn max_telegram_lines(mut self, max_lines: usize) -> Self {
+        self.max_telegram_lines = Some(max_lines);
+        self
+    }
+
+    /// the time within which a telegram has to be completed, see P1Mon::set_telegram_timeout
+    pub fn telegram_timeout(mut self, timeout: Duration) -> Self {
+        self.telegram_timeout = Some(timeout);
+        self
+    }
+
+    /// sends only the changed values, the unchanged ones at least every max_silence, see P1Mon::set_max_silence
+    pub fn max_silence(mut self, max_silence: Duration) -> Self {
+        self.max_silence = Some(max_silence);
+        self
+    }
+
+    /// the clock giving the acquisition time of the values, see P1Mon::set_time_source
+    pub fn time_source(mut self, time_source: TimeSource) -> Self {
+        self.time_source = Some(time_source);
+        self
+    }
+
+    /// the expiry of the values without an expiry in the table, see P1Mon::set_expiry
+    pub fn expiry(mut self, expiry: Expiry) -> Self {
+        self.expiry = Some(expiry);
+        self
+    }
+
+    /// publishes the values also to the MQTT broker, to the topics <prefix>/<parameter name>
+    /// or <prefix>/<source name>/<parameter name> if there are more than one sources
+    /// the connection is made when the node runs
+    pub fn mqtt(mut self, broker: &str, prefix: &str) -> Self {
+        self.mqtt = Some((broker.to_owned(), prefix.to_owned()));
+        self
+    }
+
+    /// serves the statistics and the latest values of params on http://addr/metrics, see P1Mon::set_metrics
+    #[cfg(feature = "metrics")]
+    pub fn metrics(mut self, addr: std::net::SocketAddr, params: Vec<String>) -> Self {
+        self.metrics = Some((addr, params));
+        self
+    }
+
+    /// the name checked in the certificate of a network bridge over TLS instead of the host of its address
+    #[cfg(feature = "tls")]
+    pub fn tls_server_name(mut self, server_name: &str) -> Self {
+        self.tls_server_name = Some(server_name.to_owned());
+        self
+    }
+
+    /// the PEM file of the CA certificates trusted for a network bridge over TLS instead of the Mozilla roots
+    #[cfg(feature = "tls")]
+    pub fn tls_ca_file(mut self, path: &Path) -> Self {
+        self.tls_ca_file = Some(path.to_owned());
+        self
+    }
+
+    /// creates the node, failing if the OBIS codes cannot be read, the address of a network bridge is invalid,
+    /// the timezone is unknown, the MQTT broker address is invalid or the CA certificates cannot be read
+    /// if the serial device cannot be opened, the node starts with the link failed and keeps trying to open it
+    pub fn build(self) -> Result<P1Mon> {
+        let group = self.parameter_group.as_str();
+        let codes_path = self.codes_path.as_deref();
+        // the port is opened with the read timeout of the node, as when it is reopened
+        let read_timeout = self.read_timeout.unwrap_or(serial::DEFAULT_READ_TIMEOUT);
+        let source = match (&self.serial_device, &self.codes) {
+            (Some(serial_device), None) => {
+                P1Source::new(group, serial_device, group, codes_path, read_timeout)?
+            }
+            (None, None) => P1Source::without_port(group, group, codes_path)?,
+            (serial_device, Some(codes)) => {
+                let port = match serial_device {
+                    Some(serial_device) => P1Source::open_port(serial_device, read_timeout)?,
+                    None => None,
+                };
+                let codes = parse_codes(codes.as_bytes())?;
+                let mut source = P1Source::with_codes(group, port, group, codes, None);
+                source.serial_device = serial_device.clone();
+                source
+            }
+        };
+        let mut node = P1Mon::with_source(source);
+        if let Some(name) = &self.name {
+            node.set_name(name);
+        }
+        if let Some(description) = &self.description {
+            node.set_description(description);
+        }
+        if let Some(path) = &self.state_file {
+            node.set_state_file(path);
+        }
+        if let Some(n) = self.state_save_every {
+            node.set_state_save_every(n);
+        }
+        if let Some((path, max_size)) = &self.capture {
+            node.set_capture_file(path, *max_size);
+        }
+        if let Some(read_timeout) = self.read_timeout {
+            node.set_read_timeout(read_timeout);
+        }
+        if let Some(max_reconnect_delay) = self.max_reconnect_delay {
+            node.set_max_reconnect_delay(max_reconnect_delay);
+        }
+        if let Some(min_interval) = self.min_interval {
+            node.set_min_interval(min_interval);
+        }
+        if let Some(interval) = self.status_interval {
+            node.set_status_interval(interval);
+        }
+        if let Some(interval) = self.heartbeat_interval {
+            node.set_heartbeat_interval(interval);
+        }
+        if let Some((channels, timeout)) = &self.mbus_links {
+            let channels: Vec<&str> = channels.iter().map(String::as_str).collect();
+            node.set_mbus_links(&channels, *timeout);
+        }
+        if let Some(mbus_group) = self.mbus_group {
+            node.set_mbus_group(mbus_group);
+        }
+        if let Some(profile) = self.profile {
+            node.set_profile(profile);
+        }
+        if let Some(discovery) = self.discovery {
+            node.set_discovery(discovery);
+        }
+        if let Some(allowlist) = self.allowlist {
+            node.set_allowlist(allowlist);
+        }
+        if let Some(enabled) = self.power_quality_events {
+            node.set_power_quality_events(enabled);
+        }
+        if let Some(timezone) = &self.timezone {
+            node.set_timezone(timezone)?;
+        }
+        if let Some(max_time_ahead) = self.max_time_ahead {
+            node.set_max_time_ahead(max_time_ahead);
+        }
+        if let Some(max_time_behind) = self.max_time_behind {
+            node.set_max_time_behind(max_time_behind);
+        }
+        if let Some(define_upfront) = self.define_upfront {
+            node.set_define_upfront(define_upfront);
+        }
+        if let Some(defer_link_up) = self.defer_link_up {
+            node.set_defer_link_up(defer_link_up);
+        }
+        if let Some(max_crc_failures) = self.max_crc_failures {
+            node.set_max_crc_failures(max_crc_failures);
+        }
+        if let Some(crc_policy) = self.crc_policy {
+            node.set_crc_policy(crc_policy);
+        }
+        if let Some(crc_algorithm) = self.crc_algorithm {
+            node.set_crc_algorithm(crc_algorithm);
+        }
+        if let Some(version) = self.dsmr_version {
+            node.set_dsmr_version(version);
+        }
+        if let Some(max_size) = self.max_telegram_size {
+            node.set_max_telegram_size(max_size);
+        }
+        if let Some(max_lines) = self.max_telegram_lines {
+            node.set_max_telegram_lines(max_lines);
+        }
+        if let Some(timeout) = self.telegram_timeout {
+            node.set_telegram_timeout(timeout);
+        }
+        if let Some(max_silence) = self.max_silence {
+            node.set_max_silence(max_silence);
+        }
+        if let Some(time_source) = self.time_source {
+            node.set_time_source(time_source);
+        }
+        if let Some(expiry) = self.expiry {
+            node.set_expiry(expiry);
+        }
+        if let Some((broker, prefix)) = &self.mqtt {
+            let sink = MqttSink::connect(broker, &node.props.name, prefix)?;
+            node.set_mqtt_sink(sink);
+        }
+        #[cfg(feature = "metrics")]
+        if let Some((addr, params)) = self.metrics {
+            node.set_metrics(addr, params);
+        }
+        #[cfg(feature = "tls")]
+        if self.tls_server_name.is_some() || self.tls_ca_file.is_some() {
+            node.set_tls(tcp::TlsConfig::new(
+                self.tls_server_name.as_deref(),
+                self.tls_ca_file.as_deref(),
+            )?);
+        }
+        Ok(node)
+    }
+}
+
+impl P1Source {
+    fn new(
+        name: &str,
+        serial_device: &str,
+        parameter_group: &str,
+        codes_path: Option<&Path>,
+        read_timeout: Duration,
+    ) -> Result<Self> {
+        let mut source = Self::with_port(
+            name,
+            Self::open_port(serial_device, read_timeout)?,
+            parameter_group,
+            codes_path,
+        )?;
+        source.serial_device = Some(serial_device.to_owned());
+        Ok(source)
+    }
+
+    /// opens the serial device with the read timeout of the node, returns None if it cannot be opened
+    /// the opening is then retried when running, as after a read error
+    /// a network bridge is only connected when running, its address is checked here
+    fn open_port(serial_device: &str, read_timeout: Duration) -> Result<Option<serial::Port>> {
+        if tcp::is_network(serial_device) {
+            tcp::check(serial_device)?;
+            return Ok(None);
+        }
+        match serial::open(serial_device, read_timeout) {
+            Ok(port) => Ok(Some(port)),
+            Err(e) => {
+                log::warn!("Cannot open the serial port {serial_device}: {e:?}");
+                Ok(None)
+            }
+        }
+    }
+
+    fn with_port(
+        name: &str,
+        serial_port: Option<serial::Port>,
+        parameter_group: &str,
+        codes_path: Option<&Path>,
+    ) -> Result<Self> {
+        Ok(Self::with_codes(
+            name,
+            serial_port,
+            parameter_group,
+            read_codes(codes_path)?,
+            Some(CodesWatcher::new(codes_path.map(Path::to_path_buf))),
+        ))
+    }
+
+    /// creates a source without serial port, only used to process the telegrams of a dry run
+    fn without_port(name: &str, parameter_group: &str, codes_path: Option<&Path>) -> Result<Self> {
+        Ok(Self::with_codes(
+            name,
+            None,
+            parameter_group,
+            read_codes(codes_path)?,
+            None,
+        ))
+    }
+
+    fn with_codes(
+        name: &str,
+        serial_port: Option<serial::Port>,
+        parameter_group: &str,
+        obis_codes: ObisCodes,
+        codes_watcher: Option<CodesWatcher>,
+    ) -> Self {
+        Self {
+            name: name.to_owned(),
+            parameter_group: parameter_group.to_owned(),
+            reader: serial_port.map(|port| Box::new(LineReader::new(port)) as Box<dyn LineSource>),
+            serial_device: None,
+            obis_codes,
+            codes_watcher,
+            options: SourceOptions::default(),
+            profile: Profile::Dsmr,
+            throttle: Throttle::new(Duration::ZERO),
+            dsmr_version: None,
+            mbus_links: Vec::new(),
+            meter_id: None,
+            power_quality_counts: HashMap::new(),
+            mqtt_source: None,
+        }
+    }
+
+    /// returns the definitions of the parameters published in the status group
+    fn status_definitions(&self) -> Vec<ParameterDefinition> {
+        let mut definitions = vec![Stats::clock_offset_definition()];
+        if self.options.status_interval.is_some() || self.options.heartbeat_interval.is_some() {
+            definitions.extend(Stats::definitions());
+        }
+        definitions
+    }
+
+    /// sends again the definitions of all the known parameters, including those not received yet
+    /// the parameters whose unit is only known from the telegram are defined again when next received
+    async fn resend_definitions(&mut self, p1mon_state: &mut P1MonState) {
+        log::info!("Sending again the parameter definitions of {}", self.name);
+        for p in self.obis_codes.values_mut() {
+            p.defined = false;
+        }
+        let mut definitions = self.status_definitions();
+        definitions.extend(define_upfront(&mut self.obis_codes));
+        let _ = p1mon_state
+            .tx
+            .send(YgwMessage::ParameterDefinitions(
+                p1mon_state.addr,
+                ParameterDefinitionList { definitions },
+            ))
+            .await;
+    }
+
+    /// reads the data from the serial port, reconnecting in case of error, until the node is closed
+    /// the status of the link is reported independently of the other sources
+    async fn run(mut self, mut state: P1MonState) -> Result<()> {
+        state.stats.crc_policy = self.options.crc_policy.as_str();
+        log::info!(
+            "Acquisition time of {} given by {}",
+            self.name,
+            self.options.time_source.description()
+        );
+        let pdef_list = ParameterDefinitionList {
+            definitions: self.status_definitions(),
+        };
+        let _ = state
+            .tx
+            .send(YgwMessage::ParameterDefinitions(state.addr, pdef_list))
+            .await;
+        if self.options.define_upfront {
+            let pdef_list = ParameterDefinitionList {
+                definitions: define_upfront(&mut self.obis_codes),
+            };
+            log::debug!("Sending definitions {:?}", pdef_list.definitions);
+            let _ = state
+                .tx
+                .send(YgwMessage::ParameterDefinitions(state.addr, pdef_list))
+                .await;
+        }
+        // the serial device which could not be opened when creating the node is tried again before starting
+        if self.reader.is_none() && self.serial_device.is_some() {
+            if let Err(e) = self.reopen().await {
+                log::warn!("Cannot open the serial port of {}: {:?}", self.name, e);
+                state.link_status.state_failed(format!("{:?}", e));
+                state.link_failed = true;
+            }
+        }
+        //send an initial link status indicating whether the link is up
+        state.connecting = self.options.defer_link_up;
+        state.send_link_status().await?;
+        let mut delay = INITIAL_RECONNECT_DELAY;
+        loop {
+            let result = match self.reader {
+                // the serial device not opened yet is not read but reopened after the delay
+                None if self.serial_device.is_some() => Ok(()),
+                _ => self.process_serial_data(&mut state).await,
+            };
+            if let Err(e) = result {
+                log::warn!("Error processing data from {}: {:?}", self.name, e);
+                // the backoff restarts if the link worked since the last reconnection
+                if !state.link_failed {
+                    delay = INITIAL_RECONNECT_DELAY;
+                }
+                state.link_status.state_failed(format!("{:?}", e));
+                state.link_failed = true;
+                state.send_link_status().await?;
+            }
+            if state.is_closed() {
+                break;
+            }
+
+            // the link is reported as ok again only when a valid telegram is received
+            let mut attempts = 0;
+            loop {
+                if !state.sleep_unless_closed(delay).await {
+                    break;
+                }
+                delay = (delay * 2).min(self.options.max_reconnect_delay);
+                attempts += 1;
+                state.stats.reconnects += 1;
+                match self.reopen().await {
+                    Ok(()) => break,
+                    Err(e) => {
+                        log::warn!("Cannot reopen the serial port of {}: {:?}", self.name, e);
+                        state
+                            .link_status
+                            .state_failed(format!("{:?} (reconnect attempt {attempts})", e));
+                        state.send_link_status().await?;
+                    }
+                }
+            }
+            if state.is_closed() {
+                break;
+            }
+        }
+        self.shutdown(&mut state).await;
+        Ok(())
+    }
+
+    /// publishes the values held back by the throttle and reports the link as disabled (rather than failed)
+    /// such that a controlled shutdown can be told apart from a crash
+    async fn shutdown(mut self, p1mon_state: &mut P1MonState) {
+        if let Some(pvalues) = self.throttle.flush() {
+            let now = ygw::protobuf::now();
+            self.publish_values(p1mon_state, pvalues, Some(now.clone()), now)
+                .await;
+        }
+        let ls = p1mon_state.disabled_link_status("shut down");
+        let _ = p1mon_state
+            .tx
+            .send(YgwMessage::LinkStatus(p1mon_state.addr, ls))
+            .await;
+        for link in &p1mon_state.mbus_links {
+            let ls = link.status(Some("shut down"));
+            let _ = p1mon_state
+                .tx
+                .send(YgwMessage::LinkStatus(link.addr, ls))
+                .await;
+        }
+        log::info!("Closing the serial port of {}", self.name);
+        drop(self.reader.take());
+    }
+
+    /// reopens the serial device, which may have been re-enumerated after a USB glitch, or reconnects to the
+    /// network bridge; the port is kept if it was given directly
+    async fn reopen(&mut self) -> Result<()> {
+        if let Some(serial_device) = &self.serial_device {
+            if tcp::is_network(serial_device) {
+                #[cfg(feature = "tls")]
+                let reader = tcp::connect(serial_device, self.options.tls.as_deref()).await?;
+                #[cfg(not(feature = "tls"))]
+                let reader = tcp::connect(serial_device).await?;
+                self.reader = Some(reader);
+            } else {
+                let port = serial::open(serial_device, self.options.read_timeout)?;
+                self.reader = Some(Box::new(LineReader::new(port)));
+            }
+        }
+        Ok(())
+    }
+
+    /// adds the codes of the profile not defined in the table, they are kept when reloading the OBIS codes file
+    /// unless the file defines them
+    fn add_profile_codes(&mut self, profile: Profile) {
+        self.profile = profile;
+        let codes = parse_codes(profile.codes().as_bytes()).expect("invalid profile codes");
+        let mut codes: Vec<(String, DmsrParam)> = codes
+            .params
+            .into_iter()
+            .filter(|(code, _)| !self.obis_codes.contains_key(code))
+            .collect();
+        codes.sort_by_key(|(_, p)| p.pid);
+        for (code, mut dmsr_param) in codes {
+            dmsr_param.pid = self.obis_codes.next_pid();
+            dmsr_param.origin = ParamOrigin::Profile;
+            self.obis_codes.insert(code, dmsr_param);
+        }
+    }
+
+    /// re-reads the OBIS codes file and merges it into the live table
+    /// if the file cannot be read or parsed, the current table is kept
+    fn reload_codes(&mut self) {
+        let Some(codes_watcher) = &self.codes_watcher else {
+            log::info!(
+                "The OBIS codes of {} were not read from a file, nothing to reload",
+                self.name
+            );
+            return;
+        };
+        match read_codes(codes_watcher.codes_path.as_deref()) {
+            Ok(new_codes) => {
+                let summary = merge_codes(&mut self.obis_codes, new_codes);
+                log::info!(
+                    "Reloaded the OBIS codes: added {:?}, removed {:?}, changed {:?}",
+                    summary.added,
+                    summary.removed,
+                    summary.changed
+                );
+            }
+            Err(e) => {
+                log::warn!("Cannot reload the OBIS codes, keeping the current definitions: {e}");
+            }
+        }
+    }
+
+    /// reads and processes the telegrams until an error occurs or the node is closed
+    ///
+    /// a telegram interrupted by an error is discarded rather than completed after the reconnection: the data
+    /// received while the port was failing is lost, such that the rest of the telegram would be appended to
+    /// a truncated start, giving a telegram rejected by the CRC check (or worse, processed with the tolerant
+    /// CRC policy) which would also swallow the header of the first complete telegram
+    async fn process_serial_data(&mut self, p1mon_state: &mut P1MonState) -> Result<()> {
+        let mut assembler = TelegramAssembler::new(
+            self.options.max_telegram_size,
+            self.options.max_telegram_lines,
+            self.options.telegram_timeout,
+        );
+        let result = self.read_telegrams(p1mon_state, &mut assembler).await;
+        if result.is_err() && assembler.is_receiving() {
+            log::warn!(
+                "Discarding the partial telegram from {} ({} bytes) interrupted by the error",
+                self.name,
+                assembler.p1t.len()
+            );
+        }
+        result
+    }
+
+    async fn read_telegrams(
+        &mut self,
+        p1mon_state: &mut P1MonState,
+        assembler: &mut TelegramAssembler,
+    ) -> Result<()> {
+        loop {
+            // when closing, the telegram being received is still completed
+            if p1mon_state.is_closed() && !assembler.is_receiving() {
+                break;
+            }
+            self.publish_status(p1mon_state).await;
+            self.publish_heartbeat(p1mon_state).await;
+            self.publish_throttled(p1mon_state).await;
+            self.check_silence(p1mon_state).await?;
+            if p1mon_state.link_status_sent.elapsed() >= LINK_STATUS_INTERVAL {
+                p1mon_state.send_link_status().await?;
+            }
+            let modified = self.codes_watcher.as_mut().is_some_and(|w| w.check());
+            if modified | p1mon_state.reload_requested() {
+                self.reload_codes();
+            }
+            if p1mon_state.resend_requested() {
+                self.resend_definitions(p1mon_state).await;
+            }
+            if let Some(clear_last_values) = p1mon_state.reset_requested() {
+                self.reset_counters(p1mon_state, clear_last_values).await;
+            }
+            if let Some(paused) = p1mon_state.pause_changed() {
+                self.pause_changed(p1mon_state, paused).await?;
+            }
+            if p1mon_state.read_now_requested() {
+                self.publish_latest(p1mon_state).await;
+            }
+
+            let Some(reader) = self.reader.as_mut() else {
+                return Err(YgwError::DeviceAccessError(format!(
+                    "No serial port for {}",
+                    self.name
+                )));
+            };
+            let max_size = self.options.max_telegram_size;
+            let line = match reader.next_line(self.options.read_timeout, max_size).await {
+                Ok(Some(line)) => line,
+                // no data yet, the meter is quiet between two telegrams
+                Ok(None) => {
+                    if p1mon_state.is_closed() {
+                        log::info!("Discarding the incomplete telegram from {}", self.name);
+                        break;
+                    }
+                    continue;
+                }
+                // the line cannot be part of a valid telegram, the one being received is discarded with it
+                Err(e) if serial::line_too_long(&e).is_some() => {
+                    assembler.discard();
+                    let e = P1ParseError::MissingTerminator(format!("with a {e}"));
+                    self.parse_error(p1mon_state, &e, b"").await;
+                    continue;
+                }
+                Err(e) => {
+                    return Err(YgwError::IOError(
+                        "While reading from serial port".into(),
+                        e,
+                    ));
+                }
+            };
+            self.process_line(p1mon_state, assembler, &line).await?;
+        }
+
+        Ok(())
+    }
+
+    /// adds the line to the telegram being assembled and processes the telegram if the line completes it
+    async fn process_line(
+        &mut self,
+        p1mon_state: &mut P1MonState,
+        assembler: &mut TelegramAssembler,
+        line: &[u8],
+    ) -> Result<()> {
+        let telegram = match assembler.add_line(line) {
+            Ok(Some(telegram)) => telegram,
+            Ok(None) => return Ok(()),
+            Err(e) => {
+                self.parse_error(p1mon_state, &e, line).await;
+                return Ok(());
+            }
+        };
+        self.process_telegram(p1mon_state, telegram).await
+    }
+
+    /// counts and reports the error of the telegram discarded with the line
+    async fn parse_error(&self, p1mon_state: &mut P1MonState, e: &P1ParseError, line: &[u8]) {
+        log::warn!("{}: {e}", self.name);
+        p1mon_state.stats.record_error(e);
+        let line = String::from_utf8_lossy(line);
+        self.send_event(p1mon_state, Category::ParseError, &e.to_string(), &line)
+            .await;
+    }
+
+    /// checks the CRC of the telegram and processes it
+    /// the telegrams with a wrong CRC are only processed with the tolerant CRC policy,
+    /// the telegrams without CRC only if the DSMR version of the meter is older than 4 or the CRC is not checked
+    async fn process_telegram(
+        &mut self,
+        p1mon_state: &mut P1MonState,
+        telegram: RawTelegram,
+    ) -> Result<()> {
+        let p1t = &telegram.data;
+        p1mon_state.stats.telegrams_received += 1;
+        p1mon_state.stats.cadence.record(Instant::now(), p1t.len());
+        let without_crc = self.options.crc_algorithm == CrcAlgorithm::None
+            || (self.dsmr_version().is_some_and(|v| !v.crc_required())
+                && p1t[telegram.bang + 1..].trim_ascii().is_empty());
+        let crc = if without_crc {
+            Ok(())
+        } else {
+            check_crc(p1t, telegram.bang, self.options.crc_algorithm)
+        };
+        match crc {
+            Ok(()) => {
+                self.detect_dsmr_version(p1t);
+                p1mon_state.stats.telegrams_accepted += 1;
+                // the CRC failures are only counted in the statistics, the link status has no
+                // counter for the rejected data
+                p1mon_state.link_status.data_in(1, p1t.len() as u64);
+                p1mon_state.data_in_size += p1t.len() as u64;
+                p1mon_state.stats.last_telegram = Some(Instant::now());
+                p1mon_state.stats.last_crc_ignored = false;
+                if self.waiting_time_sync(p1mon_state).await? {
+                    return Ok(());
+                }
+                self.crc_ok(p1mon_state).await?;
+                if let Some(capture) = self
+                    .options
+                    .capture
+                    .as_ref()
+                    .filter(|_| !p1mon_state.paused_seen)
+                {
+                    capture.write(&self.name, p1t);
+                }
+            }
+            Err(e) => {
+                log::info!("{e}");
+                p1mon_state.stats.record_error(&e);
+                let data = String::from_utf8_lossy(p1t);
+                self.send_event(p1mon_state, Category::CrcFailure, &e.to_string(), &data)
+                    .await;
+                self.crc_failed(p1mon_state).await?;
+                if self.options.crc_policy != CrcPolicy::Tolerant {
+                    return Ok(());
+                }
+                p1mon_state.stats.crc_ignored += 1;
+                p1mon_state.stats.last_crc_ignored = true;
+            }
+        }
+        // while paused, the telegrams are only counted: decoding them would define the parameters and
+        // advance the state of the checks without anything being published
+        if p1mon_state.paused_seen {
+            log::debug!(
+                "Acquisition of {} paused, discarding the telegram",
+                self.name
+            );
+        } else {
+            let header = String::from_utf8_lossy(&p1t[..telegram.m_idx]);
+            let header = parse_header(&header);
+            self.process_p1telegram(p1mon_state, header, &p1t[telegram.m_idx..telegram.bang])
+                .await;
+        }
+        #[cfg(feature = "metrics")]
+        if let Some(metrics) = &self.options.metrics {
+            metrics.update_stats(&self.name, &p1mon_state.stats);
+        }
+        Ok(())
+    }
+
+    /// returns the DSMR version of the meter, the configured one if given, None if not known yet
+    fn dsmr_version(&self) -> Option<DsmrVersion> {
+        self.options.configured_dsmr_version.or(self.dsmr_version)
+    }
+
+    /// records the DSMR version reported by the first valid telegram containing it
+    fn detect_dsmr_version(&mut self, p1t: &[u8]) {
+        if self.dsmr_version.is_some() {
+            return;
+        }
+        let Some(version) = DsmrVersion::from_telegram(p1t) else {
+            return;
+        };
+        self.dsmr_version = Some(version);
+        match self.options.configured_dsmr_version {
+            Some(configured) if configured != version => log::info!(
+                "The meter of {} reports DSMR {version}, using the configured DSMR {configured}",
+                self.name
+            ),
+            _ => log::info!(
+                "DSMR {version} meter on {}, expecting a telegram every {}s",
+                self.name,
+                version.telegram_interval().as_secs()
+            ),
+        }
+    }
+
+    /// returns the expiry of the values without an expiry in the table, None if they do not expire
+    /// or if the interval between the telegrams is not known yet
+    /// the interval is the one measured over the last minute, otherwise the one of the DSMR version; as the
+    /// values are published at most once per throttle interval and the unchanged ones every max_silence,
+    /// the longest of these intervals is multiplied
+    fn default_expiry(&self, stats: &Stats) -> Option<Duration> {
+        match self.options.expiry {
+            Expiry::Disabled => None,
+            Expiry::Fixed(expiry) => Some(expiry),
+            Expiry::Intervals(n) => {
+                let interval = stats
+                    .cadence
+                    .rates(Instant::now())
+                    .map(|(interval, _)| interval)
+                    .or_else(|| self.dsmr_version().map(|v| v.telegram_interval()))?;
+                let interval = interval
+                    .max(self.throttle.min_interval())
+                    .max(self.options.max_silence);
+                Some(interval * n)
+            }
+        }
+    }
+
+    /// publishes the values held back by the throttle once the minimum interval has elapsed,
+    /// such that they do not wait for the next telegram when the meter stops sending or sends only unchanged values
+    async fn publish_throttled(&mut self, p1mon_state: &mut P1MonState) {
+        if let Some(pvalues) = self.throttle.take(Instant::now()) {
+            let now = ygw::protobuf::now();
+            self.publish_values(p1mon_state, pvalues, Some(now.clone()), now)
+                .await;
+        }
+    }
+
+    /// reports the link as failed when no valid telegram was received for SILENCE_INTERVALS telegram intervals,
+    /// until the next valid telegram
+    /// nothing is reported before the first telegram or if the DSMR version, giving the interval, is not known
+    async fn check_silence(&self, p1mon_state: &mut P1MonState) -> Result<()> {
+        let (Some(version), Some(last)) = (self.dsmr_version(), p1mon_state.stats.last_telegram)
+        else {
+            return Ok(());
+        };
+        let max_silence = version.telegram_interval() * SILENCE_INTERVALS;
+        if p1mon_state.link_failed || p1mon_state.paused_seen || last.elapsed() < max_silence {
+            return Ok(());
+        }
+        let msg = format!("no telegram for {}s", max_silence.as_secs());
+        log::warn!("{msg} from {}", self.name);
+        p1mon_state.link_status.state_failed(msg);
+        p1mon_state.link_failed = true;
+        p1mon_state.send_link_status().await
+    }
+
+    /// returns the name of the parameter of each id
+    fn names(&self) -> HashMap<u32, &str> {
+        self.obis_codes
+            .values()
+            .map(|p| (p.pid, p.name.as_str()))
+            .collect()
+    }
+
+    /// returns the sequence number of the next message of the group and increments the sequence count
+    fn next_seq_num(&self, p1mon_state: &mut P1MonState, group: &str) -> u32 {
+        let seq_store = &p1mon_state.seq_store;
+        let seq_count = p1mon_state
+            .seq_counts
+            .entry(group.to_owned())
+            .or_insert_with(|| seq_store.as_ref().map_or(0, |s| s.get(&self.name, group)));
+        let seq_num = *seq_count;
+        *seq_count = seq_num.wrapping_add(1);
+        if let Some(seq_store) = seq_store {
+            seq_store.set(&self.name, group, *seq_count);
+        }
+        seq_num
+    }
+
+    /// reports the new state of the link when the acquisition is paused or resumed
+    /// after resuming, the values of the next telegram are all published, even if they did not change
+    async fn pause_changed(&mut self, p1mon_state: &mut P1MonState, paused: bool) -> Result<()> {
+        if paused {
+            log::info!("Pausing the acquisition of {}", self.name);
+        } else {
+            log::info!("Resuming the acquisition of {}", self.name);
+            self.throttle.reset();
+            for p in self.obis_codes.values_mut() {
+                p.last_sent = None;
+            }
+        }
+        p1mon_state.send_link_status().await
+    }
+
+    /// restarts the sequence counts and the statistics from 0 and publishes the statistics right away
+    /// if clear_last_values is true, the next values are sent even if they did not change
+    async fn reset_counters(&mut self, p1mon_state: &mut P1MonState, clear_last_values: bool) {
+        log::info!("Resetting the counters of {}", self.name);
+        p1mon_state.seq_counts.clear();
+        if let Some(seq_store) = &p1mon_state.seq_store {
+            seq_store.reset(&self.name);
+        }
+        p1mon_state.stats.reset();
+        if clear_last_values {
+            for p in self.obis_codes.values_mut() {
+                p.last_sent = None;
+            }
+        }
+        if self.options.status_interval.is_some() {
+            self.send_status(p1mon_state, Instant::now()).await;
+        }
+    }
+
+    /// publishes the statistics in the status group if the status interval has elapsed
+    async fn publish_status(&self, p1mon_state: &mut P1MonState) {
+        let Some(interval) = self.options.status_interval else {
+            return;
+        };
+        let now = Instant::now();
+        if p1mon_state.stats.publish_due(now, interval) {
+            self.send_status(p1mon_state, now).await;
+        }
+    }
+
+    async fn send_status(&self, p1mon_state: &mut P1MonState, now: Instant) {
+        let t = ygw::protobuf::now();
+        let pdata = ParameterData {
+            parameters: p1mon_state.stats.values(now),
+            group: STATUS_GROUP.to_owned(),
+            seq_num: self.next_seq_num(p1mon_state, STATUS_GROUP),
+            generation_time: Some(t.clone()),
+            acquisition_time: Some(t),
+        };
+        let _ = p1mon_state
+            .tx
+            .send(YgwMessage::ParameterData(p1mon_state.addr, pdata))
+            .await;
+    }
+
+    /// publishes the heartbeat count and the time since the last telegram in the status group
+    /// if the heartbeat interval has elapsed, whether telegrams are received or not
+    async fn publish_heartbeat(&self, p1mon_state: &mut P1MonState) {
+        let Some(interval) = self.options.heartbeat_interval else {
+            return;
+        };
+        let Some(parameters) = p1mon_state.stats.heartbeat(Instant::now(), interval) else {
+            return;
+        };
+        let t = ygw::protobuf::now();
+        let pdata = ParameterData {
+            parameters,
+            group: STATUS_GROUP.to_owned(),
+            seq_num: self.next_seq_num(p1mon_state, STATUS_GROUP),
+            generation_time: Some(t.clone()),
+            acquisition_time: Some(t),
+        };
+        let _ = p1mon_state
+            .tx
+            .send(YgwMessage::ParameterData(p1mon_state.addr, pdata))
+            .await;
+    }
+
+    /// counts the consecutive CRC failures, the link is reported as failed when they reach max_crc_failures
+    /// with the threshold policy, an error is returned instead such that the serial device is reopened
+    async fn crc_failed(&mut self, p1mon_state: &mut P1MonState) -> Result<()> {
+        let stats = &mut p1mon_state.stats;
+        stats.consecutive_crc_failures += 1;
+        if stats.consecutive_crc_failures == u64::from(self.options.max_crc_failures) {
+            let msg = format!(
+                "{} consecutive CRC failures",
+                stats.consecutive_crc_failures
+            );
+            log::warn!("{msg} on {}", self.name);
+            if self.options.crc_policy == CrcPolicy::Threshold {
+                stats.consecutive_crc_failures = 0;
+                return Err(YgwError::DecodeError(msg));
+            }
+            p1mon_state.link_status.state_failed(msg);
+            p1mon_state.link_failed = true;
+            p1mon_state.send_link_status().await?;
+        }
+        Ok(())
+    }
+
+    /// returns the telegram timestamp t, or None if it is further ahead or behind the host time now than allowed,
+    /// in which case it is counted and reported by an event
+    async fn check_timestamp(
+        &self,
+        p1mon_state: &mut P1MonState,
+        t: Timestamp,
+        now: &Timestamp,
+    ) -> Option<Timestamp> {
+        let offset = timestamp_to_unix(&t) - timestamp_to_unix(now);
+        let (limit, direction) = if offset > 0 {
+            (self.options.max_time_ahead, "ahead of")
+        } else {
+            (self.options.max_time_behind, "behind")
+        };
+        let offset = Duration::from_millis(offset.unsigned_abs());
+        if limit.is_none_or(|limit| offset <= limit) {
+            return Some(t);
+        }
+        p1mon_state.stats.timestamps_rejected += 1;
+        let message = format!(
+            "The meter time is {}s {direction} the host time, using the host time",
+            offset.as_secs()
+        );
+        log::warn!("{}: {message}", self.name);
+        let t = utc_converter::to_string(utc_converter::Instant::from(t));
+        self.send_event(p1mon_state, Category::TimestampRejected, &message, &t)
+            .await;
+        None
+    }
+
+    /// sends a warning event about the rejected data, unless an event of the same category was sent recently
+    async fn send_event(
+        &self,
+        p1mon_state: &mut P1MonState,
+        category: Category,
+        message: &str,
+        data: &str,
+    ) {
+        let Some(suppressed) = p1mon_state.events.check(category, Instant::now()) else {
+            return;
+        };
+        let event = events::event(
+            &self.name,
+            category,
+            message,
+            data,
+            suppressed,
+            ygw::protobuf::now(),
+        );
+        let _ = p1mon_state
+            .tx
+            .send(YgwMessage::Event(p1mon_state.addr, event))
+            .await;
+    }
+
+    /// returns true while the telegrams are discarded waiting for the host clock to be synchronized,
+    /// the link being reported as failed until then
+    async fn waiting_time_sync(&self, p1mon_state: &mut P1MonState) -> Result<bool> {
+        if self.options.time_source != TimeSource::HostSynced {
+            return Ok(false);
+        }
+        if clock_synced(&ygw::protobuf::now()) {
+            if p1mon_state.waiting_time_sync {
+                log::info!(
+                    "Host clock synchronized, publishing the values of {}",
+                    self.name
+                );
+                p1mon_state.waiting_time_sync = false;
+            }
+            return Ok(false);
+        }
+        if !p1mon_state.waiting_time_sync {
+            log::warn!(
+                "Host clock not synchronized, discarding the telegrams of {} until it is",
+                self.name
+            );
+            p1mon_state.waiting_time_sync = true;
+            p1mon_state
+                .link_status
+                .state_failed("waiting for time sync".to_owned());
+            p1mon_state.link_failed = true;
+            p1mon_state.send_link_status().await?;
+        }
+        Ok(true)
+    }
+
+    /// resets the CRC failure count, the link is reported as ok if it was failed or still connecting
+    async fn crc_ok(&mut self, p1mon_state: &mut P1MonState) -> Result<()> {
+        if p1mon_state.link_failed || p1mon_state.connecting {
+            p1mon_state.link_status.state_ok();
+            p1mon_state.link_failed = false;
+            p1mon_state.connecting = false;
+            p1mon_state.send_link_status().await?;
+        }
+        p1mon_state.stats.consecutive_crc_failures = 0;
+        Ok(())
+    }
+
+    /// processes the telegram string into parameter values and sends them to Yamcs
+    /// together with the parameter definitions for the parameters seen for the first time
+    /// the meter identification from the header is published only when it changes
+    async fn process_p1telegram(
+        &mut self,
+        p1mon_state: &mut P1MonState,
+        header: Option<&str>,
+        p1t: &[u8],
+    ) {
+        let now = ygw::protobuf::now();
+
+        log::debug!("Processing telegram {}", String::from_utf8_lossy(p1t));
+        let (mut pdefs, mut pvalues, gentime) = decode_p1telegram(
+            &mut self.obis_codes,
+            p1t,
+            self.options.discovery,
+            self.options.timezone,
+            &mut p1mon_state.stats,
+        );
+        if let Some(line) = p1mon_state.stats.last_rejected_line.take() {
+            self.send_event(
+                p1mon_state,
+                Category::ParseError,
+                "Cannot parse the line",
+                &line,
+            )
+            .await;
+        }
+
+        if let Some(meter_id) = header.filter(|h| self.meter_id.as_deref() != Some(*h)) {
+            match parse_meter_id(meter_id) {
+                Some((manufacturer, model)) => log::info!(
+                    "Meter {meter_id} (manufacturer {manufacturer}, model {model}) connected to {}",
+                    self.name
+                ),
+                None => log::info!("Meter {meter_id} connected to {}", self.name),
+            }
+            if !self.options.allowlist {
+                decode_header(&mut self.obis_codes, meter_id, &mut pdefs, &mut pvalues);
+            }
+            self.meter_id = Some(meter_id.to_owned());
+        }
+
+        // the offset of the meter clock is computed before the sanity checks on the timestamp
+        let clock_offset = gentime
+            .as_ref()
+            .map(|t| timestamp_to_unix(t) - timestamp_to_unix(&now));
+        let gentime = match gentime {
+            Some(t) => self.check_timestamp(p1mon_state, t, &now).await,
+            None => None,
+        };
+        let t = timestamp_to_unix(gentime.as_ref().unwrap_or(&now));
+        let derived_values = compute_derived(&mut self.obis_codes, &pvalues, t, &mut pdefs);
+        pvalues.extend(derived_values);
+        let range_values = check_ranges(&mut self.obis_codes, &pvalues, &mut pdefs);
+        pvalues.extend(range_values);
+        let (rollover_values, rollovers) =
+            check_rollovers(&mut self.obis_codes, &pvalues, &mut pdefs);
+        pvalues.extend(rollover_values);
+        let suspect_values = check_monotonic(&mut self.obis_codes, &pvalues, &mut pdefs);
+        pvalues.extend(suspect_values);
+
+        if !pdefs.is_empty() {
+            log::debug!("Sending definitions {:?}", pdefs);
+            let pdef_list = ParameterDefinitionList { definitions: pdefs };
+            let _ = p1mon_state
+                .tx
+                .send(YgwMessage::ParameterDefinitions(
+                    p1mon_state.addr,
+                    pdef_list,
+                ))
+                .await;
+        }
+
+        self.update_mbus_links(p1mon_state, &pvalues).await;
+        self.check_power_quality(p1mon_state, &pvalues).await;
+        for (name, previous, x) in rollovers {
+            log::warn!("{}: rollover of {name} from {previous} to {x}", self.name);
+            self.send_event(
+                p1mon_state,
+                Category::Rollover,
+                &format!("Rollover of {name}"),
+                &format!("{previous} -> {x}"),
+            )
+            .await;
+        }
+
+        let acquisition_time = match (&gentime, self.options.time_source) {
+            (Some(t), TimeSource::Meter) => t.clone(),
+            _ => now.clone(),
+        };
+        let generation_time = gentime.or(Some(now.clone()));
+        let expire_millis = self
+            .default_expiry(&p1mon_state.stats)
+            .map(|expiry| expiry.as_millis() as i64);
+        // the values read at their own time (e.g. the M-Bus values) keep it, the others get the time of the telegram
+        for pv in pvalues.iter_mut() {
+            if pv.generation_time.is_none() {
+                pv.generation_time = generation_time.clone();
+            }
+            pv.acquisition_time = Some(acquisition_time.clone());
+            if pv.expire_millis.is_none() {
+                pv.expire_millis = expire_millis;
+            }
+        }
+        for pv in &pvalues {
+            p1mon_state.last_values.insert(pv.id, pv.clone());
+        }
+
+        let pvalues = filter_unchanged(
+            &mut self.obis_codes,
+            pvalues,
+            Instant::now(),
+            self.options.max_silence,
+        );
+        self.throttle.add(pvalues, generation_time.as_ref());
+        if let Some(pvalues) = self.throttle.take(Instant::now()) {
+            self.publish_values(p1mon_state, pvalues, generation_time, acquisition_time)
+                .await;
+        }
+        if let Some(offset) = clock_offset {
+            self.publish_clock_offset(p1mon_state, offset, now).await;
+        }
+    }
+
+    /// publishes again the latest value of every parameter, whether it changed or not,
+    /// with the time of the most recent one as generation time
+    async fn publish_latest(&self, p1mon_state: &mut P1MonState) {
+        if p1mon_state.paused_seen {
+            log::info!(
+                "Acquisition of {} paused, not publishing the latest values",
+                self.name
+            );
+            return;
+        }
+        let pvalues = p1mon_state.latest_values();
+        if pvalues.is_empty() {
+            return;
+        }
+        let generation_time = pvalues
+            .iter()
+            .filter_map(|pv| pv.generation_time.clone())
+            .max_by_key(|t| (t.millis, t.picos));
+        log::info!(
+            "Publishing the latest {} values of {}",
+            pvalues.len(),
+            self.name
+        );
+        self.publish_values(p1mon_state, pvalues, generation_time, ygw::protobuf::now())
+            .await;
+    }
+
+    /// sends an event for each voltage sag or swell counter which increased since the previous telegram
+    /// the counter is the value of the code itself or, if the meter sends an event log, the count of the log
+    async fn check_power_quality(
+        &mut self,
+        p1mon_state: &mut P1MonState,
+        pvalues: &[ParameterValue],
+    ) {
+        if !self.options.power_quality_events {
+            return;
+        }
+        for &(code, entry, phase) in POWER_QUALITY_CODES {
+            let count = [code.to_owned(), format!("{code}#count")]
+                .iter()
+                .filter_map(|key| self.obis_codes.get(key))
+                .find_map(|p| pvalues.iter().find(|pv| pv.id == p.pid))
+                .and_then(|pv| match pv.eng_value.as_ref()?.v {
+                    Some(ygw::protobuf::ygw::value::V::Sint64Value(n)) => Some(n),
+                    _ => None,
+                });
+            let Some(count) = count else {
+                continue;
+            };
+            let previous = self.power_quality_counts.insert(code, count);
+            if let Some(previous) = previous.filter(|&n| count > n) {
+                self.send_event(
+                    p1mon_state,
+                    Category::PowerQuality,
+                    &format!("{entry} detected on {phase}"),
+                    &format!("{code} count {previous} -> {count}"),
+                )
+                .await;
+            }
+        }
+    }
+
+    /// returns the M-Bus channel of the parameters of the channels reported as sub-links, by parameter id
+    fn mbus_channels(&self) -> HashMap<u32, &str> {
+        self.obis_codes
+            .iter()
+            .filter_map(|(code, p)| {
+                let channel = mbus::channel(code)?;
+                self.mbus_links
+                    .iter()
+                    .any(|(c, _)| c == channel)
+                    .then_some((p.pid, channel))
+            })
+            .collect()
+    }
+
+    /// updates the state of the M-Bus sub-links from the time of the readings in the values,
+    /// sending the status of the links whose state changed
+    async fn update_mbus_links(&self, p1mon_state: &mut P1MonState, pvalues: &[ParameterValue]) {
+        if p1mon_state.mbus_links.is_empty() {
+            return;
+        }
+        let channels = self.mbus_channels();
+        let now = Instant::now();
+        for link in p1mon_state.mbus_links.iter_mut() {
+            let reading = pvalues
+                .iter()
+                .filter(|pv| channels.get(&pv.id) == Some(&link.channel.as_str()))
+                .filter_map(|pv| pv.generation_time.as_ref())
+                .max_by_key(|t| (t.millis, t.picos));
+            if link.update(reading, now) {
+                log::info!(
+                    "M-Bus channel {} of {} {}",
+                    link.channel,
+                    self.name,
+                    if link.status(None).err.is_some() {
+                        "failed"
+                    } else {
+                        "ok"
+                    }
+                );
+                let ls = link.status(None);
+                let _ = p1mon_state
+                    .tx
+                    .send(YgwMessage::LinkStatus(link.addr, ls))
+                    .await;
+            }
+        }
+    }
+
+    /// publishes the offset of the meter clock in the status group, at each telegram
+    async fn publish_clock_offset(
+        &self,
+        p1mon_state: &mut P1MonState,
+        offset_ms: i64,
+        now: Timestamp,
+    ) {
+        let pdata = ParameterData {
+            parameters: vec![Stats::clock_offset_value(offset_ms)],
+            group: STATUS_GROUP.to_owned(),
+            seq_num: self.next_seq_num(p1mon_state, STATUS_GROUP),
+            generation_time: Some(now.clone()),
+            acquisition_time: Some(now),
+        };
+        let _ = p1mon_state
+            .tx
+            .send(YgwMessage::ParameterData(p1mon_state.addr, pdata))
+            .await;
+    }
+
+    /// returns the group of the values of the M-Bus channels without group, None if they are not split
+    fn mbus_group(&self) -> Option<String> {
+        self.options
+            .mbus_group
+            .then(|| format!("{}_mbus", self.parameter_group))
+    }
+
+    /// sends the values to Yamcs (and to the MQTT sink), one message per parameter group
+    /// the M-Bus group has the time of the latest reading as generation time
+    async fn publish_values(
+        &self,
+        p1mon_state: &mut P1MonState,
+        pvalues: Vec<ParameterValue>,
+        generation_time: Option<Timestamp>,
+        acquisition_time: Timestamp,
+    ) {
+        if let Some(mqtt) = &self.options.mqtt {
+            mqtt.lock()
+                .unwrap()
+                .publish(self.mqtt_source.as_deref(), &pvalues, &self.names());
+        }
+        #[cfg(feature = "metrics")]
+        if let Some(metrics) = &self.options.metrics {
+            let names = self.names();
+            metrics.update_values(
+                &self.name,
+                pvalues.iter().filter_map(|pv| {
+                    Some((*names.get(&pv.id)?, numeric_value(pv.eng_value.as_ref()?)?))
+                }),
+            );
+        }
+
+        // the values of the M-Bus channels reported as sub-links are sent on their link
+        let channels = self.mbus_channels();
+        let mut by_link: Vec<(Addr, Vec<ParameterValue>)> = vec![(p1mon_state.addr, Vec::new())];
+        for pv in pvalues {
+            let addr = channels
+                .get(&pv.id)
+                .and_then(|c| p1mon_state.mbus_links.iter().find(|l| l.channel == *c))
+                .map_or(p1mon_state.addr, |l| l.addr);
+            match by_link.iter_mut().find(|(a, _)| *a == addr) {
+                Some((_, values)) => values.push(pv),
+                None => by_link.push((addr, vec![pv])),
+            }
+        }
+
+        // one message per group, each group has its own sequence count
+        let mbus_group = self.mbus_group();
+        for (addr, pvalues) in by_link {
+            for (group, parameters) in group_values(
+                &self.obis_codes,
+                pvalues,
+                &self.parameter_group,
+                mbus_group.as_deref(),
+            ) {
+                let generation_time = if mbus_group.as_ref() == Some(&group) {
+                    parameters
+                        .iter()
+                        .filter_map(|pv| pv.generation_time.clone())
+                        .max_by_key(|t| (t.millis, t.picos))
+                        .or(generation_time.clone())
+                } else {
+                    generation_time.clone()
+                };
+                let pdata = ParameterData {
+                    parameters,
+                    seq_num: self.next_seq_num(p1mon_state, &group),
+                    group,
+                    generation_time,
+                    acquisition_time: Some(acquisition_time.clone()),
+                };
+
+                log::debug!("Sending parameter values {:?}", pdata);
+                let _ = p1mon_state
+                    .tx
+                    .send(YgwMessage::ParameterData(addr, pdata))
+                    .await;
+            }
+        }
+    }
+}
+
+/// computes the values of the derived parameters from the values decoded from one telegram generated at the time t
+/// (in milliseconds since the UNIX epoch); the definitions of the derived parameters are added to pdefs the first time
+fn compute_derived(
+    obis_codes: &mut ObisCodes,
+    pvalues: &[ParameterValue],
+    t: i64,
+    pdefs: &mut Vec<ParameterDefinition>,
+) -> Vec<ParameterValue> {
+    let values: HashMap<u32, f64> = pvalues
+        .iter()
+        .filter_map(|pv| Some((pv.id, numeric_value(pv.eng_value.as_ref()?)?)))
+        .collect();
+    // the values and the unit of the sources of each derived parameter
+    let inputs: Vec<(String, Vec<Option<f64>>, Option<String>)> = obis_codes
+        .iter()
+        .filter_map(|(code, p)| {
+            let sources = p.derivation.as_ref()?.sources();
+            let params: Vec<Option<&DmsrParam>> =
+                sources.iter().map(|s| obis_codes.get(*s)).collect();
+            let unit = params.iter().flatten().find_map(|s| s.unit.clone());
+            let x = params
+                .iter()
+                .map(|s| s.and_then(|s| values.get(&s.pid).copied()))
+                .collect();
+            Some((code.clone(), x, unit))
+        })
+        .collect();
+
+    let mut result = Vec::new();
+    for (code, x, unit) in inputs {
+        let dmsr_param = obis_codes.get_mut(&code).unwrap();
+        let Some((ty, y)) = dmsr_param.derivation.as_mut().unwrap().compute(t, &x) else {
+            continue;
+        };
+        if !dmsr_param.defined {
+            pdefs.push(get_pdef(dmsr_param, unit.as_deref()));
+            dmsr_param.defined = true;
+        }
+        if let Some(mut pv) = logged(get_pvalue(
+            &code,
+            dmsr_param,
+            &y.to_string(),
+            unit.as_deref(),
+        )) {
+            // the values not computed at the time of the telegram (e.g. aggregates) have their own generation time
+            if ty != t {
+                pv.generation_time = Some(unix_to_timestamp(ty));
+            }
+            result.push(pv);
+        }
+    }
+    result.sort_by_key(|pv| pv.id);
+    result
+}
+
+fn numeric_value(value: &Value) -> Option<f64> {
+    use ygw::protobuf::ygw::value::V;
+    match value.v.as_ref()? {
+        V::FloatValue(x) => Some(*x as f64),
+        V::DoubleValue(x) => Some(*x),
+        V::Sint64Value(x) => Some(*x as f64),
+        _ => None,
+    }
+}
+
+/// removes the values which did not change since they were last sent, unless they were sent more than max_silence ago
+fn filter_unchanged(
+    obis_codes: &mut ObisCodes,
+    pvalues: Vec<ParameterValue>,
+    now: Instant,
+    max_silence: Duration,
+) -> Vec<ParameterValue> {
+    if max_silence.is_zero() {
+        return pvalues;
+    }
+    let mut params: HashMap<u32, &mut DmsrParam> =
+        obis_codes.values_mut().map(|p| (p.pid, p)).collect();
+
+    pvalues
+        .into_iter()
+        .filter(|pv| {
+            let (Some(dmsr_param), Some(value)) = (params.get_mut(&pv.id), &pv.eng_value) else {
+                return true;
+            };
+            if let Some((last, t)) = &dmsr_param.last_sent {
+                if now.duration_since(*t) < max_silence
+                    && same_value(last, value, dmsr_param.deadband.unwrap_or(0.0))
+                {
+                    return false;
+                }
+            }
+            dmsr_param.last_sent = Some((value.clone(), now));
+            true
+        })
+        .collect()
+}
+
+/// returns true if the two values are equal or, if they are numeric, differ by at most deadband
+fn same_value(a: &Value, b: &Value, deadband: f64) -> bool {
+    use ygw::protobuf::ygw::value::V;
+    match (&a.v, &b.v) {
+        (Some(V::FloatValue(x)), Some(V::FloatValue(y))) => (x - y).abs() as f64 <= deadband,
+        (Some(V::DoubleValue(x)), Some(V::DoubleValue(y))) => (x - y).abs() <= deadband,
+        _ => a == b,
+    }
+}
+
+/// splits the values by the group of their parameter, default_group being used for the parameters without group
+/// except for those of the M-Bus channels (e.g. 0-1:24.2.1) which are in mbus_group if given
+/// the groups are returned in the order of their first value
+fn group_values(
+    obis_codes: &HashMap<String, DmsrParam>,
+    pvalues: Vec<ParameterValue>,
+    default_group: &str,
+    mbus_group: Option<&str>,
+) -> Vec<(String, Vec<ParameterValue>)> {
+    let groups: HashMap<u32, &str> = obis_codes
+        .iter()
+        .filter_map(|(code, p)| {
+            let group = match p.group.as_deref() {
+                Some(group) => group,
+                None => mbus_group.filter(|_| mbus::channel(code).is_some())?,
+            };
+            Some((p.pid, group))
+        })
+        .collect();
+
+    let mut result: Vec<(String, Vec<ParameterValue>)> = Vec::new();
+    for pv in pvalues {
+        let group = groups.get(&pv.id).copied().unwrap_or(default_group);
+        match result.iter_mut().find(|(g, _)| g == group) {
+            Some((_, values)) => values.push(pv),
+            None => result.push((group.to_owned(), vec![pv])),
+        }
+    }
+    result
+}
+
+/// decodes the telegram into parameter values
+/// the values read at another time than the telegram (e.g. the M-Bus values) have their own generation time
+/// returns parameter values as well as parameter definitions for those parameters for which no definition was generated previously
+/// once the definition has been generated, the DmsrParam.defined is set to true
+/// if discovery is true, a string parameter is created for each code without definition
+/// the device types of the M-Bus channels are recorded such that the wildcard definitions can be named after them
+/// the timestamps are given by the meter in the local time of the timezone tz
+fn decode_p1telegram(
+    obis_codes: &mut ObisCodes,
+    p1t: &[u8],
+    discovery: bool,
+    tz: Tz,
+    stats: &mut Stats,
+) -> (
+    Vec<ParameterDefinition>,
+    Vec<ParameterValue>,
+    Option<Timestamp>,
+) {
+    let mut pdefs = Vec::new();
+    let mut pvalues = Vec::new();
+    let mut gentime = None;
+    let mut devices = Devices::default();
+
+    for line in p1t.split(|&b| b == b'\n') {
+        // a byte which is not valid UTF-8 only affects the line containing it
+        let line = String::from_utf8_lossy(line);
+        let line = line.trim_end_matches('\r');
+        if line.is_empty() {
+            continue;
+        }
+        let Some(v) = counted(split_p1_line(line), stats) else {
+            stats.last_rejected_line = Some(line.to_owned());
+            continue;
+        };
+
+        devices.record(v[0], v[1]);
+        if !obis_codes.contains_key(v[0]) {
+            expand_wildcard(obis_codes, v[0], &devices);
+            if discovery && !obis_codes.contains_key(v[0]) {
+                discover_code(obis_codes, v[0]);
+            }
+        }
+
+        if let Some(dmsr_param) = obis_codes.get_mut(v[0]) {
+            if dmsr_param.name == "ignore" {
+                continue;
+            }
+            // the groups named in the definition take precedence over the decoding of the logs
+            if let Some(names) = dmsr_param.group_names.clone() {
+                decode_groups(obis_codes, v[0], &v[1..], &names, &mut pdefs, &mut pvalues);
+                continue;
+            }
+            // the power quality counters are sent as logs by some meters, with more than one group
+            let log_entry = if POWER_FAILURE_LOGS.contains(&v[0]) {
+                Some("failure")
+            } else {
+                POWER_QUALITY_CODES
+                    .iter()
+                    .find(|(code, ..)| *code == v[0] && v.len() > 2)
+                    .map(|(_, entry, _)| *entry)
+            };
+            if let Some(entry) = log_entry {
+                decode_event_log(
+                    obis_codes,
+                    v[0],
+                    &v[1..],
+                    entry,
+                    tz,
+                    &mut pdefs,
+                    &mut pvalues,
+                );
+                continue;
+            }
+            if PEAK_LISTS.contains(&v[0]) {
+                decode_peak_list(obis_codes, v[0], &v[1..], tz, &mut pdefs, &mut pvalues);
+                continue;
+            }
+
+            // the M-Bus values (e.g. gas) are preceded by the time at which they were read: code(time)(value*unit)
+            let (value_time, value) = match (dmsr_param.value_group, dmsr_param.time_group, &v[1..])
+            {
+                (None, None, &[time, value]) if is_dsmr_timestamp(time) => {
+                    (counted(parse_timestamp(time, tz), stats), value)
+                }
+                (None, None, _) => (None, v[1]),
+                (value_group, time_group, groups) => {
+                    let value_group = value_group.unwrap_or(1);
+                    let Some(&value) = groups.get(value_group - 1) else {
+                        log::warn!(
+                            "No group {value_group} for the value of {} in the line {line}",
+                            dmsr_param.name
+                        );
+                        continue;
+                    };
+                    let time = match time_group {
+                        Some(i) => match groups.get(i - 1) {
+                            Some(time) => counted(parse_timestamp(time, tz), stats),
+                            None => {
+                                log::warn!(
+                                    "No group {i} for the time of {} in the line {line}",
+                                    dmsr_param.name
+                                );
+                                None
+                            }
+                        },
+                        None => None,
+                    };
+                    (time, value)
+                }
+            };
+            let a: Vec<&str> = value.split('*').collect();
+            let unit: Option<&str> = a.get(1).copied();
+
+            // the unit of the definition takes precedence over the one of the telegram
+            warn_unit_mismatch(v[0], dmsr_param, unit, Instant::now());
+            if !dmsr_param.defined {
+                pdefs.push(get_pdef(dmsr_param, unit));
+                dmsr_param.defined = true;
+            }
+            if dmsr_param.name == "timestamp" {
+                gentime = counted(parse_timestamp(a[0], tz), stats);
+            } else if TEXT_MESSAGE_CODES.contains(&v[0])
+                && dmsr_param.ptype == DmsrParamType::String
+            {
+                let pvalue = get_pvalue(v[0], dmsr_param, &decode_hex_text(v[1]), None);
+                if let Some(pvalue) = counted(pvalue, stats) {
+                    pvalues.push(pvalue);
+                }
+            } else if let Some(mut pvalue) =
+                counted(get_pvalue(v[0], dmsr_param, a[0], unit), stats)
+            {
+                pvalue.generation_time = value_time;
+                pvalues.push(pvalue);
+            }
+        } else {
+            // logged once, the meter sending the code with each telegram
+            let e = P1ParseError::UnknownCode(v[0].to_owned());
+            if stats.skipped_codes.insert(v[0].to_owned()) {
+                log::info!("{e}, the value is skipped");
+            }
+            stats.record_error(&e);
+        }
+    }
+
+    (pdefs, pvalues, gentime)
+}
+
+/// verifies the CRC computed with the algorithm following the ! found at index bang of the telegram
+/// as specified by DSMR, the CRC covers the bytes from the / up to and including the !, with the line endings
+/// (\r\n) as sent by the meter; the CRC itself and the \r\n following it are not included
+fn check_crc(
+    p1t: &[u8],
+    bang: usize,
+    algorithm: CrcAlgorithm,
+) -> std::result::Result<(), P1ParseError> {
+    let Some(computed_crc) = algorithm.compute(&p1t[..=bang]) else {
+        return Ok(());
+    };
+    let hex = p1t.get(bang + 1..bang + 5).ok_or_else(|| {
+        P1ParseError::BadCrc(format!(
+            "Invalid line {}",
+            String::from_utf8_lossy(&p1t[bang..])
+        ))
+    })?;
+    let crc = str::from_utf8(hex)
+        .ok()
+        .and_then(|h| u16::from_str_radix(h, 16).ok())
+        .ok_or_else(|| {
+            P1ParseError::BadCrc(format!(
+                "Cannot parse hex crc {}",
+                String::from_utf8_lossy(hex)
+            ))
+        })?;
+    if crc != computed_crc {
+        return Err(P1ParseError::BadCrc(format!(
+            "CRC verification failed: received {crc:04X}, computed {computed_crc:04X}"
+        )));
+    }
+    Ok(())
+}
+
+/// returns the meter identification from the header line of the telegram, e.g. ISK5\2M550T-1012 for /ISK5\2M550T-1012
+fn parse_header(line: &str) -> Option<&str> {
+    line.trim()
+        .strip_prefix('/')
+        .map(str::trim)
+        .filter(|s| !s.is_empty())
+}
+
+/// splits the meter identification into the manufacturer and the model, e.g. ISK and M550T-1012 for ISK5\2M550T-1012
+/// the identification starts with the three letters of the manufacturer and the baud rate character, followed
+/// in DSMR 5 by \ and one more character before the model
+/// returns None if the identification does not have this format
+fn parse_meter_id(meter_id: &str) -> Option<(&str, &str)> {
+    let manufacturer = meter_id.get(..3)?;
+    let rest = meter_id.get(4..)?;
+    let model = match rest.strip_prefix('\\') {
+        Some(rest) => rest.get(1..)?,
+        None => rest,
+    }
+    .trim();
+    (manufacturer.bytes().all(|b| b.is_ascii_alphabetic()) && !model.is_empty())
+        .then_some((manufacturer, model))
+}
+
+/// publishes the meter identification as the meter_id string parameter and, if it has the usual format,
+/// its manufacturer and model as the meter_manufacturer and meter_model parameters
+/// (the link status cannot carry them, its only text being the reason of a failure)
+fn decode_header(
+    obis_codes: &mut ObisCodes,
+    meter_id: &str,
+    pdefs: &mut Vec<ParameterDefinition>,
+    pvalues: &mut Vec<ParameterValue>,
+) {
+    let values = match parse_meter_id(meter_id) {
+        Some((manufacturer, model)) => vec![meter_id, manufacturer, model],
+        None => vec![meter_id],
+    };
+    for (&(key, name, description), value) in HEADER_PARAMS.iter().zip(values) {
+        if !obis_codes.contains_key(key) {
+            let dmsr_param = DmsrParam::new(
+                name.to_owned(),
+                DmsrParamType::String,
+                description.to_owned(),
+                obis_codes.next_pid(),
+                ParamOrigin::Header,
+            );
+            obis_codes.insert(key.to_owned(), dmsr_param);
+        }
+        let dmsr_param = obis_codes.get_mut(key).unwrap();
+        if !dmsr_param.defined {
+            pdefs.push(get_pdef(dmsr_param, None));
+            dmsr_param.defined = true;
+        }
+        if let Some(pvalue) = logged(get_pvalue(key, dmsr_param, value, None)) {
+            pvalues.push(pvalue);
+        }
+    }
+}
+
+/// if the code matches a wildcard definition, creates a new parameter for it
+/// the name of the parameter is obtained by replacing {1}, {2}... in the wildcard name with the matched digits
+/// and {device} in the name and the group with the type of the M-Bus device
+fn expand_wildcard(obis_codes: &mut ObisCodes, code: &str, devices: &Devices) {
+    let Some((pattern, captures)) = obis_codes
+        .keys()
+        .filter(|k| wildcard::is_wildcard(k))
+        .find_map(|k| wildcard::matches(k, code).map(|c| (k.clone(), c)))
+    else {
+        return;
+    };
+
+    let pid = obis_codes.next_pid();
+    let template = &obis_codes[&pattern];
+    let mut name = template.name.clone();
+    for (i, c) in captures.iter().enumerate() {
+        name = name.replace(&format!("{{{}}}", i + 1), c);
+    }
+    let device = devices.device_name(code);
+    let name = name.replace(mbus::DEVICE_PLACEHOLDER, device);
+    let group = template
+        .group
+        .as_ref()
+        .map(|g| g.replace(mbus::DEVICE_PLACEHOLDER, device));
+    let dmsr_param = DmsrParam {
+        name,
+        group,
+        defined: false,
+        pid,
+        origin: ParamOrigin::Derived(pattern),
+        ..template.clone()
+    };
+    log::debug!("Created parameter {} for code {code}", dmsr_param.name);
+    obis_codes.insert(code.to_owned(), dmsr_param);
+}
+
+/// decodes an event log (power failures, voltage sags...) into the number of entries, named entry in the
+/// descriptions, and the time and duration of the most recent one
+/// the three values are published as parameters named after the parameter defined for the log code
+fn decode_event_log(
+    obis_codes: &mut ObisCodes,
+    code: &str,
+    groups: &[&str],
+    entry: &str,
+    tz: Tz,
+    pdefs: &mut Vec<ParameterDefinition>,
+    pvalues: &mut Vec<ParameterValue>,
+) {
+    let Some(log) = eventlog::parse_event_log(groups) else {
+        log::warn!("Cannot parse the {entry} log {:?}", groups);
+        return;
+    };
+    log::debug!("Event log {} with {} entries", log.code, log.count);
+
+    let mut values = vec![(
+        "count".to_owned(),
+        DmsrParamType::Integer,
+        format!("number of {entry}s"),
+        log.count.to_string(),
+        None,
+    )];
+    if let Some(last) = log.last() {
+        if let Some(t) = get_timestamp(last.timestamp, tz) {
+            values.push((
+                "last_time".to_owned(),
+                DmsrParamType::String,
+                format!("end time of the most recent {entry}"),
+                utc_converter::to_string(utc_converter::Instant::from(t)),
+                None,
+            ));
+        }
+        values.push((
+            "last_duration".to_owned(),
+            DmsrParamType::Integer,
+            format!("duration of the most recent {entry}"),
+            last.value.to_owned(),
+            last.unit,
+        ));
+    }
+    publish_components(obis_codes, code, values, pdefs, pvalues);
+}
+
+/// decodes a list of monthly peaks into the number of peaks and, for each peak i from 1 (the oldest),
+/// the peak value, its time and the end of its month, published as the parameters name_i, name_i_time
+/// and name_i_period named after the parameter defined for the list code
+fn decode_peak_list(
+    obis_codes: &mut ObisCodes,
+    code: &str,
+    groups: &[&str],
+    tz: Tz,
+    pdefs: &mut Vec<ParameterDefinition>,
+    pvalues: &mut Vec<ParameterValue>,
+) {
+    let Some(list) = eventlog::parse_peak_list(groups) else {
+        log::warn!("Cannot parse the peak list {:?}", groups);
+        return;
+    };
+    let ptype = obis_codes[code].ptype.clone();
+    let time =
+        |t| get_timestamp(t, tz).map(|t| utc_converter::to_string(utc_converter::Instant::from(t)));
+
+    let mut values = vec![(
+        "count".to_owned(),
+        DmsrParamType::Integer,
+        "number of peaks".to_owned(),
+        list.count.to_string(),
+        None,
+    )];
+    for (i, peak) in list.peaks.iter().enumerate() {
+        let i = i + 1;
+        values.push((
+            i.to_string(),
+            ptype.clone(),
+            format!("peak {i}"),
+            peak.value.to_owned(),
+            peak.unit,
+        ));
+        if let Some(t) = time(peak.timestamp) {
+            values.push((
+                format!("{i}_time"),
+                DmsrParamType::String,
+                format!("time of the peak {i}"),
+                t,
+                None,
+            ));
+        }
+        if let Some(t) = time(peak.period) {
+            values.push((
+                format!("{i}_period"),
+                DmsrParamType::String,
+                format!("end of the month of the peak {i}"),
+                t,
+                None,
+            ));
+        }
+    }
+    publish_components(obis_codes, code, values, pdefs, pvalues);
+}
+
+/// decodes each group of the line of the code into a parameter of the type of the parameter defined for the code,
+/// named after it and the name of the group; the groups missing from the line are skipped
+fn decode_groups(
+    obis_codes: &mut ObisCodes,
+    code: &str,
+    groups: &[&str],
+    names: &[String],
+    pdefs: &mut Vec<ParameterDefinition>,
+    pvalues: &mut Vec<ParameterValue>,
+) {
+    if groups.len() < names.len() {
+        log::debug!(
+            "Only {} of the {} groups of {code} in the telegram",
+            groups.len(),
+            names.len()
+        );
+    }
+    let ptype = obis_codes[code].ptype.clone();
+    let values = names
+        .iter()
+        .zip(groups)
+        .enumerate()
+        .map(|(i, (name, group))| {
+            let (value, unit) = match group.split_once('*') {
+                Some((value, unit)) => (value, Some(unit)),
+                None => (*group, None),
+            };
+            (
+                name.clone(),
+                ptype.clone(),
+                format!("group {}", i + 1),
+                value.to_owned(),
+                unit,
+            )
+        })
+        .collect();
+    publish_components(obis_codes, code, values, pdefs, pvalues);
+}
+
+/// publishes the values (suffix, type, description, value, unit) decoded from the line of the code
+/// as parameters named after the parameter defined for the code, created the first time
+fn publish_components(
+    obis_codes: &mut ObisCodes,
+    code: &str,
+    values: Vec<(String, DmsrParamType, String, String, Option<&str>)>,
+    pdefs: &mut Vec<ParameterDefinition>,
+    pvalues: &mut Vec<ParameterValue>,
+) {
+    for (suffix, ptype, description, value, unit) in values {
+        let key = format!("{code}#{suffix}");
+        if !obis_codes.contains_key(&key) {
+            add_component_param(obis_codes, code, &key, &suffix, ptype, &description);
+        }
+        let dmsr_param = obis_codes.get_mut(&key).unwrap();
+        if !dmsr_param.defined {
+            pdefs.push(get_pdef(dmsr_param, unit));
+            dmsr_param.defined = true;
+        }
+        if let Some(pvalue) = logged(get_pvalue(&key, dmsr_param, &value, unit)) {
+            pvalues.push(pvalue);
+        }
+    }
+}
+
+/// logs a warning if the unit reported by the meter differs from the unit column of the definition,
+/// at most once per UNIT_WARNING_INTERVAL for each parameter; returns true if the warning was logged
+fn warn_unit_mismatch(
+    code: &str,
+    dmsr_param: &mut DmsrParam,
+    unit: Option<&str>,
+    now: Instant,
+) -> bool {
+    let (Some(from), Some(to)) = (unit, &dmsr_param.unit) else {
+        return false;
+    };
+    if from == to
+        || dmsr_param
+            .unit_warned
+            .is_some_and(|t| now.duration_since(t) < UNIT_WARNING_INTERVAL)
+    {
+        return false;
+    }
+    if units::conversion_factor(from, to).is_some() {
+        log::warn!(
+            "The meter reports {} ({code}) in {from} instead of {to}, the values are converted",
+            dmsr_param.name
+        );
+    } else {
+        log::warn!(
+            "The meter reports {} ({code}) in {from}, which cannot be converted into the unit {to} of its definition",
+            dmsr_param.name
+        );
+    }
+    dmsr_param.unit_warned = Some(now);
+    true
+}
+
+/// returns the range status (low, ok or high) of the values of the parameters having a minimum or a maximum,
+/// published as the string parameter `name_range` with the generation time of the value;
+/// the definitions of the status parameters are added to pdefs the first time
+fn check_ranges(
+    obis_codes: &mut ObisCodes,
+    pvalues: &[ParameterValue],
+    pdefs: &mut Vec<ParameterDefinition>,
+) -> Vec<ParameterValue> {
+    let checked: Vec<(String, &'static str, Option<Timestamp>)> = obis_codes
+        .iter()
+        .filter(|(_, p)| p.min.is_some() || p.max.is_some())
+        .filter_map(|(code, p)| {
+            let pv = pvalues.iter().find(|pv| pv.id == p.pid)?;
+            let x = numeric_value(pv.eng_value.as_ref()?)?;
+            let status = if p.min.is_some_and(|min| x < min) {
+                "low"
+            } else if p.max.is_some_and(|max| x > max) {
+                "high"
+            } else {
+                "ok"
+            };
+            Some((code.clone(), status, pv.generation_time.clone()))
+        })
+        .collect();
+
+    let mut result = Vec::new();
+    for (code, status, generation_time) in checked {
+        let key = format!("{code}#range");
+        if !obis_codes.contains_key(&key) {
+            add_component_param(
+                obis_codes,
+                &code,
+                &key,
+                "range",
+                DmsrParamType::String,
+                "range status: low, ok or high",
+            );
+        }
+        let dmsr_param = obis_codes.get_mut(&key).unwrap();
+        if !dmsr_param.defined {
+            pdefs.push(get_pdef(dmsr_param, None));
+            dmsr_param.defined = true;
+        }
+        if let Some(mut pv) = logged(get_pvalue(&key, dmsr_param, status, None)) {
+            pv.generation_time = generation_time;
+            result.push(pv);
+        }
+    }
+    result.sort_by_key(|pv| pv.id);
+    result
+}
+
+/// checks that the values of the cumulative registers did not decrease since the previous value, logging a warning
+/// otherwise; for those flagged, returns the integer parameter `name_suspect`, 1 if the value decreased and 0 if not,
+/// with the generation time of the value
+fn check_monotonic(
+    obis_codes: &mut ObisCodes,
+    pvalues: &[ParameterValue],
+    pdefs: &mut Vec<ParameterDefinition>,
+) -> Vec<ParameterValue> {
+    let mut flagged = Vec::new();
+    for (code, p) in obis_codes.iter_mut() {
+        let Some(monotonic) = p.monotonic else {
+            continue;
+        };
+        let Some(pv) = pvalues.iter().find(|pv| pv.id == p.pid) else {
+            continue;
+        };
+        let Some(x) = pv.eng_value.as_ref().and_then(numeric_value) else {
+            continue;
+        };
+        let decreased = p.last_cumulative.filter(|&last| x < last);
+        if let Some(last) = decreased {
+            log::warn!(
+                "The cumulative register {} ({code}) decreased from {last} to {x}",
+                p.name
+            );
+        }
+        p.last_cumulative = Some(x);
+        if monotonic == Monotonic::Flag {
+            let suspect = if decreased.is_some() { "1" } else { "0" };
+            flagged.push((code.clone(), suspect, pv.generation_time.clone()));
+        }
+    }
+
+    let mut result = Vec::new();
+    for (code, suspect, generation_time) in flagged {
+        let values = vec![(
+            "suspect".to_owned(),
+            DmsrParamType::Integer,
+            "1 if the value decreased since the previous one".to_owned(),
+            suspect.to_owned(),
+            None,
+        )];
+        let mut component_values = Vec::new();
+        publish_components(obis_codes, &code, values, pdefs, &mut component_values);
+        for mut pv in component_values {
+            pv.generation_time = generation_time.clone();
+            result.push(pv);
+        }
+    }
+    result.sort_by_key(|pv| pv.id);
+    result
+}
+
+/// checks the values of the parameters with a rollover threshold against their previous value
+/// returns the number of rollovers of each as the integer parameter `name_rollover_count` and, with a modulus,
+/// the value unwrapped as the double parameter `name_unwrapped`, with the generation time of the value,
+/// as well as the rollovers detected (name of the parameter, previous value and value)
+fn check_rollovers(
+    obis_codes: &mut ObisCodes,
+    pvalues: &[ParameterValue],
+    pdefs: &mut Vec<ParameterDefinition>,
+) -> (Vec<ParameterValue>, Vec<(String, f64, f64)>) {
+    let mut checked = Vec::new();
+    let mut rollovers = Vec::new();
+    for (code, p) in obis_codes.iter_mut() {
+        let Some(rollover) = p.rollover.as_mut() else {
+            continue;
+        };
+        let Some(pv) = pvalues.iter().find(|pv| pv.id == p.pid) else {
+            continue;
+        };
+        let Some(x) = pv.eng_value.as_ref().and_then(numeric_value) else {
+            continue;
+        };
+        match rollover.check(x) {
+            rollover::Check::Rollover(previous) => rollovers.push((p.name.clone(), previous, x)),
+            rollover::Check::Glitch(decrease) => {
+                log::debug!("Ignoring a decrease of {decrease} of {}", p.name)
+            }
+            rollover::Check::Normal => {}
+        }
+        checked.push((
+            code.clone(),
+            rollover.count(),
+            rollover.unwrapped(x),
+            p.unit.clone(),
+            pv.generation_time.clone(),
+        ));
+    }
+
+    let mut result = Vec::new();
+    for (code, count, unwrapped, unit, generation_time) in checked {
+        let mut values = vec![(
+            "rollover_count".to_owned(),
+            DmsrParamType::Integer,
+            "number of rollovers".to_owned(),
+            count.to_string(),
+            None,
+        )];
+        if let Some(x) = unwrapped {
+            values.push((
+                "unwrapped".to_owned(),
+                DmsrParamType::Double,
+                "unwrapped value".to_owned(),
+                x.to_string(),
+                unit.as_deref(),
+            ));
+        }
+        let mut component_values = Vec::new();
+        publish_components(obis_codes, &code, values, pdefs, &mut component_values);
+        for mut pv in component_values {
+            pv.generation_time = generation_time.clone();
+            result.push(pv);
+        }
+    }
+    result.sort_by_key(|pv| pv.id);
+    (result, rollovers)
+}
+
+/// creates the parameter holding one of the values decoded from a line with multiple values
+/// the parameter is stored under the key `code#suffix` and named `name_suffix` after the parameter defined for the code
+fn add_component_param(
+    obis_codes: &mut ObisCodes,
+    code: &str,
+    key: &str,
+    suffix: &str,
+    ptype: DmsrParamType,
+    description: &str,
+) {
+    let parent = &obis_codes[code];
+    let dmsr_param = DmsrParam {
+        group: parent.group.clone(),
+        expire_ms: parent.expire_ms,
+        ..DmsrParam::new(
+            format!("{}_{suffix}", parent.name),
+            ptype,
+            format!("{} - {description}", parent.description),
+            obis_codes.next_pid(),
+            ParamOrigin::Derived(code.to_owned()),
+        )
+    };
+    obis_codes.insert(key.to_owned(), dmsr_param);
+}
+
+/// creates a string parameter for a code without definition
+/// the name is derived from the code, e.g. raw/1_0_21_7_0 for 1-0:21.7.0
+fn discover_code(obis_codes: &mut ObisCodes, code: &str) {
+    let dmsr_param = DmsrParam::new(
+        format!("raw/{}", code.replace(['-', ':', '.'], "_")),
+        DmsrParamType::String,
+        "auto-discovered".to_owned(),
+        obis_codes.next_pid(),
+        ParamOrigin::Discovered,
+    );
+    log::info!(
+        "Discovered code {code}, publishing it as {}",
+        dmsr_param.name
+    );
+    obis_codes.insert(code.to_owned(), dmsr_param);
+}
+
+/// returns the definitions of the parameters of the table known before receiving any data
+fn parameter_definitions(obis_codes: &HashMap<String, DmsrParam>) -> Vec<ParameterDefinition> {
+    let mut pdefs: Vec<ParameterDefinition> = obis_codes
+        .iter()
+        .filter(|(code, p)| known_upfront(code, p))
+        .map(|(_, p)| get_pdef(p, None))
+        .collect();
+    pdefs.sort_by_key(|pdef| pdef.id);
+    pdefs
+}
+
+/// returns the definitions of the parameters of the table known before receiving any data and marks them as defined
+/// the numeric parameters without unit in the table stay undefined such that they are defined again when received,
+/// with the unit given in the telegram
+fn define_upfront(obis_codes: &mut ObisCodes) -> Vec<ParameterDefinition> {
+    for (code, p) in obis_codes.iter_mut() {
+        if known_upfront(code, p)
+            && (p.unit.is_some() || p.ptype == DmsrParamType::String || p.enum_values.is_some())
+        {
+            p.defined = true;
+        }
+    }
+    parameter_definitions(obis_codes)
+}
+
+/// returns true if the parameter is published under its own definition, false for the ignored codes and the
+/// definitions of which the parameters are created when receiving the data (wildcards, power failure logs,
+/// peak lists and lines with named groups)
+fn known_upfront(code: &str, p: &DmsrParam) -> bool {
+    p.name != "ignore"
+        && !wildcard::is_wildcard(code)
+        && !POWER_FAILURE_LOGS.contains(&code)
+        && !PEAK_LISTS.contains(&code)
+        && p.group_names.is_none()
+}
+
+fn get_pdef(dmsr_param: &DmsrParam, unit: Option<&str>) -> ParameterDefinition {
+    ParameterDefinition {
+        relative_name: dmsr_param.name.clone(),
+        description: Some(dmsr_param.description.clone()),
+        unit: dmsr_param.unit.clone().or(unit.map(|s| s.to_owned())),
+        // the enumerated integers are published as strings
+        ptype: if dmsr_param.enum_values.is_some() {
+            format!("{:?}", DmsrParamType::String)
+        } else {
+            format!("{:?}", dmsr_param.ptype)
+        },
+        writable: Some(false),
+        id: dmsr_param.pid,
+    }
+}
+
+/// like parse_timestamp, returns None with a warning if the timestamp is invalid
+fn get_timestamp(str_value: &str, tz: Tz) -> Option<Timestamp> {
+    logged(parse_timestamp(str_value, tz))
+}
+
+/// converts the timestamp sent by the meter in the local time of the timezone tz (YYMMDDhhmmssX) into a Yamcs timestamp
+/// the X suffix is S during the summer time and W during the winter time, it selects the time during
+/// the hour repeated when the summer time ends; older meters without suffix get the earliest one
+fn parse_timestamp(str_value: &str, tz: Tz) -> std::result::Result<Timestamp, P1ParseError> {
+    let (s, summer) = match str_value.as_bytes().last() {
+        Some(b'S') => (&str_value[0..str_value.len() - 1], Some(true)),
+        Some(b'W') => (&str_value[0..str_value.len() - 1], Some(false)),
+        _ => (str_value, None),
+    };
+
+    if let Ok(dt) = NaiveDateTime::parse_from_str(s, "%y%m%d%H%M%S") {
+        let local = match tz.from_local_datetime(&dt) {
+            LocalResult::Single(t) => t,
+            LocalResult::Ambiguous(t1, t2) => {
+                if summer == Some(t2.offset().dst_offset() != chrono::Duration::zero()) {
+                    t2
+                } else {
+                    t1
+                }
+            }
+            // in the hour skipped when the summer time starts
+            LocalResult::None => {
+                return Err(P1ParseError::BadTimestamp {
+                    value: str_value.to_owned(),
+                    reason: format!("does not exist in {tz}"),
+                });
+            }
+        };
+        Ok(utc_timestamp(&local.naive_utc()))
+    } else {
+        Err(P1ParseError::BadTimestamp {
+            value: str_value.to_owned(),
+            reason: "cannot be parsed".to_owned(),
+        })
+    }
+}
+
+/// returns true if the group has the shape of a DSMR timestamp: YYMMDDhhmmss followed by S or W
+fn is_dsmr_timestamp(s: &str) -> bool {
+    match s.as_bytes().split_last() {
+        Some((b'S' | b'W', digits)) => digits.len() == 12 && digits.iter().all(u8::is_ascii_digit),
+        _ => false,
+    }
+}
+
+/// decodes a hex encoded text message, e.g. 48656C6C6F into Hello
+/// the messages which are not hex encoded are returned unchanged, an empty message gives an empty string
+fn decode_hex_text(s: &str) -> String {
+    if !s.len().is_multiple_of(2) || !s.bytes().all(|b| b.is_ascii_hexdigit()) {
+        return s.to_owned();
+    }
+    let bytes: Vec<u8> = (0..s.len())
+        .step_by(2)
+        .filter_map(|i| u8::from_str_radix(&s[i..i + 2], 16).ok())
+        .collect();
+    // some meters pad the message with zeros
+    String::from_utf8_lossy(&bytes)
+        .trim_end_matches('\0')
+        .to_owned()
+}
+
+/// converts the UTC date-time into a Yamcs timestamp (which counts the leap seconds)
+fn utc_timestamp(dt: &NaiveDateTime) -> Timestamp {
+    utc_to_instant(DateTimeComponents {
+        year: dt.year(),
+        month: dt.month() as i32,
+        day: dt.day() as i32,
+        hour: dt.hour() as i32,
+        minute: dt.minute() as i32,
+        second: dt.second() as i32,
+        millis: (dt.nanosecond() / 1_000_000) as i32,
+    })
+    .into()
+}
+
+/// converts milliseconds since the UNIX epoch (without leap seconds) into a Yamcs timestamp
+fn unix_to_timestamp(millis: i64) -> Timestamp {
+    let dt = chrono::DateTime::from_timestamp_millis(millis).unwrap_or_default();
+    utc_timestamp(&dt.naive_utc())
+}
+
+/// converts a Yamcs timestamp into milliseconds since the UNIX epoch (without leap seconds)
+fn timestamp_to_unix(t: &Timestamp) -> i64 {
+    // the number of leap seconds is the difference between the timestamp and the same number of milliseconds
+    // interpreted as UNIX time; this is off only in the seconds following the insertion of a leap second
+    let leap_millis = unix_to_timestamp(t.millis).millis - t.millis;
+    t.millis - leap_millis
+}
+
+/// converts the value reported by the meter for the code into a parameter value
+/// returns an error if the value cannot be converted into the unit or parsed as the type of the parameter
+fn get_pvalue(
+    code: &str,
+    dmsr_param: &DmsrParam,
+    str_value: &str,
+    unit: Option<&str>,
+) -> std::result::Result<ParameterValue, P1ParseError> {
+    // factor to convert from the unit reported by the meter to the unit of the parameter
+    let factor = match (unit, &dmsr_param.unit) {
+        (Some(from), Some(to)) if from != to => {
+            let Some(f) = units::conversion_factor(from, to) else {
+                return Err(P1ParseError::BadUnit {
+                    code: code.to_owned(),
+                    from: from.to_owned(),
+                    to: to.to_owned(),
+                });
+            };
+            Some(f)
+        }
+        _ => None,
+    };
+
+    let factor = factor.unwrap_or(1.0) * dmsr_param.scale;
+    let offset = dmsr_param.offset;
+    let calibrated = factor != 1.0 || offset != 0.0;
+
+    let (raw_value, eng_value) = match dmsr_param.ptype {
+        DmsrParamType::Float => {
+            let x: f32 = parse_number(code, str_value)?;
+            (
+                ygw::protobuf::ygw::value::V::FloatValue(x),
+                ygw::protobuf::ygw::value::V::FloatValue((x as f64 * factor + offset) as f32),
+            )
+        }
+        DmsrParamType::Double => {
+            let x: f64 = parse_number(code, str_value)?;
+            (
+                ygw::protobuf::ygw::value::V::DoubleValue(x),
+                ygw::protobuf::ygw::value::V::DoubleValue(x * factor + offset),
+            )
+        }
+        DmsrParamType::Integer => {
+            let x: i64 = parse_number(code, str_value)?;
+            let eng_value = match &dmsr_param.enum_values {
+                Some(enum_values) => ygw::protobuf::ygw::value::V::StringValue(
+                    enum_values.get(&x).cloned().unwrap_or_else(|| {
+                        log::warn!("No enumeration value for {x} of {}", dmsr_param.name);
+                        x.to_string()
+                    }),
+                ),
+                None => ygw::protobuf::ygw::value::V::Sint64Value(if calibrated {
+                    (x as f64 * factor + offset).round() as i64
+                } else {
+                    x
+                }),
+            };
+            (ygw::protobuf::ygw::value::V::Sint64Value(x), eng_value)
+        }
+        DmsrParamType::String => (
+            ygw::protobuf::ygw::value::V::StringValue(str_value.to_owned()),
+            ygw::protobuf::ygw::value::V::StringValue(str_value.to_owned()),
+        ),
+    };
+
+    let pv = ParameterValue {
+        id: dmsr_param.pid,
+        raw_value: Some(Value { v: Some(raw_value) }),
+        eng_value: Some(Value { v: Some(eng_value) }),
+        acquisition_time: None,
+        generation_time: None,
+        expire_millis: dmsr_param.expire_ms.map(i64::from),
+    };
+    Ok(pv)
+}
+
+/// parses a numeric value, ignoring the surrounding whitespace and the leading zeros
+/// the values with a decimal point are rejected for the integer parameters instead of being truncated
+fn parse_number<T: std::str::FromStr>(code: &str, s: &str) -> std::result::Result<T, P1ParseError> {
+    s.trim().parse().map_err(|_| P1ParseError::BadNumber {
+        code: code.to_owned(),
+        value: s.to_owned(),
+    })
+}
+
+/// returns the result of a parsing function, or None with a warning if it failed
+fn logged<T>(result: std::result::Result<T, P1ParseError>) -> Option<T> {
+    result.map_err(|e| log::warn!("{e}")).ok()
+}
+
+/// like logged, also counting the error in the statistics
+fn counted<T>(result: std::result::Result<T, P1ParseError>, stats: &mut Stats) -> Option<T> {
+    result
+        .map_err(|e| {
+            log::warn!("{e}");
+            stats.record_error(&e);
+        })
+        .ok()
+}
+
+//split a line of the form
+// 'ABC(g1)(g2)(g3)'
+// into ['ABC', 'g1', 'g2']
+// it ignores stuff that might be in between
+pub fn split_p1_line(p1line: &str) -> std::result::Result<Vec<&str>, P1ParseError> {
+    let mut result = Vec::new();
+    // 0 = before
+    // 1 = inside
+    // 2 = outside
+    let mut state = 0;
+    let mut k = 0;
+    for (i, c) in p1line.char_indices() {
+        match c {
+            '(' => {
+                if state == 0 {
+                    result.push(&p1line[..i]);
+                } else if state != 2 {
+                    return Err(P1ParseError::MalformedLine(p1line.to_owned()));
+                }
+                state = 1;
+                k = i;
+            }
+            ')' => {
+                if state != 1 {
+                    return Err(P1ParseError::MalformedLine(p1line.to_owned()));
+                }
+                result.push(&p1line[k + 1..i]);
+                state = 2;
+            }
+            _ => {}
+        }
+    }
+
+    if state != 2 {
+        return Err(P1ParseError::MalformedLine(p1line.to_owned()));
+    }
+
+    Ok(result)
+}
+
+/// reads the first OBIS codes file found in the search locations
+/// if none is found, the default table embedded in the binary is used unless a path was given explicitly
+fn read_codes(codes_path: Option<&Path>) -> Result<ObisCodes> {
+    let locations = codes_locations(codes_path, config_home());
+    if let Some(path) = locations.iter().find(|p| p.exists()) {
+        if let Some(codes_path) = codes_path.filter(|c| c != path) {
+            log::warn!(
+                "{} not found, using {}",
+                codes_path.display(),
+                path.display()
+            );
+        }
+        log::debug!("Reading the OBIS codes from {}", path.display());
+        return read_codes_file(path);
+    }
+
+    let tried = locations
+        .iter()
+        .map(|p| p.display().to_string())
+        .collect::<Vec<_>>()
+        .join(", ");
+    if let Some(path) = codes_path {
+        return Err(YgwError::IOError(
+            format!(
+                "Cannot find the OBIS codes file {}, tried {tried}",
+                path.display()
+            ),
+            io::Error::from(io::ErrorKind::NotFound),
+        ));
+    }
+    log::info!("No OBIS codes file found (tried {tried}), using the embedded DSMR 5 definitions");
+    parse_codes(DEFAULT_OBIS_CODES.as_bytes())
+}
+
+/// returns the locations where the OBIS codes file is searched, in order:
+/// the explicit path, $XDG_CONFIG_HOME/ygw-p1mon, /etc/ygw-p1mon and the current directory
+fn codes_locations(codes_path: Option<&Path>, config_home: Option<PathBuf>) -> Vec<PathBuf> {
+    let dirs: Vec<PathBuf> = config_home
+        .map(|d| d.join("ygw-p1mon"))
+        .into_iter()
+        .chain([PathBuf::from("/etc/ygw-p1mon"), PathBuf::new()])
+        .collect();
+
+    codes_path
+        .map(Path::to_path_buf)
+        .into_iter()
+        .chain(
+            dirs.iter()
+                .flat_map(|d| OBIS_CODES_FILES.iter().map(move |f| d.join(f))),
+        )
+        .collect()
+}
+
+/// returns $XDG_CONFIG_HOME, defaulting to $HOME/.config
+fn config_home() -> Option<PathBuf> {
+    std::env::var_os("XDG_CONFIG_HOME")
+        .filter(|d| !d.is_empty())
+        .map(PathBuf::from)
+        .or_else(|| std::env::var_os("HOME").map(|h| PathBuf::from(h).join(".config")))
+}
+
+/// returns the first OBIS codes file which exists
+fn codes_file(codes_path: Option<&Path>) -> Option<PathBuf> {
+    codes_locations(codes_path, config_home())
+        .into_iter()
+        .find(|p| p.exists())
+}
+
+/// reads the definitions in TOML format if the file has the .toml extension and in CSV format otherwise
+fn read_codes_file(path: &Path) -> Result<ObisCodes> {
+    if path.extension().is_some_and(|e| e == "toml") {
+        parse_toml_codes(&fs::read_to_string(path)?)
+    } else {
+        parse_codes(BufReader::new(File::open(path)?))
+    }
+}
+
+/// parses a table of OBIS code definitions, one per line; empty lines and lines starting with # are skipped,
+/// as well as a header row (code,name,ptype,description...) before the first definition
+fn parse_codes(reader: impl BufRead) -> Result<ObisCodes> {
+    let mut m = HashMap::new();
+    let mut pid = 0;
+    // the line of each code and name, to report the duplicates
+    let mut code_lines = HashMap::new();
+    let mut name_lines = HashMap::new();
+
+    for (idx, line) in reader.lines().enumerate() {
+        let line = line?;
+        if line.trim().is_empty() || line.starts_with('#') {
+            continue;
+        }
+        if m.is_empty() && is_header_row(&line) {
+            continue;
+        }
+        let lineno = idx + 1;
+        let (code, dmsr_param) = parse_code_line(&line, lineno, pid)?;
+        if let Some(first) = code_lines.insert(code.clone(), lineno) {
+            return Err(YgwError::DecodeError(format!(
+                "the OBIS code {code} is defined twice, on the lines {first} and {lineno}"
+            )));
+        }
+        if dmsr_param.name != "ignore" {
+            if let Some(first) = name_lines.insert(dmsr_param.name.clone(), lineno) {
+                return Err(YgwError::DecodeError(format!(
+                    "the parameter name {} is used twice, on the lines {first} and {lineno}",
+                    dmsr_param.name
+                )));
+            }
+        }
+        m.insert(code, dmsr_param);
+        pid += 1;
+    }
+    check_wildcards(&m)?;
+    check_derived(&m)?;
+
+    Ok(ObisCodes::new(m))
+}
+
+/// returns true if the line is the header row of the table, whose first column is code
+fn is_header_row(line: &str) -> bool {
+    split_csv_line(line).is_ok_and(|fields| fields[0].trim().eq_ignore_ascii_case("code"))
+}
+
+/// verifies that no two wildcard definitions can match the same code
+fn check_wildcards(obis_codes: &HashMap<String, DmsrParam>) -> Result<()> {
+    let mut patterns: Vec<&String> = obis_codes
+        .keys()
+        .filter(|k| wildcard::is_wildcard(k))
+        .collect();
+    patterns.sort();
+
+    for (i, a) in patterns.iter().enumerate() {
+        for b in &patterns[i + 1..] {
+            if wildcard::overlap(a, b) {
+                return Err(YgwError::DecodeError(format!(
+                    "wildcard OBIS codes '{a}' and '{b}' can match the same code"
+                )));
+            }
+        }
+    }
+    Ok(())
+}
+
+/// verifies that the sources of the derived parameters are defined and have the same unit
+fn check_derived(obis_codes: &HashMap<String, DmsrParam>) -> Result<()> {
+    for (code, p) in obis_codes {
+        let Some(derivation) = &p.derivation else {
+            continue;
+        };
+        let mut units = HashSet::new();
+        for source in derivation.sources() {
+            let Some(sp) = obis_codes.get(source).filter(|sp| sp.derivation.is_none()) else {
+                return Err(YgwError::DecodeError(format!(
+                    "the source '{source}' of the derived parameter '{code}' is not defined"
+                )));
+            };
+            units.insert(&sp.unit);
+        }
+        if units.len() > 1 {
+            return Err(YgwError::DecodeError(format!(
+                "the sources of the derived parameter '{code}' have different units"
+            )));
+        }
+    }
+    Ok(())
+}
+
+/// parses one line of the OBIS codes file:
+/// code,name,ptype,description[,unit[,scale[,offset[,group[,deadband[,enum[,expiry[,min[,max[,value_group[,time_group[,group_names[,rollover[,monotonic]]]]]]]]]]]]]]
+/// lineno is only used in the error messages
+fn parse_code_line(line: &str, lineno: usize, pid: u32) -> Result<(String, DmsrParam)> {
+    let fields = split_csv_line(line).map_err(|e| definition_error(lineno, line, e))?;
+    let parts: Vec<&str> = fields.iter().map(String::as_str).collect();
+    if parts.len() < 4 || parts.len() > 18 {
+        return Err(definition_error(
+            lineno,
+            line,
+            format!("expected 4 to 18 columns, found {}", parts.len()),
+        ));
+    }
+    let ptype = DmsrParamType::from_str(parts[2]).map_err(|e| definition_error(lineno, line, e))?;
+    let dmsr_param = DmsrParam {
+        unit: optional_column(&parts, 4).map(|u| u.to_owned()),
+        scale: parse_optional_f64(&parts, 5, lineno, line)?.unwrap_or(1.0),
+        offset: parse_optional_f64(&parts, 6, lineno, line)?.unwrap_or(0.0),
+        group: optional_column(&parts, 7).map(|g| g.to_owned()),
+        deadband: parse_optional_f64(&parts, 8, lineno, line)?,
+        enum_values: optional_column(&parts, 9)
+            .map(parse_enum_column)
+            .transpose()
+            .map_err(|e| definition_error(lineno, line, e))?,
+        expire_ms: optional_column(&parts, 10)
+            .map(parse_expiry)
+            .transpose()
+            .map_err(|e| definition_error(lineno, line, e))?,
+        min: parse_optional_f64(&parts, 11, lineno, line)?,
+        max: parse_optional_f64(&parts, 12, lineno, line)?,
+        value_group: parse_optional_index(&parts, 13, lineno, line)?,
+        time_group: parse_optional_index(&parts, 14, lineno, line)?,
+        group_names: optional_column(&parts, 15)
+            .map(|s| s.split(';').map(|name| name.trim().to_owned()).collect()),
+        rollover: optional_column(&parts, 16)
+            .map(Rollover::parse)
+            .transpose()
+            .map_err(|e| definition_error(lineno, line, e))?,
+        monotonic: optional_column(&parts, 17)
+            .map(Monotonic::from_str)
+            .transpose()
+            .map_err(|e| definition_error(lineno, line, e))?,
+        derivation: parse_derivation(parts[0]).map_err(|e| definition_error(lineno, line, e))?,
+        ..DmsrParam::new(
+            parts[1].to_owned(),
+            ptype,
+            parts[3].to_owned(),
+            pid,
+            ParamOrigin::File,
+        )
+    };
+    check_definition(parts[0], &dmsr_param).map_err(|e| definition_error(lineno, line, e))?;
+
+    Ok((parts[0].to_owned(), dmsr_param))
+}
+
+/// verifies the consistency of the definition of one code, independently of the file format
+fn check_definition(code: &str, p: &DmsrParam) -> std::result::Result<(), String> {
+    if p.name.is_empty() || p.name.starts_with('/') || p.name.contains(char::is_whitespace) {
+        return Err(format!(
+            "invalid parameter name '{}', it cannot be empty, start with / or contain spaces",
+            p.name
+        ));
+    }
+    if p.name == "timestamp" && (p.ptype != DmsrParamType::String || p.enum_values.is_some()) {
+        return Err("the timestamp has to be a string parameter".to_owned());
+    }
+    if p.name == "ignore"
+        && (p.derivation.is_some() || p.rollover.is_some() || p.monotonic.is_some())
+    {
+        return Err("an ignored code cannot be derived or checked".to_owned());
+    }
+    if p.ptype == DmsrParamType::String && (p.unit.is_some() || p.scale != 1.0 || p.offset != 0.0) {
+        return Err("unit, scale and offset cannot be used for a string parameter".to_owned());
+    }
+    if p.deadband.is_some() && !matches!(p.ptype, DmsrParamType::Float | DmsrParamType::Double) {
+        return Err("a deadband can only be used for a float or double parameter".to_owned());
+    }
+    if p.enum_values.is_some() && p.ptype != DmsrParamType::Integer {
+        return Err("an enumeration can only be used for an integer parameter".to_owned());
+    }
+    if (p.min.is_some() || p.max.is_some())
+        && (p.ptype == DmsrParamType::String || p.enum_values.is_some())
+    {
+        return Err("a range can only be used for a numeric parameter".to_owned());
+    }
+    if let (Some(min), Some(max)) = (p.min, p.max) {
+        if min > max {
+            return Err(format!("the minimum {min} is above the maximum {max}"));
+        }
+    }
+    if p.value_group == Some(0) || p.time_group == Some(0) {
+        return Err("the value and time groups are numbered from 1".to_owned());
+    }
+    if p.value_group.is_some() && p.value_group == p.time_group {
+        return Err("the value and the time cannot be in the same group".to_owned());
+    }
+    if let Some(names) = &p.group_names {
+        if p.value_group.is_some() || p.time_group.is_some() || p.derivation.is_some() {
+            return Err(
+                "the group names cannot be used with a value or time group or for a derived parameter"
+                    .to_owned(),
+            );
+        }
+        let mut seen = HashSet::new();
+        if let Some(name) = names
+            .iter()
+            .find(|n| n.is_empty() || !seen.insert(n.as_str()))
+        {
+            return Err(format!("empty or duplicate group name '{name}'"));
+        }
+    }
+    if p.rollover.is_some() && (p.ptype == DmsrParamType::String || p.enum_values.is_some()) {
+        return Err("a rollover can only be detected for a numeric parameter".to_owned());
+    }
+    if p.monotonic.is_some() && (p.ptype == DmsrParamType::String || p.enum_values.is_some()) {
+        return Err("only a numeric parameter can be checked to be monotonic".to_owned());
+    }
+    if p.derivation.is_some() && !matches!(p.ptype, DmsrParamType::Float | DmsrParamType::Double) {
+        return Err("a derived parameter has to be of type float or double".to_owned());
+    }
+    let uses_device = p.name.contains(mbus::DEVICE_PLACEHOLDER)
+        || p.group
+            .as_ref()
+            .is_some_and(|g| g.contains(mbus::DEVICE_PLACEHOLDER));
+    if uses_device && !wildcard::is_wildcard(code) {
+        return Err("{device} can only be used in a wildcard definition".to_owned());
+    }
+    let n = wildcard::count(code);
+    if let Some(i) = (1..=n).find(|i| !p.name.contains(&format!("{{{i}}}"))) {
+        return Err(format!(
+            "the name of a wildcard definition has to contain {{{i}}}"
+        ));
+    }
+    if let Some(u) = &p.unit {
+        if !units::is_known(u) {
+            return Err(format!("unknown unit '{u}'"));
+        }
+    }
+    Ok(())
+}
+
+/// parses the expiry of the values given as an interval (e.g. 30s, 2h) into milliseconds
+fn parse_expiry(s: &str) -> std::result::Result<u32, String> {
+    crate::throttle::parse_interval(s)
+        .and_then(|d| u32::try_from(d.as_millis()).ok())
+        .ok_or_else(|| format!("invalid expiry '{s}'"))
+}
+
+/// parses the enumeration column of the form 0001=low;0002=high
+fn parse_enum_column(s: &str) -> std::result::Result<HashMap<i64, String>, String> {
+    s.split(';')
+        .map(|entry| {
+            let (k, v) = entry
+                .split_once('=')
+                .ok_or_else(|| format!("expected value=name in the enumeration entry '{entry}'"))?;
+            let k = k
+                .trim()
+                .parse::<i64>()
+                .map_err(|_| format!("cannot parse enumeration value '{k}'"))?;
+            Ok((k, v.trim().to_owned()))
+        })
+        .collect()
+}
+
+/// returns the derivation of a derived parameter code (starting with =) or None for an OBIS code
+fn parse_derivation(code: &str) -> std::result::Result<Option<Derivation>, String> {
+    code.strip_prefix('=').map(derived::parse).transpose()
+}
+
+/// one OBIS code in the TOML definitions file
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct TomlParam {
+    name: String,
+    #[serde(rename = "type")]
+    ptype: String,
+    description: String,
+    unit: Option<String>,
+    scale: Option<f64>,
+    offset: Option<f64>,
+    group: Option<String>,
+    #[serde(rename = "enum")]
+    enum_values: Option<HashMap<String, String>>,
+    expiry: Option<String>,
+    // the expiry in milliseconds, accepted as in the earlier versions of the format
+    expire_ms: Option<u32>,
+    deadband: Option<f64>,
+    min: Option<f64>,
+    max: Option<f64>,
+    value_group: Option<usize>,
+    time_group: Option<usize>,
+    group_names: Option<Vec<String>>,
+    rollover: Option<String>,
+    monotonic: Option<String>,
+}
+
+/// parses the TOML definitions, one table per OBIS code:
+///
+/// ["1-0:1.7.0"]
+/// name = "all_phases_consumption"
+/// type = "float"
+/// description = "All phases consumption"
+/// unit = "W"
+///
+/// the parameter ids are assigned in the order of the tables in the file
+fn parse_toml_codes(s: &str) -> Result<ObisCodes> {
+    let table: toml::Table = s
+        .parse()
+        .map_err(|e| YgwError::DecodeError(format!("cannot parse the TOML definitions: {e}")))?;
+
+    let mut m = HashMap::new();
+    // the code of each name, to report the duplicates
+    let mut name_codes: HashMap<String, String> = HashMap::new();
+    for (pid, (code, value)) in table.into_iter().enumerate() {
+        let toml_error = |msg: String| {
+            YgwError::DecodeError(format!("{msg} in the TOML definition of '{code}'"))
+        };
+        let tp: TomlParam = value.try_into().map_err(|e| toml_error(format!("{e}")))?;
+        let ptype = DmsrParamType::from_str(&tp.ptype).map_err(|e| toml_error(format!("{e}")))?;
+        let enum_values = tp
+            .enum_values
+            .map(|ev| {
+                ev.into_iter()
+                    .map(|(k, v)| match k.parse::<i64>() {
+                        Ok(k) => Ok((k, v)),
+                        Err(_) => Err(toml_error(format!("cannot parse enumeration value '{k}'"))),
+                    })
+                    .collect::<Result<HashMap<_, _>>>()
+            })
+            .transpose()?;
+        let dmsr_param = DmsrParam {
+            unit: tp.unit,
+            scale: tp.scale.unwrap_or(1.0),
+            offset: tp.offset.unwrap_or(0.0),
+            group: tp.group,
+            enum_values,
+            expire_ms: match (tp.expiry.as_deref(), tp.expire_ms) {
+                (Some(_), Some(_)) => {
+                    return Err(toml_error("both expiry and expire_ms given".to_owned()))
+                }
+                (Some(expiry), None) => Some(parse_expiry(expiry).map_err(toml_error)?),
+                (None, expire_ms) => expire_ms,
+            },
+            deadband: tp.deadband,
+            min: tp.min,
+            max: tp.max,
+            value_group: tp.value_group,
+            time_group: tp.time_group,
+            group_names: tp.group_names,
+            rollover: tp
+                .rollover
+                .as_deref()
+                .map(Rollover::parse)
+                .transpose()
+                .map_err(toml_error)?,
+            monotonic: tp
+                .monotonic
+                .as_deref()
+                .map(Monotonic::from_str)
+                .transpose()
+                .map_err(toml_error)?,
+            derivation: parse_derivation(&code).map_err(toml_error)?,
+            ..DmsrParam::new(
+                tp.name,
+                ptype,
+                tp.description,
+                pid as u32,
+                ParamOrigin::File,
+            )
+        };
+        check_definition(&code, &dmsr_param).map_err(toml_error)?;
+        if dmsr_param.name != "ignore" {
+            if let Some(first) = name_codes.insert(dmsr_param.name.clone(), code.clone()) {
+                return Err(YgwError::DecodeError(format!(
+                    "the parameter name {} is used by both '{first}' and '{code}'",
+                    dmsr_param.name
+                )));
+            }
+        }
+        m.insert(code, dmsr_param);
+    }
+    check_wildcards(&m)?;
+    check_derived(&m)?;
+
+    Ok(ObisCodes::new(m))
+}
+
+/// splits a line of the OBIS codes file into its columns, without their trailing whitespace
+/// a column enclosed in double quotes may contain commas, a double quote is written twice inside the quotes;
+/// whitespace is allowed around the quotes
+fn split_csv_line(line: &str) -> std::result::Result<Vec<String>, String> {
+    let mut fields = Vec::new();
+    let mut chars = line.chars().peekable();
+    loop {
+        let mut field = String::new();
+        while let Some(c) = chars.next_if(|&c| c == ' ' || c == '\t') {
+            field.push(c);
+        }
+        if chars.peek() == Some(&'"') {
+            field.clear();
+            chars.next();
+            loop {
+                match chars.next() {
+                    Some('"') if chars.peek() == Some(&'"') => {
+                        chars.next();
+                        field.push('"');
+                    }
+                    Some('"') => break,
+                    Some(c) => field.push(c),
+                    None => return Err("unterminated quoted column".to_owned()),
+                }
+            }
+            while chars.next_if(|&c| c == ' ' || c == '\t').is_some() {}
+            if !matches!(chars.peek(), None | Some(',')) {
+                return Err(format!(
+                    "unexpected text after the quoted column \"{field}\""
+                ));
+            }
+        } else {
+            while let Some(c) = chars.next_if(|&c| c != ',') {
+                field.push(c);
+            }
+            field.truncate(field.trim_end().len());
+        }
+        fields.push(field);
+        if chars.next().is_none() {
+            return Ok(fields);
+        }
+    }
+}
+
+/// returns the trimmed column idx or None if the column is missing or empty
+fn optional_column<'a>(parts: &[&'a str], idx: usize) -> Option<&'a str> {
+    parts.get(idx).map(|s| s.trim()).filter(|s| !s.is_empty())
+}
+
+fn parse_optional_f64(
+    parts: &[&str],
+    idx: usize,
+    lineno: usize,
+    line: &str,
+) -> Result<Option<f64>> {
+    match optional_column(parts, idx) {
+        Some(s) => s
+            .parse()
+            .map(Some)
+            .map_err(|_| definition_error(lineno, line, format!("cannot parse '{s}' as a number"))),
+        None => Ok(None),
+    }
+}
+
+fn parse_optional_index(
+    parts: &[&str],
+    idx: usize,
+    lineno: usize,
+    line: &str,
+) -> Result<Option<usize>> {
+    match optional_column(parts, idx) {
+        Some(s) => s.parse().map(Some).map_err(|_| {
+            definition_error(lineno, line, format!("cannot parse '{s}' as a group index"))
+        }),
+        None => Ok(None),
+    }
+}
+
+fn definition_error(lineno: usize, line: &str, msg: impl std::fmt::Display) -> YgwError {
+    YgwError::DecodeError(format!(
+        "line {lineno}: {msg} in OBIS code definition '{line}'"
+    ))
+}
+
+/// merges the new_codes read from the file into the live obis_codes table
+///
+/// The parameter ids of the codes already known are preserved such that the values sent to Yamcs
+/// keep their meaning; the new codes get ids above all the ids given so far and the removed codes are
+/// marked as ignored.
+/// The new or changed codes have their defined flag reset such that fresh definitions are sent
+/// with the next telegram.
+fn merge_codes(obis_codes: &mut ObisCodes, new_codes: ObisCodes) -> ReloadSummary {
+    let mut summary = ReloadSummary::default();
+
+    // the derived parameters are kept only if the definition they derive from did not change
+    let unchanged: HashSet<String> = obis_codes
+        .iter()
+        .filter(|(code, p)| new_codes.get(*code).is_some_and(|n| n.same_definition(p)))
+        .map(|(code, _)| code.clone())
+        .collect();
+
+    obis_codes.retain(|code, p| {
+        match &p.origin {
+            ParamOrigin::Derived(parent) => {
+                return unchanged.contains(parent) && !new_codes.contains_key(code)
+            }
+            ParamOrigin::Discovered | ParamOrigin::Profile => return !new_codes.contains_key(code),
+            ParamOrigin::Header => return true,
+            ParamOrigin::File => {}
+        }
+        // the removed codes are kept as ignored such that their id is not reused
+        if !new_codes.contains_key(code) && p.name != "ignore" {
+            p.name = "ignore".to_owned();
+            summary.removed.push(code.clone());
+        }
+        true
+    });
+
+    // assign the ids of the new codes in the order they appear in the file
+    let mut new_codes: Vec<(String, DmsrParam)> = new_codes.params.into_iter().collect();
+    new_codes.sort_by_key(|(_, p)| p.pid);
+
+    for (code, mut new_param) in new_codes {
+        match obis_codes.get_mut(&code) {
+            Some(old_param) => {
+                if !old_param.same_definition(&new_param) {
+                    new_param.pid = old_param.pid;
+                    *old_param = new_param;
+                    summary.changed.push(code);
+                }
+            }
+            None => {
+                new_param.pid = obis_codes.next_pid();
+                obis_codes.insert(code.clone(), new_param);
+                summary.added.push(code);
+            }
+        }
+    }
+
+    summary
+}
+
+fn file_mtime(path: &Path) -> Option<SystemTime> {
+    fs::metadata(path).and_then(|m| m.modified()).ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use ygw::utc_converter::{self, Instant};
+
+    use super::*;
+    use crate::feed::{self, with_crc};
+    use tokio::sync::mpsc::UnboundedSender;
+
+    #[test]
+    fn test_extract_groups() {
+        let input = "1-0:32.7.0(235.2*V)(40*A)(Test*T)";
+        let expected = vec!["1-0:32.7.0", "235.2*V", "40*A", "Test*T"];
+        let result = split_p1_line(input).unwrap();
+        assert_eq!(result, expected);
+    }
+
+    #[test]
+    fn test_extract_groups_err() {
+        let input = "1-0:32.7.0";
+        let result = split_p1_line(input);
+        assert!(result.is_err())
+    }
+
+    #[test]
+    fn test_parse_errors() {
+        // a telegram longer than allowed
+        let mut assembler =
+            TelegramAssembler::new(DEFAULT_MAX_TELEGRAM_SIZE, 2, DEFAULT_TELEGRAM_TIMEOUT);
+        assembler.add_line(b"/ISK5\\2M550T-1012\r\n").unwrap();
+        assembler.add_line(b"\r\n").unwrap();
+        assert!(matches!(
+            assembler.add_line(b"1-0:1.7.0(00.316*kW)\r\n"),
+            Err(P1ParseError::MissingTerminator(_))
+        ));
+
+        let mut telegram = with_crc(b"/ISK5\\2M550T-1012\r\n\r\n1-0:1.7.0(00.316*kW)\r\n!");
+        let bang = telegram.iter().position(|&b| b == b'!').unwrap();
+        telegram[25] = b'4';
+        assert!(matches!(
+            check_crc(&telegram, bang, CrcAlgorithm::Arc),
+            Err(P1ParseError::BadCrc(_))
+        ));
+
+        assert_eq!(
+            split_p1_line("1-0:1.7.0(00.316*kW"),
+            Err(P1ParseError::MalformedLine(
+                "1-0:1.7.0(00.316*kW".to_owned()
+            ))
+        );
+        assert!(matches!(
+            parse_timestamp("240331023000S", DEFAULT_TIMEZONE),
+            Err(P1ParseError::BadTimestamp { .. })
+        ));
+
+        let (_, dmsr_param) = parse_code_line("1-0:1.7.0,power,float,Power,kW", 1, 0).unwrap();
+        assert_eq!(
+            get_pvalue("1-0:1.7.0", &dmsr_param, "00.3I6", Some("kW")),
+            Err(P1ParseError::BadNumber {
+                code: "1-0:1.7.0".to_owned(),
+                value: "00.3I6".to_owned()
+            })
+        );
+        assert!(matches!(
+            get_pvalue("1-0:1.7.0", &dmsr_param, "00.316", Some("V")),
+            Err(P1ParseError::BadUnit { .. })
+        ));
+
+        // the errors found when decoding a telegram are counted by kind
+        let mut stats = Stats::default();
+        let (_, pvalues, gentime) = decode_p1telegram(
+            &mut parse_codes(
+                "0-0:1.0.0,timestamp,string,Time\n1-0:1.7.0,power,float,Power,kW\n".as_bytes(),
+            )
+            .unwrap(),
+            b"0-0:1.0.0(240331023000S)\r\n1-0:1.7.0(00.3I6*kW)\r\n1-0:2.7.0(00.000*kW)\r\n1-0:1.7.0(00.316*kW\r\n",
+            false,
+            DEFAULT_TIMEZONE,
+            &mut stats,
+        );
+        assert!(pvalues.is_empty());
+        assert!(gentime.is_none());
+        assert_eq!(
+            (
+                stats.bad_timestamps,
+                stats.bad_values,
+                stats.unknown_codes,
+                stats.parse_errors
+            ),
+            (1, 1, 1, 1)
+        );
+    }
+    #[test]
+    fn test_timestamp() {
+        let utc = |s| {
+            let t = get_timestamp(s, DEFAULT_TIMEZONE).unwrap();
+            utc_converter::to_string(Instant::from(t))
+        };
+        // summer time, UTC+2
+        assert_eq!(utc("240506201011S"), "2024-05-06T18:10:11.000Z");
+        // winter time, UTC+1
+        assert_eq!(utc("240115120000W"), "2024-01-15T11:00:00.000Z");
+        // the hour repeated when the summer time ends
+        assert_eq!(utc("241027023000S"), "2024-10-27T00:30:00.000Z");
+        assert_eq!(utc("241027023000W"), "2024-10-27T01:30:00.000Z");
+        // older meters without suffix
+        assert_eq!(utc("240506201011"), "2024-05-06T18:10:11.000Z");
+        assert_eq!(utc("241027023000"), "2024-10-27T00:30:00.000Z");
+        assert!(get_timestamp("2405062010", DEFAULT_TIMEZONE).is_none());
+        assert!(get_timestamp("240506201011X", DEFAULT_TIMEZONE).is_none());
+        // the hour skipped when the summer time starts
+        assert!(get_timestamp("240331023000S", DEFAULT_TIMEZONE).is_none());
+
+        let t = get_timestamp("240506201011S", chrono_tz::UTC).unwrap();
+        assert_eq!(
+            utc_converter::to_string(Instant::from(t)),
+            "2024-05-06T20:10:11.000Z"
+        );
+    }
+
+    fn param(name: &str, description: &str, pid: u32) -> DmsrParam {
+        DmsrParam::new(
+            name.to_owned(),
+            DmsrParamType::Float,
+            description.to_owned(),
+            pid,
+            ParamOrigin::File,
+        )
+    }
+
+    #[test]
+    fn test_header() {
+        assert_eq!(
+            parse_header("/ISK5\\2M550T-1012\r\n"),
+            Some("ISK5\\2M550T-1012")
+        );
+        assert_eq!(
+            parse_header("/FLU5\\253769484_A  \n"),
+            Some("FLU5\\253769484_A")
+        );
+        assert_eq!(parse_header("/\r\n"), None);
+
+        assert_eq!(
+            parse_meter_id("ISK5\\2M550T-1012"),
+            Some(("ISK", "M550T-1012"))
+        );
+        assert_eq!(
+            parse_meter_id("KFM5KAIFA-METER"),
+            Some(("KFM", "KAIFA-METER"))
+        );
+        assert_eq!(parse_meter_id("ISK5\\2"), None);
+        assert_eq!(parse_meter_id("5\\2M550T"), None);
+
+        let mut codes = ObisCodes::default();
+        let mut pdefs = Vec::new();
+        let mut pvalues = Vec::new();
+        decode_header(&mut codes, "ISK5\\2M550T-1012", &mut pdefs, &mut pvalues);
+        let names: Vec<&str> = pdefs.iter().map(|p| p.relative_name.as_str()).collect();
+        assert_eq!(names, vec!["meter_id", "meter_manufacturer", "meter_model"]);
+        let values: Vec<_> = pvalues
+            .iter()
+            .map(|pv| pv.eng_value.clone().unwrap().v.unwrap())
+            .collect();
+        assert_eq!(
+            values,
+            ["ISK5\\2M550T-1012", "ISK", "M550T-1012"]
+                .map(|s| ygw::protobuf::ygw::value::V::StringValue(s.to_owned()))
+        );
+    }
+
+    #[test]
+    fn test_toml_codes() {
+        let csv = "0-0:1.0.0,timestamp,string,Timestamp\n\
+                   1-0:1.7.0,power,float,Power,W\n\
+                   1-0:*2.7.0,l{1}_voltage,float,Voltage,V,1.01,-0.5\n";
+        let toml = r#"
+            ["0-0:1.0.0"]
+            name = "timestamp"
+            type = "string"
+            description = "Timestamp"
+
+            ["1-0:1.7.0"]
+            name = "power"
+            type = "float"
+            description = "Power"
+            unit = "W"
+
+            ["1-0:*2.7.0"]
+            name = "l{1}_voltage"
+            type = "float"
+            description = "Voltage"
+            unit = "V"
+            scale = 1.01
+            offset = -0.5
+        "#;
+        let csv_codes = parse_codes(csv.as_bytes()).unwrap();
+        let toml_codes = parse_toml_codes(toml).unwrap();
+
+        assert_eq!(csv_codes.len(), toml_codes.len());
+        for (code, p) in csv_codes.iter() {
+            let t = &toml_codes[code];
+            assert!(p.same_definition(t), "{code} differs");
+            assert_eq!(p.pid, t.pid);
+        }
+    }
+
+    #[test]
+    fn test_toml_group_enum_expiry() {
+        let toml = r#"
+            ["0-0:96.14.0"]
+            name = "tariff"
+            type = "integer"
+            description = "Tariff indicator"
+            enum = { 1 = "low", 2 = "high" }
+
+            ["0-1:24.2.1"]
+            name = "gas"
+            type = "double"
+            description = "Gas"
+            group = "gas"
+            expiry = "2h"
+        "#;
+        let mut codes = parse_toml_codes(toml).unwrap();
+        let telegram = "0-0:96.14.0(0002)\n0-1:24.2.1(00012.345*m3)\n";
+        let (pdefs, pvalues, _) = decode_p1telegram(
+            &mut codes,
+            telegram.as_bytes(),
+            false,
+            DEFAULT_TIMEZONE,
+            &mut Stats::default(),
+        );
+        assert_eq!(pdefs[0].ptype, "String");
+        assert_eq!(
+            pvalues[0].eng_value.as_ref().unwrap().v,
+            Some(ygw::protobuf::ygw::value::V::StringValue("high".to_owned()))
+        );
+        assert_eq!(
+            pvalues[0].raw_value.as_ref().unwrap().v,
+            Some(ygw::protobuf::ygw::value::V::Sint64Value(2))
+        );
+
+        assert_eq!(pvalues[1].expire_millis, Some(7200000));
+
+        let groups = group_values(&codes, pvalues.clone(), "energy", None);
+        let groups: Vec<(&str, usize)> =
+            groups.iter().map(|(g, v)| (g.as_str(), v.len())).collect();
+        assert_eq!(groups, vec![("energy", 1), ("gas", 1)]);
+        // the group of the table takes precedence over the M-Bus group
+        let groups = group_values(&codes, pvalues, "energy", Some("energy_mbus"));
+        assert_eq!(groups[1].0, "gas");
+
+        let bad = "[\"0-0:96.14.0\"]\nname = \"tariff\"\ntype = \"float\"\ndescription = \"\"\nenum = { 1 = \"low\" }\n";
+        assert!(parse_toml_codes(bad).is_err());
+
+        // the expiry has the same syntax in both formats
+        let (_, dmsr_param) =
+            parse_code_line("0-1:24.2.1,gas,double,Gas,,,,gas,,,2h", 1, 0).unwrap();
+        assert_eq!(dmsr_param.expire_ms, codes["0-1:24.2.1"].expire_ms);
+        let bad = "[\"0-1:24.2.1\"]\nname = \"gas\"\ntype = \"double\"\ndescription = \"\"\nexpiry = \"soon\"\n";
+        assert!(parse_toml_codes(bad).is_err());
+        assert!(parse_code_line("0-1:24.2.1,gas,double,Gas,,,,,,,soon", 1, 0).is_err());
+    }
+
+    #[test]
+    fn test_toml_expire_ms() {
+        let toml = "[\"0-1:24.2.1\"]\nname = \"gas\"\ntype = \"double\"\ndescription = \"\"\nexpire_ms = 7200000\n";
+        let codes = parse_toml_codes(toml).unwrap();
+        assert_eq!(codes["0-1:24.2.1"].expire_ms, Some(7_200_000));
+
+        let both = format!("{toml}expiry = \"2h\"\n");
+        assert!(parse_toml_codes(&both).is_err());
+    }
+
+    #[test]
+    fn test_codes_locations() {
+        let locations = codes_locations(
+            Some(Path::new("/opt/p1/codes.csv")),
+            Some(PathBuf::from("/home/p1/.config")),
+        );
+        let locations: Vec<&str> = locations.iter().map(|p| p.to_str().unwrap()).collect();
+        assert_eq!(
+            locations,
+            vec![
+                "/opt/p1/codes.csv",
+                "/home/p1/.config/ygw-p1mon/obiscodes.toml",
+                "/home/p1/.config/ygw-p1mon/obiscodes.csv",
+                "/etc/ygw-p1mon/obiscodes.toml",
+                "/etc/ygw-p1mon/obiscodes.csv",
+                "obiscodes.toml",
+                "obiscodes.csv",
+            ]
+        );
+
+        // the codes file in the current directory is found after the explicit path
+        let codes = read_codes(Some(Path::new("/nonexistent/codes.csv"))).unwrap();
+        assert!(codes.contains_key("0-0:1.0.0"));
+    }
+
+    #[test]
+    fn test_filter_unchanged() {
+        let mut codes = parse_codes(
+            "1-0:1.7.0,power,float,Power,,,,,0.01\n0-0:96.14.0,tariff,integer,Tariff\n".as_bytes(),
+        )
+        .unwrap();
+        let t0 = std::time::Instant::now();
+        let max_silence = Duration::from_secs(60);
+        let mut sent = Vec::new();
+        for (i, (power, tariff)) in [
+            ("0.316", "1"),
+            ("0.320", "1"),
+            ("0.330", "1"),
+            ("0.330", "2"),
+        ]
+        .iter()
+        .enumerate()
+        {
+            let telegram = format!("1-0:1.7.0({power}*kW)\n0-0:96.14.0({tariff})\n");
+            let (_, pvalues, _) = decode_p1telegram(
+                &mut codes,
+                telegram.as_bytes(),
+                false,
+                DEFAULT_TIMEZONE,
+                &mut Stats::default(),
+            );
+            let now = t0 + Duration::from_secs(i as u64);
+            let pvalues = filter_unchanged(&mut codes, pvalues, now, max_silence);
+            sent.push(pvalues.iter().map(|pv| pv.id).collect::<Vec<_>>());
+        }
+        // 0.320 is within the deadband of 0.316, 0.330 is not
+        assert_eq!(sent, vec![vec![0, 1], vec![], vec![0], vec![1]]);
+
+        // the unchanged values are sent again after max_silence
+        let (_, pvalues, _) = decode_p1telegram(
+            &mut codes,
+            b"1-0:1.7.0(0.330*kW)\n0-0:96.14.0(2)\n",
+            false,
+            DEFAULT_TIMEZONE,
+            &mut Stats::default(),
+        );
+        let pvalues = filter_unchanged(
+            &mut codes,
+            pvalues,
+            t0 + Duration::from_secs(63),
+            max_silence,
+        );
+        assert_eq!(pvalues.len(), 2);
+    }
+
+    #[test]
+    fn test_parameter_definitions() {
+        let codes = parse_codes(
+            "1-0:1.8.1,consumption_rate1,double,Consumption rate 1,kWh
+1-0:1.7.0,power,float,Power
+0-0:96.14.0,current_rate,integer,Current rate,,,,,,0001=low;0002=high
+0-0:96.1.1,ignore,string,Serial number
+0-0:99.97.0,power_failures,integer,Long power failures
+1-0:*2.7.0,l{1}_voltage,float,Voltage
+=avg(1-0:1.7.0;15min),power_15min,float,Average power
+"
+            .as_bytes(),
+        )
+        .unwrap();
+        let pdefs = parameter_definitions(&codes);
+        let pdefs: Vec<(&str, &str, Option<&str>)> = pdefs
+            .iter()
+            .map(|p| {
+                (
+                    p.relative_name.as_str(),
+                    p.ptype.as_str(),
+                    p.unit.as_deref(),
+                )
+            })
+            .collect();
+        assert_eq!(
+            pdefs,
+            vec![
+                ("consumption_rate1", "Double", Some("kWh")),
+                ("power", "Float", None),
+                ("current_rate", "String", None),
+                ("power_15min", "Float", None),
+            ]
+        );
+
+        let pdefs = parameter_definitions(&parse_codes(DEFAULT_OBIS_CODES.as_bytes()).unwrap());
+        assert!(pdefs.windows(2).all(|w| w[0].id < w[1].id));
+    }
+
+    #[test]
+    fn test_check_crc() {
+        // the ! alone on the last line
+        let telegram = test_telegram().as_bytes();
+        let bang = telegram.iter().position(|&b| b == b'!').unwrap();
+        assert_eq!(check_crc(telegram, bang, CrcAlgorithm::Arc), Ok(()));
+
+        // the ! following the last data line
+        let telegram = with_crc(b"/ISK5\\2M550T-1012\r\n\r\n1-0:1.7.0(00.316*kW)!");
+        let bang = telegram.iter().position(|&b| b == b'!').unwrap();
+        assert_eq!(check_crc(&telegram, bang, CrcAlgorithm::Arc), Ok(()));
+        let m_idx = telegram.iter().position(|&b| b == b'\n').unwrap() + 1;
+        let (_, pvalues, _) = decode_p1telegram(
+            &mut parse_codes("1-0:1.7.0,power,float,Power\n".as_bytes()).unwrap(),
+            &telegram[m_idx..bang],
+            false,
+            DEFAULT_TIMEZONE,
+            &mut Stats::default(),
+        );
+        assert_eq!(pvalues.len(), 1);
+
+        let mut corrupted = telegram.clone();
+        corrupted[30] = b'9';
+        assert!(check_crc(&corrupted, bang, CrcAlgorithm::Arc).is_err());
+        assert!(check_crc(&telegram[..bang + 3], bang, CrcAlgorithm::Arc).is_err());
+    }
+
+    #[test]
+    fn test_assembler() {
+        let mut assembler = TelegramAssembler::new(
+            DEFAULT_MAX_TELEGRAM_SIZE,
+            DEFAULT_MAX_TELEGRAM_LINES,
+            DEFAULT_TELEGRAM_TIMEOUT,
+        );
+        // the end of a telegram received partially
+        assert!(assembler
+            .add_line(b"1-0:1.7.0(00.316*kW)\r\n")
+            .unwrap()
+            .is_none());
+        assert!(assembler.add_line(b"!1234\r\n").unwrap().is_none());
+        assert!(!assembler.is_receiving());
+
+        let mut telegram = None;
+        for line in test_telegram().split_inclusive('\n') {
+            assert!(telegram.is_none());
+            telegram = assembler.add_line(line.as_bytes()).unwrap();
+        }
+        let telegram = telegram.unwrap();
+        assert!(!assembler.is_receiving());
+        assert_eq!(telegram.data, test_telegram().as_bytes());
+        assert_eq!(&telegram.data[..telegram.m_idx], b"/FLU5\\253770234_A\r\n");
+        assert_eq!(
+            check_crc(&telegram.data, telegram.bang, CrcAlgorithm::Arc),
+            Ok(())
+        );
+    }
+
+    #[test]
+    fn test_stray_carriage_returns() {
+        // the adapter adds a \r before each \n
+        let telegram = test_telegram().replace("\r\n", "\r\r\n");
+        let mut assembler = TelegramAssembler::new(
+            DEFAULT_MAX_TELEGRAM_SIZE,
+            DEFAULT_MAX_TELEGRAM_LINES,
+            DEFAULT_TELEGRAM_TIMEOUT,
+        );
+        let mut result = None;
+        for line in telegram.split_inclusive('\n') {
+            result = assembler.add_line(line.as_bytes()).unwrap();
+        }
+        let result = result.unwrap();
+        assert_eq!(result.data, test_telegram().as_bytes());
+        assert_eq!(
+            check_crc(&result.data, result.bang, CrcAlgorithm::Arc),
+            Ok(())
+        );
+
+        assert_eq!(
+            &*normalize_line_end(b"1-0:1.7.0(00.316*kW)\r\r\r\n"),
+            b"1-0:1.7.0(00.316*kW)\r\n"
+        );
+        assert_eq!(&*normalize_line_end(b"\r\r\n"), b"\r\n");
+        assert_eq!(
+            &*normalize_line_end(b"1-0:1.7.0(00.316*kW)\n"),
+            b"1-0:1.7.0(00.316*kW)\n"
+        );
+    }
+
+    #[test]
+    fn test_telegram_timeout() {
+        let mut assembler = TelegramAssembler::new(
+            DEFAULT_MAX_TELEGRAM_SIZE,
+            DEFAULT_MAX_TELEGRAM_LINES,
+            Duration::from_secs(5),
+        );
+        let t0 = std::time::Instant::now();
+        let lines: Vec<&str> = test_telegram().split_inclusive('\n').collect();
+
+        // a telegram cut off in the middle, followed 10 seconds later by a complete one
+        for line in &lines[..5] {
+            assert!(assembler
+                .add_line_at(line.as_bytes(), t0)
+                .unwrap()
+                .is_none());
+        }
+        let t1 = t0 + Duration::from_secs(10);
+        assert!(assembler.add_line_at(lines[0].as_bytes(), t1).is_err());
+        let mut telegram = None;
+        for line in &lines[1..] {
+            telegram = assembler.add_line_at(line.as_bytes(), t1).unwrap();
+        }
+        let telegram = telegram.unwrap();
+        assert_eq!(telegram.data, test_telegram().as_bytes());
+        assert_eq!(
+            check_crc(&telegram.data, telegram.bang, CrcAlgorithm::Arc),
+            Ok(())
+        );
+
+        // without the timeout, the two telegrams would have been glued together
+        let mut assembler = TelegramAssembler::new(
+            DEFAULT_MAX_TELEGRAM_SIZE,
+            DEFAULT_MAX_TELEGRAM_LINES,
+            Duration::from_secs(60),
+        );
+        let mut telegram = None;
+        for line in lines[..5].iter().chain(&lines) {
+            telegram = assembler.add_line_at(line.as_bytes(), t1).unwrap();
+        }
+        let telegram = telegram.unwrap();
+        assert!(check_crc(&telegram.data, telegram.bang, CrcAlgorithm::Arc).is_err());
+    }
+
+    #[test]
+    fn test_assembler_limits() {
+        let mut assembler = TelegramAssembler::new(2048, 40, DEFAULT_TELEGRAM_TIMEOUT);
+        // garbage starting with / but never terminated
+        let mut discarded = 0;
+        for i in 0..100_000 {
+            let line = if i % 100 == 0 {
+                "/garbage\r\n"
+            } else {
+                "1-0:1.7.0(00.316*kW)\r\n"
+            };
+            if assembler.add_line(line.as_bytes()).is_err() {
+                discarded += 1;
+            }
+            assert!(assembler.p1t.len() <= 2048);
+        }
+        assert_eq!(discarded, 1000);
+
+        // a long line exceeds the size before the number of lines
+        assert!(assembler
+            .add_line(b"/ISK5\\2M550T-1012\r\n")
+            .unwrap()
+            .is_none());
+        assert!(assembler.add_line(&[b'0'; 2048]).is_err());
+        assert!(!assembler.is_receiving());
+
+        // the parser accepts the next telegram
+        let mut telegram = None;
+        for line in test_telegram().split_inclusive('\n') {
+            telegram = assembler.add_line(line.as_bytes()).unwrap();
+        }
+        assert!(telegram.is_some());
+    }
+
+    #[test]
+    fn test_default_codes() {
+        let codes = parse_codes(DEFAULT_OBIS_CODES.as_bytes()).unwrap();
+        for code in [
+            "0-0:1.0.0",
+            "1-0:1.8.1",
+            "1-0:1.8.2",
+            "1-0:2.8.1",
+            "1-0:2.8.2",
+            "1-0:21.7.0",
+            "1-0:41.7.0",
+            "1-0:61.7.0",
+            "1-0:32.7.0",
+            "1-0:31.7.0",
+            "0-1:24.2.1",
+        ] {
+            assert!(
+                codes.contains_key(code),
+                "{code} missing from the default table"
+            );
+        }
+        assert_eq!(codes["0-0:1.0.0"].name, "timestamp");
+    }
+
+    // the example telegram of the DSMR 5.0.2 specification
+    const DSMR5_TELEGRAM: &str = "1-3:0.2.8(50)\r\n\
+        0-0:1.0.0(101209113020W)\r\n\
+        0-0:96.1.1(4B384547303034303436333935353037)\r\n\
+        1-0:1.8.1(123456.789*kWh)\r\n\
+        1-0:1.8.2(123456.789*kWh)\r\n\
+        1-0:2.8.1(123456.789*kWh)\r\n\
+        1-0:2.8.2(123456.789*kWh)\r\n\
+        0-0:96.14.0(0002)\r\n\
+        1-0:1.7.0(01.193*kW)\r\n\
+        1-0:2.7.0(00.000*kW)\r\n\
+        0-0:96.7.21(00004)\r\n\
+        0-0:96.7.9(00002)\r\n\
+        1-0:99.97.0(2)(0-0:96.7.19)(101208152415W)(0000000240*s)(101208151004W)(0000000301*s)\r\n\
+        1-0:32.32.0(00002)\r\n\
+        1-0:52.32.0(00001)\r\n\
+        1-0:72.32.0(00000)\r\n\
+        1-0:32.36.0(00000)\r\n\
+        1-0:52.36.0(00003)\r\n\
+        1-0:72.36.0(00000)\r\n\
+        0-0:96.13.0(303132333435363738393A3B3C3D3E3F)\r\n\
+        1-0:32.7.0(220.1*V)\r\n\
+        1-0:52.7.0(220.2*V)\r\n\
+        1-0:72.7.0(220.3*V)\r\n\
+        1-0:31.7.0(001*A)\r\n\
+        1-0:51.7.0(002*A)\r\n\
+        1-0:71.7.0(003*A)\r\n\
+        1-0:21.7.0(01.111*kW)\r\n\
+        1-0:41.7.0(02.222*kW)\r\n\
+        1-0:61.7.0(03.333*kW)\r\n\
+        1-0:22.7.0(04.444*kW)\r\n\
+        1-0:42.7.0(05.555*kW)\r\n\
+        1-0:62.7.0(06.666*kW)\r\n\
+        0-1:24.1.0(003)\r\n\
+        0-1:96.1.0(3232323241424344313233343536373839)\r\n\
+        0-1:24.2.1(101209112500W)(12785.123*m3)\r\n";
+
+    #[test]
+    fn test_dsmr5_telegram() {
+        use ygw::protobuf::ygw::value::V;
+
+        let mut codes = parse_codes(DEFAULT_OBIS_CODES.as_bytes()).unwrap();
+        let mut stats = Stats::default();
+        let (mut pdefs, mut pvalues, gentime) = decode_p1telegram(
+            &mut codes,
+            DSMR5_TELEGRAM.as_bytes(),
+            false,
+            DEFAULT_TIMEZONE,
+            &mut stats,
+        );
+        let t = timestamp_to_unix(gentime.as_ref().unwrap());
+        pvalues.extend(compute_derived(&mut codes, &pvalues, t, &mut pdefs));
+        assert_eq!(stats.parse_errors + stats.unknown_codes, 0);
+        // 2010-12-09 11:30:20 CET
+        assert_eq!(t, 1291890620000);
+        // the power failure log gives three parameters and the timestamp has no value
+        assert_eq!(pdefs.len(), 38);
+        assert_eq!(pvalues.len(), 37);
+
+        let names: HashMap<u32, &str> = pdefs
+            .iter()
+            .map(|p| (p.id, p.relative_name.as_str()))
+            .collect();
+        let value = |name: &str| {
+            let pv = pvalues
+                .iter()
+                .find(|pv| names.get(&pv.id) == Some(&name))
+                .unwrap_or_else(|| panic!("no value for {name}"));
+            pv.eng_value.clone().unwrap().v.unwrap()
+        };
+        assert_eq!(value("dsmr_version"), V::StringValue("50".to_owned()));
+        assert_eq!(value("current_rate"), V::Sint64Value(2));
+        assert_eq!(value("all_phases_consumption"), V::FloatValue(1.193));
+        assert_eq!(value("l3_voltage"), V::FloatValue(220.3));
+        assert_eq!(value("total_consumption"), V::DoubleValue(246913.578));
+        assert_eq!(
+            value("text_message"),
+            V::StringValue("0123456789:;<=>?".to_owned())
+        );
+        assert_eq!(value("gas_consumption"), V::DoubleValue(12785.123));
+        // the gas meter was read at 11:25:00 CET
+        let gas = pvalues.iter().find(|pv| names[&pv.id] == "gas_consumption");
+        let gas_time = gas.and_then(|pv| pv.generation_time.as_ref()).unwrap();
+        assert_eq!(timestamp_to_unix(gas_time), 1291890300000);
+    }
+
+    #[test]
+    fn test_difference() {
+        let table = "1-0:1.7.0,delivered,float,Power delivered,W\n\
+                     1-0:2.7.0,returned,float,Power returned,W\n\
+                     =1-0:1.7.0 - 1-0:2.7.0,net_power,float,Net power\n";
+        let mut codes = parse_codes(table.as_bytes()).unwrap();
+        let pid = codes["=1-0:1.7.0 - 1-0:2.7.0"].pid;
+        let mut pdefs = Vec::new();
+
+        let telegram = b"1-0:1.7.0(00.000*kW)\n1-0:2.7.0(01.250*kW)\n";
+        let (_, pvalues, _) = decode_p1telegram(
+            &mut codes,
+            telegram,
+            false,
+            DEFAULT_TIMEZONE,
+            &mut Stats::default(),
+        );
+        let net = compute_derived(&mut codes, &pvalues, 0, &mut pdefs);
+        assert_eq!(pdefs.len(), 1);
+        assert_eq!(pdefs[0].unit.as_deref(), Some("W"));
+        assert_eq!(net.len(), 1);
+        assert_eq!(net[0].id, pid);
+        assert_eq!(
+            net[0].eng_value.as_ref().unwrap().v,
+            Some(ygw::protobuf::ygw::value::V::FloatValue(-1250.0))
+        );
+        // the values computed from the telegram have the generation time of the telegram
+        assert_eq!(net[0].generation_time, None);
+
+        // no value if one of the sources is missing
+        let (_, pvalues, _) = decode_p1telegram(
+            &mut codes,
+            b"1-0:1.7.0(00.100*kW)\n",
+            false,
+            DEFAULT_TIMEZONE,
+            &mut Stats::default(),
+        );
+        assert!(compute_derived(&mut codes, &pvalues, 0, &mut pdefs).is_empty());
+
+        let table = "1-0:1.7.0,delivered,float,Power delivered,W\n\
+                     1-0:2.7.0,returned,float,Power returned,kW\n\
+                     =1-0:1.7.0 - 1-0:2.7.0,net_power,float,Net power\n";
+        assert!(parse_codes(table.as_bytes()).is_err());
+        let table = "1-0:1.7.0,delivered,float,Power delivered\n=1-0:1.7.0 - 1-0:2.7.0,net_power,float,Net\n";
+        assert!(parse_codes(table.as_bytes()).is_err());
+    }
+
+    #[test]
+    fn test_aggregate() {
+        let table = "0-0:1.0.0,timestamp,string,Timestamp\n\
+                     1-0:1.7.0,power,float,Power,W\n\
+                     =avg(1-0:1.7.0;1min),power_avg,float,Power 1 minute average\n";
+        let mut codes = parse_codes(table.as_bytes()).unwrap();
+
+        let mut published = Vec::new();
+        let mut derived_pdefs = Vec::new();
+        for (time, power) in [("20:10:11", 1.0), ("20:10:41", 2.0), ("20:11:05", 5.0)] {
+            let telegram = format!(
+                "0-0:1.0.0(240506{}S)\n1-0:1.7.0({power:06.3}*kW)\n",
+                time.replace(':', "")
+            );
+            let (_, pvalues, gentime) = decode_p1telegram(
+                &mut codes,
+                telegram.as_bytes(),
+                false,
+                DEFAULT_TIMEZONE,
+                &mut Stats::default(),
+            );
+            let t = timestamp_to_unix(&gentime.unwrap());
+            published.extend(compute_derived(&mut codes, &pvalues, t, &mut derived_pdefs));
+        }
+
+        // the window closed by the third telegram is published with its end time
+        let pid = codes["=avg(1-0:1.7.0;1min)"].pid;
+        assert_eq!(derived_pdefs.len(), 1);
+        assert_eq!(derived_pdefs[0].id, pid);
+        assert_eq!(derived_pdefs[0].unit.as_deref(), Some("W"));
+        assert_eq!(published.len(), 1);
+        assert_eq!(published[0].id, pid);
+        assert_eq!(
+            published[0].eng_value.as_ref().unwrap().v,
+            Some(ygw::protobuf::ygw::value::V::FloatValue(1500.0))
+        );
+        let end = get_timestamp("240506201100S", DEFAULT_TIMEZONE).unwrap();
+        assert_eq!(published[0].generation_time, Some(end));
+
+        let table = "1-0:1.7.0,power,float,Power\n=avg(1-0:1.7.0;1min),power_avg,integer,Power\n";
+        assert!(parse_codes(table.as_bytes()).is_err());
+        let table = "1-0:1.7.0,power,float,Power\n=median(1-0:1.7.0;1min),power_avg,float,Power\n";
+        assert!(parse_codes(table.as_bytes()).is_err());
+    }
+
+    #[test]
+    fn test_enum_column() {
+        let table = "0-0:96.14.0,current_rate,integer,Current rate,,,,,,0001=low;0002=high\n";
+        let mut codes = parse_codes(table.as_bytes()).unwrap();
+        let telegram = b"0-0:96.14.0(0002)\n";
+        let (pdefs, pvalues, _) = decode_p1telegram(
+            &mut codes,
+            telegram,
+            false,
+            DEFAULT_TIMEZONE,
+            &mut Stats::default(),
+        );
+        assert_eq!(pdefs[0].ptype, "String");
+        assert_eq!(
+            pvalues[0].eng_value.as_ref().unwrap().v,
+            Some(ygw::protobuf::ygw::value::V::StringValue("high".to_owned()))
+        );
+        assert_eq!(
+            pvalues[0].raw_value.as_ref().unwrap().v,
+            Some(ygw::protobuf::ygw::value::V::Sint64Value(2))
+        );
+
+        // an unknown value is published as its number
+        let (_, pvalues, _) = decode_p1telegram(
+            &mut codes,
+            b"0-0:96.14.0(0003)\n",
+            false,
+            DEFAULT_TIMEZONE,
+            &mut Stats::default(),
+        );
+        assert_eq!(
+            pvalues[0].eng_value.as_ref().unwrap().v,
+            Some(ygw::protobuf::ygw::value::V::StringValue("3".to_owned()))
+        );
+
+        for table in [
+            "0-0:96.14.0,current_rate,integer,Current rate,,,,,,low;high\n",
+            "0-0:96.14.0,current_rate,integer,Current rate,,,,,,one=low\n",
+            "0-0:96.14.0,current_rate,float,Current rate,,,,,,1=low\n",
+        ] {
+            assert!(parse_codes(table.as_bytes()).is_err(), "{table}");
+        }
+    }
+
+    #[test]
+    fn test_expiry_column() {
+        let table = "1-0:1.7.0,power,float,Power,,,,,,,10s\n\
+                     0-1:24.2.1,gas,double,Gas consumption,,,,,,,2h\n\
+                     1-0:2.7.0,returned,float,Power returned\n";
+        let mut codes = parse_codes(table.as_bytes()).unwrap();
+        let telegram = b"1-0:1.7.0(00.316*kW)\n0-1:24.2.1(12785.123*m3)\n1-0:2.7.0(00.000*kW)\n";
+        let (_, pvalues, _) = decode_p1telegram(
+            &mut codes,
+            telegram,
+            false,
+            DEFAULT_TIMEZONE,
+            &mut Stats::default(),
+        );
+        let expiry: Vec<Option<i64>> = pvalues.iter().map(|pv| pv.expire_millis).collect();
+        assert_eq!(expiry, vec![Some(10_000), Some(7_200_000), None]);
+
+        let table = "1-0:1.7.0,power,float,Power,,,,,,,soon\n";
+        assert!(parse_codes(table.as_bytes()).is_err());
+    }
+
+    #[test]
+    fn test_parse_codes_error() {
+        let table = "# comment\n\n1-0:1.8.1,rate_1,double,Rate 1\n1-0:1.8.2,rate_2,foo,Rate 2\n";
+        let err = parse_codes(table.as_bytes()).unwrap_err();
+        assert!(format!("{err}").contains("line 4"));
+    }
+
+    #[test]
+    fn test_reload_codes() {
+        let telegram = "1-0:1.7.0(00.316*kW)\n1-0:2.7.0(00.000*kW)\n1-0:21.7.0(00.316*kW)\n";
+        let mut codes = ObisCodes::new(HashMap::from([
+            (
+                "1-0:1.7.0".to_owned(),
+                param("power_delivered", "Power delivered", 0),
+            ),
+            ("1-0:21.7.0".to_owned(), param("l1_power", "L1 power", 1)),
+        ]));
+
+        let (pdefs, pvalues, _) = decode_p1telegram(
+            &mut codes,
+            telegram.as_bytes(),
+            false,
+            DEFAULT_TIMEZONE,
+            &mut Stats::default(),
+        );
+        assert_eq!(pdefs.len(), 2);
+        assert_eq!(pvalues.len(), 2);
+
+        let (pdefs, _, _) = decode_p1telegram(
+            &mut codes,
+            telegram.as_bytes(),
+            false,
+            DEFAULT_TIMEZONE,
+            &mut Stats::default(),
+        );
+        assert!(pdefs.is_empty());
+
+        // swap the table: one code added, one changed and one removed
+        let new_codes = ObisCodes::new(HashMap::from([
+            (
+                "1-0:1.7.0".to_owned(),
+                param("power_delivered", "Power delivered (all phases)", 0),
+            ),
+            (
+                "1-0:2.7.0".to_owned(),
+                param("power_returned", "Power returned", 1),
+            ),
+        ]));
+        let summary = merge_codes(&mut codes, new_codes);
+        assert_eq!(summary.added, vec!["1-0:2.7.0"]);
+        assert_eq!(summary.removed, vec!["1-0:21.7.0"]);
+        assert_eq!(codes["1-0:21.7.0"].name, "ignore");
+        assert_eq!(summary.changed, vec!["1-0:1.7.0"]);
+
+        // the known code keeps its id, the new one gets a fresh id
+        assert_eq!(codes["1-0:1.7.0"].pid, 0);
+        assert_eq!(codes["1-0:2.7.0"].pid, 2);
+        assert_eq!(codes["1-0:21.7.0"].pid, 1);
+
+        let (mut pdefs, pvalues, _) = decode_p1telegram(
+            &mut codes,
+            telegram.as_bytes(),
+            false,
+            DEFAULT_TIMEZONE,
+            &mut Stats::default(),
+        );
+        pdefs.sort_by_key(|p| p.id);
+        assert_eq!(pdefs.len(), 2);
+        assert_eq!(
+            pdefs[0].description.as_deref(),
+            Some("Power delivered (all phases)")
+        );
+        assert_eq!(pdefs[1].relative_name, "power_returned");
+        assert_eq!(pvalues.len(), 2);
+    }
+
+    #[test]
+    fn test_reload_derived_ids() {
+        let telegram = "1-0:32.7.0(235.2*V)\n";
+        let decode = |codes: &mut ObisCodes| {
+            decode_p1telegram(
+                codes,
+                telegram.as_bytes(),
+                false,
+                DEFAULT_TIMEZONE,
+                &mut Stats::default(),
+            )
+        };
+        let mut codes = parse_codes("1-0:*2.7.0,voltage_{1},float,Voltage\n".as_bytes()).unwrap();
+        decode(&mut codes);
+        assert_eq!(codes["1-0:32.7.0"].pid, 1);
+
+        // the wildcard changes, the parameter derived from it is dropped
+        let new_codes = parse_codes("1-0:*2.7.0,voltage_{1},float,Phase voltage\n".as_bytes());
+        merge_codes(&mut codes, new_codes.unwrap());
+        assert!(!codes.contains_key("1-0:32.7.0"));
+
+        // the id of the dropped parameter is not given to the code added by the next reload
+        let new_codes = parse_codes(
+            "1-0:*2.7.0,voltage_{1},float,Phase voltage\n1-0:1.7.0,power,float,Power\n".as_bytes(),
+        );
+        let summary = merge_codes(&mut codes, new_codes.unwrap());
+        assert_eq!(summary.added, vec!["1-0:1.7.0"]);
+        assert_eq!(codes["1-0:1.7.0"].pid, 2);
+
+        decode(&mut codes);
+        assert_eq!(codes["1-0:32.7.0"].pid, 3);
+    }
+
+    #[test]
+    fn test_unit_conversion() {
+        let (_, dmsr_param) = parse_code_line("1-0:1.7.0,power,float,Power,W", 1, 0).unwrap();
+        let pdef = get_pdef(&dmsr_param, Some("kW"));
+        assert_eq!(pdef.unit.as_deref(), Some("W"));
+
+        let pv = get_pvalue("1-0:1.7.0", &dmsr_param, "01.234", Some("kW")).unwrap();
+        assert_eq!(
+            pv.eng_value.unwrap().v,
+            Some(ygw::protobuf::ygw::value::V::FloatValue(1234.0))
+        );
+        assert_eq!(
+            pv.raw_value.unwrap().v,
+            Some(ygw::protobuf::ygw::value::V::FloatValue(1.234))
+        );
+
+        // wrong unit reported by the meter
+        assert!(get_pvalue("1-0:1.7.0", &dmsr_param, "01.234", Some("V")).is_err());
+
+        // a meter reporting the energy in Wh instead of kWh
+        let (_, dmsr_param) = parse_code_line("1-0:1.8.1,energy,double,Energy,kWh", 1, 0).unwrap();
+        let pdef = get_pdef(&dmsr_param, Some("Wh"));
+        assert_eq!(pdef.unit.as_deref(), Some("kWh"));
+        let pv = get_pvalue("1-0:1.8.1", &dmsr_param, "001234567", Some("Wh")).unwrap();
+        assert_eq!(
+            pv.eng_value.unwrap().v,
+            Some(ygw::protobuf::ygw::value::V::DoubleValue(1234.567))
+        );
+    }
+
+    #[test]
+    fn test_unit_column() {
+        // the unit of the column is registered even if the first value has no unit or another spelling,
+        // without column the unit of the first value is registered
+        let mut codes = parse_codes(
+            "0-1:24.2.1,gas,double,Gas,m3\n1-0:1.7.0,power,float,Power,kW\n1-0:2.7.0,returned,float,Returned\n"
+                .as_bytes(),
+        )
+        .unwrap();
+        let (mut pdefs, pvalues, _) = decode_p1telegram(
+            &mut codes,
+            b"0-1:24.2.1(240506200000S)(00981.443*m\xc2\xb3)\n1-0:1.7.0(01.234)\n1-0:2.7.0(00.100*kW)\n",
+            false,
+            DEFAULT_TIMEZONE,
+            &mut Stats::default(),
+        );
+        pdefs.sort_by_key(|p| p.id);
+        let units: Vec<Option<&str>> = pdefs.iter().map(|p| p.unit.as_deref()).collect();
+        assert_eq!(units, vec![Some("m3"), Some("kW"), Some("kW")]);
+        assert_eq!(pvalues.len(), 3);
+
+        // the unit of each value is compared with the column, the warning repeated at most once per interval
+        let gas = codes.get_mut("0-1:24.2.1").unwrap();
+        assert!(gas.unit_warned.is_some());
+        gas.unit_warned = None;
+        let t0 = std::time::Instant::now();
+        assert!(!warn_unit_mismatch("0-1:24.2.1", gas, Some("m3"), t0));
+        assert!(!warn_unit_mismatch("0-1:24.2.1", gas, None, t0));
+        assert!(warn_unit_mismatch("0-1:24.2.1", gas, Some("m³"), t0));
+        assert!(!warn_unit_mismatch(
+            "0-1:24.2.1",
+            gas,
+            Some("m³"),
+            t0 + Duration::from_secs(60)
+        ));
+        assert!(warn_unit_mismatch(
+            "0-1:24.2.1",
+            gas,
+            Some("kWh"),
+            t0 + UNIT_WARNING_INTERVAL
+        ));
+        // without column, the values are not compared
+        let returned = codes.get_mut("1-0:2.7.0").unwrap();
+        assert!(!warn_unit_mismatch("1-0:2.7.0", returned, Some("W"), t0));
+    }
+
+    #[test]
+    fn test_numeric_values() {
+        use ygw::protobuf::ygw::value::V;
+
+        // power returned by a solar installation
+        let (_, dmsr_param) = parse_code_line("1-0:1.7.0,power,float,Power", 1, 0).unwrap();
+        let pv = get_pvalue("1-0:1.7.0", &dmsr_param, "-01.250", Some("kW")).unwrap();
+        assert_eq!(pv.eng_value.unwrap().v, Some(V::FloatValue(-1.25)));
+        let pv = get_pvalue("1-0:1.7.0", &dmsr_param, "1.5e-3", Some("kW")).unwrap();
+        assert_eq!(pv.eng_value.unwrap().v, Some(V::FloatValue(0.0015)));
+
+        let (_, dmsr_param) =
+            parse_code_line("0-0:96.7.21,power_failures,integer,Power failures", 1, 0).unwrap();
+        let pv = get_pvalue("0-0:96.7.21", &dmsr_param, " 00012 ", None).unwrap();
+        assert_eq!(pv.eng_value.unwrap().v, Some(V::Sint64Value(12)));
+        // not truncated to an integer
+        assert!(get_pvalue("0-0:96.7.21", &dmsr_param, "12.5", None).is_err());
+
+        let (_, dmsr_param) =
+            parse_code_line("1-0:32.7.0,l1_voltage,double,L1 voltage", 1, 0).unwrap();
+        assert!(get_pvalue("1-0:32.7.0", &dmsr_param, "23O.1", Some("V")).is_err());
+        assert!(get_pvalue("1-0:32.7.0", &dmsr_param, "", Some("V")).is_err());
+    }
+
+    #[test]
+    fn test_unit_unknown() {
+        assert!(parse_code_line("1-0:1.7.0,power,float,Power,furlong", 1, 0).is_err());
+        assert!(parse_code_line("0-0:96.1.1,serial,string,Serial number,W", 1, 0).is_err());
+    }
+
+    #[test]
+    fn test_scale_offset() {
+        let (_, dmsr_param) = parse_code_line("1-0:1.7.0,power,float,Power,,1000", 1, 0).unwrap();
+        let pv = get_pvalue("1-0:1.7.0", &dmsr_param, "1.234", Some("kW")).unwrap();
+        assert_eq!(
+            pv.eng_value.unwrap().v,
+            Some(ygw::protobuf::ygw::value::V::FloatValue(1234.0))
+        );
+        assert_eq!(
+            pv.raw_value.unwrap().v,
+            Some(ygw::protobuf::ygw::value::V::FloatValue(1.234))
+        );
+
+        let (_, dmsr_param) =
+            parse_code_line("1-0:32.7.0,voltage,integer,Voltage,,2,-5", 1, 0).unwrap();
+        let pv = get_pvalue("1-0:1.7.0", &dmsr_param, "230", Some("V")).unwrap();
+        assert_eq!(
+            pv.eng_value.unwrap().v,
+            Some(ygw::protobuf::ygw::value::V::Sint64Value(455))
+        );
+    }
+
+    #[test]
+    fn test_ranges() {
+        let mut codes = parse_codes(
+            "1-0:32.7.0,l1_voltage,float,L1 voltage,V,,,,,,,200,250\n\
+             1-0:52.7.0,l2_voltage,float,L2 voltage,V,,,,,,,200,250\n\
+             1-0:72.7.0,l3_voltage,float,L3 voltage,V,,,,,,,200\n"
+                .as_bytes(),
+        )
+        .unwrap();
+        let telegram = "1-0:32.7.0(185.2*V)\n1-0:52.7.0(230.1*V)\n";
+        let (mut pdefs, pvalues, _) = decode_p1telegram(
+            &mut codes,
+            telegram.as_bytes(),
+            false,
+            DEFAULT_TIMEZONE,
+            &mut Stats::default(),
+        );
+        let range_values = check_ranges(&mut codes, &pvalues, &mut pdefs);
+        let names: Vec<&str> = pdefs[2..]
+            .iter()
+            .map(|p| p.relative_name.as_str())
+            .collect();
+        assert_eq!(names.len(), 2);
+        assert!(names.contains(&"l1_voltage_range") && names.contains(&"l2_voltage_range"));
+
+        use ygw::protobuf::ygw::value::V;
+        fn status(
+            codes: &HashMap<String, DmsrParam>,
+            range_values: &[ParameterValue],
+            code: &str,
+        ) -> V {
+            let pid = codes[&format!("{code}#range")].pid;
+            let pv = range_values.iter().find(|pv| pv.id == pid).unwrap();
+            pv.eng_value.clone().unwrap().v.unwrap()
+        }
+        // the brownout is flagged, the voltage itself is still published
+        assert_eq!(
+            status(&codes, &range_values, "1-0:32.7.0"),
+            V::StringValue("low".to_owned())
+        );
+        assert_eq!(
+            status(&codes, &range_values, "1-0:52.7.0"),
+            V::StringValue("ok".to_owned())
+        );
+        assert_eq!(pvalues.len(), 2);
+
+        // only a minimum
+        let telegram = "1-0:72.7.0(251.0*V)\n";
+        let (_, pvalues, _) = decode_p1telegram(
+            &mut codes,
+            telegram.as_bytes(),
+            false,
+            DEFAULT_TIMEZONE,
+            &mut Stats::default(),
+        );
+        let range_values = check_ranges(&mut codes, &pvalues, &mut pdefs);
+        assert_eq!(
+            status(&codes, &range_values, "1-0:72.7.0"),
+            V::StringValue("ok".to_owned())
+        );
+        assert_eq!(range_values.len(), 1);
+
+        assert!(parse_code_line(
+            "1-0:32.7.0,l1_voltage,float,L1 voltage,V,,,,,,,250,200",
+            1,
+            0
+        )
+        .is_err());
+        assert!(parse_code_line("0-0:96.1.1,serial,string,Serial,,,,,,,,0,1", 1, 0).is_err());
+    }
+
+    #[test]
+    fn test_rollovers() {
+        let mut codes = parse_codes(
+            "0-1:24.2.1,gas,double,Gas,m3,,,,,,,,,,,,100;100000\n\
+             1-0:1.8.1,energy,double,Energy,kWh,,,,,,,,,,,,1000\n"
+                .as_bytes(),
+        )
+        .unwrap();
+        let mut pdefs = Vec::new();
+        let mut check = |telegram: &str, pdefs: &mut Vec<ParameterDefinition>| {
+            let (_, pvalues, _) = decode_p1telegram(
+                &mut codes,
+                telegram.as_bytes(),
+                false,
+                DEFAULT_TIMEZONE,
+                &mut Stats::default(),
+            );
+            let (values, rollovers) = check_rollovers(&mut codes, &pvalues, pdefs);
+            let values: Vec<(String, f64)> = values
+                .iter()
+                .map(|pv| {
+                    let p = codes.values().find(|p| p.pid == pv.id).unwrap();
+                    (
+                        p.name.clone(),
+                        numeric_value(pv.eng_value.as_ref().unwrap()).unwrap(),
+                    )
+                })
+                .collect();
+            (values, rollovers)
+        };
+
+        let (values, rollovers) = check(
+            "0-1:24.2.1(240506200000S)(99990.000*m3)\n1-0:1.8.1(012345.000*kWh)\n",
+            &mut pdefs,
+        );
+        assert!(rollovers.is_empty());
+        assert!(values.contains(&("gas_rollover_count".to_owned(), 0.0)));
+        assert!(values.contains(&("gas_unwrapped".to_owned(), 99990.0)));
+        assert!(values.contains(&("energy_rollover_count".to_owned(), 0.0)));
+        // no unwrapped value without modulus
+        assert_eq!(values.len(), 3);
+        let unwrapped = pdefs
+            .iter()
+            .find(|p| p.relative_name == "gas_unwrapped")
+            .unwrap();
+        assert_eq!(unwrapped.unit.as_deref(), Some("m3"));
+
+        // the gas register wraps, the energy one decreases slightly
+        let (values, rollovers) = check(
+            "0-1:24.2.1(240506210000S)(00002.500*m3)\n1-0:1.8.1(012344.900*kWh)\n",
+            &mut pdefs,
+        );
+        assert_eq!(rollovers, vec![("gas".to_owned(), 99990.0, 2.5)]);
+        assert!(values.contains(&("gas_rollover_count".to_owned(), 1.0)));
+        assert!(values.contains(&("gas_unwrapped".to_owned(), 100002.5)));
+        assert!(values.contains(&("energy_rollover_count".to_owned(), 0.0)));
+
+        // the electricity meter is replaced
+        let (_, rollovers) = check("1-0:1.8.1(000000.100*kWh)\n", &mut pdefs);
+        assert_eq!(rollovers, vec![("energy".to_owned(), 12344.9, 0.1)]);
+
+        assert!(parse_code_line("0-0:96.1.1,serial,string,Serial,,,,,,,,,,,,,100", 1, 0).is_err());
+        assert!(parse_code_line("1-0:1.8.1,energy,double,Energy,kWh,,,,,,,,,,,,0", 1, 0).is_err());
+    }
+
+    #[test]
+    fn test_monotonic() {
+        let mut codes = parse_codes(
+            "0-1:24.2.1,gas,double,Gas,m3,,,,,,,,,,,,,flag\n\
+             1-0:1.8.1,energy,double,Energy,kWh,,,,,,,,,,,,,warn\n"
+                .as_bytes(),
+        )
+        .unwrap();
+        let mut pdefs = Vec::new();
+        let mut check = |telegram: &str, pdefs: &mut Vec<ParameterDefinition>| {
+            let (_, pvalues, _) = decode_p1telegram(
+                &mut codes,
+                telegram.as_bytes(),
+                false,
+                DEFAULT_TIMEZONE,
+                &mut Stats::default(),
+            );
+            let values = check_monotonic(&mut codes, &pvalues, pdefs);
+            // the values of the registers are still published
+            assert_eq!(pvalues.len(), 2);
+            values
+                .iter()
+                .map(|pv| numeric_value(pv.eng_value.as_ref().unwrap()).unwrap())
+                .collect::<Vec<f64>>()
+        };
+
+        let telegram =
+            |gas, energy| format!("0-1:24.2.1(240506200000S)({gas}*m3)\n1-0:1.8.1({energy}*kWh)\n");
+        assert_eq!(
+            check(&telegram("00010.000", "000100.000"), &mut pdefs),
+            vec![0.0]
+        );
+        assert_eq!(
+            check(&telegram("00010.500", "000100.000"), &mut pdefs),
+            vec![0.0]
+        );
+        // both decrease, only the gas is flagged
+        assert_eq!(
+            check(&telegram("00010.400", "000099.000"), &mut pdefs),
+            vec![1.0]
+        );
+        assert_eq!(
+            check(&telegram("00010.400", "000099.500"), &mut pdefs),
+            vec![0.0]
+        );
+        assert_eq!(pdefs.len(), 1);
+        assert_eq!(pdefs[0].relative_name, "gas_suspect");
+
+        assert!(
+            parse_code_line("1-0:1.8.1,energy,double,Energy,kWh,,,,,,,,,,,,,never", 1, 0).is_err()
+        );
+        assert!(
+            parse_code_line("0-0:96.1.1,serial,string,Serial,,,,,,,,,,,,,,warn", 1, 0).is_err()
+        );
+    }
+
+    #[test]
+    fn test_quoted_columns() {
+        let (code, dmsr_param) =
+            parse_code_line("1-0:32.7.0,l1_voltage,float,\"Voltage, phase L1\",V", 1, 0).unwrap();
+        assert_eq!(code, "1-0:32.7.0");
+        assert_eq!(dmsr_param.description, "Voltage, phase L1");
+        assert_eq!(dmsr_param.unit.as_deref(), Some("V"));
+
+        assert_eq!(
+            split_csv_line("a,\"b \"\"c\"\", d\",,\"\"").unwrap(),
+            vec!["a", "b \"c\", d", "", ""]
+        );
+
+        let err = parse_code_line("1-0:32.7.0,l1_voltage,float,\"Voltage, phase L1", 5, 0)
+            .err()
+            .unwrap();
+        assert!(err.to_string().contains("line 5"));
+        let err = parse_code_line("1-0:32.7.0,l1_voltage,\"Voltage, phase L1\"", 6, 0)
+            .err()
+            .unwrap();
+        assert!(err
+            .to_string()
+            .contains("line 6: expected 4 to 18 columns, found 3"));
+    }
+
+    #[test]
+    fn test_csv_styles() {
+        assert_eq!(
+            split_csv_line("a , \"b, c\" ,d\t").unwrap(),
+            vec!["a", "b, c", "d"]
+        );
+        assert_eq!(
+            split_csv_line("0-0:96.3.10,switch, Switch ").unwrap(),
+            vec!["0-0:96.3.10", "switch", " Switch"]
+        );
+        assert!(split_csv_line("a,\"b\" c").is_err());
+
+        // the same table written simply, and with a header row, quotes and trailing whitespace
+        let simple = "# comment\n\
+            1-0:31.7.0,l1_current,float,Instantaneous current phase L1,A\n\
+            1-0:32.7.0,l1_voltage,float,Voltage,V,,,,,,,200,250\n";
+        let quoted = "code,name,ptype,description,unit\n\
+            1-0:31.7.0 ,l1_current,float,\"Instantaneous current, phase L1\",A  \n\
+            \n\
+            1-0:32.7.0,l1_voltage ,float, \"Voltage\" ,V,,,,,,,200,250\n";
+        let simple = parse_codes(simple.as_bytes()).unwrap();
+        let quoted = parse_codes(quoted.as_bytes()).unwrap();
+        assert_eq!(simple.len(), 2);
+        for (code, p) in simple.iter() {
+            let q = &quoted[code];
+            assert_eq!(
+                (&q.name, &q.unit, q.min, q.pid),
+                (&p.name, &p.unit, p.min, p.pid)
+            );
+        }
+        assert_eq!(
+            quoted["1-0:31.7.0"].description,
+            "Instantaneous current, phase L1"
+        );
+        assert_eq!(quoted["1-0:32.7.0"].description, "Voltage");
+
+        // the header is only skipped before the first definition, the errors give the line number and the line
+        let err = parse_codes(
+            "1-0:31.7.0,l1_current,float,Current\ncode,name,ptype,description\n".as_bytes(),
+        )
+        .err()
+        .unwrap()
+        .to_string();
+        assert!(
+            err.contains("line 2:") && err.contains("'code,name,ptype,description'"),
+            "{err}"
+        );
+    }
+
+    #[test]
+    fn test_definition_errors() {
+        let err = parse_code_line("1-0:31.7.0,l1_current,float,L1 current,A,abc", 3, 0)
+            .err()
+            .unwrap();
+        assert!(err.to_string().contains("line 3"));
+
+        assert!(parse_code_line("0-0:96.13.0,message,string,Text message,,2", 7, 0).is_err());
+        assert!(parse_code_line("1-0:31.7.0,l1_current,float", 8, 0).is_err());
+
+        // no extra columns
+        let (_, dmsr_param) =
+            parse_code_line("1-0:31.7.0,l1_current,double,L1 current", 9, 0).unwrap();
+        assert_eq!(dmsr_param.scale, 1.0);
+        assert_eq!(dmsr_param.offset, 0.0);
+        let pv = get_pvalue("1-0:1.7.0", &dmsr_param, "001.93", Some("A")).unwrap();
+        assert_eq!(
+            pv.eng_value.unwrap().v,
+            Some(ygw::protobuf::ygw::value::V::DoubleValue(1.93))
+        );
+    }
+
+    #[test]
+    fn test_duplicate_definitions() {
+        let err = parse_codes(
+            "# power\n1-0:1.7.0,power,float,Power,kW\n1-0:2.7.0,power,float,Power returned,kW\n"
+                .as_bytes(),
+        )
+        .err()
+        .unwrap();
+        assert!(err
+            .to_string()
+            .contains("the parameter name power is used twice, on the lines 2 and 3"));
+
+        let err = parse_codes(
+            "1-0:1.7.0,power,float,Power,kW\n\n1-0:1.7.0,power2,float,Power,kW\n".as_bytes(),
+        )
+        .err()
+        .unwrap();
+        assert!(err
+            .to_string()
+            .contains("the OBIS code 1-0:1.7.0 is defined twice, on the lines 1 and 3"));
+
+        // several codes can be ignored
+        parse_codes("0-0:96.1.1,ignore,string,Serial\n0-0:96.1.4,ignore,string,Id\n".as_bytes())
+            .unwrap();
+        let toml = "[\"1-0:1.7.0\"]\nname = \"power\"\ntype = \"float\"\ndescription = \"Power\"\n\
+                    [\"1-0:2.7.0\"]\nname = \"power\"\ntype = \"float\"\ndescription = \"Power\"\n";
+        assert!(parse_toml_codes(toml).is_err());
+
+        for line in [
+            "1-0:1.7.0,active power,float,Power",
+            "1-0:1.7.0,/power,float,Power",
+            "1-0:1.7.0, power,float,Power",
+            "0-0:1.0.0,timestamp,integer,Timestamp",
+            "1-0:1.7.0,ignore,float,Power,,,,,,,,,,,,,,warn",
+        ] {
+            assert!(parse_code_line(line, 1, 0).is_err(), "{line}");
+        }
+    }
+
+    #[test]
+    fn test_raw_value() {
+        let (_, dmsr_param) =
+            parse_code_line("1-0:32.7.0,l1_voltage,float,L1 voltage", 1, 0).unwrap();
+        let pv = get_pvalue("1-0:1.7.0", &dmsr_param, "235.2", Some("V")).unwrap();
+        assert_eq!(
+            pv.raw_value.unwrap().v,
+            Some(ygw::protobuf::ygw::value::V::FloatValue(235.2))
+        );
+        assert_eq!(
+            pv.eng_value.unwrap().v,
+            Some(ygw::protobuf::ygw::value::V::FloatValue(235.2))
+        );
+    }
+
+    #[test]
+    fn test_wildcard() {
+        let mut codes = HashMap::new();
+        for (pid, line) in [
+            "1-0:*2.7.0,voltage_{1},float,Voltage",
+            "1-0:72.7.0,l3_voltage,float,L3 voltage",
+        ]
+        .iter()
+        .enumerate()
+        {
+            let (code, dmsr_param) = parse_code_line(line, pid + 1, pid as u32).unwrap();
+            codes.insert(code, dmsr_param);
+        }
+        check_wildcards(&codes).unwrap();
+        let mut codes = ObisCodes::new(codes);
+
+        let telegram = "1-0:32.7.0(235.2*V)\n1-0:52.7.0(234.1*V)\n1-0:72.7.0(236.0*V)\n";
+        let (mut pdefs, pvalues, _) = decode_p1telegram(
+            &mut codes,
+            telegram.as_bytes(),
+            false,
+            DEFAULT_TIMEZONE,
+            &mut Stats::default(),
+        );
+        pdefs.sort_by_key(|p| p.id);
+        let names: Vec<&str> = pdefs.iter().map(|p| p.relative_name.as_str()).collect();
+        assert_eq!(names, vec!["l3_voltage", "voltage_3", "voltage_5"]);
+        assert_eq!(pvalues.len(), 3);
+
+        // the parameters created from the wildcard keep their ids
+        let pid = codes["1-0:32.7.0"].pid;
+        decode_p1telegram(
+            &mut codes,
+            telegram.as_bytes(),
+            false,
+            DEFAULT_TIMEZONE,
+            &mut Stats::default(),
+        );
+        assert_eq!(codes["1-0:32.7.0"].pid, pid);
+    }
+
+    #[test]
+    fn test_wildcard_errors() {
+        assert!(parse_code_line("1-0:*2.7.0,voltage,float,Voltage", 1, 0).is_err());
+
+        let mut codes = HashMap::new();
+        for (pid, line) in [
+            "0-*:24.2.1,mbus_{1},float,M-Bus value",
+            "0-1:24.2.*,gas_{1},float,Gas",
+        ]
+        .iter()
+        .enumerate()
+        {
+            let (code, dmsr_param) = parse_code_line(line, pid + 1, pid as u32).unwrap();
+            codes.insert(code, dmsr_param);
+        }
+        assert!(check_wildcards(&codes).is_err());
+    }
+
+    #[test]
+    fn test_value_group() {
+        let codes = "1-0:99.1.0,peak,float,Peak power,kW,,,,,,,,,2,1\n\
+                     1-0:99.2.0,peak_count,integer,Number of peaks,,,,,,,,,,3\n\
+                     1-0:99.3.0,missing,float,Missing group,kW,,,,,,,,,4\n";
+        let mut obis_codes = parse_codes(codes.as_bytes()).unwrap();
+        let telegram = "/ISK5\\2M550T-1012\r\n\r\n\
+                        1-0:99.1.0(240505094500S)(04.103*kW)(13)\r\n\
+                        1-0:99.2.0(240505094500S)(04.103*kW)(13)\r\n\
+                        1-0:99.3.0(240505094500S)(04.103*kW)(13)\r\n!";
+        let (_, pvalues, _) = decode_p1telegram(
+            &mut obis_codes,
+            telegram.as_bytes(),
+            false,
+            DEFAULT_TIMEZONE,
+            &mut Stats::default(),
+        );
+        // the value of the line without its group is not published
+        let [peak, count] = &pvalues[..] else {
+            panic!("unexpected values {pvalues:?}");
+        };
+        assert_eq!(
+            peak.eng_value.as_ref().unwrap().v,
+            Some(ygw::protobuf::ygw::value::V::FloatValue(4.103))
+        );
+        assert_eq!(
+            peak.generation_time,
+            get_timestamp("240505094500S", DEFAULT_TIMEZONE)
+        );
+        assert_eq!(
+            count.eng_value.as_ref().unwrap().v,
+            Some(ygw::protobuf::ygw::value::V::Sint64Value(13))
+        );
+        assert_eq!(count.generation_time, None);
+
+        assert!(parse_code_line("1-0:99.1.0,peak,float,Peak,kW,,,,,,,,,0", 1, 0).is_err());
+        assert!(parse_code_line("1-0:99.1.0,peak,float,Peak,kW,,,,,,,,,2,2", 1, 0).is_err());
+    }
+
+    #[test]
+    fn test_two_groups() {
+        let codes = "0-1:24.2.1,gas,double,Gas,m3\n1-0:99.4.0,pair,float,Pair,kW\n";
+        let mut obis_codes = parse_codes(codes.as_bytes()).unwrap();
+        let telegram = "0-1:24.2.1(240505094500S)(00012.345*m3)\r\n\
+                        1-0:99.4.0(01.500*kW)(02.500*kW)\r\n";
+        let (_, pvalues, _) = decode_p1telegram(
+            &mut obis_codes,
+            telegram.as_bytes(),
+            false,
+            DEFAULT_TIMEZONE,
+            &mut Stats::default(),
+        );
+        let [gas, pair] = &pvalues[..] else {
+            panic!("unexpected values {pvalues:?}");
+        };
+        assert_eq!(
+            gas.generation_time,
+            get_timestamp("240505094500S", DEFAULT_TIMEZONE)
+        );
+        // the first group is not a timestamp, so it is the value
+        assert_eq!(
+            pair.eng_value.as_ref().unwrap().v,
+            Some(ygw::protobuf::ygw::value::V::FloatValue(1.5))
+        );
+        assert_eq!(pair.generation_time, None);
+
+        assert!(is_dsmr_timestamp("240505094500W"));
+        assert!(!is_dsmr_timestamp("240505094500"));
+        assert!(!is_dsmr_timestamp("2405050945S"));
+    }
+
+    #[test]
+    fn test_group_names() {
+        use ygw::protobuf::ygw::value::V;
+
+        let codes =
+            "1-0:32.32.0,sags_l1,integer,Voltage sags in phase L1,,,,,,,,,,,,count;duration\n";
+        let mut obis_codes = parse_codes(codes.as_bytes()).unwrap();
+        let (pdefs, pvalues, _) = decode_p1telegram(
+            &mut obis_codes,
+            b"1-0:32.32.0(00002)(120*s)\r\n",
+            false,
+            DEFAULT_TIMEZONE,
+            &mut Stats::default(),
+        );
+        let names: Vec<&str> = pdefs.iter().map(|p| p.relative_name.as_str()).collect();
+        assert_eq!(names, vec!["sags_l1_count", "sags_l1_duration"]);
+        assert_eq!(pdefs[1].unit.as_deref(), Some("s"));
+        let values: Vec<_> = pvalues
+            .iter()
+            .map(|pv| pv.eng_value.clone().unwrap().v.unwrap())
+            .collect();
+        assert_eq!(values, vec![V::Sint64Value(2), V::Sint64Value(120)]);
+        assert!(!known_upfront("1-0:32.32.0", &obis_codes["1-0:32.32.0"]));
+
+        // the duration is missing
+        let (pdefs, pvalues, _) = decode_p1telegram(
+            &mut obis_codes,
+            b"1-0:32.32.0(00003)\r\n",
+            false,
+            DEFAULT_TIMEZONE,
+            &mut Stats::default(),
+        );
+        assert!(pdefs.is_empty());
+        assert_eq!(pvalues.len(), 1);
+        assert_eq!(
+            pvalues[0].eng_value.clone().unwrap().v,
+            Some(V::Sint64Value(3))
+        );
+
+        for line in [
+            "1-0:32.32.0,sags,integer,Sags,,,,,,,,,,,,count;count",
+            "1-0:32.32.0,sags,integer,Sags,,,,,,,,,,,,count;",
+            "1-0:32.32.0,sags,integer,Sags,,,,,,,,,,1,,count;duration",
+        ] {
+            assert!(parse_code_line(line, 1, 0).is_err(), "{line}");
+        }
+    }
+
+    #[test]
+    fn test_mbus_device_names() {
+        assert!(parse_code_line("0-1:24.2.1,{device},float,M-Bus value", 1, 0).is_err());
+
+        let (code, dmsr_param) = parse_code_line(
+            "0-*:24.2.1,{device}_{1},double,M-Bus value,,,,{device}",
+            1,
+            0,
+        )
+        .unwrap();
+        let mut codes = ObisCodes::new(HashMap::from([(code, dmsr_param)]));
+        let telegram = "0-2:24.1.0(003)\n0-2:24.2.1(240506200500S)(00012.345*m3)\n\
+                        0-3:24.2.1(240506200500S)(00001.000*m3)\n";
+
+        let (pdefs, pvalues, _) = decode_p1telegram(
+            &mut codes,
+            telegram.as_bytes(),
+            false,
+            DEFAULT_TIMEZONE,
+            &mut Stats::default(),
+        );
+        let names: Vec<&str> = pdefs.iter().map(|p| p.relative_name.as_str()).collect();
+        assert_eq!(names, vec!["gas_2", "mbus_3"]);
+        assert_eq!(codes["0-2:24.2.1"].group.as_deref(), Some("gas"));
+        assert_eq!(pvalues.len(), 2);
+    }
+
+    #[test]
+    fn test_discovery() {
+        let mut codes = ObisCodes::new(HashMap::from([(
+            "1-0:1.7.0".to_owned(),
+            param("power_delivered", "Power delivered", 0),
+        )]));
+        let telegram = "1-0:1.7.0(00.316*kW)\n1-0:21.7.0(00.316*kW)\n";
+
+        let (pdefs, pvalues, _) = decode_p1telegram(
+            &mut codes,
+            telegram.as_bytes(),
+            false,
+            DEFAULT_TIMEZONE,
+            &mut Stats::default(),
+        );
+        assert_eq!(pdefs.len(), 1);
+        assert_eq!(pvalues.len(), 1);
+
+        let (pdefs, pvalues, _) = decode_p1telegram(
+            &mut codes,
+            telegram.as_bytes(),
+            true,
+            DEFAULT_TIMEZONE,
+            &mut Stats::default(),
+        );
+        assert_eq!(pdefs.len(), 1);
+        assert_eq!(pdefs[0].relative_name, "raw/1_0_21_7_0");
+        assert_eq!(pdefs[0].ptype, "String");
+        assert_eq!(pdefs[0].description.as_deref(), Some("auto-discovered"));
+        assert_eq!(pdefs[0].id, 1);
+        assert_eq!(pvalues.len(), 2);
+        assert_eq!(
+            pvalues[1].eng_value.clone().unwrap().v,
+            Some(ygw::protobuf::ygw::value::V::StringValue(
+                "00.316".to_owned()
+            ))
+        );
+    }
+
+    // the first telegram from the test data
+    fn test_telegram() -> &'static str {
+        let data = include_str!("../test-data.txt");
+        let start = data.find('/').unwrap();
+        let end = data[start..].find('!').unwrap() + start;
+        &data[start..end + 7]
+    }
+
+    fn test_source(name: &str) -> (P1Source, serialport::TTYPort) {
+        use serialport::SerialPort;
+
+        let (mut peer, port) = serialport::TTYPort::pair().unwrap();
+        peer.set_timeout(Duration::from_millis(100)).unwrap();
+        let source = P1Source::with_port(
+            name,
+            Some(serial::from_tty(port, serial::DEFAULT_READ_TIMEOUT).unwrap()),
+            name,
+            None,
+        )
+        .unwrap();
+        (source, peer)
+    }
+
+    /// returns a source reading the lines sent to the returned channel, in place of a serial port
+    fn scripted_source(name: &str) -> (P1Source, UnboundedSender<Vec<u8>>) {
+        let (lines, tx) = serial::ScriptedLines::new();
+        let mut source = P1Source::with_port(name, None, name, None).unwrap();
+        source.reader = Some(Box::new(lines));
+        (source, tx)
+    }
+
+    fn test_node(source: P1Source) -> P1Mon {
+        P1Mon {
+            props: YgwLinkNodeProperties {
+                name: "P1MON".to_owned(),
+                description: "test".to_owned(),
+                tm: false,
+                tc: false,
+            },
+            sources: vec![source],
+            links: Vec::new(),
+            mbus_channels: Vec::new(),
+            state_file: None,
+            state_save_every: None,
+            mqtt: None,
+            #[cfg(feature = "metrics")]
+            metrics: None,
+        }
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn test_sub_links() {
+        use std::io::Write;
+
+        let (source, _peer1) = test_source("main");
+        let mut p1mon = test_node(source);
+        assert!(p1mon.sub_links().is_empty());
+
+        let (source, mut peer2) = test_source("solar");
+        p1mon.push_source(source);
+        let links: Vec<(u32, &str)> = p1mon
+            .sub_links()
+            .iter()
+            .map(|l| (l.id, l.props.name.as_str()))
+            .collect();
+        assert_eq!(links, vec![(1, "main"), (2, "solar")]);
+
+        let (tx, mut rx) = tokio::sync::mpsc::channel(100);
+        let (node_tx, node_rx) = tokio::sync::mpsc::channel(100);
+        let jh = tokio::spawn(Box::new(p1mon).run(3, tx, node_rx));
+
+        peer2.write_all(test_telegram().as_bytes()).unwrap();
+
+        loop {
+            let msg = tokio::time::timeout(Duration::from_secs(5), rx.recv())
+                .await
+                .unwrap()
+                .unwrap();
+            if let YgwMessage::ParameterData(addr, pdata) = msg {
+                assert_eq!(addr, Addr::new(3, 2));
+                assert_eq!(pdata.group, "solar");
+                break;
+            }
+        }
+
+        drop(node_tx);
+        tokio::time::timeout(Duration::from_secs(5), jh)
+            .await
+            .unwrap()
+            .unwrap()
+            .unwrap();
+    }
+
+    async fn next_link_state(rx: &mut Receiver<YgwMessage>) -> (i32, Option<String>) {
+        loop {
+            let msg = tokio::time::timeout(Duration::from_secs(5), rx.recv())
+                .await
+                .unwrap()
+                .unwrap();
+            if let YgwMessage::LinkStatus(_, ls) = msg {
+                return (ls.state, ls.err);
+            }
+        }
+    }
+
+    #[test]
+    fn test_node_properties() {
+        let mut house = P1Mon::with_source(scripted_source("main").0);
+        assert_eq!(house.properties().name, "P1MON");
+        house.set_name("house");
+        let mut solar = P1Mon::with_source(scripted_source("main").0);
+        solar.set_name("solar");
+        solar.set_description("Solar inverter meter");
+        assert_eq!(house.properties().name, "house");
+        assert_eq!(
+            house.properties().description,
+            "Monitor electricity usage via P1 port"
+        );
+        assert_eq!(solar.properties().name, "solar");
+        assert_eq!(solar.properties().description, "Solar inverter meter");
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn test_open_retried() {
+        use serialport::SerialPort;
+        use std::io::Write;
+        use ygw::protobuf::ygw::LinkState;
+
+        let dir = std::env::temp_dir().join(format!("p1mon-open-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let device = dir.join("ttyP1");
+        let _ = fs::remove_file(&device);
+
+        // the device does not exist yet
+        let mut p1mon = P1Mon::new(device.to_str().unwrap(), "main", None).unwrap();
+        p1mon.set_name("house");
+        assert_eq!(p1mon.properties().name, "house");
+
+        let (tx, mut rx) = tokio::sync::mpsc::channel(100);
+        let (node_tx, node_rx) = tokio::sync::mpsc::channel(100);
+        let jh = tokio::spawn(Box::new(p1mon).run(3, tx, node_rx));
+        assert_eq!(next_link_state(&mut rx).await.0, LinkState::Failed as i32);
+
+        // the device appears and is opened by the next attempt
+        let (mut peer, port) = serialport::TTYPort::pair().unwrap();
+        peer.set_timeout(Duration::from_millis(100)).unwrap();
+        std::os::unix::fs::symlink(port.name().unwrap(), &device).unwrap();
+        tokio::time::sleep(Duration::from_millis(1500)).await;
+        peer.write_all(test_telegram().as_bytes()).unwrap();
+        assert_eq!(next_link_state(&mut rx).await.0, LinkState::Ok as i32);
+
+        drop(node_tx);
+        tokio::time::timeout(Duration::from_secs(5), jh)
+            .await
+            .unwrap()
+            .unwrap()
+            .unwrap();
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn test_telegram_interrupted() {
+        use serialport::SerialPort;
+        use std::io::Write;
+        use ygw::protobuf::ygw::LinkState;
+
+        let dir = std::env::temp_dir().join(format!("p1mon-interrupted-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let device = dir.join("ttyP1");
+        let _ = fs::remove_file(&device);
+        let pty = |device: &Path| {
+            let (mut peer, port) = serialport::TTYPort::pair().unwrap();
+            peer.set_timeout(Duration::from_millis(100)).unwrap();
+            std::os::unix::fs::symlink(port.name().unwrap(), device).unwrap();
+            (peer, port)
+        };
+
+        let (mut peer, port) = pty(&device);
+        let p1mon = P1Mon::new(device.to_str().unwrap(), "main", None).unwrap();
+        let (tx, mut rx) = tokio::sync::mpsc::channel(100);
+        let (node_tx, node_rx) = tokio::sync::mpsc::channel(100);
+        let jh = tokio::spawn(Box::new(p1mon).run(3, tx, node_rx));
+        assert_eq!(next_link_state(&mut rx).await.0, LinkState::Ok as i32);
+
+        // the meter is disconnected in the middle of a telegram
+        let telegram = test_telegram().as_bytes();
+        peer.write_all(&telegram[..telegram.len() / 2]).unwrap();
+        tokio::time::sleep(Duration::from_millis(300)).await;
+        drop((peer, port));
+        assert_eq!(next_link_state(&mut rx).await.0, LinkState::Failed as i32);
+
+        // the first telegram after the reconnection is complete, not appended to the interrupted one
+        fs::remove_file(&device).unwrap();
+        let (mut peer, _port) = pty(&device);
+        tokio::time::sleep(Duration::from_millis(1500)).await;
+        peer.write_all(telegram).unwrap();
+        let msg = loop {
+            let msg = tokio::time::timeout(Duration::from_secs(5), rx.recv())
+                .await
+                .unwrap()
+                .unwrap();
+            if let YgwMessage::LinkStatus(_, ls) = msg {
+                break ls;
+            }
+        };
+        assert_eq!(
+            (msg.state, msg.data_in_count, msg.data_in_size),
+            (LinkState::Ok as i32, 1, telegram.len() as u64)
+        );
+
+        drop(node_tx);
+        tokio::time::timeout(Duration::from_secs(5), jh)
+            .await
+            .unwrap()
+            .unwrap()
+            .unwrap();
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_builder() {
+        use serialport::SerialPort;
+
+        let dir = std::env::temp_dir().join(format!("p1mon-builder-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let codes_path = dir.join("codes.csv");
+        fs::write(&codes_path, "1-0:1.7.0,power,float,Power,kW\n").unwrap();
+
+        let (_peer, port) = serialport::TTYPort::pair().unwrap();
+        let p1mon = P1MonBuilder::new("house")
+            .serial_device(&port.name().unwrap())
+            .codes_path(&codes_path)
+            .name("HOUSE")
+            .description("Meter of the house")
+            .state_file(&dir.join("state"))
+            .capture_file(&dir.join("capture.txt"), 1000)
+            .read_timeout(Duration::from_millis(500))
+            .max_reconnect_delay(Duration::from_secs(10))
+            .min_interval(Duration::from_secs(10))
+            .status_interval(Duration::from_secs(60))
+            .crc_policy(CrcPolicy::Tolerant)
+            .timezone("Europe/Brussels")
+            .mbus_links(&["1"], Duration::from_secs(3600))
+            .build()
+            .unwrap();
+        assert_eq!(p1mon.props.name, "HOUSE");
+        assert_eq!(p1mon.props.description, "Meter of the house");
+        assert_eq!(p1mon.state_file, Some(dir.join("state")));
+        let source = &p1mon.sources[0];
+        assert_eq!(source.parameter_group, "house");
+        assert!(source.reader.is_some());
+        assert!(source.options.capture.is_some());
+        assert_eq!(source.obis_codes.len(), 1);
+        assert_eq!(source.obis_codes["1-0:1.7.0"].name, "power");
+        assert_eq!(source.options.read_timeout, Duration::from_millis(500));
+        assert_eq!(source.options.max_reconnect_delay, Duration::from_secs(10));
+        assert_eq!(source.throttle.min_interval(), Duration::from_secs(10));
+        assert_eq!(
+            source.options.status_interval,
+            Some(Duration::from_secs(60))
+        );
+        assert_eq!(source.options.crc_policy, CrcPolicy::Tolerant);
+        assert_eq!(source.options.timezone, chrono_tz::Europe::Brussels);
+        assert_eq!(source.options.mbus_timeout, Duration::from_secs(3600));
+        assert_eq!(p1mon.sub_links()[0].props.name, "mbus1");
+
+        // without serial device nor options
+        let p1mon = P1MonBuilder::new("p1mon")
+            .codes_path(&codes_path)
+            .build()
+            .unwrap();
+        assert_eq!(p1mon.props.name, "P1MON");
+        assert!(p1mon.sources[0].reader.is_none());
+        assert!(p1mon.sources[0].options.capture.is_none());
+        assert_eq!(p1mon.state_file, None);
+        let options = &p1mon.sources[0].options;
+        assert_eq!(options.read_timeout, serial::DEFAULT_READ_TIMEOUT);
+        assert_eq!(options.status_interval, None);
+
+        // with the codes given instead of a file
+        let p1mon = P1MonBuilder::new("p1mon")
+            .codes("1-0:2.7.0,power_returned,float,Power returned\n")
+            .build()
+            .unwrap();
+        assert_eq!(p1mon.sources[0].obis_codes.len(), 1);
+        assert!(p1mon.sources[0].codes_watcher.is_none());
+
+        assert!(P1MonBuilder::new("p1mon")
+            .codes_path(&codes_path)
+            .timezone("Europe/Atlantis")
+            .build()
+            .is_err());
+
+        fs::write(&codes_path, "1-0:1.7.0,power,float\n").unwrap();
+        assert!(P1MonBuilder::new("p1mon")
+            .codes_path(&codes_path)
+            .build()
+            .is_err());
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn test_end_to_end() {
+        use chrono::Utc;
+        use serialport::SerialPort;
+        use std::io::Write;
+
+        // the node opens the pseudo-terminal by its name, as a serial device given on the command line
+        let (mut peer, port) = serialport::TTYPort::pair().unwrap();
+        let p1mon = P1Mon::new(&port.name().unwrap(), "p1mon", None).unwrap();
+        let (tx, mut rx) = tokio::sync::mpsc::channel(100);
+        let (node_tx, node_rx) = tokio::sync::mpsc::channel(100);
+        let jh = tokio::spawn(Box::new(p1mon).run(3, tx, node_rx));
+
+        // the time of the telegrams is in whole seconds
+        let start = Utc::now().with_timezone(&chrono_tz::Europe::Amsterdam);
+        let times: Vec<_> = (0..3)
+            .map(|i| start + chrono::Duration::seconds(i - 10))
+            .collect();
+        let feeder = {
+            let times = times.clone();
+            std::thread::spawn(move || {
+                for (n, t) in times.iter().enumerate() {
+                    peer.write_all(&with_crc(&feed::generated_telegram(n as u64, t)))
+                        .unwrap();
+                    std::thread::sleep(Duration::from_millis(100));
+                }
+                peer
+            })
+        };
+
+        let mut defined = HashSet::new();
+        let mut received = Vec::new();
+        while received.len() < times.len() {
+            let msg = tokio::time::timeout(Duration::from_secs(5), rx.recv())
+                .await
+                .unwrap()
+                .unwrap();
+            match msg {
+                YgwMessage::ParameterDefinitions(_, pdefs) => {
+                    defined.extend(pdefs.definitions.iter().map(|pdef| pdef.id));
+                }
+                YgwMessage::ParameterData(addr, pdata) if pdata.group == "p1mon" => {
+                    assert_eq!(addr, Addr::new(3, 0));
+                    // the values are defined before being sent
+                    assert!(pdata.parameters.iter().all(|pv| defined.contains(&pv.id)));
+                    received.push(pdata);
+                }
+                _ => {}
+            }
+        }
+        for (pdata, t) in received.iter().zip(&times) {
+            let gentime = pdata.generation_time.as_ref().unwrap();
+            assert_eq!(timestamp_to_unix(gentime), t.timestamp() * 1000);
+        }
+        let _peer = feeder.join().unwrap();
+
+        drop(node_tx);
+        tokio::time::timeout(Duration::from_secs(5), jh)
+            .await
+            .unwrap()
+            .unwrap()
+            .unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_network_bridge() {
+        use tokio::io::AsyncWriteExt;
+
+        // an invalid address fails the creation of the node
+        assert!(P1Mon::new("tcp://bridge", "p1mon", None).is_err());
+
+        // the bridge is connected when the node starts and again after closing the connection
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let device = format!("tcp://{}", listener.local_addr().unwrap());
+        let p1mon = P1Mon::new(&device, "p1mon", None).unwrap();
+        assert!(p1mon.sources[0].reader.is_none());
+        let (tx, mut rx) = tokio::sync::mpsc::channel(100);
+        let (node_tx, node_rx) = tokio::sync::mpsc::channel(100);
+        let jh = tokio::spawn(Box::new(p1mon).run(3, tx, node_rx));
+
+        let telegram = with_crc(b"/ISK5\\2M550T-1012\r\n\r\n1-0:1.7.0(00.316*kW)\r\n!");
+        for _ in 0..2 {
+            let (mut stream, _) = tokio::time::timeout(Duration::from_secs(5), listener.accept())
+                .await
+                .unwrap()
+                .unwrap();
+            stream.write_all(&telegram).await.unwrap();
+            loop {
+                let msg = tokio::time::timeout(Duration::from_secs(5), rx.recv())
+                    .await
+                    .unwrap()
+                    .unwrap();
+                if matches!(msg, YgwMessage::ParameterData(_, pdata) if pdata.group == "p1mon") {
+                    break;
+                }
+            }
+        }
+
+        drop(node_tx);
+        tokio::time::timeout(Duration::from_secs(5), jh)
+            .await
+            .unwrap()
+            .unwrap()
+            .unwrap();
+    }
+
+    #[test]
+    fn test_check() {
+        let mut codes = parse_codes(
+            "1-0:1.7.0,power,float,Power,kW\n\
+             1-0:2.7.0,power_returned,float,Power returned,kW\n\
+             1-0:32.7.0,l1_voltage,float,Voltage,V\n\
+             0-*:24.2.1,gas_{1},double,Gas\n\
+             0-0:96.1.1,ignore,string,Serial\n\
+             0-0:96.1.4,ignore,string,Id\n"
+                .as_bytes(),
+        )
+        .unwrap();
+        // the duplicate names are rejected when loading the file, not when the table is built otherwise
+        codes.get_mut("1-0:2.7.0").unwrap().name = "power".to_owned();
+        let p1mon = P1Mon::with_source(P1Source::with_codes("main", None, "main", codes, None));
+        let mut out = Vec::new();
+        assert_eq!(p1mon.check(None, &mut out).unwrap(), 1);
+        let out = String::from_utf8(out).unwrap();
+        let lines: Vec<&str> = out.lines().collect();
+        assert_eq!(lines.len(), 7);
+        assert_eq!(lines[0], "1-0:1.7.0\tpower\tFloat\tkW\t0");
+        assert_eq!(
+            lines[6],
+            "problem: the codes 1-0:1.7.0 and 1-0:2.7.0 have the same name power"
+        );
+
+        let mut out = Vec::new();
+        let recording = "/ISK5\\2M550T-1012\r\n\r\n1-0:1.7.0(00.316*kW)\r\n0-1:24.2.1(240506200000S)(00981.443*m3)\r\n\
+                         1-0:31.7.0(001*A)\r\n!1234\r\n";
+        assert_eq!(
+            p1mon.check(Some(recording.as_bytes()), &mut out).unwrap(),
+            1
+        );
+        let out = String::from_utf8(out).unwrap();
+        assert!(out.contains("no definition: 1-0:31.7.0\n"));
+        for code in ["1-0:2.7.0", "1-0:32.7.0", "0-0:96.1.1"] {
+            assert!(out.contains(&format!("not received: {code}\n")), "{code}");
+        }
+        assert!(!out.contains("not received: 0-*:24.2.1"));
+        assert!(!out.contains("not received: 1-0:1.7.0"));
+    }
+
+    #[tokio::test]
+    async fn test_defer_link_up() {
+        use ygw::protobuf::ygw::LinkState;
+
+        let (source, lines) = scripted_source("main");
+        let mut p1mon = test_node(source);
+        p1mon.set_defer_link_up(true);
+        let (tx, mut rx) = tokio::sync::mpsc::channel(100);
+        let (node_tx, node_rx) = tokio::sync::mpsc::channel(100);
+        let jh = tokio::spawn(Box::new(p1mon).run(3, tx, node_rx));
+
+        // the source is silent
+        assert_eq!(
+            next_link_state(&mut rx).await,
+            (LinkState::Unavail as i32, Some("connecting".to_owned()))
+        );
+        tokio::time::sleep(Duration::from_millis(300)).await;
+        while let Ok(msg) = rx.try_recv() {
+            if let YgwMessage::LinkStatus(_, ls) = msg {
+                assert_eq!(ls.state, LinkState::Unavail as i32);
+            }
+        }
+
+        lines.send(test_telegram().as_bytes().to_vec()).unwrap();
+        assert_eq!(next_link_state(&mut rx).await, (LinkState::Ok as i32, None));
+
+        drop(node_tx);
+        drop(lines);
+        tokio::time::timeout(Duration::from_secs(5), jh)
+            .await
+            .unwrap()
+            .unwrap()
+            .unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_scripted_source() {
+        let (source, lines) = scripted_source("main");
+        let p1mon = test_node(source);
+        let (tx, mut rx) = tokio::sync::mpsc::channel(100);
+        let (node_tx, node_rx) = tokio::sync::mpsc::channel(100);
+        let jh = tokio::spawn(Box::new(p1mon).run(3, tx, node_rx));
+
+        let time = chrono::TimeZone::with_ymd_and_hms(
+            &chrono_tz::Europe::Amsterdam,
+            2024,
+            5,
+            6,
+            20,
+            10,
+            8,
+        )
+        .unwrap();
+        for n in 0..2 {
+            lines
+                .send(with_crc(&feed::generated_telegram(
+                    n,
+                    &(time + chrono::Duration::seconds(n as i64)),
+                )))
+                .unwrap();
+        }
+
+        // the definitions of the values come first, followed by the values of each telegram
+        let mut defined = HashSet::new();
+        let mut received = Vec::new();
+        while received.len() < 2 {
+            let msg = tokio::time::timeout(Duration::from_secs(5), rx.recv())
+                .await
+                .unwrap()
+                .unwrap();
+            match msg {
+                YgwMessage::ParameterDefinitions(_, pdefs) => {
+                    assert!(received.is_empty());
+                    defined.extend(
+                        pdefs
+                            .definitions
+                            .iter()
+                            .map(|pdef| pdef.relative_name.clone()),
+                    );
+                }
+                YgwMessage::ParameterData(_, pdata) if pdata.group == "main" => {
+                    received.push(pdata);
+                }
+                _ => {}
+            }
+        }
+        assert!(defined.contains("all_phases_consumption"));
+        assert!(defined.contains("rate_day_total_consumption"));
+        assert_eq!(received[0].seq_num + 1, received[1].seq_num);
+        assert_eq!(
+            timestamp_to_unix(received[1].generation_time.as_ref().unwrap()),
+            time.timestamp() * 1000 + 1000
+        );
+
+        // the end of the scripted lines is seen by the node as the loss of the serial port
+        drop(lines);
+        drop(node_tx);
+        tokio::time::timeout(Duration::from_secs(5), jh)
+            .await
+            .unwrap()
+            .unwrap()
+            .unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_allowlist() {
+        let telegram = "/ISK5\\2M550T-1012\r\n\r\n1-0:1.7.0(00.316*kW)\r\n1-0:2.7.0(00.000*kW)\r\n1-0:32.7.0(230.0*V)\r\n!";
+        let codes = "1-0:1.7.0,power,float,Power\n1-0:2.7.0,production,float,Production\n";
+
+        // the code without definition is counted once however many times it is received
+        let mut obis_codes = parse_codes(codes.as_bytes()).unwrap();
+        let mut stats = Stats::default();
+        for _ in 0..2 {
+            let (_, pvalues, _) = decode_p1telegram(
+                &mut obis_codes,
+                telegram.as_bytes(),
+                false,
+                DEFAULT_TIMEZONE,
+                &mut stats,
+            );
+            assert_eq!(pvalues.len(), 2);
+        }
+        assert_eq!(stats.unknown_codes, 2);
+        assert_eq!(
+            stats.skipped_codes.iter().collect::<Vec<_>>(),
+            vec!["1-0:32.7.0"]
+        );
+
+        // the meter identification is not published either
+        let (mut source, lines) = scripted_source("main");
+        source.obis_codes = parse_codes(codes.as_bytes()).unwrap();
+        let mut p1mon = test_node(source);
+        p1mon.set_allowlist(true);
+        let (tx, mut rx) = tokio::sync::mpsc::channel(100);
+        let (node_tx, node_rx) = tokio::sync::mpsc::channel(100);
+        let jh = tokio::spawn(Box::new(p1mon).run(3, tx, node_rx));
+        lines.send(with_crc(telegram.as_bytes())).unwrap();
+
+        let mut names = Vec::new();
+        let pdata = loop {
+            let msg = tokio::time::timeout(Duration::from_secs(5), rx.recv())
+                .await
+                .unwrap()
+                .unwrap();
+            match msg {
+                YgwMessage::ParameterDefinitions(_, pdefs) => names.extend(
+                    pdefs
+                        .definitions
+                        .into_iter()
+                        .map(|p| p.relative_name)
+                        .filter(|name| !name.starts_with("status/")),
+                ),
+                YgwMessage::ParameterData(_, pdata) if pdata.group == "main" => break pdata,
+                _ => {}
+            }
+        };
+        assert_eq!(names, vec!["power", "production"]);
+        assert_eq!(pdata.parameters.len(), 2);
+
+        drop(node_tx);
+        tokio::time::timeout(Duration::from_secs(5), jh)
+            .await
+            .unwrap()
+            .unwrap()
+            .unwrap();
+    }
+
+    // a telegram of a DSMR 4.2 meter, without its CRC
+    const DSMR42_TELEGRAM: &str = "/KFM5KAIFA-METER\r\n\r\n\
+        1-3:0.2.8(42)\r\n\
+        0-0:1.0.0(161113205757W)\r\n\
+        1-0:1.8.1(001581.123*kWh)\r\n\
+        1-0:1.7.0(02.793*kW)\r\n\
+        0-1:24.2.1(161113200000W)(00981.443*m3)\r\n\
+        !";
+
+    /// processes the telegram line by line as received from the meter
+    async fn process(source: &mut P1Source, state: &mut P1MonState, telegram: Vec<u8>) {
+        let mut assembler = TelegramAssembler::new(
+            DEFAULT_MAX_TELEGRAM_SIZE,
+            DEFAULT_MAX_TELEGRAM_LINES,
+            DEFAULT_TELEGRAM_TIMEOUT,
+        );
+        for line in telegram.split_inclusive(|&b| b == b'\n') {
+            source
+                .process_line(state, &mut assembler, line)
+                .await
+                .unwrap();
+        }
+    }
+
+    #[tokio::test]
+    async fn test_dsmr_version() {
+        let (tx, _rx) = tokio::sync::mpsc::channel(100);
+        let without_crc = format!("{DSMR42_TELEGRAM}\r\n").into_bytes();
+
+        // the version is detected from the first valid telegram, the CRC is then still required
+        let mut state = P1MonState::new(Addr::new(3, 0), tx.clone());
+        let (mut source, _lines) = scripted_source("main");
+        assert_eq!(source.dsmr_version(), None);
+        process(
+            &mut source,
+            &mut state,
+            with_crc(DSMR42_TELEGRAM.as_bytes()),
+        )
+        .await;
+        let version = source.dsmr_version().unwrap();
+        assert_eq!(version.to_string(), "4.2");
+        assert_eq!(version.telegram_interval(), Duration::from_secs(10));
+        process(&mut source, &mut state, without_crc.clone()).await;
+        assert_eq!(
+            (state.stats.telegrams_accepted, state.stats.crc_failures),
+            (1, 1)
+        );
+
+        // the configured version takes precedence over the one reported by the meter
+        let mut state = P1MonState::new(Addr::new(3, 0), tx);
+        let (source, _lines) = scripted_source("main");
+        let mut p1mon = test_node(source);
+        p1mon.set_dsmr_version(DsmrVersion::from_str("3.0").unwrap());
+        let source = &mut p1mon.sources[0];
+        process(source, &mut state, without_crc).await;
+        assert_eq!(
+            (state.stats.telegrams_accepted, state.stats.crc_failures),
+            (1, 0)
+        );
+        assert_eq!(source.dsmr_version, Some(version));
+        assert_eq!(source.dsmr_version().unwrap().to_string(), "3.0");
+
+        let version = DsmrVersion::from_telegram(DSMR5_TELEGRAM.as_bytes()).unwrap();
+        assert_eq!(version.to_string(), "5.0");
+        assert_eq!(version.telegram_interval(), Duration::from_secs(1));
+    }
+
+    #[tokio::test]
+    async fn test_crc_algorithm() {
+        assert_eq!(
+            CrcAlgorithm::from_str("XModem").unwrap(),
+            CrcAlgorithm::Xmodem
+        );
+        assert!(CrcAlgorithm::from_str("crc32").is_err());
+
+        // a test rig computing the CRC with CRC-16/XMODEM
+        let mut telegram = DSMR42_TELEGRAM.as_bytes().to_vec();
+        let crc = crc16::State::<crc16::XMODEM>::calculate(&telegram);
+        telegram.extend_from_slice(format!("{crc:04X}\r\n").as_bytes());
+        let bang = telegram.iter().position(|&b| b == b'!').unwrap();
+        assert!(check_crc(&telegram, bang, CrcAlgorithm::Arc).is_err());
+        assert!(check_crc(&telegram, bang, CrcAlgorithm::Kermit).is_err());
+        assert_eq!(check_crc(&telegram, bang, CrcAlgorithm::Xmodem), Ok(()));
+        assert_eq!(check_crc(&telegram, bang, CrcAlgorithm::None), Ok(()));
+
+        let (tx, _rx) = tokio::sync::mpsc::channel(100);
+        for (algorithm, accepted) in [
+            (CrcAlgorithm::Arc, 0),
+            (CrcAlgorithm::Xmodem, 1),
+            (CrcAlgorithm::None, 1),
+        ] {
+            let mut state = P1MonState::new(Addr::new(3, 0), tx.clone());
+            let (source, _lines) = scripted_source("main");
+            let mut p1mon = test_node(source);
+            p1mon.set_crc_algorithm(algorithm);
+            process(&mut p1mon.sources[0], &mut state, telegram.clone()).await;
+            assert_eq!(
+                (state.stats.telegrams_accepted, state.stats.crc_failures),
+                (accepted, 1 - accepted),
+                "{algorithm:?}"
+            );
+        }
+
+        // without CRC check, the telegrams without CRC are accepted as well
+        let mut state = P1MonState::new(Addr::new(3, 0), tx);
+        let (mut source, _lines) = scripted_source("main");
+        source.options.crc_algorithm = CrcAlgorithm::None;
+        let without_crc = format!("{DSMR42_TELEGRAM}\r\n").into_bytes();
+        process(&mut source, &mut state, without_crc).await;
+        assert_eq!(state.stats.telegrams_accepted, 1);
+    }
+
+    #[tokio::test]
+    async fn test_default_expiry() {
+        assert_eq!(Expiry::from_str("3x").unwrap(), Expiry::Intervals(3));
+        assert_eq!(
+            Expiry::from_str("30s").unwrap(),
+            Expiry::Fixed(Duration::from_secs(30))
+        );
+        assert_eq!(Expiry::from_str("OFF").unwrap(), Expiry::Disabled);
+        for s in ["0x", "0", "x", "soon"] {
+            assert!(Expiry::from_str(s).is_err(), "{s}");
+        }
+
+        let (tx, mut rx) = tokio::sync::mpsc::channel(100);
+        let mut state = P1MonState::new(Addr::new(3, 0), tx);
+        let (mut source, _lines) = scripted_source("main");
+        source.obis_codes = parse_codes(
+            "1-0:1.7.0,power,float,Power\n0-1:24.2.1,gas,double,Gas,m3,,,,,,2h\n".as_bytes(),
+        )
+        .unwrap();
+        source.options.allowlist = true;
+        source.options.mbus_group = false;
+        async fn expiry(
+            source: &mut P1Source,
+            state: &mut P1MonState,
+            rx: &mut Receiver<YgwMessage>,
+        ) -> Vec<Option<i64>> {
+            process(source, state, with_crc(DSMR42_TELEGRAM.as_bytes())).await;
+            let (_, pdata) = next_pdata(rx).await;
+            pdata.parameters.iter().map(|pv| pv.expire_millis).collect()
+        }
+
+        // three times the 10s interval of a DSMR 4.2 meter, the gas keeping its own expiry
+        let expected = [Some(30_000), Some(7_200_000)];
+        assert_eq!(expiry(&mut source, &mut state, &mut rx).await, expected);
+        // the unchanged values being sent again only every minute, they expire after three minutes
+        source.options.max_silence = Duration::from_secs(60);
+        let expected = [Some(180_000), Some(7_200_000)];
+        assert_eq!(expiry(&mut source, &mut state, &mut rx).await, expected);
+        source.options.max_silence = Duration::ZERO;
+        source.options.expiry = Expiry::Fixed(Duration::from_secs(5));
+        let expected = [Some(5_000), Some(7_200_000)];
+        assert_eq!(expiry(&mut source, &mut state, &mut rx).await, expected);
+        source.options.expiry = Expiry::Disabled;
+        assert_eq!(
+            expiry(&mut source, &mut state, &mut rx).await,
+            [None, Some(7_200_000)]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_time_source() {
+        assert_eq!(TimeSource::from_str("Meter").unwrap(), TimeSource::Meter);
+        assert!(TimeSource::from_str("ntp").is_err());
+        assert!(clock_synced(&ygw::protobuf::now()));
+        assert!(!clock_synced(&unix_to_timestamp(0)));
+
+        let (tx, mut rx) = tokio::sync::mpsc::channel(100);
+        let mut state = P1MonState::new(Addr::new(3, 0), tx);
+        let (mut source, _lines) = scripted_source("main");
+        source.obis_codes = parse_codes(
+            "0-0:1.0.0,timestamp,string,Timestamp\n1-0:1.7.0,power,float,Power\n".as_bytes(),
+        )
+        .unwrap();
+        source.options.allowlist = true;
+
+        // the acquisition time is the time of the telegram, the host clock being synchronized anyway
+        for (time_source, meter_time) in
+            [(TimeSource::Meter, true), (TimeSource::HostSynced, false)]
+        {
+            source.options.time_source = time_source;
+            process(
+                &mut source,
+                &mut state,
+                with_crc(DSMR42_TELEGRAM.as_bytes()),
+            )
+            .await;
+            let (_, pdata) = next_pdata(&mut rx).await;
+            assert_eq!(pdata.group, "main");
+            assert_eq!(pdata.parameters[0].acquisition_time, pdata.acquisition_time);
+            let t = utc_converter::to_string(Instant::from(pdata.acquisition_time.unwrap()));
+            assert_eq!(
+                t == "2016-11-13T19:57:57.000Z",
+                meter_time,
+                "{time_source:?}"
+            );
+            assert!(!state.waiting_time_sync);
+            // the offset of the meter clock
+            assert_eq!(next_pdata(&mut rx).await.1.group, STATUS_GROUP);
+        }
+    }
+
+    #[tokio::test]
+    async fn test_telegram_silence() {
+        use ygw::protobuf::ygw::LinkState;
+
+        let (source, lines) = scripted_source("main");
+        let p1mon = test_node(source);
+        let (tx, mut rx) = tokio::sync::mpsc::channel(100);
+        let (node_tx, node_rx) = tokio::sync::mpsc::channel(100);
+        let jh = tokio::spawn(Box::new(p1mon).run(3, tx, node_rx));
+        let time = chrono::Utc::now().with_timezone(&chrono_tz::Europe::Amsterdam);
+        let telegram = with_crc(&feed::generated_telegram(0, &time));
+        assert_eq!(next_link_state(&mut rx).await.0, LinkState::Ok as i32);
+
+        // the DSMR 5 meter is expected to send a telegram every second
+        lines.send(telegram.clone()).unwrap();
+        let t0 = std::time::Instant::now();
+        let (state, err) = next_link_state(&mut rx).await;
+        assert_eq!(state, LinkState::Failed as i32);
+        assert_eq!(err.as_deref(), Some("no telegram for 3s"));
+        assert!(t0.elapsed() >= Duration::from_secs(3));
+
+        lines.send(telegram).unwrap();
+        assert_eq!(next_link_state(&mut rx).await.0, LinkState::Ok as i32);
+
+        drop(node_tx);
+        tokio::time::timeout(Duration::from_secs(5), jh)
+            .await
+            .unwrap()
+            .unwrap()
+            .unwrap();
+    }
+
+    // a telegram of a Belgian meter, with an eMUCS-P1 list of monthly peaks
+    const EMUCS_TELEGRAM: &str = "0-0:96.1.4(50217)\r\n\
+        0-0:1.0.0(200512135409S)\r\n\
+        1-0:1.8.1(000040.196*kWh)\r\n\
+        1-0:1.4.0(02.351*kW)\r\n\
+        1-0:1.6.0(200509134558S)(02.589*kW)\r\n\
+        0-0:98.1.0(3)(1-0:1.6.0)(1-0:1.6.0)(200301000000W)(200207171500W)(04.174*kW)\
+        (200401000000S)(200330181500S)(03.695*kW)(200501000000S)(200423192538S)(03.020*kW)\r\n\
+        0-0:96.3.10(1)\r\n";
+
+    #[test]
+    fn test_belgian_profile() {
+        use ygw::protobuf::ygw::value::V;
+
+        let (mut source, _lines) = scripted_source("main");
+        source.obis_codes = parse_codes(DEFAULT_OBIS_CODES.as_bytes()).unwrap();
+        let timestamp_pid = source.obis_codes["0-0:1.0.0"].pid;
+        let mut p1mon = test_node(source);
+        p1mon.set_profile(Profile::Belgium);
+        let obis_codes = &mut p1mon.sources[0].obis_codes;
+        // the codes of the table are kept
+        assert_eq!(obis_codes["0-0:1.0.0"].pid, timestamp_pid);
+
+        let (pdefs, pvalues, _) = decode_p1telegram(
+            obis_codes,
+            EMUCS_TELEGRAM.as_bytes(),
+            false,
+            DEFAULT_TIMEZONE,
+            &mut Stats::default(),
+        );
+        let value = |name: &str| {
+            let pid = pdefs.iter().find(|p| p.relative_name == name).unwrap().id;
+            let pv = pvalues.iter().find(|pv| pv.id == pid).unwrap();
+            pv.eng_value.clone().unwrap().v.unwrap()
+        };
+        assert_eq!(value("emucs_version"), V::StringValue("50217".to_owned()));
+        assert_eq!(value("current_average_demand"), V::FloatValue(2.351));
+        assert_eq!(value("current_month_peak"), V::FloatValue(2.589));
+        assert_eq!(value("breaker_state"), V::Sint64Value(1));
+        assert_eq!(value("monthly_peaks_count"), V::Sint64Value(3));
+        assert_eq!(value("monthly_peaks_1"), V::FloatValue(4.174));
+        assert_eq!(value("monthly_peaks_3"), V::FloatValue(3.02));
+        assert_eq!(
+            value("monthly_peaks_3_time"),
+            V::StringValue("2020-04-23T17:25:38.000Z".to_owned())
+        );
+        assert_eq!(
+            value("monthly_peaks_1_period"),
+            V::StringValue("2020-02-29T23:00:00.000Z".to_owned())
+        );
+        let unit = |name: &str| {
+            pdefs
+                .iter()
+                .find(|p| p.relative_name == name)
+                .unwrap()
+                .unit
+                .clone()
+        };
+        assert_eq!(unit("monthly_peaks_2").as_deref(), Some("kW"));
+        assert!(!pdefs.iter().any(|p| p.relative_name == "monthly_peaks"));
+
+        // the headroom to the limiter threshold, only when a limit is set
+        let mut headroom = |threshold: &str| {
+            let telegram = format!("0-0:17.0.0({threshold}*kW)\r\n1-0:1.7.0(02.500*kW)\r\n");
+            let (mut pdefs, pvalues, _) = decode_p1telegram(
+                obis_codes,
+                telegram.as_bytes(),
+                false,
+                DEFAULT_TIMEZONE,
+                &mut Stats::default(),
+            );
+            let derived = compute_derived(obis_codes, &pvalues, 0, &mut pdefs);
+            derived
+                .first()
+                .map(|pv| pv.eng_value.clone().unwrap().v.unwrap())
+        };
+        assert_eq!(headroom("016.5"), Some(V::FloatValue(14.0)));
+        assert_eq!(headroom("999.9"), None);
+
+        // the list is not full yet for a new meter
+        let (pdefs, pvalues, _) = decode_p1telegram(
+            obis_codes,
+            b"0-0:98.1.0(1)(1-0:1.6.0)(1-0:1.6.0)(200501000000S)(200423192538S)(03.020*kW)\r\n",
+            false,
+            DEFAULT_TIMEZONE,
+            &mut Stats::default(),
+        );
+        assert!(pdefs.is_empty());
+        assert_eq!(pvalues.len(), 4);
+    }
+
+    #[test]
+    fn test_power_failure_log() {
+        let (code, dmsr_param) = parse_code_line(
+            "0-0:99.97.0,power_failures,integer,Long power failures",
+            1,
+            0,
+        )
+        .unwrap();
+        let mut codes = ObisCodes::new(HashMap::from([(code, dmsr_param)]));
+        let telegram = "0-0:99.97.0(2)(0-0:96.7.19)(230101120000W)(0000000240*s)(230608091501S)(0000000351*s)\n";
+
+        let (pdefs, pvalues, _) = decode_p1telegram(
+            &mut codes,
+            telegram.as_bytes(),
+            false,
+            DEFAULT_TIMEZONE,
+            &mut Stats::default(),
+        );
+        let names: Vec<&str> = pdefs.iter().map(|p| p.relative_name.as_str()).collect();
+        assert_eq!(
+            names,
+            vec![
+                "power_failures_count",
+                "power_failures_last_time",
+                "power_failures_last_duration"
+            ]
+        );
+        assert_eq!(pdefs[2].unit.as_deref(), Some("s"));
+
+        let values: Vec<_> = pvalues
+            .into_iter()
+            .map(|pv| pv.eng_value.unwrap().v.unwrap())
+            .collect();
+        assert_eq!(
+            values,
+            vec![
+                ygw::protobuf::ygw::value::V::Sint64Value(2),
+                // the end of the failure at 09:15:01 summer time
+                ygw::protobuf::ygw::value::V::StringValue("2023-06-08T07:15:01.000Z".to_owned()),
+                ygw::protobuf::ygw::value::V::Sint64Value(351),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_voltage_sags() {
+        let mut codes = parse_codes(DEFAULT_OBIS_CODES.as_bytes()).unwrap();
+        // a counter for L1, a log for L2
+        let telegram = "1-0:32.32.0(00003)\n\
+                        1-0:52.32.0(1)(0-0:96.7.20)(230608091501S)(0000000002*s)\n";
+        let (pdefs, pvalues, _) = decode_p1telegram(
+            &mut codes,
+            telegram.as_bytes(),
+            false,
+            DEFAULT_TIMEZONE,
+            &mut Stats::default(),
+        );
+        let names: Vec<&str> = pdefs.iter().map(|p| p.relative_name.as_str()).collect();
+        assert_eq!(
+            names,
+            vec![
+                "l1_voltage_sags",
+                "l2_voltage_sags_count",
+                "l2_voltage_sags_last_time",
+                "l2_voltage_sags_last_duration"
+            ]
+        );
+        assert_eq!(
+            pdefs[1].description.as_deref(),
+            Some("Number of voltage sags in phase L2 - number of voltage sags")
+        );
+        let values: Vec<_> = pvalues
+            .into_iter()
+            .map(|pv| pv.eng_value.unwrap().v.unwrap())
+            .collect();
+        assert_eq!(values[0], ygw::protobuf::ygw::value::V::Sint64Value(3));
+        assert_eq!(values[1], ygw::protobuf::ygw::value::V::Sint64Value(1));
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn test_power_quality_events() {
+        use std::io::Write;
+
+        let (source, mut peer) = test_source("main");
+        let mut p1mon = test_node(source);
+        p1mon.set_power_quality_events(true);
+        let (tx, mut rx) = tokio::sync::mpsc::channel(100);
+        let (node_tx, node_rx) = tokio::sync::mpsc::channel(100);
+        let jh = tokio::spawn(Box::new(p1mon).run(3, tx, node_rx));
+
+        // no event for the first value received nor for an unchanged counter
+        for sags in ["00003", "00003", "00004"] {
+            peer.write_all(&with_crc(
+                format!("/ISK5\\2M550T-1012\r\n\r\n1-0:32.32.0({sags})\r\n!").as_bytes(),
+            ))
+            .unwrap();
+            std::thread::sleep(Duration::from_millis(100));
+        }
+        let mut events = Vec::new();
+        let mut telegrams = 0;
+        while telegrams < 3 {
+            let msg = tokio::time::timeout(Duration::from_secs(5), rx.recv())
+                .await
+                .unwrap()
+                .unwrap();
+            match msg {
+                YgwMessage::ParameterData(..) => telegrams += 1,
+                YgwMessage::Event(_, event) => events.push(event.message),
+                _ => {}
+            }
+        }
+        assert_eq!(
+            events,
+            vec!["main: voltage sag detected on L1: '1-0:32.32.0 count 3 -> 4'"]
+        );
+
+        drop(node_tx);
+        tokio::time::timeout(Duration::from_secs(5), jh)
+            .await
+            .unwrap()
+            .unwrap()
+            .unwrap();
+    }
+
+    #[test]
+    fn test_text_message() {
+        let (code, dmsr_param) =
+            parse_code_line("0-0:96.13.0,text_message,string,Text message", 1, 0).unwrap();
+        let mut codes = ObisCodes::new(HashMap::from([(code, dmsr_param)]));
+        let mut text = |telegram: &str| {
+            let (_, pvalues, _) = decode_p1telegram(
+                &mut codes,
+                telegram.as_bytes(),
+                false,
+                DEFAULT_TIMEZONE,
+                &mut Stats::default(),
+            );
+            pvalues[0].eng_value.clone().unwrap().v.unwrap()
+        };
+        let string = |s: &str| ygw::protobuf::ygw::value::V::StringValue(s.to_owned());
+
+        assert_eq!(
+            text("0-0:96.13.0(4D61696E74656E616E636520746F6D6F72726F77)\n"),
+            string("Maintenance tomorrow")
+        );
+        assert_eq!(text("0-0:96.13.0()\n"), string(""));
+        assert_eq!(text("0-0:96.13.0(48690000)\n"), string("Hi"));
+        assert_eq!(text("0-0:96.13.0(not hex)\n"), string("not hex"));
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn test_non_utf8_telegram() {
+        use std::io::Write;
+
+        let (source, mut peer) = test_source("main");
+        let p1mon = test_node(source);
+
+        let (tx, mut rx) = tokio::sync::mpsc::channel(100);
+        let (node_tx, node_rx) = tokio::sync::mpsc::channel(100);
+        let jh = tokio::spawn(Box::new(p1mon).run(3, tx, node_rx));
+
+        // the serial number of the meter is ignored in obiscodes.csv
+        let telegram = with_crc(b"/ISK5\\2M550T-1012\r\n\r\n0-0:96.1.1(4B\xff4C)\r\n1-0:1.8.1(000123.456*kWh)\r\n1-0:1.7.0(00.316*kW)\r\n!");
+        peer.write_all(&telegram).unwrap();
+
+        let (_, pdata) = next_pdata(&mut rx).await;
+        // the two numeric values and the meter identification, manufacturer and model
+        assert_eq!(pdata.parameters.len(), 5);
+
+        drop(node_tx);
+        tokio::time::timeout(Duration::from_secs(5), jh)
+            .await
+            .unwrap()
+            .unwrap()
+            .unwrap();
+    }
+
+    /// waits for the next parameter data message, skipping the other messages
+    async fn next_pdata(rx: &mut Receiver<YgwMessage>) -> (Addr, ParameterData) {
+        loop {
+            let msg = tokio::time::timeout(Duration::from_secs(5), rx.recv())
+                .await
+                .unwrap()
+                .unwrap();
+            if let YgwMessage::ParameterData(addr, pdata) = msg {
+                return (addr, pdata);
+            }
+        }
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn test_define_upfront() {
+        use std::io::Write;
+
+        let (mut source, mut peer) = test_source("main");
+        source.obis_codes = parse_codes(
+            "1-0:1.7.0,power,float,Power\n0-1:24.2.1,gas,double,Gas,m3\n0-0:96.7.21,power_failures,integer,Power failures\n"
+                .as_bytes(),
+        )
+        .unwrap();
+        let mut p1mon = test_node(source);
+        p1mon.set_define_upfront(true);
+        let (tx, mut rx) = tokio::sync::mpsc::channel(100);
+        let (node_tx, node_rx) = tokio::sync::mpsc::channel(100);
+        let jh = tokio::spawn(Box::new(p1mon).run(3, tx, node_rx));
+
+        // all the definitions are sent before any telegram, after those of the status parameters
+        let names = loop {
+            let msg = tokio::time::timeout(Duration::from_secs(5), rx.recv())
+                .await
+                .unwrap()
+                .unwrap();
+            let msg = match msg {
+                YgwMessage::CommandDefinitions(..) => continue,
+                msg => msg,
+            };
+            let YgwMessage::ParameterDefinitions(_, pdefs) = msg else {
+                panic!("unexpected message {msg:?}");
+            };
+            let names: Vec<String> = pdefs
+                .definitions
+                .into_iter()
+                .map(|p| p.relative_name)
+                .collect();
+            if !names[0].starts_with("status/") {
+                break names;
+            }
+        };
+        assert_eq!(names, vec!["power", "gas", "power_failures"]);
+
+        // only the parameter without unit in the table is defined again, with the unit of the telegram
+        peer.write_all(test_telegram().as_bytes()).unwrap();
+        let mut redefined = Vec::new();
+        loop {
+            let msg = tokio::time::timeout(Duration::from_secs(5), rx.recv())
+                .await
+                .unwrap()
+                .unwrap();
+            match msg {
+                YgwMessage::ParameterDefinitions(_, pdefs) => redefined.extend(pdefs.definitions),
+                YgwMessage::ParameterData(..) => break,
+                _ => {}
+            }
+        }
+        // (besides the parameters decoded from the header)
+        let redefined: Vec<(&str, Option<&str>)> = redefined
+            .iter()
+            .map(|p| (p.relative_name.as_str(), p.unit.as_deref()))
+            .filter(|(name, _)| !name.starts_with("meter_"))
+            .collect();
+        assert_eq!(redefined, vec![("power", Some("kW"))]);
+
+        drop(node_tx);
+        tokio::time::timeout(Duration::from_secs(5), jh)
+            .await
+            .unwrap()
+            .unwrap()
+            .unwrap();
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn test_reset_counters() {
+        use std::io::Write;
+        use ygw::protobuf::ygw::{value::V, CommandAssignment, CommandId, PreparedCommand, Value};
+
+        let (mut source, mut peer) = test_source("main");
+        source.obis_codes = parse_codes("1-0:1.7.0,power,float,Power\n".as_bytes()).unwrap();
+        source.options.status_interval = Some(Duration::from_secs(3600));
+        source.options.max_silence = Duration::from_secs(3600);
+        let p1mon = test_node(source);
+        let (tx, mut rx) = tokio::sync::mpsc::channel(100);
+        let (node_tx, node_rx) = tokio::sync::mpsc::channel(100);
+        let jh = tokio::spawn(Box::new(p1mon).run(3, tx, node_rx));
+        let telegram = with_crc(b"/ISK5\\2M550T-1012\r\n\r\n1-0:1.7.0(00.316*kW)\r\n!");
+
+        // returns the next data of the group
+        async fn next_group_pdata(rx: &mut Receiver<YgwMessage>, group: &str) -> ParameterData {
+            loop {
+                let (_, pdata) = next_pdata(rx).await;
+                if pdata.group == group && !pdata.parameters.is_empty() {
+                    return pdata;
                 }
-                if dmsr_param.name == "timestamp" {
-                    gentime = get_timestamp(a[0]);
-                    if gentime.is_none() {
-                        log::warn!("Cannot parse timestamp {}", a[0]);
+            }
+        }
+        let received = |pdata: &ParameterData| pdata.parameters[0].eng_value.clone().unwrap().v;
+
+        assert_eq!(next_group_pdata(&mut rx, STATUS_GROUP).await.seq_num, 0);
+        peer.write_all(&telegram).unwrap();
+        assert_eq!(next_group_pdata(&mut rx, "main").await.seq_num, 0);
+
+        let reset = PreparedCommand {
+            command_id: CommandId {
+                command_name: Some("/P1MON/reset_counters".to_owned()),
+                ..Default::default()
+            },
+            assignments: vec![CommandAssignment {
+                name: "clear_last_values".to_owned(),
+                raw_value: None,
+                eng_value: Some(Value {
+                    v: Some(V::BooleanValue(true)),
+                }),
+            }],
+            ..Default::default()
+        };
+        node_tx
+            .send(YgwMessage::TcPacket(Addr::new(3, 0), reset))
+            .await
+            .unwrap();
+
+        // the zeroed statistics are published right away
+        let pdata = loop {
+            let pdata = next_group_pdata(&mut rx, STATUS_GROUP).await;
+            if pdata.parameters.len() > 1 {
+                break pdata;
+            }
+        };
+        assert_eq!(pdata.seq_num, 0);
+        assert_eq!(received(&pdata), Some(V::Sint64Value(0)));
+
+        // the unchanged value is sent again, restarting the sequence count
+        peer.write_all(&telegram).unwrap();
+        let pdata = next_group_pdata(&mut rx, "main").await;
+        assert_eq!(pdata.seq_num, 0);
+        assert_eq!(pdata.parameters.len(), 1);
+
+        drop(node_tx);
+        tokio::time::timeout(Duration::from_secs(5), jh)
+            .await
+            .unwrap()
+            .unwrap()
+            .unwrap();
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn test_pause_resume() {
+        use std::io::Write;
+        use ygw::protobuf::ygw::{CommandId, LinkState, PreparedCommand};
+
+        let (mut source, mut peer) = test_source("main");
+        source.obis_codes = parse_codes("1-0:1.7.0,power,float,Power\n".as_bytes()).unwrap();
+        source.options.max_silence = Duration::from_secs(3600);
+        let p1mon = test_node(source);
+        let (tx, mut rx) = tokio::sync::mpsc::channel(100);
+        let (node_tx, node_rx) = tokio::sync::mpsc::channel(100);
+        let jh = tokio::spawn(Box::new(p1mon).run(3, tx, node_rx));
+        let telegram = |power: &str| {
+            with_crc(format!("/ISK5\\2M550T-1012\r\n\r\n1-0:1.7.0({power}*kW)\r\n!").as_bytes())
+        };
+        let command = |name: &str| PreparedCommand {
+            command_id: CommandId {
+                command_name: Some(name.to_owned()),
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+        // returns the next values of the parameter group main, with the names of the parameters defined before
+        async fn next_power(rx: &mut Receiver<YgwMessage>) -> (ParameterData, Vec<String>) {
+            let mut defined = Vec::new();
+            loop {
+                let msg = tokio::time::timeout(Duration::from_secs(5), rx.recv())
+                    .await
+                    .unwrap()
+                    .unwrap();
+                match msg {
+                    YgwMessage::ParameterDefinitions(_, pdefs) => {
+                        defined.extend(pdefs.definitions.into_iter().map(|p| p.relative_name))
                     }
-                } else {
-                    if let Some(pvalue) = get_pvalue(dmsr_param, a[0]) {
-                        pvalues.push(pvalue);
+                    YgwMessage::ParameterData(_, pdata) if pdata.group == "main" => {
+                        return (pdata, defined)
                     }
+                    _ => {}
                 }
-            } else {
-                log::info!("no parameter for code {}", v[0]);
             }
         }
 
-        if pdefs.len() > 0 {
-            log::debug!("Sending definitions {:?}", pdefs);
-            let pdef_list = ParameterDefinitionList { definitions: pdefs };
-            let _ = p1mon_state
-                .tx
-                .send(YgwMessage::ParameterDefinitions(
-                    p1mon_state.addr,
-                    pdef_list,
-                ))
-                .await;
-        }
-        
+        // paused before the first telegram, nothing is defined until resumed
+        assert_eq!(next_link_state(&mut rx).await.0, LinkState::Ok as i32);
+        node_tx
+            .send(YgwMessage::TcPacket(
+                Addr::new(3, 0),
+                command("/P1MON/pause"),
+            ))
+            .await
+            .unwrap();
+        assert_eq!(
+            next_link_state(&mut rx).await,
+            (LinkState::Disabled as i32, Some("paused".to_owned()))
+        );
+        peer.write_all(&telegram("00.200")).unwrap();
+        tokio::time::sleep(Duration::from_millis(500)).await;
+        node_tx
+            .send(YgwMessage::TcPacket(
+                Addr::new(3, 0),
+                command("/P1MON/resume"),
+            ))
+            .await
+            .unwrap();
+        assert_eq!(next_link_state(&mut rx).await.0, LinkState::Ok as i32);
 
-        let generation_time = gentime.or(Some(now.clone()));
+        peer.write_all(&telegram("00.316")).unwrap();
+        let (pdata, defined) = next_power(&mut rx).await;
+        assert_eq!(pdata.seq_num, 0);
+        assert!(defined.contains(&"power".to_owned()), "{defined:?}");
 
-        if pvalues.len() > 0 {
-            let pdata = ParameterData {
-                parameters: pvalues,
-                group: self.parameter_group.clone(),
-                seq_num: p1mon_state.seq_count,
-                generation_time,
-                acquisition_time: Some(now)
-            };
+        node_tx
+            .send(YgwMessage::TcPacket(
+                Addr::new(3, 0),
+                command("/P1MON/pause"),
+            ))
+            .await
+            .unwrap();
+        assert_eq!(
+            next_link_state(&mut rx).await,
+            (LinkState::Disabled as i32, Some("paused".to_owned()))
+        );
+        peer.write_all(&telegram("00.500")).unwrap();
+        tokio::time::sleep(Duration::from_millis(500)).await;
 
-            p1mon_state.seq_count += 1;
-            log::debug!("Sending parameter values {:?}", pdata);
-            let _ = p1mon_state
-                .tx
-                .send(YgwMessage::ParameterData(p1mon_state.addr, pdata))
-                .await;
-        }
+        node_tx
+            .send(YgwMessage::TcPacket(
+                Addr::new(3, 0),
+                command("/P1MON/resume"),
+            ))
+            .await
+            .unwrap();
+        assert_eq!(next_link_state(&mut rx).await.0, LinkState::Ok as i32);
+
+        // the value did not change since it was last published but it is published again after resuming
+        peer.write_all(&telegram("00.316")).unwrap();
+        let (pdata, _) = next_power(&mut rx).await;
+        assert_eq!(pdata.seq_num, 1);
+        assert_eq!(
+            pdata.parameters[0].eng_value.clone().unwrap().v,
+            Some(ygw::protobuf::ygw::value::V::FloatValue(0.316))
+        );
+
+        drop(node_tx);
+        tokio::time::timeout(Duration::from_secs(5), jh)
+            .await
+            .unwrap()
+            .unwrap()
+            .unwrap();
     }
-}
 
-fn get_pdef(dmsr_param: &DmsrParam, unit: Option<&str>) -> ParameterDefinition {
-    ParameterDefinition {
-        relative_name: dmsr_param.name.clone(),
-        description: Some(dmsr_param.description.clone()),
-        unit: unit.map(|s| s.to_owned()),
-        ptype: format!("{:?}", dmsr_param.ptype),
-        writable: Some(false),
-        id: dmsr_param.pid,
+    #[tokio::test]
+    async fn test_latest_values() {
+        use ygw::protobuf::ygw::value::V;
+
+        let (tx, _rx) = tokio::sync::mpsc::channel(100);
+        let mut state = P1MonState::new(Addr::new(3, 0), tx);
+        let (mut source, _lines) = scripted_source("main");
+        source.obis_codes =
+            parse_codes("1-0:1.7.0,power,float,Power\n0-1:24.2.1,gas,double,Gas,m3\n".as_bytes())
+                .unwrap();
+        source.options.allowlist = true;
+        assert!(state.latest_values().is_empty());
+
+        process(
+            &mut source,
+            &mut state,
+            with_crc(DSMR42_TELEGRAM.as_bytes()),
+        )
+        .await;
+        let telegram = DSMR42_TELEGRAM
+            .replace("02.793", "01.500")
+            .replace("0-1:24.2.1(161113200000W)(00981.443*m3)\r\n", "");
+        process(&mut source, &mut state, with_crc(telegram.as_bytes())).await;
+        assert_eq!(state.stats.telegrams_accepted, 2);
+
+        let latest = state.latest_values();
+        let value = |code: &str| {
+            let pid = source.obis_codes[code].pid;
+            let pv = latest.iter().find(|pv| pv.id == pid).unwrap();
+            pv.eng_value.clone().unwrap().v.unwrap()
+        };
+        assert_eq!(latest.len(), 2);
+        assert_eq!(value("1-0:1.7.0"), V::FloatValue(1.5));
+        // the gas is kept from the first telegram
+        assert_eq!(value("0-1:24.2.1"), V::DoubleValue(981.443));
     }
-}
 
-fn get_timestamp(str_value: &str) -> Option<Timestamp> {
-    //skip the 'S' at the end
-    let s = if str_value.ends_with('S') {
-        &str_value[0..str_value.len() - 1]
-    } else {
-        str_value
-    };
+    #[tokio::test]
+    async fn test_read_now() {
+        use ygw::protobuf::ygw::{CommandId, PreparedCommand};
 
-    if let Ok(dt) = NaiveDateTime::parse_from_str(s, "%y%m%d%H%M%S") {
-        let t = utc_to_instant(DateTimeComponents {
-            year: dt.year(),
-            month: dt.month() as i32,
-            day: dt.day() as i32,
-            hour: dt.hour() as i32,
-            minute: dt.minute() as i32,
-            second: dt.second() as i32,
-            millis: 0,
-        })
-        .into();
-        Some(t)
-    } else {
-        println!("bum");
-        None
+        let (mut source, lines) = scripted_source("main");
+        source.obis_codes =
+            parse_codes("1-0:1.7.0,power,float,Power\n0-1:24.2.1,gas,double,Gas,m3\n".as_bytes())
+                .unwrap();
+        source.options.max_silence = Duration::from_secs(3600);
+        // the gas published with the electricity
+        source.options.mbus_group = false;
+        let p1mon = test_node(source);
+        let (tx, mut rx) = tokio::sync::mpsc::channel(100);
+        let (node_tx, node_rx) = tokio::sync::mpsc::channel(100);
+        let jh = tokio::spawn(Box::new(p1mon).run(3, tx, node_rx));
+        async fn next_main(rx: &mut Receiver<YgwMessage>) -> ParameterData {
+            loop {
+                let (_, pdata) = next_pdata(rx).await;
+                if pdata.group == "main" {
+                    return pdata;
+                }
+            }
+        }
+
+        // the gas is read once per hour by the meter, the power does not change
+        lines
+            .send(with_crc(
+                b"/ISK5\\2M550T-1012\r\n\r\n1-0:1.7.0(00.316*kW)\r\n0-1:24.2.1(240506200000S)(00981.443*m3)\r\n!",
+            ))
+            .unwrap();
+        // with the three parameters of the header
+        assert_eq!(next_main(&mut rx).await.parameters.len(), 5);
+        lines
+            .send(with_crc(
+                b"/ISK5\\2M550T-1012\r\n\r\n1-0:1.7.0(00.316*kW)\r\n!",
+            ))
+            .unwrap();
+        tokio::time::sleep(Duration::from_millis(300)).await;
+
+        let pc = PreparedCommand {
+            command_id: CommandId {
+                command_name: Some("/P1MON/read_now".to_owned()),
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+        node_tx
+            .send(YgwMessage::TcPacket(Addr::new(3, 0), pc))
+            .await
+            .unwrap();
+        let pdata = next_main(&mut rx).await;
+        assert_eq!(pdata.seq_num, 1);
+        let values: Vec<_> = pdata
+            .parameters
+            .iter()
+            .map(|pv| pv.eng_value.clone().unwrap().v.unwrap())
+            .collect();
+        assert_eq!(values.len(), 5);
+        assert_eq!(
+            values[..2],
+            [
+                ygw::protobuf::ygw::value::V::FloatValue(0.316),
+                ygw::protobuf::ygw::value::V::DoubleValue(981.443)
+            ]
+        );
+        // with the time at which the meter read the gas
+        let gas_time = pdata.parameters[1].generation_time.as_ref().unwrap();
+        assert_eq!(
+            utc_converter::to_string(Instant::from(gas_time.clone())),
+            "2024-05-06T18:00:00.000Z"
+        );
+
+        drop(node_tx);
+        tokio::time::timeout(Duration::from_secs(5), jh)
+            .await
+            .unwrap()
+            .unwrap()
+            .unwrap();
     }
-}
 
-fn get_pvalue(dmsr_param: &DmsrParam, str_value: &str) -> Option<ParameterValue> {
-    let eng_value = match dmsr_param.ptype {
-        DmsrParamType::Float => str_value.parse().ok().map(|x| Value {
-            v: Some(ygw::protobuf::ygw::value::V::FloatValue(x)),
-        }),
-        DmsrParamType::Integer => str_value.parse().ok().map(|x| Value {
-            v: Some(ygw::protobuf::ygw::value::V::Sint64Value(x)),
-        }),
-        DmsrParamType::String => Some(Value {
-            v: Some(ygw::protobuf::ygw::value::V::StringValue(
-                str_value.to_owned(),
-            )),
-        }),
-    };
+    #[tokio::test]
+    async fn test_dry_run() {
+        let mut source = P1Source::with_codes(
+            "main",
+            None,
+            "main",
+            parse_codes(
+                "0-0:1.0.0,timestamp,string,Timestamp\n1-0:1.7.0,power,float,Power\n\
+                 0-0:96.1.1,ignore,string,Equipment identifier\n"
+                    .as_bytes(),
+            )
+            .unwrap(),
+            None,
+        );
+        source.options.max_time_behind = Some(Duration::ZERO);
+        let p1mon = P1Mon::with_source(source);
+        let mut input = test_telegram().as_bytes().to_vec();
+        // a telegram with a wrong CRC is processed anyway
+        input.extend_from_slice(b"/ISK5\\2M550T-1012\r\n\r\n1-0:1.7.0(00.500*kW)\r\n!0000\r\n");
 
-    let pv = ParameterValue {
-        id: dmsr_param.pid,
-        raw_value: None,
-        eng_value,
-        acquisition_time: None,
-        generation_time: None,
-        expire_millis: None,
-    };
-    Some(pv)
-}
+        let mut out = Vec::new();
+        p1mon.dry_run(input.as_slice(), &mut out).await.unwrap();
+        let out = String::from_utf8(out).unwrap();
+        let lines: Vec<&str> = out.lines().collect();
+        let names: Vec<&str> = lines
+            .iter()
+            .filter(|l| !l.starts_with("event"))
+            .map(|l| l.split('\t').next().unwrap())
+            .collect();
+        assert_eq!(
+            names,
+            vec![
+                "power",
+                "meter_id",
+                "meter_manufacturer",
+                "meter_model",
+                "status/meter_clock_offset_ms",
+                "power",
+                "meter_id",
+                "meter_manufacturer",
+                "meter_model"
+            ]
+        );
+        assert!(lines.contains(&"power\tFloat\t0.5 kW"), "{out}");
+        assert!(lines
+            .iter()
+            .any(|l| l.starts_with("event TIMESTAMP_REJECTED:")));
+        assert!(lines.iter().any(|l| l.starts_with("event CRC_FAILURE:")));
+    }
 
-//split a line of the form
-// 'ABC(g1)(g2)(g3)'
-// into ['ABC', 'g1', 'g2']
-// it ignores stuff that might be in between
-pub fn split_p1_line(p1line: &str) -> ygw::Result<Vec<&str>> {
-    let mut result = Vec::new();
-    // 0 = before
-    // 1 = inside
-    // 2 = outside
-    let mut state = 0;
-    let mut k = 0;
-    for (i, c) in p1line.chars().enumerate() {
-        match c {
-            '(' => {
-                if state == 0 {
-                    result.push(&p1line[..i]);
-                } else if state != 2 {
-                    return Err(ygw::YgwError::DecodeError(format!(
-                        "Invalid string '{p1line}'"
-                    )));
+    #[tokio::test(flavor = "multi_thread")]
+    async fn test_mbus_links() {
+        use std::io::Write;
+        use ygw::protobuf::ygw::LinkState;
+
+        let (mut source, mut peer) = test_source("main");
+        source.obis_codes =
+            parse_codes("1-0:1.7.0,power,float,Power\n0-1:24.2.1,gas,double,Gas,m3\n".as_bytes())
+                .unwrap();
+        let mut p1mon = test_node(source);
+        p1mon.set_mbus_links(&["1"], Duration::from_millis(500));
+        let links: Vec<(u32, &str)> = p1mon
+            .sub_links()
+            .iter()
+            .map(|l| (l.id, l.props.name.as_str()))
+            .collect();
+        assert_eq!(links, vec![(1, "mbus1")]);
+
+        let (tx, mut rx) = tokio::sync::mpsc::channel(100);
+        let (node_tx, node_rx) = tokio::sync::mpsc::channel(100);
+        let jh = tokio::spawn(Box::new(p1mon).run(3, tx, node_rx));
+        // the meter keeps sending the last reading of the gas meter
+        let telegram = with_crc(
+            b"/ISK5\\2M550T-1012\r\n\r\n1-0:1.7.0(00.316*kW)\r\n\
+              0-1:24.2.1(240506200500S)(00012.345*m3)\r\n!",
+        );
+
+        let mut values = Vec::new();
+        let mut link_states = Vec::new();
+        for _ in 0..8 {
+            peer.write_all(&telegram).unwrap();
+            tokio::time::sleep(Duration::from_millis(100)).await;
+        }
+        while let Ok(msg) = rx.try_recv() {
+            match msg {
+                YgwMessage::ParameterData(addr, pdata) if pdata.group != STATUS_GROUP => {
+                    for pv in pdata.parameters {
+                        values.push((addr.link_id(), pv.id));
+                    }
                 }
-                state = 1;
-                k = i;
+                YgwMessage::LinkStatus(addr, ls) if ls.state != LinkState::Ok as i32 => {
+                    link_states.push((addr.link_id(), ls.state));
+                }
+                _ => {}
             }
-            ')' => {
-                if state != 1 {
-                    return Err(ygw::YgwError::DecodeError(format!(
-                        "Invalid string '{p1line}'"
-                    )));
+        }
+        // the gas on the channel link, the power on the node link
+        assert!(values.contains(&(0, 0)), "{values:?}");
+        assert!(values.contains(&(1, 1)), "{values:?}");
+        assert!(!values.contains(&(0, 1)), "{values:?}");
+        assert_eq!(link_states, vec![(1, LinkState::Failed as i32)]);
+
+        drop(node_tx);
+        tokio::time::timeout(Duration::from_secs(5), jh)
+            .await
+            .unwrap()
+            .unwrap()
+            .unwrap();
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn test_resend_definitions() {
+        use std::io::Write;
+        use ygw::protobuf::ygw::{command_ack::AckStatus, CommandId, PreparedCommand};
+
+        let (mut source, mut peer) = test_source("main");
+        source.obis_codes =
+            parse_codes("1-0:1.7.0,power,float,Power\n0-1:24.2.1,gas,double,Gas,m3\n".as_bytes())
+                .unwrap();
+        let p1mon = test_node(source);
+        let (tx, mut rx) = tokio::sync::mpsc::channel(100);
+        let (node_tx, node_rx) = tokio::sync::mpsc::channel(100);
+        let jh = tokio::spawn(Box::new(p1mon).run(3, tx, node_rx));
+
+        // the gas is not in the telegram
+        peer.write_all(&with_crc(
+            b"/ISK5\\2M550T-1012\r\n\r\n1-0:1.7.0(00.316*kW)\r\n!",
+        ))
+        .unwrap();
+        next_pdata(&mut rx).await;
+
+        let command = |name: &str| PreparedCommand {
+            command_id: CommandId {
+                command_name: Some(name.to_owned()),
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+        node_tx
+            .send(YgwMessage::TcPacket(
+                Addr::new(3, 0),
+                command("/P1MON/resend_definitions"),
+            ))
+            .await
+            .unwrap();
+        node_tx
+            .send(YgwMessage::TcPacket(
+                Addr::new(3, 0),
+                command("/P1MON/format_disk"),
+            ))
+            .await
+            .unwrap();
+
+        let mut acks = Vec::new();
+        let mut names = Vec::new();
+        while acks.len() < 2 || names.is_empty() {
+            let msg = tokio::time::timeout(Duration::from_secs(5), rx.recv())
+                .await
+                .unwrap()
+                .unwrap();
+            match msg {
+                YgwMessage::TcAck(_, ack) => acks.push((ack.ack, ack.message)),
+                YgwMessage::ParameterDefinitions(_, pdefs) => {
+                    names = pdefs
+                        .definitions
+                        .into_iter()
+                        .map(|p| p.relative_name)
+                        .collect()
                 }
-                result.push(&p1line[k + 1..i]);
-                state = 2;
+                _ => {}
             }
-            _ => {}
         }
+        assert_eq!(acks[0], (AckStatus::Ok as i32, None));
+        assert_eq!(acks[1].0, AckStatus::Nok as i32);
+        assert!(acks[1].1.as_deref().unwrap().contains("unknown command"));
+        for name in ["status/meter_clock_offset_ms", "power", "gas", "meter_id"] {
+            assert!(names.iter().any(|n| n == name), "{name} not in {names:?}");
+        }
+
+        drop(node_tx);
+        tokio::time::timeout(Duration::from_secs(5), jh)
+            .await
+            .unwrap()
+            .unwrap()
+            .unwrap();
     }
 
-    if state != 2 {
-        return Err(ygw::YgwError::DecodeError(format!(
-            "Invalid string '{p1line}'"
-        )));
+    #[tokio::test(flavor = "multi_thread")]
+    async fn test_parameter_groups() {
+        use std::io::Write;
+
+        let (mut source, mut peer) = test_source("energy");
+        source.obis_codes = parse_codes(
+            "1-0:1.7.0,power,float,Power\n0-1:24.2.1,gas,double,Gas,,,,gas\n".as_bytes(),
+        )
+        .unwrap();
+        let p1mon = test_node(source);
+
+        let (tx, mut rx) = tokio::sync::mpsc::channel(100);
+        let (node_tx, node_rx) = tokio::sync::mpsc::channel(100);
+        let jh = tokio::spawn(Box::new(p1mon).run(3, tx, node_rx));
+
+        let telegram = with_crc(
+            b"/ISK5\\2M550T-1012\r\n\r\n1-0:1.7.0(00.316*kW)\r\n0-1:24.2.1(00012.345*m3)\r\n!",
+        );
+        for _ in 0..2 {
+            peer.write_all(&telegram).unwrap();
+        }
+
+        let mut received = Vec::new();
+        for _ in 0..4 {
+            let (_, pdata) = next_pdata(&mut rx).await;
+            received.push((pdata.group, pdata.seq_num));
+        }
+        assert_eq!(
+            received,
+            vec![
+                ("energy".to_owned(), 0),
+                ("gas".to_owned(), 0),
+                ("energy".to_owned(), 1),
+                ("gas".to_owned(), 1),
+            ]
+        );
+
+        drop(node_tx);
+        tokio::time::timeout(Duration::from_secs(5), jh)
+            .await
+            .unwrap()
+            .unwrap()
+            .unwrap();
     }
 
-    Ok(result)
-}
+    #[tokio::test(flavor = "multi_thread")]
+    async fn test_value_times() {
+        use std::io::Write;
 
-fn read_codes() -> Result<HashMap<String, DmsrParam>> {
-    let file = File::open("obiscodes.csv")?;
+        let (mut source, mut peer) = test_source("main");
+        source.obis_codes = parse_codes(
+            "0-0:1.0.0,timestamp,string,Timestamp\n1-0:1.7.0,power,float,Power\n0-1:24.2.1,gas,double,Gas\n"
+                .as_bytes(),
+        )
+        .unwrap();
+        let p1mon = test_node(source);
 
-    let reader = io::BufReader::new(file);
+        let (tx, mut rx) = tokio::sync::mpsc::channel(100);
+        let (node_tx, node_rx) = tokio::sync::mpsc::channel(100);
+        let jh = tokio::spawn(Box::new(p1mon).run(3, tx, node_rx));
 
-    let mut m = HashMap::new();
-    let mut pid = 0;
+        let telegram = with_crc(
+            b"/ISK5\\2M550T-1012\r\n\r\n0-0:1.0.0(240506201011S)\r\n1-0:1.7.0(00.316*kW)\r\n\
+              0-1:24.2.1(240506200500S)(00012.345*m3)\r\n!",
+        );
+        peer.write_all(&telegram).unwrap();
 
-    for line in reader.lines() {
-        if let Ok(line) = line {
-            if line.is_empty() || line.starts_with('#') {
-                continue;
+        let (_, pdata) = next_pdata(&mut rx).await;
+        assert_eq!(pdata.group, "main");
+        let power = &pdata.parameters[0];
+        assert_eq!(power.generation_time, pdata.generation_time);
+        assert_eq!(
+            power.generation_time,
+            get_timestamp("240506201011S", DEFAULT_TIMEZONE)
+        );
+        // the gas meter was read a few minutes before the telegram, its value is published in the M-Bus group
+        // with the time of the reading
+        let (_, mbus_pdata) = next_pdata(&mut rx).await;
+        assert_eq!(mbus_pdata.group, "main_mbus");
+        let gas = &mbus_pdata.parameters[0];
+        assert_eq!(
+            gas.generation_time,
+            get_timestamp("240506200500S", DEFAULT_TIMEZONE)
+        );
+        assert_eq!(gas.generation_time, mbus_pdata.generation_time);
+        assert_eq!(
+            gas.eng_value.as_ref().unwrap().v,
+            Some(ygw::protobuf::ygw::value::V::DoubleValue(12.345))
+        );
+        assert!(power.acquisition_time.is_some());
+        assert_eq!(power.acquisition_time, gas.acquisition_time);
+        assert_eq!(power.acquisition_time, pdata.acquisition_time);
+
+        // followed by the offset of the meter clock
+        let (_, pdata) = next_pdata(&mut rx).await;
+        assert_eq!(pdata.group, STATUS_GROUP);
+        let Some(ygw::protobuf::ygw::value::V::Sint64Value(offset)) =
+            pdata.parameters[0].eng_value.as_ref().unwrap().v
+        else {
+            panic!("unexpected clock offset {pdata:?}");
+        };
+        let expected =
+            timestamp_to_unix(&get_timestamp("240506201011S", DEFAULT_TIMEZONE).unwrap())
+                - timestamp_to_unix(power.acquisition_time.as_ref().unwrap());
+        assert_eq!(offset, expected);
+
+        drop(node_tx);
+        tokio::time::timeout(Duration::from_secs(5), jh)
+            .await
+            .unwrap()
+            .unwrap()
+            .unwrap();
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn test_timestamp_limits() {
+        use std::io::Write;
+
+        let (mut source, mut peer) = test_source("main");
+        source.obis_codes = parse_codes(
+            "0-0:1.0.0,timestamp,string,Timestamp\n1-0:1.7.0,power,float,Power\n".as_bytes(),
+        )
+        .unwrap();
+        let mut p1mon = test_node(source);
+        p1mon.set_max_time_ahead(Duration::from_secs(3600));
+        p1mon.set_max_time_behind(Duration::from_secs(48 * 3600));
+
+        let (tx, mut rx) = tokio::sync::mpsc::channel(100);
+        let (node_tx, node_rx) = tokio::sync::mpsc::channel(100);
+        let jh = tokio::spawn(Box::new(p1mon).run(3, tx, node_rx));
+
+        // the clock of the meter was reset
+        let telegram = with_crc(
+            b"/ISK5\\2M550T-1012\r\n\r\n0-0:1.0.0(010101000012W)\r\n1-0:1.7.0(00.316*kW)\r\n!",
+        );
+        peer.write_all(&telegram).unwrap();
+
+        let mut event = None;
+        let pdata = loop {
+            let msg = tokio::time::timeout(Duration::from_secs(5), rx.recv())
+                .await
+                .unwrap()
+                .unwrap();
+            match msg {
+                YgwMessage::Event(_, ev) => event = Some(ev),
+                YgwMessage::ParameterData(_, pdata) => break pdata,
+                _ => {}
             }
-            let parts: Vec<&str> = line.split(',').collect();
-            if parts.len() == 4 {
-                m.insert(
-                    parts[0].to_owned(),
-                    DmsrParam {
-                        name: parts[1].to_owned(),
-                        ptype: DmsrParamType::from_str(parts[2])?,
-                        description: parts[3].to_owned(),
-                        defined: false,
-                        pid,
-                    },
-                );
-                pid += 1;
-            } else {
-                return Err(YgwError::DecodeError(format!(
-                    "wrong OBIS code definition '{line}'"
-                )));
+        };
+        // the host time is used instead
+        let host_time = timestamp_to_unix(&ygw::protobuf::now());
+        let gentime = timestamp_to_unix(pdata.generation_time.as_ref().unwrap());
+        assert!((host_time - gentime).abs() < 10_000);
+        let event = event.unwrap();
+        assert_eq!(event.r#type.as_deref(), Some("TIMESTAMP_REJECTED"));
+        assert!(event.message.contains("behind the host time"));
+        assert!(event.message.contains("2000-12-31T23:00:12"));
+
+        drop(node_tx);
+        tokio::time::timeout(Duration::from_secs(5), jh)
+            .await
+            .unwrap()
+            .unwrap()
+            .unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_open_port_read_timeout() {
+        use serialport::SerialPort;
+
+        // the port is opened with the read timeout given, not only when reopened
+        let (_peer, port) = serialport::TTYPort::pair().unwrap();
+        let name = port.name().unwrap();
+        drop(port);
+        let timeout = Duration::from_millis(500);
+        let port = P1Source::open_port(&name, timeout).unwrap().unwrap();
+        // tokio-serial makes the port non-blocking, the timeout only applies to the blocking reads
+        #[cfg(not(feature = "async-serial"))]
+        assert_eq!(port.timeout(), timeout);
+        #[cfg(feature = "async-serial")]
+        assert_eq!(port.timeout(), Duration::ZERO);
+        drop(port);
+
+        let p1mon = P1MonBuilder::new("p1mon")
+            .serial_device(&name)
+            .codes("1-0:1.7.0,power,float,Power,kW\n")
+            .read_timeout(timeout)
+            .build()
+            .unwrap();
+        assert!(p1mon.sources[0].reader.is_some());
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn test_read_timeouts() {
+        use std::io::Write;
+
+        let (source, mut peer) = test_source("main");
+        let p1mon = test_node(source);
+
+        let (tx, mut rx) = tokio::sync::mpsc::channel(100);
+        let (node_tx, node_rx) = tokio::sync::mpsc::channel(100);
+        let jh = tokio::spawn(Box::new(p1mon).run(3, tx, node_rx));
+
+        // the telegram is interrupted in the middle of a line for several read timeouts
+        let telegram = test_telegram().as_bytes();
+        let (a, b) = telegram.split_at(telegram.len() / 2);
+        peer.write_all(a).unwrap();
+        tokio::time::sleep(Duration::from_millis(500)).await;
+        peer.write_all(b).unwrap();
+
+        let mut link_errors = Vec::new();
+        loop {
+            let msg = tokio::time::timeout(Duration::from_secs(5), rx.recv())
+                .await
+                .unwrap()
+                .unwrap();
+            match msg {
+                YgwMessage::LinkStatus(_, ls) => link_errors.push(ls.err),
+                YgwMessage::ParameterData(..) => break,
+                _ => {}
             }
         }
+        assert_eq!(link_errors, vec![None]);
+
+        drop(node_tx);
+        tokio::time::timeout(Duration::from_secs(5), jh)
+            .await
+            .unwrap()
+            .unwrap()
+            .unwrap();
     }
 
-    Ok(m)
-}
+    #[tokio::test(flavor = "multi_thread")]
+    async fn test_net_power() {
+        use std::io::Write;
 
-#[cfg(test)]
-mod tests {
-    use ygw::utc_converter::{self, Instant};
+        let (mut source, mut peer) = test_source("energy");
+        source.obis_codes = parse_codes(DEFAULT_OBIS_CODES.as_bytes()).unwrap();
+        let net_pid = source.obis_codes["=1-0:1.7.0 - 1-0:2.7.0"].pid;
+        let p1mon = test_node(source);
 
-    use super::*;
+        let (tx, mut rx) = tokio::sync::mpsc::channel(100);
+        let (node_tx, node_rx) = tokio::sync::mpsc::channel(100);
+        let jh = tokio::spawn(Box::new(p1mon).run(3, tx, node_rx));
 
-    #[test]
-    fn test_extract_groups() {
-        let input = "1-0:32.7.0(235.2*V)(40*A)(Test*T)";
-        let expected = vec!["1-0:32.7.0", "235.2*V", "40*A", "Test*T"];
-        let result = split_p1_line(input).unwrap();
-        assert_eq!(result, expected);
+        peer.write_all(&with_crc(
+            b"/ISK5\\2M550T-1012\r\n\r\n1-0:1.7.0(00.316*kW)\r\n1-0:2.7.0(01.500*kW)\r\n!",
+        ))
+        .unwrap();
+
+        let (_, pdata) = next_pdata(&mut rx).await;
+        let net = pdata.parameters.iter().find(|pv| pv.id == net_pid).unwrap();
+        let Some(ygw::protobuf::ygw::value::V::FloatValue(x)) = net.eng_value.as_ref().unwrap().v
+        else {
+            panic!("unexpected value {net:?}");
+        };
+        assert!((x - -1.184).abs() < 1e-6);
+
+        drop(node_tx);
+        tokio::time::timeout(Duration::from_secs(5), jh)
+            .await
+            .unwrap()
+            .unwrap()
+            .unwrap();
     }
 
-    #[test]
-    fn test_extract_groups_err() {
-        let input = "1-0:32.7.0";
-        let result = split_p1_line(input);
-        assert!(result.is_err())
+    #[tokio::test(flavor = "multi_thread")]
+    async fn test_seq_count_restored() {
+        use std::io::Write;
+
+        let path = std::env::temp_dir().join(format!("p1mon-state-{}", std::process::id()));
+        fs::write(&path, "main,main,41\n").unwrap();
+
+        let (source, mut peer) = test_source("main");
+        let mut p1mon = test_node(source);
+        p1mon.set_state_file(&path);
+
+        let (tx, mut rx) = tokio::sync::mpsc::channel(100);
+        let (node_tx, node_rx) = tokio::sync::mpsc::channel(100);
+        let jh = tokio::spawn(Box::new(p1mon).run(3, tx, node_rx));
+
+        peer.write_all(test_telegram().as_bytes()).unwrap();
+        let (_, pdata) = next_pdata(&mut rx).await;
+        assert_eq!(pdata.seq_num, 41);
+
+        // the counts are saved when the node stops
+        drop(node_tx);
+        tokio::time::timeout(Duration::from_secs(5), jh)
+            .await
+            .unwrap()
+            .unwrap()
+            .unwrap();
+        // the clock offset published in the status group has its own sequence count
+        assert!(fs::read_to_string(&path)
+            .unwrap()
+            .lines()
+            .any(|l| l == "main,main,42"));
+        fs::remove_file(&path).unwrap();
     }
-    #[test]
-    fn test_timestamp() {
-        let t = get_timestamp("240506201011S").unwrap();
-        let t  = Instant::from(t);
 
-        assert_eq!(utc_converter::to_string(t), "2024-05-06T20:10:11.000Z");
+    #[tokio::test(flavor = "multi_thread")]
+    async fn test_capture() {
+        use std::io::Write;
+
+        let path = std::env::temp_dir().join(format!("p1mon-capture-{}.txt", std::process::id()));
+        let (source, mut peer) = test_source("main");
+        let mut p1mon = test_node(source);
+        p1mon.set_capture_file(&path, 1_000_000);
+
+        let (tx, mut rx) = tokio::sync::mpsc::channel(100);
+        let (node_tx, node_rx) = tokio::sync::mpsc::channel(100);
+        let jh = tokio::spawn(Box::new(p1mon).run(3, tx, node_rx));
+
+        peer.write_all(test_telegram().as_bytes()).unwrap();
+        next_pdata(&mut rx).await;
+
+        // the capture file is complete once the node has stopped
+        drop(node_tx);
+        tokio::time::timeout(Duration::from_secs(5), jh)
+            .await
+            .unwrap()
+            .unwrap()
+            .unwrap();
+        let captured = fs::read_to_string(&path).unwrap();
+        assert!(captured.starts_with("# "));
+        assert!(captured.ends_with(&format!(" main\r\n{}", test_telegram())));
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn test_line_too_long() {
+        use std::io::Write;
+
+        let (source, mut peer) = test_source("main");
+        let mut p1mon = test_node(source);
+        let max_size = test_telegram().len() + 100;
+        p1mon.set_max_telegram_size(max_size);
+        let (tx, mut rx) = tokio::sync::mpsc::channel(100);
+        let (node_tx, node_rx) = tokio::sync::mpsc::channel(100);
+        let jh = tokio::spawn(Box::new(p1mon).run(3, tx, node_rx));
+
+        // garbage without line end, dropped up to the end of the line, followed by a valid telegram
+        peer.write_all(&vec![b'x'; 2 * max_size]).unwrap();
+        peer.write_all(b"\r\n").unwrap();
+        peer.write_all(test_telegram().as_bytes()).unwrap();
+        let mut events = Vec::new();
+        loop {
+            let msg = tokio::time::timeout(Duration::from_secs(5), rx.recv())
+                .await
+                .unwrap()
+                .unwrap();
+            match msg {
+                YgwMessage::ParameterData(..) => break,
+                YgwMessage::Event(_, event) => events.push(event.message),
+                _ => {}
+            }
+        }
+        assert_eq!(
+            events,
+            vec![format!(
+                "main: Discarding a telegram with a line exceeding {max_size} bytes: ''"
+            )]
+        );
+
+        drop(node_tx);
+        tokio::time::timeout(Duration::from_secs(5), jh)
+            .await
+            .unwrap()
+            .unwrap()
+            .unwrap();
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn test_shutdown() {
+        use std::io::Write;
+
+        let (source, mut peer) = test_source("main");
+        let mut p1mon = test_node(source);
+        p1mon.set_min_interval(Duration::from_secs(60));
+
+        let (tx, mut rx) = tokio::sync::mpsc::channel(100);
+        let (node_tx, node_rx) = tokio::sync::mpsc::channel(100);
+        let jh = tokio::spawn(Box::new(p1mon).run(3, tx, node_rx));
+
+        for _ in 0..2 {
+            peer.write_all(test_telegram().as_bytes()).unwrap();
+        }
+        next_pdata(&mut rx).await;
+        // the values of the second telegram are held back by the minimum interval
+        tokio::time::sleep(Duration::from_millis(500)).await;
+        drop(node_tx);
+        tokio::time::timeout(Duration::from_secs(5), jh)
+            .await
+            .unwrap()
+            .unwrap()
+            .unwrap();
+
+        let mut messages = Vec::new();
+        while let Some(msg) = rx.recv().await {
+            messages.push(msg);
+        }
+        let flushed = messages
+            .iter()
+            .filter(|m| matches!(m, YgwMessage::ParameterData(_, pdata) if pdata.group == "main"))
+            .count();
+        assert_eq!(flushed, 1);
+        let Some(YgwMessage::LinkStatus(_, ls)) = messages.last() else {
+            panic!("no final link status");
+        };
+        assert_eq!(ls.state, ygw::protobuf::ygw::LinkState::Disabled as i32);
+        assert_eq!(ls.data_in_count, 2);
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn test_shutdown_silent_meter() {
+        use std::io::Write;
+
+        let (source, mut peer) = test_source("main");
+        let p1mon = test_node(source);
+
+        let (tx, _rx) = tokio::sync::mpsc::channel(100);
+        let (node_tx, node_rx) = tokio::sync::mpsc::channel(100);
+        let jh = tokio::spawn(Box::new(p1mon).run(3, tx, node_rx));
+
+        // the meter stops sending in the middle of a telegram and of a line
+        peer.write_all(b"/ISK5\\2M550T-1012\r\n\r\n1-0:1.7.0(00.3")
+            .unwrap();
+        tokio::time::sleep(Duration::from_millis(300)).await;
+
+        let t0 = std::time::Instant::now();
+        drop(node_tx);
+        tokio::time::timeout(Duration::from_secs(1), jh)
+            .await
+            .unwrap()
+            .unwrap()
+            .unwrap();
+        assert!(t0.elapsed() < Duration::from_secs(1));
+        drop(peer);
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn test_crc_failures() {
+        use std::io::Write;
+
+        let (source, mut peer) = test_source("main");
+        let mut p1mon = test_node(source);
+        p1mon.set_max_crc_failures(3);
+
+        let (tx, mut rx) = tokio::sync::mpsc::channel(100);
+        let (node_tx, node_rx) = tokio::sync::mpsc::channel(100);
+        let jh = tokio::spawn(Box::new(p1mon).run(3, tx, node_rx));
+
+        let bad = b"/ISK5\\2M550T-1012\r\n\r\n1-0:1.7.0(00.316*kW)\r\n!0000\r\n";
+        for _ in 0..3 {
+            peer.write_all(bad).unwrap();
+        }
+        peer.write_all(test_telegram().as_bytes()).unwrap();
+
+        let mut errors = Vec::new();
+        loop {
+            let msg = tokio::time::timeout(Duration::from_secs(5), rx.recv())
+                .await
+                .unwrap()
+                .unwrap();
+            match msg {
+                YgwMessage::LinkStatus(_, ls) => errors.push((ls.err, ls.data_in_count)),
+                YgwMessage::ParameterData(..) => break,
+                _ => {}
+            }
+        }
+        // initial status, failed after the third bad telegram and ok again with the good one
+        // which is the only one counted as received data
+        assert_eq!(
+            errors,
+            vec![
+                (None, 0),
+                (Some("3 consecutive CRC failures".to_owned()), 0),
+                (None, 1)
+            ]
+        );
+
+        drop(node_tx);
+        tokio::time::timeout(Duration::from_secs(5), jh)
+            .await
+            .unwrap()
+            .unwrap()
+            .unwrap();
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn test_crc_policies() {
+        use std::io::Write;
+        let bad = b"/ISK5\\2M550T-1012\r\n\r\n1-0:1.7.0(00.316*kW)\r\n!0000\r\n";
+
+        // the telegram with a wrong CRC is processed
+        let (source, mut peer) = test_source("main");
+        let mut p1mon = test_node(source);
+        p1mon.set_crc_policy(CrcPolicy::Tolerant);
+        let (tx, mut rx) = tokio::sync::mpsc::channel(100);
+        let (node_tx, node_rx) = tokio::sync::mpsc::channel(100);
+        let jh = tokio::spawn(Box::new(p1mon).run(3, tx, node_rx));
+        peer.write_all(bad).unwrap();
+        let (_, pdata) = next_pdata(&mut rx).await;
+        // the power and the meter identification, manufacturer and model
+        assert_eq!(pdata.parameters.len(), 4);
+        drop(node_tx);
+        tokio::time::timeout(Duration::from_secs(5), jh)
+            .await
+            .unwrap()
+            .unwrap()
+            .unwrap();
+
+        // the link fails and the port is reopened after the third bad telegram
+        let (source, mut peer) = test_source("main");
+        let mut p1mon = test_node(source);
+        p1mon.set_crc_policy(CrcPolicy::Threshold);
+        p1mon.set_max_crc_failures(3);
+        let (tx, mut rx) = tokio::sync::mpsc::channel(100);
+        let (node_tx, node_rx) = tokio::sync::mpsc::channel(100);
+        let jh = tokio::spawn(Box::new(p1mon).run(3, tx, node_rx));
+        for _ in 0..3 {
+            peer.write_all(bad).unwrap();
+        }
+        peer.write_all(test_telegram().as_bytes()).unwrap();
+        let mut errors = Vec::new();
+        loop {
+            let msg = tokio::time::timeout(Duration::from_secs(5), rx.recv())
+                .await
+                .unwrap()
+                .unwrap();
+            match msg {
+                YgwMessage::LinkStatus(_, ls) => errors.push(ls.err),
+                YgwMessage::ParameterData(..) => break,
+                _ => {}
+            }
+        }
+        assert_eq!(errors.len(), 3);
+        assert!(errors[1]
+            .as_deref()
+            .is_some_and(|e| e.contains("3 consecutive CRC failures")));
+        assert_eq!(errors[2], None);
+        drop(node_tx);
+        tokio::time::timeout(Duration::from_secs(5), jh)
+            .await
+            .unwrap()
+            .unwrap()
+            .unwrap();
     }
 }
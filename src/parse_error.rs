@@ -0,0 +1,49 @@
+//! Errors found when parsing the telegrams, one variant per kind of failure such that they can be counted
+//! separately in the statistics and reported with their own severity.
+//!
+//! The telegrams which cannot be assembled or fail the CRC check are discarded as a whole,
+//! the other errors only concern one line or one value of the telegram.
+
+use std::fmt;
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum P1ParseError {
+    /// the telegram was discarded before its terminating !, because it took too long or was too large
+    MissingTerminator(String),
+    /// the CRC following the ! is missing, invalid or different from the one computed
+    BadCrc(String),
+    /// the line is not of the form code(group)(group)...
+    MalformedLine(String),
+    /// the timestamp cannot be parsed or does not exist in the timezone of the meter
+    BadTimestamp { value: String, reason: String },
+    /// there is no definition for the code
+    UnknownCode(String),
+    /// the value of the code cannot be parsed as a number of the type of its parameter
+    BadNumber { code: String, value: String },
+    /// the value of the code is in a unit which cannot be converted into the unit of its parameter
+    BadUnit {
+        code: String,
+        from: String,
+        to: String,
+    },
+}
+
+impl fmt::Display for P1ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            P1ParseError::MissingTerminator(reason) => write!(f, "Discarding a telegram {reason}"),
+            P1ParseError::BadCrc(reason) => write!(f, "{reason}"),
+            P1ParseError::MalformedLine(line) => write!(f, "Cannot parse p1 line '{line}'"),
+            P1ParseError::BadTimestamp { value, reason } => {
+                write!(f, "The timestamp '{value}' {reason}")
+            }
+            P1ParseError::UnknownCode(code) => write!(f, "No parameter for code {code}"),
+            P1ParseError::BadNumber { code, value } => {
+                write!(f, "Cannot parse the value '{value}' of {code}")
+            }
+            P1ParseError::BadUnit { code, from, to } => {
+                write!(f, "Cannot convert the value of {code} from {from} to {to}")
+            }
+        }
+    }
+}
@@ -0,0 +1,35 @@
+//! Profiles of the meters following a national variant of DSMR, which adds its own codes.
+//!
+//! The Belgian meters follow eMUCS-P1 which adds among others the version of the specification (`0-0:96.1.4`),
+//! the average demand over the current 15 minutes (`1-0:1.4.0`), the peak demand of the month (`1-0:1.6.0`)
+//! and the list of the monthly peaks of the last 13 months (`0-0:98.1.0`).
+
+use ygw::{Result, YgwError};
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Profile {
+    /// the codes of DSMR only, as found in the OBIS codes table
+    Dsmr,
+    /// the codes of eMUCS-P1 are added to those of the table
+    Belgium,
+}
+
+impl Profile {
+    pub fn from_str(s: &str) -> Result<Profile> {
+        match s.to_lowercase().as_str() {
+            "dsmr" => Ok(Profile::Dsmr),
+            "belgium" => Ok(Profile::Belgium),
+            _ => Err(YgwError::ParseError(format!(
+                "invalid profile '{s}', expected dsmr or belgium"
+            ))),
+        }
+    }
+
+    /// returns the definitions added by the profile, in the format of the OBIS codes file
+    pub fn codes(&self) -> &'static str {
+        match self {
+            Profile::Dsmr => "",
+            Profile::Belgium => include_str!("emucs.csv"),
+        }
+    }
+}
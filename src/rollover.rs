@@ -0,0 +1,105 @@
+//! Detection of the rollovers of the cumulative registers (energy, gas), enabled per parameter with the rollover
+//! column of the OBIS codes file: `threshold` or `threshold;modulus`.
+//!
+//! A value lower than the previous one by more than the threshold is a rollover of the register (or its reset
+//! when the meter is replaced), counted by the parameter `name_rollover_count`. A smaller drop is a glitch
+//! of the meter, which is ignored. With a modulus (e.g. 1000000 for a register of 6 digits), the parameter
+//! `name_unwrapped` adds the modulus for each rollover such that it keeps increasing.
+
+/// the result of the check of a new value
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Check {
+    /// the value did not decrease, or it is the first one
+    Normal,
+    /// the value decreased by at most the threshold
+    Glitch(f64),
+    /// the value decreased by more than the threshold from the previous value
+    Rollover(f64),
+}
+
+#[derive(Debug, Clone)]
+pub struct Rollover {
+    threshold: f64,
+    modulus: Option<f64>,
+    previous: Option<f64>,
+    count: u32,
+}
+
+impl Rollover {
+    /// parses the threshold, optionally followed by the modulus, e.g. 1000;1000000
+    pub fn parse(s: &str) -> Result<Rollover, String> {
+        let (threshold, modulus) = match s.split_once(';') {
+            Some((threshold, modulus)) => (threshold, Some(modulus)),
+            None => (s, None),
+        };
+        let positive = |s: &str| s.trim().parse::<f64>().ok().filter(|&x| x > 0.0);
+        let threshold = positive(threshold)
+            .ok_or_else(|| format!("invalid rollover threshold '{threshold}'"))?;
+        let modulus = modulus
+            .map(|m| positive(m).ok_or_else(|| format!("invalid rollover modulus '{m}'")))
+            .transpose()?;
+        Ok(Rollover {
+            threshold,
+            modulus,
+            previous: None,
+            count: 0,
+        })
+    }
+
+    /// compares the value x to the previous one, counting the rollover if it decreased by more than the threshold
+    pub fn check(&mut self, x: f64) -> Check {
+        let check = match self.previous {
+            Some(previous) if previous - x > self.threshold => {
+                self.count += 1;
+                Check::Rollover(previous)
+            }
+            Some(previous) if previous > x => Check::Glitch(previous - x),
+            _ => Check::Normal,
+        };
+        self.previous = Some(x);
+        check
+    }
+
+    /// returns the threshold and the modulus
+    pub fn limits(&self) -> (f64, Option<f64>) {
+        (self.threshold, self.modulus)
+    }
+
+    /// returns the number of rollovers detected
+    pub fn count(&self) -> u32 {
+        self.count
+    }
+
+    /// returns the value x with the modulus added for each rollover, None without modulus
+    pub fn unwrapped(&self, x: f64) -> Option<f64> {
+        self.modulus.map(|m| x + f64::from(self.count) * m)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_rollover() {
+        assert!(Rollover::parse("").is_err());
+        assert!(Rollover::parse("-5").is_err());
+        assert!(Rollover::parse("1000;x").is_err());
+
+        let mut r = Rollover::parse("1000;1000000").unwrap();
+        assert_eq!(r.check(999_500.0), Check::Normal);
+        assert_eq!(r.check(999_900.0), Check::Normal);
+        // a small drop is a glitch, not counted
+        assert_eq!(r.check(999_899.5), Check::Glitch(0.5));
+        assert_eq!(r.count(), 0);
+        // the register wraps
+        assert_eq!(r.check(12.0), Check::Rollover(999_899.5));
+        assert_eq!(r.count(), 1);
+        assert_eq!(r.unwrapped(12.0), Some(1_000_012.0));
+        assert_eq!(r.check(15.0), Check::Normal);
+        assert_eq!(r.count(), 1);
+
+        let r = Rollover::parse("50").unwrap();
+        assert_eq!(r.unwrapped(12.0), None);
+    }
+}
@@ -0,0 +1,206 @@
+//! Persistence of the sequence counts of the parameter data.
+//!
+//! The counts are kept in memory and saved periodically and when the node stops to a small state file with one
+//! `source,group,count` line per parameter group, such that the sequence continues after a restart instead of
+//! going back to 0. The count saved is the next sequence number to be used.
+//!
+//! The file is written by a blocking task, not to block the runtime, and not after each publication
+//! not to wear out the flash memory of the small computers connected to the meters; it can also be
+//! saved every N publications to lose fewer counts if the process is killed.
+
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+
+use tokio::task::JoinHandle;
+
+/// the interval between two saves of the counts changed since the last save
+pub const FLUSH_INTERVAL: std::time::Duration = std::time::Duration::from_secs(60);
+
+#[derive(Default)]
+struct Counts {
+    counts: BTreeMap<(String, String), u32>,
+    // the number of updates since the last save
+    unsaved: u32,
+}
+
+pub struct SeqStore {
+    path: PathBuf,
+    counts: Arc<Mutex<Counts>>,
+    // held while writing the file, such that the last write has the latest counts
+    write_lock: Arc<Mutex<()>>,
+    // if set, the file is also saved after this number of updates
+    save_every: Option<u32>,
+}
+
+impl SeqStore {
+    /// reads the state file; if it cannot be read or parsed, the counts start from 0
+    /// with save_every, the file is saved after this number of updates, not only when flushed
+    pub fn load(path: &Path, save_every: Option<u32>) -> Self {
+        let counts = match fs::read_to_string(path) {
+            Ok(s) => parse(&s).unwrap_or_else(|| {
+                log::warn!(
+                    "Invalid state file {}, the sequence counts start from 0",
+                    path.display()
+                );
+                BTreeMap::new()
+            }),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => {
+                log::info!(
+                    "No state file {}, the sequence counts start from 0",
+                    path.display()
+                );
+                BTreeMap::new()
+            }
+            Err(e) => {
+                log::warn!(
+                    "Cannot read the state file {}, the sequence counts start from 0: {e}",
+                    path.display()
+                );
+                BTreeMap::new()
+            }
+        };
+        Self {
+            path: path.to_owned(),
+            counts: Arc::new(Mutex::new(Counts { counts, unsaved: 0 })),
+            write_lock: Arc::new(Mutex::new(())),
+            save_every,
+        }
+    }
+
+    /// returns the saved count of the group of the source or 0 if there is none
+    pub fn get(&self, source: &str, group: &str) -> u32 {
+        let counts = self.counts.lock().unwrap();
+        counts
+            .counts
+            .get(&(source.to_owned(), group.to_owned()))
+            .copied()
+            .unwrap_or(0)
+    }
+
+    /// updates the count of the group of the source, saved with the next flush
+    /// or in the background when the number of updates given to load is reached
+    pub fn set(&self, source: &str, group: &str, count: u32) {
+        let mut counts = self.counts.lock().unwrap();
+        counts
+            .counts
+            .insert((source.to_owned(), group.to_owned()), count);
+        counts.unsaved += 1;
+        if self.save_every.is_some_and(|n| counts.unsaved >= n) {
+            // not saved again by the following updates until this save is done
+            counts.unsaved = 0;
+            drop(counts);
+            self.save();
+        }
+    }
+
+    /// removes the counts of all the groups of the source, which then start again from 0
+    /// the file is saved right away, such that the old counts are not loaded after a restart
+    pub fn reset(&self, source: &str) {
+        let mut counts = self.counts.lock().unwrap();
+        counts.counts.retain(|(s, _), _| s != source);
+        counts.unsaved = 0;
+        drop(counts);
+        self.save();
+    }
+
+    /// saves the counts if they changed since the last save
+    pub async fn flush(&self) {
+        if self.counts.lock().unwrap().unsaved == 0 {
+            return;
+        }
+        if let Err(e) = self.save().await {
+            log::warn!("State file task failed: {e}");
+        }
+    }
+
+    /// writes the counts to the file in a blocking task; write errors are only logged
+    fn save(&self) -> JoinHandle<()> {
+        let path = self.path.clone();
+        let counts = self.counts.clone();
+        let write_lock = self.write_lock.clone();
+        tokio::task::spawn_blocking(move || {
+            let _guard = write_lock.lock().unwrap();
+            let s: String = {
+                let mut counts = counts.lock().unwrap();
+                counts.unsaved = 0;
+                counts
+                    .counts
+                    .iter()
+                    .map(|((source, group), count)| format!("{source},{group},{count}\n"))
+                    .collect()
+            };
+            // write a temporary file first such that a crash does not leave a truncated state file
+            let tmp = path.with_extension("tmp");
+            if let Err(e) = fs::write(&tmp, s).and_then(|_| fs::rename(&tmp, &path)) {
+                log::warn!("Cannot write the state file {}: {e}", path.display());
+            }
+        })
+    }
+}
+
+fn parse(s: &str) -> Option<BTreeMap<(String, String), u32>> {
+    s.lines()
+        .filter(|l| !l.is_empty())
+        .map(|l| {
+            let [source, group, count] = l.split(',').collect::<Vec<_>>()[..] else {
+                return None;
+            };
+            Some(((source.to_owned(), group.to_owned()), count.parse().ok()?))
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_seq_store() {
+        let path = std::env::temp_dir().join(format!("p1mon-seq-{}", std::process::id()));
+        let store = SeqStore::load(&path, None);
+        assert_eq!(store.get("main", "energy"), 0);
+        store.set("main", "energy", 12);
+        store.set("main", "gas", 3);
+        // kept in memory until flushed
+        assert!(!path.exists());
+        store.flush().await;
+
+        let store = SeqStore::load(&path, None);
+        assert_eq!(store.get("main", "energy"), 12);
+        assert_eq!(store.get("main", "gas"), 3);
+
+        fs::write(&path, "main,energy,twelve\n").unwrap();
+        assert_eq!(SeqStore::load(&path, None).get("main", "energy"), 0);
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_save_every() {
+        let path = std::env::temp_dir().join(format!("p1mon-seq-every-{}", std::process::id()));
+        let store = SeqStore::load(&path, Some(3));
+        store.set("main", "energy", 1);
+        store.set("main", "energy", 2);
+        tokio::time::sleep(std::time::Duration::from_millis(100)).await;
+        assert!(!path.exists());
+
+        // saved in the background at the third update
+        store.set("main", "energy", 3);
+        let mut saved = String::new();
+        for _ in 0..50 {
+            tokio::time::sleep(std::time::Duration::from_millis(20)).await;
+            if let Ok(s) = fs::read_to_string(&path) {
+                saved = s;
+                break;
+            }
+        }
+        assert_eq!(saved, "main,energy,3\n");
+
+        // nothing left to flush until the next update
+        store.set("main", "gas", 7);
+        store.flush().await;
+        assert_eq!(SeqStore::load(&path, None).get("main", "gas"), 7);
+        fs::remove_file(&path).unwrap();
+    }
+}
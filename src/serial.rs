@@ -0,0 +1,425 @@
+//! Reading of the lines sent by a meter on a serial port.
+//!
+//! With the `async-serial` feature (enabled by default) the port is read asynchronously with tokio-serial.
+//! Without it, the port is read with blocking reads on a dedicated thread which hands the lines over a channel;
+//! this is meant for the platforms where tokio-serial misbehaves.
+//!
+//! The node reads the lines through the [`LineSource`] trait, such that the tests can replace the serial port
+//! by a scripted sequence of telegrams.
+
+use std::fmt;
+use std::io;
+use std::time::Duration;
+
+use async_trait::async_trait;
+use tokio::io::{AsyncBufRead, AsyncBufReadExt, AsyncReadExt};
+use ygw::{Result, YgwError};
+
+#[cfg(feature = "async-serial")]
+pub use async_serial::{LineReader, Port};
+#[cfg(not(feature = "async-serial"))]
+pub use blocking::{LineReader, Port};
+
+const BAUD_RATE: u32 = 115_200;
+// the time after which a read without data returns, such that the reading loop can do its periodic tasks
+pub const DEFAULT_READ_TIMEOUT: Duration = Duration::from_millis(100);
+
+/// a source of the lines sent by a meter
+#[async_trait]
+pub trait LineSource: Send + Sync {
+    /// returns the next line (including the \n) or None if no complete line was received within the timeout
+    /// a line longer than max_size bytes is dropped and reported by a [`LineTooLong`] error, the reading then
+    /// resumes after its end; any other error ends the connection with the meter
+    async fn next_line(
+        &mut self,
+        timeout: Duration,
+        max_size: usize,
+    ) -> io::Result<Option<Vec<u8>>>;
+}
+
+/// the error of a line dropped for exceeding the given size, such that the memory used by a meter (or bridge)
+/// sending garbage without line ends is bounded
+#[derive(Debug)]
+pub struct LineTooLong(pub usize);
+
+impl fmt::Display for LineTooLong {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "line exceeding {} bytes", self.0)
+    }
+}
+
+impl std::error::Error for LineTooLong {}
+
+/// returns the size exceeded if the error reports a dropped line
+pub fn line_too_long(e: &io::Error) -> Option<usize> {
+    e.get_ref()?.downcast_ref::<LineTooLong>().map(|e| e.0)
+}
+
+/// the line being received from an asynchronous reader
+#[derive(Default)]
+pub struct LineBuffer {
+    // the part of the line received so far
+    line: Vec<u8>,
+    // true while dropping the rest of a line which was too long
+    dropping: bool,
+}
+
+impl LineBuffer {
+    /// reads the next line, at most max_size bytes of it are kept in memory
+    /// the part of a line received before being cancelled (e.g. by a timeout) is kept for the next call
+    pub async fn read<R: AsyncBufRead + Unpin>(
+        &mut self,
+        reader: &mut R,
+        max_size: usize,
+    ) -> io::Result<Option<Vec<u8>>> {
+        loop {
+            // one byte more than the maximum tells a line of max_size bytes apart from a longer one
+            let limit = (max_size + 1).saturating_sub(self.line.len()) as u64;
+            if (&mut *reader)
+                .take(limit)
+                .read_until(b'\n', &mut self.line)
+                .await?
+                == 0
+            {
+                return Err(io::Error::from(io::ErrorKind::UnexpectedEof));
+            }
+            if self.line.last() == Some(&b'\n') {
+                let line = std::mem::take(&mut self.line);
+                if std::mem::take(&mut self.dropping) {
+                    continue;
+                }
+                return Ok(Some(line));
+            }
+            if self.line.len() <= max_size {
+                // end of file after a partial line, reported by the next read
+                return Ok(None);
+            }
+            self.line = Vec::new();
+            if !self.dropping {
+                self.dropping = true;
+                return Err(io::Error::other(LineTooLong(max_size)));
+            }
+        }
+    }
+}
+
+/// opens the serial device with the given read timeout
+pub fn open(serial_device: &str, read_timeout: Duration) -> Result<Port> {
+    #[cfg(feature = "async-serial")]
+    let port = async_serial::open(serial_device, read_timeout);
+    #[cfg(not(feature = "async-serial"))]
+    let port = blocking::open(serial_device, read_timeout);
+
+    port.map_err(|e| YgwError::DeviceAccessError(format!("Cannot access {serial_device}: {}", e)))
+}
+
+/// converts the pseudo terminal used by the tests into a port with the given read timeout
+#[cfg(test)]
+pub fn from_tty(mut port: serialport::TTYPort, read_timeout: Duration) -> io::Result<Port> {
+    serialport::SerialPort::set_timeout(&mut port, read_timeout)?;
+    #[cfg(feature = "async-serial")]
+    return Ok(Port::try_from(port)?);
+    #[cfg(not(feature = "async-serial"))]
+    Ok(Box::new(port))
+}
+
+/// a source of lines fed by the tests in place of a meter, without serial port or pseudo-terminal
+/// the data sent to the channel (e.g. whole telegrams) is returned line by line,
+/// the source ends with an end of file error when the sender is dropped
+#[cfg(test)]
+pub struct ScriptedLines {
+    data: tokio::sync::mpsc::UnboundedReceiver<Vec<u8>>,
+    // the data received and not yet returned
+    buf: Vec<u8>,
+}
+
+#[cfg(test)]
+impl ScriptedLines {
+    /// returns the source and the sender with which the test feeds it
+    pub fn new() -> (Self, tokio::sync::mpsc::UnboundedSender<Vec<u8>>) {
+        let (tx, rx) = tokio::sync::mpsc::unbounded_channel();
+        let source = Self {
+            data: rx,
+            buf: Vec::new(),
+        };
+        (source, tx)
+    }
+}
+
+#[cfg(test)]
+#[async_trait]
+impl LineSource for ScriptedLines {
+    async fn next_line(
+        &mut self,
+        timeout: Duration,
+        _max_size: usize,
+    ) -> io::Result<Option<Vec<u8>>> {
+        loop {
+            if let Some(end) = self.buf.iter().position(|&b| b == b'\n') {
+                return Ok(Some(self.buf.drain(..=end).collect()));
+            }
+            match tokio::time::timeout(timeout, self.data.recv()).await {
+                Err(_) => return Ok(None),
+                Ok(Some(data)) => self.buf.extend_from_slice(&data),
+                Ok(None) => return Err(io::Error::from(io::ErrorKind::UnexpectedEof)),
+            }
+        }
+    }
+}
+
+#[cfg(feature = "async-serial")]
+mod async_serial {
+    use super::*;
+    use tokio::io::BufReader;
+    use tokio_serial::{SerialPortBuilderExt, SerialStream};
+
+    pub type Port = SerialStream;
+
+    pub(super) fn open(serial_device: &str, read_timeout: Duration) -> tokio_serial::Result<Port> {
+        tokio_serial::new(serial_device, BAUD_RATE)
+            .timeout(read_timeout)
+            .open_native_async()
+    }
+
+    pub struct LineReader {
+        reader: BufReader<Port>,
+        line: LineBuffer,
+    }
+
+    impl LineReader {
+        pub fn new(port: Port) -> Self {
+            Self {
+                reader: BufReader::new(port),
+                line: LineBuffer::default(),
+            }
+        }
+    }
+
+    #[async_trait]
+    impl LineSource for LineReader {
+        /// the part of a line received before the timeout is kept for the next call
+        async fn next_line(
+            &mut self,
+            timeout: Duration,
+            max_size: usize,
+        ) -> io::Result<Option<Vec<u8>>> {
+            tokio::time::timeout(timeout, self.line.read(&mut self.reader, max_size))
+                .await
+                .unwrap_or(Ok(None))
+        }
+    }
+}
+
+#[cfg(not(feature = "async-serial"))]
+mod blocking {
+    use super::*;
+    use std::io::{BufRead, BufReader, Read};
+    use std::sync::Mutex;
+    use tokio::sync::mpsc::Receiver;
+
+    pub type Port = Box<dyn serialport::SerialPort>;
+
+    // the reads time out such that the reading thread notices when the line reader is dropped
+    pub(super) fn open(serial_device: &str, read_timeout: Duration) -> serialport::Result<Port> {
+        serialport::new(serial_device, BAUD_RATE)
+            .timeout(read_timeout)
+            .open()
+    }
+
+    pub struct LineReader {
+        // only used to clone the handle given to the reading thread, the mutex makes the reader Sync
+        port: Mutex<Port>,
+        // None until the reading thread is started and after it ended with an error
+        lines: Option<Receiver<io::Result<Vec<u8>>>>,
+    }
+
+    impl LineReader {
+        pub fn new(port: Port) -> Self {
+            Self {
+                port: Mutex::new(port),
+                lines: None,
+            }
+        }
+    }
+
+    #[async_trait]
+    impl LineSource for LineReader {
+        /// the part of a line received before the timeout is kept by the reading thread
+        /// the maximum size of the lines is the one given when the reading thread is started
+        async fn next_line(
+            &mut self,
+            timeout: Duration,
+            max_size: usize,
+        ) -> io::Result<Option<Vec<u8>>> {
+            if self.lines.is_none() {
+                // the reading thread notices within the timeout when the line reader is dropped
+                let mut port = self.port.lock().unwrap().try_clone()?;
+                port.set_timeout(timeout)?;
+                self.lines = Some(spawn_line_reader(port, max_size));
+            }
+            let lines = self.lines.as_mut().unwrap();
+            match tokio::time::timeout(timeout, lines.recv()).await {
+                Err(_) => Ok(None),
+                Ok(Some(Ok(line))) => Ok(Some(line)),
+                Ok(Some(Err(e))) if line_too_long(&e).is_some() => Err(e),
+                Ok(Some(Err(e))) => {
+                    self.lines = None;
+                    Err(e)
+                }
+                Ok(None) => {
+                    self.lines = None;
+                    Err(io::Error::from(io::ErrorKind::BrokenPipe))
+                }
+            }
+        }
+    }
+
+    /// reads the lines from the serial port on a dedicated thread, such that the blocking reads do not hold
+    /// a thread of the runtime, and sends them to the returned channel
+    /// the thread ends after sending an error (other than a line too long) or when the receiver is dropped
+    fn spawn_line_reader(port: Port, max_size: usize) -> Receiver<io::Result<Vec<u8>>> {
+        let (tx, rx) = tokio::sync::mpsc::channel(64);
+        std::thread::spawn(move || {
+            let mut reader = BufReader::new(port);
+            let mut line = Vec::new();
+            // true while dropping the rest of a line which was too long
+            let mut dropping = false;
+            loop {
+                // one byte more than the maximum tells a line of max_size bytes apart from a longer one
+                let limit = (max_size + 1).saturating_sub(line.len()) as u64;
+                match (&mut reader).take(limit).read_until(b'\n', &mut line) {
+                    Ok(0) => {
+                        let _ =
+                            tx.blocking_send(Err(io::Error::from(io::ErrorKind::UnexpectedEof)));
+                        return;
+                    }
+                    Ok(_) if line.last() == Some(&b'\n') => {
+                        let line = std::mem::take(&mut line);
+                        if !std::mem::take(&mut dropping) && tx.blocking_send(Ok(line)).is_err() {
+                            return;
+                        }
+                    }
+                    // end of file after a partial line, reported by the next read
+                    Ok(_) if line.len() <= max_size => {}
+                    Ok(_) => {
+                        line = Vec::new();
+                        if !dropping {
+                            dropping = true;
+                            let e = io::Error::other(LineTooLong(max_size));
+                            if tx.blocking_send(Err(e)).is_err() {
+                                return;
+                            }
+                        }
+                    }
+                    Err(e) if is_timeout(&e) => {
+                        if tx.is_closed() {
+                            return;
+                        }
+                    }
+                    Err(e) => {
+                        let _ = tx.blocking_send(Err(e));
+                        return;
+                    }
+                }
+            }
+        });
+        rx
+    }
+
+    /// returns true if the error only means that no data was received within the timeout of the serial port
+    /// depending on the platform, this is reported as TimedOut or WouldBlock
+    fn is_timeout(e: &io::Error) -> bool {
+        matches!(
+            e.kind(),
+            io::ErrorKind::TimedOut | io::ErrorKind::WouldBlock | io::ErrorKind::Interrupted
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Write;
+    use std::time::Instant;
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn test_read_timeout() {
+        let (mut peer, port) = serialport::TTYPort::pair().unwrap();
+        let timeout = Duration::from_millis(20);
+        let mut reader = LineReader::new(from_tty(port, timeout).unwrap());
+
+        // the read returns soon after the timeout when the meter is quiet
+        let t0 = Instant::now();
+        assert!(reader.next_line(timeout, 1024).await.unwrap().is_none());
+        assert!(t0.elapsed() < Duration::from_millis(500));
+
+        // a line received in parts over several timeouts is returned complete
+        peer.write_all(b"1-0:1.7.0(00.").unwrap();
+        std::thread::sleep(timeout * 3);
+        assert!(reader.next_line(timeout, 1024).await.unwrap().is_none());
+        peer.write_all(b"316*kW)\r\n").unwrap();
+        let mut line = None;
+        for _ in 0..100 {
+            line = reader.next_line(timeout, 1024).await.unwrap();
+            if line.is_some() {
+                break;
+            }
+        }
+        assert_eq!(line.unwrap(), b"1-0:1.7.0(00.316*kW)\r\n");
+    }
+
+    #[tokio::test]
+    async fn test_scripted_lines() {
+        let timeout = Duration::from_millis(20);
+        let (mut source, tx) = ScriptedLines::new();
+        assert!(source.next_line(timeout, 1024).await.unwrap().is_none());
+
+        tx.send(b"/FLU5\r\n\r\n1-0:1.7.0(00.".to_vec()).unwrap();
+        assert_eq!(
+            source.next_line(timeout, 1024).await.unwrap().unwrap(),
+            b"/FLU5\r\n"
+        );
+        assert_eq!(
+            source.next_line(timeout, 1024).await.unwrap().unwrap(),
+            b"\r\n"
+        );
+        assert!(source.next_line(timeout, 1024).await.unwrap().is_none());
+        tx.send(b"316*kW)\r\n".to_vec()).unwrap();
+        assert_eq!(
+            source.next_line(timeout, 1024).await.unwrap().unwrap(),
+            b"1-0:1.7.0(00.316*kW)\r\n"
+        );
+        drop(tx);
+        assert!(source.next_line(timeout, 1024).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_line_too_long() {
+        let data = b"/FLU5\r\n1-0:1.7.0(00.316*kW)(garbage)(garbage)\r\n!ABCD\r\n";
+        let mut reader = &data[..];
+        let mut line = LineBuffer::default();
+        assert_eq!(
+            line.read(&mut reader, 20).await.unwrap().unwrap(),
+            b"/FLU5\r\n"
+        );
+        // the line is dropped up to its end, the next one is returned
+        let e = line.read(&mut reader, 20).await.unwrap_err();
+        assert_eq!(line_too_long(&e), Some(20));
+        assert_eq!(
+            line.read(&mut reader, 20).await.unwrap().unwrap(),
+            b"!ABCD\r\n"
+        );
+        assert!(line.read(&mut reader, 20).await.is_err());
+
+        // a line of exactly the maximum size is kept
+        let mut reader = &b"1-0:1.7.0(00.316*kW)\r\n"[..];
+        let mut line = LineBuffer::default();
+        assert_eq!(line.read(&mut reader, 22).await.unwrap().unwrap().len(), 22);
+
+        // io errors are not taken for lines too long
+        assert_eq!(
+            line_too_long(&io::Error::from(io::ErrorKind::UnexpectedEof)),
+            None
+        );
+    }
+}
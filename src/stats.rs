@@ -0,0 +1,472 @@
+//! Statistics about the telegrams received from a meter, published periodically as parameters of the status group
+//! such that a degrading serial link can be detected in Yamcs.
+//!
+//! The ids of the status parameters are reserved at the top of the id range,
+//! far above the ids given to the OBIS codes.
+//!
+//! The cadence of the telegrams over the last minute tells a DSMR 5 meter (one telegram per second)
+//! from a DSMR 4 meter or a throttled feed (one telegram every 10 seconds), and shows when it drifts.
+
+use std::collections::{BTreeSet, VecDeque};
+use std::time::{Duration, Instant};
+
+use ygw::protobuf::ygw::{value::V, ParameterDefinition, ParameterValue, Value};
+
+use crate::parse_error::P1ParseError;
+
+pub const STATUS_GROUP: &str = "p1mon_status";
+
+const STATUS_PID_BASE: u32 = 0xFFFF_0000;
+// the offset of the meter clock is published with each telegram, independently of the status parameters
+const CLOCK_OFFSET_PID: u32 = STATUS_PID_BASE + 0x100;
+
+// the name, type and description of the status parameters, the id is given by the position
+const STATUS_PARAMS: &[(&str, &str, &str)] = &[
+    (
+        "telegrams_received",
+        "Integer",
+        "Number of telegrams received",
+    ),
+    (
+        "telegrams_accepted",
+        "Integer",
+        "Number of telegrams with a valid CRC",
+    ),
+    (
+        "crc_failures",
+        "Integer",
+        "Number of telegrams with a wrong CRC",
+    ),
+    (
+        "parse_errors",
+        "Integer",
+        "Number of lines which could not be parsed",
+    ),
+    (
+        "unknown_codes",
+        "Integer",
+        "Number of values with an OBIS code without definition",
+    ),
+    (
+        "reconnects",
+        "Integer",
+        "Number of reconnections to the serial port",
+    ),
+    (
+        "seconds_since_last_telegram",
+        "Double",
+        "Time since the last telegram with a valid CRC",
+    ),
+    (
+        "consecutive_crc_failures",
+        "Integer",
+        "Number of consecutive telegrams with a wrong CRC",
+    ),
+    (
+        "crc_ignored",
+        "Integer",
+        "Number of telegrams processed despite a wrong CRC",
+    ),
+    (
+        "last_crc_ignored",
+        "Boolean",
+        "True if the last telegram processed had a wrong CRC",
+    ),
+    (
+        "crc_policy",
+        "String",
+        "Handling of the telegrams with a wrong CRC: strict, tolerant or threshold",
+    ),
+    (
+        "timestamps_rejected",
+        "Integer",
+        "Number of telegram timestamps too far from the host time, replaced by the host time",
+    ),
+    (
+        "heartbeat",
+        "Integer",
+        "Number of heartbeats published, increasing even when the meter is silent",
+    ),
+    (
+        "skipped_codes",
+        "Integer",
+        "Number of different OBIS codes received without definition, which are not published",
+    ),
+    (
+        "telegram_interval",
+        "Double",
+        "Average time between two telegrams over the last minute",
+    ),
+    (
+        "bytes_per_second",
+        "Double",
+        "Average number of bytes of telegrams received per second over the last minute",
+    ),
+    (
+        "incomplete_telegrams",
+        "Integer",
+        "Number of telegrams discarded before their end, being too long or too slow",
+    ),
+    (
+        "bad_timestamps",
+        "Integer",
+        "Number of timestamps which could not be parsed",
+    ),
+    (
+        "bad_values",
+        "Integer",
+        "Number of values which could not be parsed or converted into the unit of their parameter",
+    ),
+];
+// the telegrams received over this time give the cadence of the meter
+const CADENCE_WINDOW: Duration = Duration::from_secs(60);
+// the indexes of the status parameters published with each heartbeat
+const HEARTBEAT_PARAMS: &[u32] = &[6, 12];
+
+#[derive(Debug, Default)]
+pub struct Stats {
+    pub telegrams_received: u64,
+    pub telegrams_accepted: u64,
+    pub crc_failures: u64,
+    pub parse_errors: u64,
+    pub unknown_codes: u64,
+    pub reconnects: u64,
+    pub last_telegram: Option<Instant>,
+    pub consecutive_crc_failures: u64,
+    pub crc_ignored: u64,
+    pub last_crc_ignored: bool,
+    pub crc_policy: &'static str,
+    pub timestamps_rejected: u64,
+    pub heartbeats: u64,
+    pub incomplete_telegrams: u64,
+    pub bad_timestamps: u64,
+    pub bad_values: u64,
+    // the different codes received without definition
+    pub skipped_codes: BTreeSet<String>,
+    pub cadence: Cadence,
+    // the last line which could not be parsed, taken when reporting it
+    pub last_rejected_line: Option<String>,
+    last_publish: Option<Instant>,
+    last_heartbeat: Option<Instant>,
+}
+
+impl Stats {
+    /// returns the definitions of the status parameters
+    pub fn definitions() -> Vec<ParameterDefinition> {
+        STATUS_PARAMS
+            .iter()
+            .enumerate()
+            .map(|(idx, (name, ptype, description))| ParameterDefinition {
+                relative_name: format!("status/{name}"),
+                description: Some(description.to_string()),
+                unit: match *name {
+                    "seconds_since_last_telegram" | "telegram_interval" => Some("s".to_owned()),
+                    "bytes_per_second" => Some("B/s".to_owned()),
+                    _ => None,
+                },
+                ptype: ptype.to_string(),
+                writable: Some(false),
+                id: STATUS_PID_BASE + idx as u32,
+            })
+            .collect()
+    }
+
+    /// returns the definition of the offset of the meter clock relative to the host clock
+    pub fn clock_offset_definition() -> ParameterDefinition {
+        ParameterDefinition {
+            relative_name: "status/meter_clock_offset_ms".to_owned(),
+            description: Some(
+                "Time of the last telegram according to the meter minus the time of its reception"
+                    .to_owned(),
+            ),
+            unit: Some("ms".to_owned()),
+            ptype: "Integer".to_owned(),
+            writable: Some(false),
+            id: CLOCK_OFFSET_PID,
+        }
+    }
+
+    /// returns the value of the offset of the meter clock
+    pub fn clock_offset_value(offset_ms: i64) -> ParameterValue {
+        ParameterValue {
+            id: CLOCK_OFFSET_PID,
+            raw_value: None,
+            eng_value: Some(Value {
+                v: Some(V::Sint64Value(offset_ms)),
+            }),
+            acquisition_time: None,
+            generation_time: None,
+            expire_millis: None,
+        }
+    }
+
+    /// sets the counters back to 0, keeping the time of the last telegram, the cadence and the CRC policy
+    pub fn reset(&mut self) {
+        *self = Stats {
+            cadence: std::mem::take(&mut self.cadence),
+            last_telegram: self.last_telegram,
+            last_crc_ignored: self.last_crc_ignored,
+            crc_policy: self.crc_policy,
+            last_publish: self.last_publish,
+            last_heartbeat: self.last_heartbeat,
+            ..Default::default()
+        };
+    }
+
+    /// counts the parsing error with the errors of its kind
+    pub fn record_error(&mut self, e: &P1ParseError) {
+        let counter = match e {
+            P1ParseError::MissingTerminator(_) => &mut self.incomplete_telegrams,
+            P1ParseError::BadCrc(_) => &mut self.crc_failures,
+            P1ParseError::MalformedLine(_) => &mut self.parse_errors,
+            P1ParseError::BadTimestamp { .. } => &mut self.bad_timestamps,
+            P1ParseError::UnknownCode(_) => &mut self.unknown_codes,
+            P1ParseError::BadNumber { .. } | P1ParseError::BadUnit { .. } => &mut self.bad_values,
+        };
+        *counter += 1;
+    }
+
+    /// returns true if the statistics have to be published at the time now, at most once every interval
+    pub fn publish_due(&mut self, now: Instant, interval: Duration) -> bool {
+        due(&mut self.last_publish, now, interval)
+    }
+
+    /// returns the values published by the heartbeat at the time now, at most once every interval:
+    /// the heartbeat count, incremented at each call, and the time since the last telegram
+    /// returns None if the interval has not elapsed since the last heartbeat
+    pub fn heartbeat(&mut self, now: Instant, interval: Duration) -> Option<Vec<ParameterValue>> {
+        if !due(&mut self.last_heartbeat, now, interval) {
+            return None;
+        }
+        self.heartbeats += 1;
+        Some(
+            self.values(now)
+                .into_iter()
+                .filter(|pv| HEARTBEAT_PARAMS.contains(&(pv.id - STATUS_PID_BASE)))
+                .collect(),
+        )
+    }
+
+    /// returns the values of the status parameters at the time now
+    /// the time since the last telegram is not included if no telegram has been received,
+    /// the cadence if less than two telegrams were received in the last minute
+    /// and the CRC policy is not included if not set
+    pub fn values(&self, now: Instant) -> Vec<ParameterValue> {
+        let counters = [
+            self.telegrams_received,
+            self.telegrams_accepted,
+            self.crc_failures,
+            self.parse_errors,
+            self.unknown_codes,
+            self.reconnects,
+        ];
+        let mut values: Vec<(usize, V)> = counters
+            .iter()
+            .enumerate()
+            .map(|(idx, &n)| (idx, V::Sint64Value(n as i64)))
+            .collect();
+        if let Some(t) = self.last_telegram {
+            values.push((
+                counters.len(),
+                V::DoubleValue(now.duration_since(t).as_secs_f64()),
+            ));
+        }
+        values.push((7, V::Sint64Value(self.consecutive_crc_failures as i64)));
+        values.push((8, V::Sint64Value(self.crc_ignored as i64)));
+        values.push((9, V::BooleanValue(self.last_crc_ignored)));
+        if !self.crc_policy.is_empty() {
+            values.push((10, V::StringValue(self.crc_policy.to_owned())));
+        }
+        values.push((11, V::Sint64Value(self.timestamps_rejected as i64)));
+        values.push((12, V::Sint64Value(self.heartbeats as i64)));
+        values.push((13, V::Sint64Value(self.skipped_codes.len() as i64)));
+        if let Some((interval, bytes_per_second)) = self.cadence.rates(now) {
+            values.push((14, V::DoubleValue(interval.as_secs_f64())));
+            values.push((15, V::DoubleValue(bytes_per_second)));
+        }
+        values.push((16, V::Sint64Value(self.incomplete_telegrams as i64)));
+        values.push((17, V::Sint64Value(self.bad_timestamps as i64)));
+        values.push((18, V::Sint64Value(self.bad_values as i64)));
+
+        values
+            .into_iter()
+            .map(|(idx, v)| ParameterValue {
+                id: STATUS_PID_BASE + idx as u32,
+                raw_value: None,
+                eng_value: Some(Value { v: Some(v) }),
+                acquisition_time: None,
+                generation_time: None,
+                expire_millis: None,
+            })
+            .collect()
+    }
+}
+
+/// the time and size of the telegrams received over the last minute
+#[derive(Debug, Default)]
+pub struct Cadence {
+    telegrams: VecDeque<(Instant, usize)>,
+}
+
+impl Cadence {
+    /// records a telegram of size bytes received at the time now, forgetting those older than the window
+    pub fn record(&mut self, now: Instant, size: usize) {
+        while self
+            .telegrams
+            .front()
+            .is_some_and(|(t, _)| now.duration_since(*t) > CADENCE_WINDOW)
+        {
+            self.telegrams.pop_front();
+        }
+        self.telegrams.push_back((now, size));
+    }
+
+    /// returns the average interval between the telegrams received within the window before now
+    /// and the number of bytes received per second in that time (the first telegram only starting the time)
+    /// returns None if less than two telegrams were received
+    pub fn rates(&self, now: Instant) -> Option<(Duration, f64)> {
+        let mut recent = self
+            .telegrams
+            .iter()
+            .filter(|(t, _)| now.duration_since(*t) <= CADENCE_WINDOW);
+        let &(first, _) = recent.next()?;
+        let (n, bytes, last) = recent.fold((0, 0, first), |(n, bytes, _), &(t, size)| {
+            (n + 1, bytes + size, t)
+        });
+        let span = last.duration_since(first);
+        if span.is_zero() {
+            return None;
+        }
+        Some((span / n, bytes as f64 / span.as_secs_f64()))
+    }
+}
+
+/// returns true if the interval has elapsed at the time now since the last time, which is then set to now
+fn due(last: &mut Option<Instant>, now: Instant, interval: Duration) -> bool {
+    if last.is_some_and(|t| now.duration_since(t) < interval) {
+        return false;
+    }
+    *last = Some(now);
+    true
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_stats() {
+        let t0 = Instant::now();
+        let mut stats = Stats {
+            telegrams_received: 10,
+            telegrams_accepted: 9,
+            crc_failures: 1,
+            ..Default::default()
+        };
+        let pdefs = Stats::definitions();
+        assert_eq!(stats.values(t0).len(), pdefs.len() - 4);
+
+        stats.last_telegram = Some(t0);
+        stats.crc_policy = "strict";
+        stats.cadence.record(t0 - Duration::from_secs(1), 1000);
+        stats.cadence.record(t0, 1000);
+        let values = stats.values(t0 + Duration::from_secs(3));
+        assert_eq!(values.len(), pdefs.len());
+        for (pv, pdef) in values.iter().zip(&pdefs) {
+            assert_eq!(pv.id, pdef.id);
+        }
+        assert_eq!(
+            values[1].eng_value.as_ref().unwrap().v,
+            Some(V::Sint64Value(9))
+        );
+        assert_eq!(
+            values[6].eng_value.as_ref().unwrap().v,
+            Some(V::DoubleValue(3.0))
+        );
+        for (pv, pdef) in values.iter().zip(&pdefs) {
+            let ptype = match pv.eng_value.as_ref().unwrap().v.as_ref().unwrap() {
+                V::Sint64Value(_) => "Integer",
+                V::DoubleValue(_) => "Double",
+                V::BooleanValue(_) => "Boolean",
+                V::StringValue(_) => "String",
+                v => panic!("unexpected value {v:?}"),
+            };
+            assert_eq!(ptype, pdef.ptype);
+        }
+
+        stats.record_error(&P1ParseError::BadCrc("wrong CRC".to_owned()));
+        stats.record_error(&P1ParseError::MissingTerminator("too long".to_owned()));
+        assert_eq!((stats.crc_failures, stats.incomplete_telegrams), (2, 1));
+
+        let interval = Duration::from_secs(60);
+        assert!(stats.publish_due(t0, interval));
+        assert!(!stats.publish_due(t0 + Duration::from_secs(59), interval));
+        assert!(stats.publish_due(t0 + Duration::from_secs(60), interval));
+    }
+
+    #[test]
+    fn test_cadence() {
+        let t0 = Instant::now();
+        let mut cadence = Cadence::default();
+        assert!(cadence.rates(t0).is_none());
+
+        // a DSMR 5 meter sending a telegram of 800 bytes every second, with some jitter
+        for i in 0..120u64 {
+            let jitter = Duration::from_millis(i % 3 * 10);
+            cadence.record(t0 + Duration::from_secs(i) + jitter, 800);
+        }
+        let now = t0 + Duration::from_secs(120);
+        let (interval, bytes_per_second) = cadence.rates(now).unwrap();
+        assert!((interval.as_secs_f64() - 1.0).abs() < 0.01, "{interval:?}");
+        assert!(
+            (bytes_per_second - 800.0).abs() < 10.0,
+            "{bytes_per_second}"
+        );
+        // only the last minute is kept
+        assert!(cadence.telegrams.len() <= 62);
+
+        // the meter slows down to one telegram every 10 seconds
+        for i in 1..=6u64 {
+            cadence.record(now + Duration::from_secs(i * 10), 800);
+        }
+        let (interval, bytes_per_second) = cadence.rates(now + Duration::from_secs(65)).unwrap();
+        assert_eq!(interval, Duration::from_secs(10));
+        assert_eq!(bytes_per_second, 80.0);
+
+        // and stops
+        assert!(cadence.rates(now + Duration::from_secs(300)).is_none());
+    }
+
+    #[test]
+    fn test_heartbeat() {
+        let t0 = Instant::now();
+        let interval = Duration::from_secs(10);
+        let mut stats = Stats::default();
+        let value = |pv: &ParameterValue| pv.eng_value.clone().unwrap().v.unwrap();
+
+        // no telegram received yet
+        let values = stats.heartbeat(t0, interval).unwrap();
+        assert_eq!(values.len(), 1);
+        assert_eq!(value(&values[0]), V::Sint64Value(1));
+        assert!(stats
+            .heartbeat(t0 + Duration::from_secs(5), interval)
+            .is_none());
+
+        // the meter then stays silent
+        stats.last_telegram = Some(t0);
+        let mut ages = Vec::new();
+        for i in 1..=3 {
+            let values = stats.heartbeat(t0 + interval * i, interval).unwrap();
+            assert_eq!(value(&values[1]), V::Sint64Value(i as i64 + 1));
+            ages.push(value(&values[0]));
+        }
+        assert_eq!(
+            ages,
+            vec![
+                V::DoubleValue(10.0),
+                V::DoubleValue(20.0),
+                V::DoubleValue(30.0)
+            ]
+        );
+    }
+}
@@ -0,0 +1,339 @@
+//! Reading of the lines sent by a meter through a network bridge (e.g. a P1 to Wi-Fi adapter), given in place of
+//! the serial device as `tcp://host:port`, or with the `tls` feature as `tls://host:port` for a bridge behind TLS.
+//!
+//! The bridge is connected when the node starts and reconnected after an error as a serial device is reopened.
+//! Over TLS, the certificate of the bridge is checked against the CA certificates of a PEM file if given, otherwise
+//! against the Mozilla root certificates, for the host of the address unless another server name is given.
+//! A certificate which cannot be verified fails the connection, there is no fallback to an unencrypted stream.
+
+use std::io;
+use std::time::Duration;
+
+use async_trait::async_trait;
+use tokio::io::{AsyncRead, BufReader};
+use tokio::net::TcpStream;
+use ygw::{Result, YgwError};
+
+use crate::serial::{LineBuffer, LineSource};
+
+const TCP_SCHEME: &str = "tcp://";
+const TLS_SCHEME: &str = "tls://";
+// a connection (including the TLS handshake) not established within this time is retried later
+const CONNECT_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// returns true if the device is the address of a network bridge rather than a serial device
+pub fn is_network(device: &str) -> bool {
+    device.starts_with(TCP_SCHEME) || device.starts_with(TLS_SCHEME)
+}
+
+/// checks the address of a network bridge, such that a wrong address fails the creation of the node
+/// rather than each connection attempt
+pub fn check(device: &str) -> Result<()> {
+    let (tls, _, _) = parse(device)?;
+    if tls && cfg!(not(feature = "tls")) {
+        return Err(YgwError::ParseError(format!(
+            "the address {device} requires the tls feature"
+        )));
+    }
+    Ok(())
+}
+
+/// splits tcp://host:port or tls://host:port into the TLS flag, the address host:port and the host
+fn parse(device: &str) -> Result<(bool, &str, &str)> {
+    let (tls, address) = match device.strip_prefix(TCP_SCHEME) {
+        Some(address) => (false, address),
+        None => (true, device.strip_prefix(TLS_SCHEME).unwrap_or_default()),
+    };
+    match address.rsplit_once(':') {
+        Some((host, port)) if !host.is_empty() && port.parse::<u16>().is_ok() => Ok((
+            tls,
+            address,
+            host.trim_start_matches('[').trim_end_matches(']'),
+        )),
+        _ => Err(YgwError::ParseError(format!(
+            "invalid address '{device}', expected tcp://host:port or tls://host:port"
+        ))),
+    }
+}
+
+/// connects to the bridge at the address tcp://host:port or tls://host:port
+/// tls_config gives the certificates trusted over TLS, the Mozilla root certificates if None
+pub async fn connect(
+    device: &str,
+    #[cfg(feature = "tls")] tls_config: Option<&TlsConfig>,
+) -> Result<Box<dyn LineSource>> {
+    let (tls, address, _host) = parse(device)?;
+    let stream = match tokio::time::timeout(CONNECT_TIMEOUT, TcpStream::connect(address)).await {
+        Ok(Ok(stream)) => stream,
+        Ok(Err(e)) => return Err(YgwError::IOError(format!("Cannot connect to {address}"), e)),
+        Err(_) => {
+            return Err(YgwError::DeviceAccessError(format!(
+                "Cannot connect to {address}: timed out"
+            )))
+        }
+    };
+    if !tls {
+        return Ok(Box::new(StreamLines::new(stream)));
+    }
+    #[cfg(feature = "tls")]
+    {
+        let default_config;
+        let tls_config = match tls_config {
+            Some(tls_config) => tls_config,
+            None => {
+                default_config = TlsConfig::new(None, None)?;
+                &default_config
+            }
+        };
+        tls_config.handshake(stream, _host).await
+    }
+    #[cfg(not(feature = "tls"))]
+    Err(YgwError::ParseError(format!(
+        "the address {device} requires the tls feature"
+    )))
+}
+
+/// the lines read from a stream connected to the bridge
+pub struct StreamLines<S> {
+    reader: BufReader<S>,
+    line: LineBuffer,
+}
+
+impl<S: AsyncRead + Unpin + Send + Sync> StreamLines<S> {
+    pub fn new(stream: S) -> Self {
+        Self {
+            reader: BufReader::new(stream),
+            line: LineBuffer::default(),
+        }
+    }
+}
+
+#[async_trait]
+impl<S: AsyncRead + Unpin + Send + Sync> LineSource for StreamLines<S> {
+    /// the part of a line received before the timeout is kept for the next call
+    /// the bridge closing the connection ends it with an end of file error
+    async fn next_line(
+        &mut self,
+        timeout: Duration,
+        max_size: usize,
+    ) -> io::Result<Option<Vec<u8>>> {
+        tokio::time::timeout(timeout, self.line.read(&mut self.reader, max_size))
+            .await
+            .unwrap_or(Ok(None))
+    }
+}
+
+#[cfg(feature = "tls")]
+pub use tls::TlsConfig;
+
+#[cfg(feature = "tls")]
+mod tls {
+    use super::*;
+    use std::path::Path;
+    use std::sync::Arc;
+    use tokio_rustls::rustls::crypto::ring;
+    use tokio_rustls::rustls::pki_types::{pem::PemObject, CertificateDer, ServerName};
+    use tokio_rustls::rustls::{ClientConfig, RootCertStore};
+    use tokio_rustls::TlsConnector;
+
+    /// the certificates trusted and the server name checked when connecting to a bridge over TLS
+    pub struct TlsConfig {
+        connector: TlsConnector,
+        // the name expected in the certificate instead of the host of the address
+        server_name: Option<ServerName<'static>>,
+    }
+
+    impl TlsConfig {
+        /// trusts the CA certificates of the PEM file ca_file if given, otherwise the Mozilla root certificates
+        pub fn new(server_name: Option<&str>, ca_file: Option<&Path>) -> Result<Self> {
+            let mut roots = RootCertStore::empty();
+            match ca_file {
+                Some(path) => {
+                    let certs = CertificateDer::pem_file_iter(path)
+                        .and_then(|certs| certs.collect::<std::result::Result<Vec<_>, _>>())
+                        .map_err(|e| {
+                            YgwError::ParseError(format!(
+                                "cannot read the CA certificates {}: {e}",
+                                path.display()
+                            ))
+                        })?;
+                    let (added, _) = roots.add_parsable_certificates(certs);
+                    if added == 0 {
+                        return Err(YgwError::ParseError(format!(
+                            "no valid CA certificate in {}",
+                            path.display()
+                        )));
+                    }
+                }
+                None => roots.extend(webpki_roots::TLS_SERVER_ROOTS.iter().cloned()),
+            }
+            let config = ClientConfig::builder_with_provider(Arc::new(ring::default_provider()))
+                .with_safe_default_protocol_versions()
+                .map_err(|e| YgwError::ParseError(format!("invalid TLS configuration: {e}")))?
+                .with_root_certificates(roots)
+                .with_no_client_auth();
+            let server_name = server_name.map(to_server_name).transpose()?;
+            Ok(Self {
+                connector: TlsConnector::from(Arc::new(config)),
+                server_name,
+            })
+        }
+
+        /// starts TLS on the stream connected to the host, failing if the certificate cannot be verified
+        pub(super) async fn handshake(
+            &self,
+            stream: TcpStream,
+            host: &str,
+        ) -> Result<Box<dyn LineSource>> {
+            let server_name = match &self.server_name {
+                Some(server_name) => server_name.clone(),
+                None => to_server_name(host)?,
+            };
+            let handshake = self.connector.connect(server_name, stream);
+            match tokio::time::timeout(CONNECT_TIMEOUT, handshake).await {
+                Ok(Ok(stream)) => Ok(Box::new(StreamLines::new(stream))),
+                Ok(Err(e)) => Err(YgwError::DeviceAccessError(format!(
+                    "TLS handshake with {host} failed: {e}"
+                ))),
+                Err(_) => Err(YgwError::DeviceAccessError(format!(
+                    "TLS handshake with {host} failed: timed out"
+                ))),
+            }
+        }
+    }
+
+    fn to_server_name(name: &str) -> Result<ServerName<'static>> {
+        ServerName::try_from(name.to_owned())
+            .map_err(|_| YgwError::ParseError(format!("invalid TLS server name '{name}'")))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tokio::io::AsyncWriteExt;
+    use tokio::net::TcpListener;
+
+    const TELEGRAM: &[u8] = b"/ISK5\\2M550T-1012\r\n\r\n1-0:1.7.0(00.316*kW)\r\n!ABCD\r\n";
+
+    /// reads the lines until the end of the telegram
+    async fn read_telegram(source: &mut Box<dyn LineSource>) -> Vec<u8> {
+        let mut telegram = Vec::new();
+        while !telegram.ends_with(b"!ABCD\r\n") {
+            if let Some(line) = source
+                .next_line(Duration::from_secs(1), 1024)
+                .await
+                .unwrap()
+            {
+                telegram.extend_from_slice(&line);
+            }
+        }
+        telegram
+    }
+
+    #[test]
+    fn test_check() {
+        assert!(is_network("tcp://192.168.1.20:8088"));
+        assert!(!is_network("/dev/ttyUSB0"));
+        assert!(check("tcp://192.168.1.20:8088").is_ok());
+        assert!(check("tcp://[::1]:8088").is_ok());
+        assert!(check("tcp://192.168.1.20").is_err());
+        assert!(check("tcp://:8088").is_err());
+        assert!(check("tcp://bridge:http").is_err());
+        assert_eq!(check("tls://bridge:443").is_ok(), cfg!(feature = "tls"));
+    }
+
+    #[tokio::test]
+    async fn test_tcp() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let port = listener.local_addr().unwrap().port();
+        let server = tokio::spawn(async move {
+            let (mut stream, _) = listener.accept().await.unwrap();
+            // a telegram received in two parts
+            stream.write_all(&TELEGRAM[..25]).await.unwrap();
+            tokio::time::sleep(Duration::from_millis(50)).await;
+            stream.write_all(&TELEGRAM[25..]).await.unwrap();
+        });
+
+        #[cfg(feature = "tls")]
+        let mut source = connect(&format!("tcp://127.0.0.1:{port}"), None)
+            .await
+            .unwrap();
+        #[cfg(not(feature = "tls"))]
+        let mut source = connect(&format!("tcp://127.0.0.1:{port}")).await.unwrap();
+        assert_eq!(read_telegram(&mut source).await, TELEGRAM);
+
+        // the bridge closing the connection ends it
+        server.await.unwrap();
+        let err = source
+            .next_line(Duration::from_secs(1), 1024)
+            .await
+            .unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::UnexpectedEof);
+    }
+
+    #[cfg(feature = "tls")]
+    #[tokio::test]
+    async fn test_tls() {
+        use std::sync::Arc;
+        use tokio_rustls::rustls::crypto::ring;
+        use tokio_rustls::rustls::pki_types::{PrivateKeyDer, PrivatePkcs8KeyDer};
+        use tokio_rustls::rustls::ServerConfig;
+        use tokio_rustls::TlsAcceptor;
+
+        // a bridge with a self-signed certificate for localhost, streaming a telegram to each client
+        let cert = rcgen::generate_simple_self_signed(vec!["localhost".to_owned()]).unwrap();
+        let key = PrivateKeyDer::Pkcs8(PrivatePkcs8KeyDer::from(cert.key_pair.serialize_der()));
+        let config = ServerConfig::builder_with_provider(Arc::new(ring::default_provider()))
+            .with_safe_default_protocol_versions()
+            .unwrap()
+            .with_no_client_auth()
+            .with_single_cert(vec![cert.cert.der().clone()], key)
+            .unwrap();
+        let acceptor = TlsAcceptor::from(Arc::new(config));
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let address = format!("tls://127.0.0.1:{}", listener.local_addr().unwrap().port());
+        tokio::spawn(async move {
+            loop {
+                let (stream, _) = listener.accept().await.unwrap();
+                let acceptor = acceptor.clone();
+                tokio::spawn(async move {
+                    if let Ok(mut stream) = acceptor.accept(stream).await {
+                        let _ = stream.write_all(TELEGRAM).await;
+                        let _ = stream.shutdown().await;
+                    }
+                });
+            }
+        });
+
+        let dir = std::env::temp_dir().join(format!("p1mon-tls-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let ca_file = dir.join("ca.pem");
+        std::fs::write(&ca_file, cert.cert.pem()).unwrap();
+
+        // trusting the certificate of the bridge, for the name in its certificate
+        let tls_config = TlsConfig::new(Some("localhost"), Some(&ca_file)).unwrap();
+        let mut source = connect(&address, Some(&tls_config)).await.unwrap();
+        assert_eq!(read_telegram(&mut source).await, TELEGRAM);
+
+        // the certificate is not valid for the host of the address
+        let tls_config = TlsConfig::new(None, Some(&ca_file)).unwrap();
+        let err = connect(&address, Some(&tls_config)).await.err().unwrap();
+        assert!(
+            matches!(&err, YgwError::DeviceAccessError(msg) if msg.contains("TLS handshake")),
+            "{err:?}"
+        );
+
+        // the certificate is not signed by a trusted CA
+        let err = connect(&address, None).await.err().unwrap();
+        assert!(
+            matches!(&err, YgwError::DeviceAccessError(msg) if msg.contains("TLS handshake")),
+            "{err:?}"
+        );
+
+        // the CA file contains no certificate
+        std::fs::write(&ca_file, "not a certificate").unwrap();
+        assert!(TlsConfig::new(None, Some(&ca_file)).is_err());
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,211 @@
+//! Limitation of the rate at which the parameter values are published.
+//!
+//! The values decoded from the telegrams received faster than the minimum interval are coalesced,
+//! only the latest value of each parameter being kept until the interval has elapsed.
+
+use std::collections::HashMap;
+use std::time::{Duration, Instant};
+
+use ygw::protobuf::ygw::{ParameterValue, Timestamp};
+
+pub struct Throttle {
+    min_interval: Duration,
+    last_publish: Option<Instant>,
+    // the latest value of each parameter, by parameter id
+    pending: HashMap<u32, ParameterValue>,
+}
+
+impl Throttle {
+    pub fn new(min_interval: Duration) -> Self {
+        Self {
+            min_interval,
+            last_publish: None,
+            pending: HashMap::new(),
+        }
+    }
+
+    pub fn min_interval(&self) -> Duration {
+        self.min_interval
+    }
+
+    pub fn set_min_interval(&mut self, min_interval: Duration) {
+        self.min_interval = min_interval;
+    }
+
+    /// adds the values decoded from one telegram, replacing the older values of the same parameters
+    /// when throttling, the values without their own generation time keep the generation time of their telegram
+    /// such that a value published together with the values of a later telegram is not misdated
+    pub fn add(&mut self, pvalues: Vec<ParameterValue>, generation_time: Option<&Timestamp>) {
+        for mut pv in pvalues {
+            if !self.min_interval.is_zero() && pv.generation_time.is_none() {
+                pv.generation_time = generation_time.cloned();
+            }
+            self.pending.insert(pv.id, pv);
+        }
+    }
+
+    /// returns the values not yet published, regardless of the minimum interval, or None if there are none
+    pub fn flush(&mut self) -> Option<Vec<ParameterValue>> {
+        if self.pending.is_empty() {
+            return None;
+        }
+        let mut pvalues: Vec<ParameterValue> = self.pending.drain().map(|(_, pv)| pv).collect();
+        pvalues.sort_by_key(|pv| pv.id);
+        Some(pvalues)
+    }
+
+    /// discards the values not yet published such that the next values are published right away
+    pub fn reset(&mut self) {
+        self.pending.clear();
+        self.last_publish = None;
+    }
+
+    /// returns the values to be published at the time now, sorted by parameter id,
+    /// or None if the minimum interval has not elapsed since the last publication or if there is nothing to publish
+    pub fn take(&mut self, now: Instant) -> Option<Vec<ParameterValue>> {
+        if self.pending.is_empty()
+            || self
+                .last_publish
+                .is_some_and(|t| now.duration_since(t) < self.min_interval)
+        {
+            return None;
+        }
+        self.last_publish = Some(now);
+        self.flush()
+    }
+}
+
+/// parses an interval given as a number of seconds or with one of the ms, s, min or h suffixes, e.g. 10s
+pub fn parse_interval(s: &str) -> Option<Duration> {
+    let s = s.trim();
+    let idx = s
+        .find(|c: char| !(c.is_ascii_digit() || c == '.'))
+        .unwrap_or(s.len());
+    let x: f64 = s[..idx].parse().ok()?;
+    let factor = match &s[idx..] {
+        "ms" => 1e-3,
+        "" | "s" => 1.0,
+        "min" => 60.0,
+        "h" => 3600.0,
+        _ => return None,
+    };
+    Duration::try_from_secs_f64(x * factor).ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ygw::protobuf::ygw::{value::V, Value};
+
+    fn pvalue(id: u32, x: i64) -> ParameterValue {
+        ParameterValue {
+            id,
+            eng_value: Some(Value {
+                v: Some(V::Sint64Value(x)),
+            }),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_throttle() {
+        let mut throttle = Throttle::new(Duration::from_secs(5));
+        let t0 = Instant::now();
+        let mut published = Vec::new();
+
+        // one telegram per second during 12 seconds
+        for i in 0..12 {
+            throttle.add(vec![pvalue(0, i)], None);
+            if i % 2 == 0 {
+                throttle.add(vec![pvalue(1, i)], None);
+            }
+            if let Some(pvalues) = throttle.take(t0 + Duration::from_secs(i as u64)) {
+                published.push((i, pvalues));
+            }
+        }
+
+        let times: Vec<i64> = published.iter().map(|(i, _)| *i).collect();
+        assert_eq!(times, vec![0, 5, 10]);
+        // the latest value of each parameter is published
+        assert_eq!(published[1].1, vec![pvalue(0, 5), pvalue(1, 4)]);
+    }
+
+    #[test]
+    fn test_generation_time_preserved() {
+        let mut throttle = Throttle::new(Duration::from_secs(10));
+        let t0 = Instant::now();
+        let gentime = |seconds: i64| Timestamp {
+            millis: seconds * 1000,
+            picos: 0,
+        };
+
+        throttle.add(vec![pvalue(0, 1)], Some(&gentime(100)));
+        assert!(throttle.take(t0).is_some());
+        throttle.add(vec![pvalue(0, 2), pvalue(1, 2)], Some(&gentime(101)));
+        throttle.add(vec![pvalue(0, 3)], Some(&gentime(102)));
+
+        let pvalues = throttle.take(t0 + Duration::from_secs(10)).unwrap();
+        assert_eq!(pvalues[0].generation_time, Some(gentime(102)));
+        assert_eq!(pvalues[1].generation_time, Some(gentime(101)));
+    }
+
+    #[test]
+    fn test_take_without_add() {
+        let mut throttle = Throttle::new(Duration::from_secs(5));
+        let t0 = Instant::now();
+        throttle.add(vec![pvalue(0, 1)], None);
+        assert!(throttle.take(t0).is_some());
+        throttle.add(vec![pvalue(0, 2)], None);
+        assert_eq!(throttle.take(t0 + Duration::from_secs(1)), None);
+
+        // the value held back comes out once the interval has elapsed, without any further telegram
+        assert_eq!(
+            throttle.take(t0 + Duration::from_secs(5)),
+            Some(vec![pvalue(0, 2)])
+        );
+        assert_eq!(throttle.take(t0 + Duration::from_secs(10)), None);
+    }
+
+    #[test]
+    fn test_parse_interval() {
+        assert_eq!(parse_interval("10"), Some(Duration::from_secs(10)));
+        assert_eq!(parse_interval("10s"), Some(Duration::from_secs(10)));
+        assert_eq!(parse_interval("500ms"), Some(Duration::from_millis(500)));
+        assert_eq!(parse_interval("1.5min"), Some(Duration::from_secs(90)));
+        assert_eq!(parse_interval("0"), Some(Duration::ZERO));
+        assert_eq!(parse_interval("10 furlongs"), None);
+        assert_eq!(parse_interval("s"), None);
+    }
+
+    #[test]
+    fn test_no_throttle() {
+        let mut throttle = Throttle::new(Duration::ZERO);
+        let t0 = Instant::now();
+        for _ in 0..3 {
+            throttle.add(vec![pvalue(0, 1)], None);
+            assert_eq!(throttle.take(t0), Some(vec![pvalue(0, 1)]));
+        }
+        assert_eq!(throttle.take(t0), None);
+    }
+
+    #[test]
+    fn test_flush() {
+        let mut throttle = Throttle::new(Duration::from_secs(10));
+        let t0 = Instant::now();
+        throttle.add(vec![pvalue(0, 1)], None);
+        assert!(throttle.take(t0).is_some());
+        throttle.add(vec![pvalue(0, 2)], None);
+        assert_eq!(throttle.take(t0 + Duration::from_secs(1)), None);
+        assert_eq!(throttle.flush(), Some(vec![pvalue(0, 2)]));
+        assert_eq!(throttle.flush(), None);
+
+        throttle.add(vec![pvalue(0, 3)], None);
+        throttle.reset();
+        assert_eq!(throttle.flush(), None);
+        throttle.add(vec![pvalue(0, 4)], None);
+        assert_eq!(
+            throttle.take(t0 + Duration::from_secs(2)),
+            Some(vec![pvalue(0, 4)])
+        );
+    }
+}
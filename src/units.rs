@@ -0,0 +1,98 @@
+//! Conversion between the units reported by the meters.
+//!
+//! Each known unit is attached to a physical quantity and a factor relative to the base unit of that quantity.
+//! Two units can be converted into each other only if they measure the same quantity.
+
+#[derive(Debug, PartialEq, Clone, Copy)]
+enum Quantity {
+    Power,
+    Energy,
+    ReactivePower,
+    ReactiveEnergy,
+    Voltage,
+    Current,
+    Volume,
+    Time,
+}
+
+// unit, quantity, factor to the base unit of the quantity
+const UNITS: &[(&str, Quantity, f64)] = &[
+    ("W", Quantity::Power, 1.0),
+    ("kW", Quantity::Power, 1e3),
+    ("MW", Quantity::Power, 1e6),
+    // energy in J, such that heat meters reporting GJ can be converted too
+    ("J", Quantity::Energy, 1.0),
+    ("kJ", Quantity::Energy, 1e3),
+    ("MJ", Quantity::Energy, 1e6),
+    ("GJ", Quantity::Energy, 1e9),
+    ("Wh", Quantity::Energy, 3.6e3),
+    ("kWh", Quantity::Energy, 3.6e6),
+    ("MWh", Quantity::Energy, 3.6e9),
+    ("var", Quantity::ReactivePower, 1.0),
+    ("kvar", Quantity::ReactivePower, 1e3),
+    ("varh", Quantity::ReactiveEnergy, 1.0),
+    ("kvarh", Quantity::ReactiveEnergy, 1e3),
+    ("mV", Quantity::Voltage, 1e-3),
+    ("V", Quantity::Voltage, 1.0),
+    ("kV", Quantity::Voltage, 1e3),
+    ("mA", Quantity::Current, 1e-3),
+    ("A", Quantity::Current, 1.0),
+    ("kA", Quantity::Current, 1e3),
+    ("L", Quantity::Volume, 1e-3),
+    ("dm3", Quantity::Volume, 1e-3),
+    ("m3", Quantity::Volume, 1.0),
+    // some meters write the exponent as a superscript
+    ("dm³", Quantity::Volume, 1e-3),
+    ("m³", Quantity::Volume, 1.0),
+    ("ms", Quantity::Time, 1e-3),
+    ("s", Quantity::Time, 1.0),
+    ("min", Quantity::Time, 60.0),
+    ("h", Quantity::Time, 3600.0),
+];
+
+fn lookup(unit: &str) -> Option<(Quantity, f64)> {
+    UNITS
+        .iter()
+        .find(|(u, _, _)| *u == unit)
+        .map(|(_, q, f)| (*q, *f))
+}
+
+/// returns true if the unit is part of the conversion table
+pub fn is_known(unit: &str) -> bool {
+    lookup(unit).is_some()
+}
+
+/// returns the factor by which a value expressed in the `from` unit has to be multiplied to be expressed in the `to` unit
+/// returns None if any of the units is unknown or if they do not measure the same quantity
+pub fn conversion_factor(from: &str, to: &str) -> Option<f64> {
+    let (qfrom, ffrom) = lookup(from)?;
+    let (qto, fto) = lookup(to)?;
+
+    if qfrom == qto {
+        Some(ffrom / fto)
+    } else {
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_conversion_factor() {
+        assert_eq!(conversion_factor("kW", "W"), Some(1000.0));
+        assert_eq!(conversion_factor("kWh", "Wh"), Some(1000.0));
+        assert_eq!(conversion_factor("mA", "A"), Some(0.001));
+        assert_eq!(conversion_factor("m3", "L"), Some(1000.0));
+        assert_eq!(conversion_factor("V", "V"), Some(1.0));
+        assert_eq!(conversion_factor("m³", "m3"), Some(1.0));
+    }
+
+    #[test]
+    fn test_conversion_factor_incompatible() {
+        assert_eq!(conversion_factor("kW", "kWh"), None);
+        assert_eq!(conversion_factor("kW", "furlong"), None);
+        assert!(!is_known("furlong"));
+    }
+}
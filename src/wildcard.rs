@@ -0,0 +1,104 @@
+//! Wildcard OBIS codes.
+//!
+//! A `*` in a code matches a sequence of one or more digits, such that `1-0:*2.7.0` matches the
+//! per-phase voltages `1-0:32.7.0`, `1-0:52.7.0` and `1-0:72.7.0` and `0-*:24.2.1` matches
+//! the readings of all M-Bus channels.
+
+pub fn is_wildcard(code: &str) -> bool {
+    code.contains('*')
+}
+
+/// returns the number of wildcards in the pattern
+pub fn count(pattern: &str) -> usize {
+    pattern.matches('*').count()
+}
+
+/// matches the code against the pattern
+/// returns the sequences of digits matched by each `*` or None if the code does not match
+pub fn matches<'a>(pattern: &str, code: &'a str) -> Option<Vec<&'a str>> {
+    let mut captures = Vec::new();
+    if match_from(pattern.as_bytes(), code, 0, &mut captures) {
+        Some(captures)
+    } else {
+        None
+    }
+}
+
+fn match_from<'a>(p: &[u8], code: &'a str, ci: usize, captures: &mut Vec<&'a str>) -> bool {
+    let c = code.as_bytes();
+    match p.first() {
+        None => ci == c.len(),
+        Some(b'*') => {
+            let mut end = ci;
+            while end < c.len() && c[end].is_ascii_digit() {
+                end += 1;
+            }
+            // try the longest sequence of digits first
+            for e in (ci + 1..=end).rev() {
+                captures.push(&code[ci..e]);
+                if match_from(&p[1..], code, e, captures) {
+                    return true;
+                }
+                captures.pop();
+            }
+            false
+        }
+        Some(&x) => ci < c.len() && c[ci] == x && match_from(&p[1..], code, ci + 1, captures),
+    }
+}
+
+/// returns true if there is at least one code matched by both patterns
+pub fn overlap(a: &str, b: &str) -> bool {
+    overlap_from(&expand(a), &expand(b))
+}
+
+// in the expanded form, '#' stands for exactly one digit and '*' for zero or more digits
+fn expand(pattern: &str) -> Vec<u8> {
+    pattern.replace('*', "#*").into_bytes()
+}
+
+fn overlap_from(a: &[u8], b: &[u8]) -> bool {
+    match (a.first(), b.first()) {
+        (None, None) => true,
+        (Some(b'*'), _) => {
+            overlap_from(&a[1..], b)
+                || (b.first().is_some_and(|&x| can_be_digit(x)) && overlap_from(a, &b[1..]))
+        }
+        (_, Some(b'*')) => {
+            overlap_from(a, &b[1..])
+                || (a.first().is_some_and(|&x| can_be_digit(x)) && overlap_from(&a[1..], b))
+        }
+        (Some(&x), Some(&y)) => {
+            let compatible =
+                x == y || (x == b'#' && y.is_ascii_digit()) || (y == b'#' && x.is_ascii_digit());
+            compatible && overlap_from(&a[1..], &b[1..])
+        }
+        _ => false,
+    }
+}
+
+fn can_be_digit(x: u8) -> bool {
+    x == b'#' || x == b'*' || x.is_ascii_digit()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_matches() {
+        assert_eq!(matches("1-0:*2.7.0", "1-0:32.7.0"), Some(vec!["3"]));
+        assert_eq!(matches("0-*:24.2.1", "0-1:24.2.1"), Some(vec!["1"]));
+        assert_eq!(matches("*-*:24.2.1", "0-12:24.2.1"), Some(vec!["0", "12"]));
+        assert_eq!(matches("1-0:*2.7.0", "1-0:2.7.0"), None);
+        assert_eq!(matches("1-0:*2.7.0", "1-0:32.7.1"), None);
+    }
+
+    #[test]
+    fn test_overlap() {
+        assert!(overlap("1-0:*2.7.0", "1-0:3*.7.0"));
+        assert!(overlap("0-*:24.2.1", "0-1:24.2.*"));
+        assert!(!overlap("1-0:*2.7.0", "1-0:*1.7.0"));
+        assert!(!overlap("0-*:24.2.1", "0-*:24.2.3"));
+    }
+}